@@ -0,0 +1,127 @@
+use crate::glob;
+use crate::repository;
+use eyre::{Context, Result};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// The attributes assigned to paths by `.gitattributes` files.
+#[derive(Debug, Default)]
+pub struct Attributes {
+    rules: Vec<AttributeRule>,
+}
+
+#[derive(Debug)]
+struct AttributeRule {
+    pattern: String,
+    /// Each attribute's state; `None` (from `!name`) resets it to unspecified.
+    attributes: Vec<(String, Option<AttributeState>)>,
+}
+
+/// The state of a single attribute for a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttributeState {
+    /// `name`
+    Set,
+    /// `-name`
+    Unset,
+    /// `name=value`
+    Value(String),
+}
+
+static CURRENT: OnceLock<Attributes> = OnceLock::new();
+
+impl Attributes {
+    /// The attributes for the current repository, which are read from the
+    /// top-level `.gitattributes` and `.git/info/attributes` the first time
+    /// they're needed (the latter takes precedence).
+    // FIXME: read `.gitattributes` files in subdirectories
+    pub fn current() -> Result<&'static Self> {
+        if let Some(attributes) = CURRENT.get() {
+            return Ok(attributes);
+        }
+
+        let mut attributes = Self::read(repository::work_tree_path(".gitattributes"))?;
+        attributes.extend(Self::read(
+            repository::common_dir().join("info/attributes"),
+        )?);
+        Ok(CURRENT.get_or_init(|| attributes))
+    }
+
+    /// Add the rules from `other` after these, so that they take precedence.
+    pub fn extend(&mut self, other: Self) {
+        self.rules.extend(other.rules);
+    }
+
+    /// Read the attributes file at the given path; a missing file is treated as empty.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        match std::fs::read_to_string(path.as_ref()) {
+            Ok(contents) => Ok(Self::parse(&contents)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).context("read attributes file"),
+        }
+    }
+
+    pub fn parse(s: &str) -> Self {
+        let mut rules = Vec::new();
+
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let Some(pattern) = words.next() else {
+                continue;
+            };
+
+            let mut attributes = Vec::new();
+            for word in words {
+                if let Some(name) = word.strip_prefix('-') {
+                    attributes.push((name.to_owned(), Some(AttributeState::Unset)));
+                } else if let Some(name) = word.strip_prefix('!') {
+                    attributes.push((name.to_owned(), None));
+                } else if let Some((name, value)) = word.split_once('=') {
+                    attributes.push((name.to_owned(), Some(AttributeState::Value(value.into()))));
+                } else if word == "binary" {
+                    // `binary` is a built-in macro for `-diff -merge -text`
+                    for name in ["binary", "diff", "merge", "text"] {
+                        let state = if name == "binary" {
+                            AttributeState::Set
+                        } else {
+                            AttributeState::Unset
+                        };
+                        attributes.push((name.to_owned(), Some(state)));
+                    }
+                } else {
+                    attributes.push((word.to_owned(), Some(AttributeState::Set)));
+                }
+            }
+
+            rules.push(AttributeRule {
+                pattern: pattern.to_owned(),
+                attributes,
+            });
+        }
+
+        Self { rules }
+    }
+
+    /// Look up the state of an attribute for a path (relative to the root of
+    /// the repository). Later rules take precedence over earlier ones, and
+    /// `None` means that the attribute is unspecified.
+    pub fn get(&self, path: &[u8], name: &str) -> Option<&AttributeState> {
+        self.rules
+            .iter()
+            .rev()
+            .filter(|rule| glob::matches_path(&rule.pattern, path))
+            .find_map(|rule| {
+                rule.attributes
+                    .iter()
+                    .rev()
+                    .find(|(attr, _)| attr == name)
+                    .map(|(_, state)| state.as_ref())
+            })
+            .flatten()
+    }
+}
@@ -0,0 +1,75 @@
+use crate::config::Config;
+use ansi_term::Style;
+use eyre::Result;
+use std::io::IsTerminal;
+
+/// When output should be colored, as set by `color.ui` (or a command-specific
+/// setting like `color.status`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    Always,
+    Never,
+    /// Only when writing to a terminal.
+    Auto,
+}
+
+impl ColorMode {
+    /// The color setting for the given command (like `color.status`), which
+    /// falls back to `color.ui`.
+    pub fn from_config(config: &Config, command: &str) -> Result<Self> {
+        match config
+            .get(&format!("color.{command}"))
+            .or_else(|| config.get("color.ui"))
+        {
+            None => Ok(Self::Auto),
+            Some(value) => value.parse(),
+        }
+    }
+}
+
+impl std::str::FromStr for ColorMode {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // like git, `true` only means to color output for a terminal
+        match s.to_lowercase().as_str() {
+            "always" => Ok(Self::Always),
+            "never" | "false" | "no" | "off" | "0" => Ok(Self::Never),
+            "auto" | "true" | "yes" | "on" | "1" => Ok(Self::Auto),
+            _ => eyre::bail!("invalid color value '{s}'"),
+        }
+    }
+}
+
+/// Styles for a command's output, which are only applied if it should be
+/// colored.
+pub struct Colors {
+    enabled: bool,
+}
+
+impl Colors {
+    /// Colors for a command's output written to stdout. Unless configured
+    /// otherwise, they're disabled when stdout isn't a terminal (so redirected
+    /// output stays plain) or when `NO_COLOR` is set.
+    pub fn for_stdout(command: &str) -> Result<Self> {
+        let enabled = match ColorMode::from_config(&Config::read_default()?, command)? {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                std::io::stdout().is_terminal()
+                    && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+        };
+
+        Ok(Self { enabled })
+    }
+
+    /// The given style, or a plain one if color is disabled.
+    pub fn style(&self, style: Style) -> Style {
+        if self.enabled {
+            style
+        } else {
+            Style::new()
+        }
+    }
+}
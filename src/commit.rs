@@ -1,9 +1,11 @@
+use crate::config::Config;
 use crate::object::{ObjectBuf, ObjectType};
+use crate::object_store::{FsObjectStore, ObjectStore};
 use eyre::{Context, Result};
 use std::fmt::{Debug, Display};
 use std::io::BufRead;
 use std::str::FromStr;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[derive(Debug)]
 pub struct Commit {
@@ -11,17 +13,40 @@ pub struct Commit {
     pub parent_hashes: Vec<String>,
     pub author: CommitAttribution,
     pub committer: CommitAttribution,
-    pub message: String,
+    /// Any other headers (like `encoding`, `mergetag`, or the `gpgsig`
+    /// signature), in their original order, which git always writes after
+    /// `committer`. A signature covers the rest of the commit, so it has to
+    /// stay where it was for the commit to keep its hash.
+    pub extra_headers: Vec<(String, Vec<u8>)>,
+    /// Everything after the blank line that ends the headers, byte for byte
+    /// (including the trailing newline, if there is one). Like the names in
+    /// the attributions, this is in the commit's `encoding`, which needn't be
+    /// UTF-8.
+    pub message: Vec<u8>,
 }
 
 #[derive(Debug)]
 pub struct CommitAttribution {
-    pub name: String,
-    pub email: String,
+    pub name: Vec<u8>,
+    pub email: Vec<u8>,
     pub timestamp: SystemTime,
+    /// Offset from UTC, in minutes.
+    pub tz_offset: i16,
 }
 
 impl Commit {
+    pub fn read_at_hash(hash: &str) -> Result<Self> {
+        Self::read_in(FsObjectStore::current(), hash)
+    }
+
+    /// Like [`Commit::read_at_hash`], but from the given object store.
+    pub fn read_in(store: &dyn ObjectStore, hash: &str) -> Result<Self> {
+        let obj = store
+            .read(hash)
+            .with_context(|| format!("read commit {hash}"))?;
+        Self::from_buf(obj)
+    }
+
     pub fn from_buf<R>(mut object: ObjectBuf<R>) -> Result<Self>
     where
         R: BufRead + Debug,
@@ -30,57 +55,332 @@ impl Commit {
             eyre::bail!("attempted to parse {} as commit", object.object_type);
         }
 
-        let mut buf = vec![0; object.content_len];
-        object.contents.read_exact(&mut buf)?;
-        let s = std::str::from_utf8(&buf).context("commit should contain valid UTF-8")?;
+        // commits are kept as bytes, since (other than the hashes) they
+        // needn't be UTF-8
+        let buf = object.read_to_vec()?;
 
         let mut tree_hash: Option<String> = None;
         let mut parent_hashes: Vec<String> = Vec::new();
-        let mut author: Option<String> = None;
-        let mut committer: Option<String> = None;
+        let mut author: Option<CommitAttribution> = None;
+        let mut committer: Option<CommitAttribution> = None;
+        let mut extra_headers: Vec<(String, Vec<u8>)> = Vec::new();
 
-        let mut lines = s.lines();
-        for line in lines.by_ref() {
-            if line.is_empty() {
-                break;
+        let (header, body) = match buf.windows(2).position(|pair| pair == b"\n\n") {
+            Some(end) => (&buf[..end], &buf[end + 2..]),
+            None => (buf.strip_suffix(b"\n").unwrap_or(&buf), &[][..]),
+        };
+
+        // lines are split on '\n' alone, so that a '\r' stays part of its
+        // value, and an empty continuation line still adds a line to it
+        let mut headers: Vec<(&[u8], Vec<u8>)> = Vec::new();
+        for line in header.split(|&b| b == b'\n') {
+            // values that span multiple lines (like signatures) are continued
+            // on lines starting with a single space
+            if let Some(continuation) = line.strip_prefix(b" ") {
+                let (_, value) = headers
+                    .last_mut()
+                    .ok_or_else(|| eyre::eyre!("unexpected continuation line in commit"))?;
+                value.push(b'\n');
+                value.extend_from_slice(continuation);
+                continue;
             }
 
-            let (t, value) = line.split_once(' ').unwrap();
-            let value = value.to_owned();
+            let space = line.iter().position(|&b| b == b' ').ok_or_else(|| {
+                eyre::eyre!(
+                    "unexpected line in commit \"{}\"",
+                    String::from_utf8_lossy(line)
+                )
+            })?;
+            headers.push((&line[..space], line[space + 1..].to_vec()));
+        }
+
+        for (t, value) in headers {
             match t {
-                "tree" => tree_hash = Some(value),
-                "parent" => parent_hashes.push(value),
-                "author" => author = Some(value),
-                "committer" => committer = Some(value),
-                _ => eyre::bail!("unexpected line in commit \"{value}\""),
+                b"tree" => tree_hash = Some(String::from_utf8(value).context("parse tree hash")?),
+                b"parent" => {
+                    parent_hashes.push(String::from_utf8(value).context("parse parent hash")?)
+                }
+                b"author" => author = Some(CommitAttribution::from_bytes(&value)?),
+                b"committer" => committer = Some(CommitAttribution::from_bytes(&value)?),
+                _ => extra_headers.push((
+                    String::from_utf8(t.to_vec()).context("parse commit header name")?,
+                    value,
+                )),
             }
         }
 
         Ok(Self {
-            tree_hash: tree_hash
-                .ok_or_else(|| eyre::eyre!("tree must be provided"))?
-                .to_owned(),
+            tree_hash: tree_hash.ok_or_else(|| eyre::eyre!("tree must be provided"))?,
             parent_hashes,
-            author: author
-                .ok_or_else(|| eyre::eyre!("author must be provided"))?
-                .parse()?,
-            committer: committer
-                .ok_or_else(|| eyre::eyre!("committer must be provided"))?
-                .parse()?,
-            message: lines.map(String::from).collect(),
+            author: author.ok_or_else(|| eyre::eyre!("author must be provided"))?,
+            committer: committer.ok_or_else(|| eyre::eyre!("committer must be provided"))?,
+            extra_headers,
+            message: body.to_vec(),
         })
     }
+
+    /// The first line of the message, with anything that isn't valid UTF-8
+    /// replaced.
+    pub fn summary(&self) -> String {
+        let message = String::from_utf8_lossy(&self.message);
+        message.lines().next().unwrap_or_default().to_owned()
+    }
+
+    /// Expand the `%(<field>)` placeholders in `format` with this commit's
+    /// fields: `tree`, `parent` (space-separated, for merges), `author`,
+    /// `committer`, `subject` (the first paragraph of the message, on one
+    /// line), and `body` (everything after it).
+    pub fn format(&self, format: &str) -> Result<String> {
+        let message = String::from_utf8_lossy(&self.message);
+        let (subject, body) = message.split_once("\n\n").unwrap_or((&message, ""));
+
+        let mut out = String::with_capacity(format.len());
+        let mut rest = format;
+        while let Some(start) = rest.find("%(") {
+            out.push_str(&rest[..start]);
+
+            let end = rest[start..]
+                .find(')')
+                .ok_or_else(|| eyre::eyre!("unterminated placeholder in format '{format}'"))?;
+            match &rest[start + 2..start + end] {
+                "tree" => out.push_str(&self.tree_hash),
+                "parent" => out.push_str(&self.parent_hashes.join(" ")),
+                "author" => out.push_str(&self.author.to_string()),
+                "committer" => out.push_str(&self.committer.to_string()),
+                "subject" => out.push_str(&subject.lines().collect::<Vec<_>>().join(" ")),
+                "body" => out.push_str(body.trim_end_matches('\n')),
+                field => eyre::bail!("unknown field '{field}' in format '{format}'"),
+            }
+
+            rest = &rest[start + end + 1..];
+        }
+        out.push_str(rest);
+
+        Ok(out)
+    }
 }
 
 impl CommitAttribution {
+    /// The fallback attribution, used when neither the environment nor config
+    /// say who you are.
     pub fn yours_truly() -> Self {
-        // FIXME: this should read from config
         Self {
-            name: "Aaron Ross".into(),
-            email: "superhawky610@gmail.com".into(),
+            name: b"Aaron Ross".to_vec(),
+            email: b"superhawky610@gmail.com".to_vec(),
             timestamp: SystemTime::now(),
+            tz_offset: 0,
+        }
+    }
+
+    /// The author of a new commit, taken from `GIT_AUTHOR_{NAME,EMAIL,DATE}`,
+    /// falling back to `user.name`/`user.email` and then the defaults.
+    pub fn author() -> Result<Self> {
+        Self::from_env("AUTHOR")
+    }
+
+    /// The committer of a new commit (or reflog entry), taken from
+    /// `GIT_COMMITTER_{NAME,EMAIL,DATE}`, falling back the same way as
+    /// [`CommitAttribution::author`].
+    pub fn committer() -> Result<Self> {
+        Self::from_env("COMMITTER")
+    }
+
+    fn from_env(role: &str) -> Result<Self> {
+        let config = Config::read_default()?;
+        let mut attribution = Self::yours_truly();
+
+        if let Some(name) = std::env::var(format!("GIT_{role}_NAME"))
+            .ok()
+            .or_else(|| config.get("user.name").map(str::to_owned))
+        {
+            attribution.name = name.into_bytes();
+        }
+        if let Some(email) = std::env::var(format!("GIT_{role}_EMAIL"))
+            .ok()
+            .or_else(|| config.get("user.email").map(str::to_owned))
+        {
+            attribution.email = email.into_bytes();
+        }
+        if let Ok(date) = std::env::var(format!("GIT_{role}_DATE")) {
+            (attribution.timestamp, attribution.tz_offset) =
+                parse_date(&date).ok_or_else(|| eyre::eyre!("invalid date format: {date}"))?;
+        }
+
+        Ok(attribution)
+    }
+
+    /// The name and email, like `Aaron Ross <superhawk610@gmail.com>`, with
+    /// anything that isn't valid UTF-8 replaced.
+    pub fn ident(&self) -> String {
+        format!(
+            "{} <{}>",
+            String::from_utf8_lossy(&self.name),
+            String::from_utf8_lossy(&self.email)
+        )
+    }
+
+    /// Format the timestamp like git's default date format, in the
+    /// attribution's own timezone, e.g. `Tue Mar 12 19:55:26 2019 -0400`.
+    pub fn format_date(&self) -> String {
+        let date = self.local_date();
+        format!(
+            "{} {} {} {} {} {}",
+            date.weekday, date.month, date.day, date.time, date.year, date.tz_offset
+        )
+    }
+
+    /// Format the timestamp as an RFC 2822 date, like the `Date` header of an
+    /// email, e.g. `Tue, 12 Mar 2019 19:55:26 -0400`.
+    pub fn format_rfc2822(&self) -> String {
+        let date = self.local_date();
+        format!(
+            "{}, {} {} {} {} {}",
+            date.weekday, date.day, date.month, date.year, date.time, date.tz_offset
+        )
+    }
+
+    fn local_date(&self) -> LocalDate {
+        const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+        let seconds = self.timestamp.duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+            + i64::from(self.tz_offset) * 60;
+        let days = seconds.div_euclid(86400);
+        let time = seconds.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+
+        let sign = if self.tz_offset < 0 { '-' } else { '+' };
+        let tz_offset = self.tz_offset.unsigned_abs();
+
+        LocalDate {
+            weekday: DAYS[days.rem_euclid(7) as usize],
+            year,
+            month: MONTHS[month as usize - 1],
+            day,
+            time: format!("{:02}:{:02}:{:02}", time / 3600, time / 60 % 60, time % 60),
+            tz_offset: format!("{sign}{:02}{:02}", tz_offset / 60, tz_offset % 60),
+        }
+    }
+}
+
+/// The parts of an attribution's timestamp, in its own timezone.
+struct LocalDate {
+    weekday: &'static str,
+    year: i64,
+    month: &'static str,
+    day: i64,
+    time: String,
+    tz_offset: String,
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Parse a date as given in `GIT_*_DATE`. Like git, this accepts the raw
+/// `<unix timestamp> <tz>` format (optionally with a leading `@`), ISO 8601
+/// (`YYYY-MM-DD[T ]HH:MM:SS`), and RFC 2822 (`[Tue, ]12 Mar 2019 19:55:26`).
+/// The timezone is optional and defaults to UTC.
+pub(crate) fn parse_date(s: &str) -> Option<(SystemTime, i16)> {
+    let s = s.trim();
+    let (datetime, tz_offset) = match s.rsplit_once(' ') {
+        Some((datetime, tz)) if tz.starts_with(['+', '-']) => (datetime, parse_tz_offset(tz)?),
+        Some((datetime, "Z")) => (datetime, 0),
+        _ => (s, 0),
+    };
+
+    let raw = datetime.strip_prefix('@').unwrap_or(datetime);
+    if let Ok(timestamp) = raw.parse::<u64>() {
+        return Some((UNIX_EPOCH + Duration::from_secs(timestamp), tz_offset));
+    }
+
+    // both formats give times local to the given offset
+    let (year, month, day, time) = match parse_rfc2822_date(datetime) {
+        Some(parsed) => parsed,
+        None => {
+            let (date, time) = datetime.split_once(['T', ' '])?;
+            let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+            let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+            (year, month, day, time)
         }
+    };
+    let mut time = time.splitn(3, ':').map(str::parse::<i64>);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
     }
+
+    let seconds = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second
+        - i64::from(tz_offset) * 60;
+    Some((
+        UNIX_EPOCH + Duration::from_secs(u64::try_from(seconds).ok()?),
+        tz_offset,
+    ))
+}
+
+/// Split an RFC 2822 date (without its timezone), like `Tue, 12 Mar 2019
+/// 19:55:26`, into its year, month, day, and time of day.
+fn parse_rfc2822_date(datetime: &str) -> Option<(i64, i64, i64, &str)> {
+    // the day of the week is optional, and redundant anyway
+    let datetime = datetime.split_once(", ").map_or(datetime, |(_, rest)| rest);
+    let mut parts = datetime.split_whitespace();
+    let day = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(month))? as i64
+        + 1;
+    let year = parts.next()?.parse().ok()?;
+    let time = parts.next()?;
+    parts.next().is_none().then_some((year, month, day, time))
+}
+
+/// Days since the unix epoch of the given (proleptic Gregorian) date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // see http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The (proleptic Gregorian) date of the given number of days since the
+/// unix epoch; the inverse of [`days_from_civil`].
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    // see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Parse a timezone offset formatted as `[+-]HHMM` into minutes.
+fn parse_tz_offset(s: &str) -> Option<i16> {
+    let (sign, hhmm) = match s.split_at_checked(1) {
+        Some(("+", hhmm)) => (1, hhmm),
+        Some(("-", hhmm)) => (-1, hhmm),
+        _ => return None,
+    };
+    if hhmm.len() != 4 || !hhmm.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hhmm: i16 = hhmm.parse().ok()?;
+
+    Some(sign * ((hhmm / 100) * 60 + hhmm % 100))
+}
+
+/// Split the bytes around the first occurrence of `delim`.
+fn split_at(s: &[u8], delim: u8) -> Option<(&[u8], &[u8])> {
+    let i = s.iter().position(|&b| b == delim)?;
+    Some((&s[..i], &s[i + 1..]))
 }
 
 #[derive(Debug)]
@@ -98,23 +398,173 @@ impl FromStr for CommitAttribution {
     type Err = ParseCommitAttributionError;
 
     fn from_str(s: &str) -> std::prelude::v1::Result<Self, Self::Err> {
-        // FIXME: actually parse attribution
+        Self::from_bytes(s.as_bytes())
+    }
+}
+
+impl CommitAttribution {
+    /// Parse an attribution as it appears in a commit, where the name and
+    /// email are in the commit's encoding (which needn't be UTF-8).
+    pub fn from_bytes(s: &[u8]) -> std::prelude::v1::Result<Self, ParseCommitAttributionError> {
+        // Aaron Ross <superhawk610@gmail.com> 1552434926 -0400
+        let (name, rest) = split_at(s, b'<').ok_or(ParseCommitAttributionError)?;
+        let (email, rest) = split_at(rest, b'>').ok_or(ParseCommitAttributionError)?;
+        let rest = std::str::from_utf8(rest).map_err(|_| ParseCommitAttributionError)?;
+        let (timestamp, tz_offset) = rest
+            .trim()
+            .split_once(' ')
+            .ok_or(ParseCommitAttributionError)?;
+
+        let timestamp: u64 = timestamp.parse().map_err(|_| ParseCommitAttributionError)?;
+
+        let tz_offset = parse_tz_offset(tz_offset).ok_or(ParseCommitAttributionError)?;
+
         Ok(Self {
-            name: s.to_owned(),
-            email: String::new(),
-            timestamp: SystemTime::now(),
+            name: name.trim_ascii_end().to_vec(),
+            email: email.to_vec(),
+            timestamp: UNIX_EPOCH + Duration::from_secs(timestamp),
+            tz_offset,
         })
     }
+
+    /// Format the attribution as it's written in a commit, leaving the name
+    /// and email as they are.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let sign = if self.tz_offset < 0 { '-' } else { '+' };
+        let tz_offset = self.tz_offset.unsigned_abs();
+
+        let mut out = Vec::with_capacity(self.name.len() + self.email.len() + 24);
+        out.extend_from_slice(&self.name);
+        out.extend_from_slice(b" <");
+        out.extend_from_slice(&self.email);
+        out.extend_from_slice(
+            format!(
+                "> {} {}{:02}{:02}",
+                self.timestamp.duration_since(UNIX_EPOCH).unwrap().as_secs(),
+                sign,
+                tz_offset / 60,
+                tz_offset % 60
+            )
+            .as_bytes(),
+        );
+        out
+    }
 }
 
 impl Display for CommitAttribution {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{} <{}> {} +0000",
-            self.name,
-            self.email,
-            self.timestamp.duration_since(UNIX_EPOCH).unwrap().as_secs()
-        )
+        f.write_str(&String::from_utf8_lossy(&self.to_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::HashAlgorithm;
+    use crate::object::{Object, ObjectHashable};
+    use crate::parser::Parser;
+    use std::io::Cursor;
+
+    fn parse(raw: &[u8]) -> Commit {
+        Commit::from_buf(ObjectBuf {
+            object_type: ObjectType::Commit,
+            content_len: raw.len(),
+            contents: Parser::new(Cursor::new(raw.to_vec())),
+        })
+        .unwrap()
+    }
+
+    /// Write a commit back out, without its object header.
+    fn write(commit: Commit) -> Vec<u8> {
+        let mut out = Vec::new();
+        Object::commit(commit).write(&mut out, None).unwrap();
+        let start = out.iter().position(|&b| b == 0).unwrap();
+        out.split_off(start + 1)
+    }
+
+    #[test]
+    fn real_commit_round_trips_to_the_same_hash() {
+        // the first commit in git's own history
+        let raw = b"tree 2b5bfdf7798569e0b59b16eb9602d5fa572d6038\n\
+            author Linus Torvalds <torvalds@ppc970.osdl.org> 1112911993 -0700\n\
+            committer Linus Torvalds <torvalds@ppc970.osdl.org> 1112911993 -0700\n\
+            \n\
+            Initial revision of \"git\", the information manager from hell\n";
+
+        let commit = parse(raw);
+        assert_eq!(commit.author.name, b"Linus Torvalds");
+        assert_eq!(commit.author.tz_offset, -420);
+
+        let mut hasher = HashAlgorithm::Sha1.hasher();
+        hasher.update(format!("commit {}\0", raw.len()).as_bytes());
+        hasher.update(&write(commit));
+        assert_eq!(
+            hasher.finalize().as_hex(),
+            "e83c5163316f89bfbde7d9ab23ca2e25604af290"
+        );
+    }
+
+    #[test]
+    fn non_utf8_commits_round_trip() {
+        let raw = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            author Jos\xe9 <jose@example.com> 0 +0000\n\
+            committer Jos\xe9 <jose@example.com> 0 +0000\n\
+            encoding ISO-8859-1\n\
+            \n\
+            caf\xe9\n";
+
+        let commit = parse(raw);
+        assert_eq!(commit.author.name, b"Jos\xe9");
+        assert_eq!(
+            commit.extra_headers,
+            [("encoding".to_owned(), b"ISO-8859-1".to_vec())]
+        );
+        assert_eq!(commit.message, b"caf\xe9\n");
+        assert_eq!(write(commit), raw);
+    }
+
+    #[test]
+    fn signed_commits_round_trip() {
+        // the signature isn't necessarily the last header
+        let raw = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            author A <a@example.com> 0 +0000\n\
+            committer A <a@example.com> 0 +0000\n\
+            gpgsig -----BEGIN PGP SIGNATURE-----\n \n \
+            iQEzBAABCAAdFiEE\n \
+            =abcd\n \
+            -----END PGP SIGNATURE-----\n\
+            encoding UTF-8\n\
+            \n\
+            m\n";
+
+        let commit = parse(raw);
+        let keys: Vec<&str> = commit
+            .extra_headers
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect();
+        assert_eq!(keys, ["gpgsig", "encoding"]);
+        assert!(commit.extra_headers[0]
+            .1
+            .ends_with(b"\n-----END PGP SIGNATURE-----"));
+        assert_eq!(write(commit), raw);
+    }
+
+    #[test]
+    fn multi_line_extra_headers_round_trip() {
+        // a carriage return, then an empty continuation line at the end
+        let raw = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            author A <a@example.com> 0 +0000\n\
+            committer A <a@example.com> 0 +0000\n\
+            x-note first\r\n second\n \n\
+            \n\
+            m\n";
+
+        let commit = parse(raw);
+        assert_eq!(
+            commit.extra_headers,
+            [("x-note".to_owned(), b"first\r\nsecond\n".to_vec())]
+        );
+        assert_eq!(write(commit), raw);
     }
 }
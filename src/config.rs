@@ -0,0 +1,360 @@
+use crate::repository;
+use eyre::{Context, Result};
+use std::path::Path;
+
+/// A parsed git config file, such as `.git/config`.
+#[derive(Debug, Default)]
+pub struct Config {
+    entries: Vec<ConfigEntry>,
+}
+
+#[derive(Debug)]
+pub struct ConfigEntry {
+    /// The section name, e.g. `remote` (case-insensitive, stored lowercase).
+    pub section: String,
+    /// The subsection name, e.g. `origin` (case-sensitive).
+    pub subsection: Option<String>,
+    /// The variable name, e.g. `url` (case-insensitive, stored lowercase).
+    pub key: String,
+    pub value: String,
+}
+
+impl Config {
+    pub fn read_default() -> Result<Self> {
+        Self::read(repository::common_dir().join("config"))
+    }
+
+    /// Read the config file at the given path; a missing file is treated as empty.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(&read_raw(path.as_ref())?)
+    }
+
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+        let mut section: Option<(String, Option<String>)> = None;
+
+        for (lineno, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[') {
+                let (header, _) = header.split_once(']').ok_or_else(|| {
+                    eyre::eyre!("unterminated section header on line {}", lineno + 1)
+                })?;
+
+                section = Some(parse_section_header(header));
+                continue;
+            }
+
+            let Some((section, subsection)) = &section else {
+                eyre::bail!(
+                    "config variable outside of a section on line {}",
+                    lineno + 1
+                );
+            };
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), parse_value(value.trim())),
+                // a bare variable name is shorthand for `key = true`
+                None => (line, String::from("true")),
+            };
+
+            entries.push(ConfigEntry {
+                section: section.clone(),
+                subsection: subsection.clone(),
+                key: key.to_lowercase(),
+                value,
+            });
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Look up the value of a dotted key like `remote.origin.url`. If the key
+    /// is set more than once, the last value wins.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_all(key).last()
+    }
+
+    /// Look up a boolean key, accepting the same spellings as git (`true`,
+    /// `yes`, `on`, `1`, or no value at all for true).
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>> {
+        let Some(value) = self.get(key) else {
+            return Ok(None);
+        };
+
+        match value.to_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" | "" => Ok(Some(true)),
+            "false" | "no" | "off" | "0" => Ok(Some(false)),
+            _ => eyre::bail!("bad boolean config value '{value}' for '{key}'"),
+        }
+    }
+
+    /// Look up a non-negative integer key, which (as in git) may have a `k`,
+    /// `m`, or `g` suffix to scale it by 1024, 1024², or 1024³.
+    pub fn get_usize(&self, key: &str) -> Result<Option<usize>> {
+        let Some(value) = self.get(key) else {
+            return Ok(None);
+        };
+
+        let (digits, scale) = match value.chars().last().map(|c| c.to_ascii_lowercase()) {
+            Some('k') => (&value[..value.len() - 1], 1 << 10),
+            Some('m') => (&value[..value.len() - 1], 1 << 20),
+            Some('g') => (&value[..value.len() - 1], 1 << 30),
+            _ => (value, 1),
+        };
+        let n: usize = digits
+            .parse()
+            .with_context(|| format!("bad numeric config value '{value}' for '{key}'"))?;
+        n.checked_mul(scale).map(Some).ok_or_else(|| {
+            eyre::eyre!("numeric config value '{value}' for '{key}' is out of range")
+        })
+    }
+
+    /// The distinct subsections of the given section (like the name of each
+    /// remote, for `remote`), in the order they first appear.
+    pub fn subsections(&self, section: &str) -> Vec<&str> {
+        let mut subsections: Vec<&str> = Vec::new();
+        for entry in self.entries.iter() {
+            if let Some(subsection) = entry.subsection.as_deref() {
+                if entry.section == section && !subsections.contains(&subsection) {
+                    subsections.push(subsection);
+                }
+            }
+        }
+        subsections
+    }
+
+    /// Look up every value of a (possibly multi-valued) dotted key.
+    pub fn get_all<'a>(&'a self, key: &str) -> impl Iterator<Item = &'a str> + 'a {
+        let (section, subsection, name) = split_key(key);
+        self.entries
+            .iter()
+            .filter(move |entry| {
+                entry.section == section
+                    && entry.subsection.as_deref() == subsection.as_deref()
+                    && entry.key == name
+            })
+            .map(|entry| entry.value.as_str())
+    }
+}
+
+/// Set a dotted key like `remote.origin.url` in the config file at `path`,
+/// replacing its value if it's already set (the last one, if it's set more
+/// than once), or else adding it to the end of its section, which is created
+/// if need be. Everything else in the file is left as it was.
+pub fn set_value(path: impl AsRef<Path>, key: &str, value: &str) -> Result<()> {
+    let path = path.as_ref();
+    let contents = read_raw(path)?;
+    let (section, subsection, name) = split_key(key);
+    let target = (section, subsection);
+    let assignment = format!("\t{name} = {}", quote_value(value));
+
+    let raw_lines = raw_lines(&contents);
+    let in_section = |line: &RawLine| line.section.as_ref() == Some(&target);
+    let existing = raw_lines
+        .iter()
+        .rposition(|line| in_section(line) && line.var.as_deref() == Some(&name));
+    // the line after the last header or variable of the (last) matching
+    // section, so any comments or blank lines after it stay where they are
+    let section_end = raw_lines
+        .iter()
+        .rposition(|line| in_section(line) && (line.is_header || line.var.is_some()))
+        .map(|i| i + 1);
+
+    let mut lines: Vec<&str> = raw_lines.iter().map(|line| line.text).collect();
+    let header = section_header(&target.0, target.1.as_deref());
+    match (existing, section_end) {
+        (Some(i), _) => lines[i] = &assignment,
+        (None, Some(i)) => lines.insert(i, &assignment),
+        (None, None) => lines.extend([header.as_str(), &assignment]),
+    }
+
+    write_raw(path, &lines)
+}
+
+/// Remove every value of a dotted key from the config file at `path`,
+/// returning whether it was set at all.
+pub fn unset_value(path: impl AsRef<Path>, key: &str) -> Result<bool> {
+    let path = path.as_ref();
+    let contents = read_raw(path)?;
+    let (section, subsection, name) = split_key(key);
+    let target = Some((section, subsection));
+
+    let raw_lines = raw_lines(&contents);
+    let lines: Vec<&str> = raw_lines
+        .iter()
+        .filter(|line| !(line.section == target && line.var.as_deref() == Some(&name)))
+        .map(|line| line.text)
+        .collect();
+    if lines.len() == raw_lines.len() {
+        return Ok(false);
+    }
+
+    write_raw(path, &lines)?;
+    Ok(true)
+}
+
+/// Remove a whole section (like `[remote "origin"]`), along with everything
+/// in it, from the config file at `path`, returning whether it was there.
+pub fn remove_section(
+    path: impl AsRef<Path>,
+    section: &str,
+    subsection: Option<&str>,
+) -> Result<bool> {
+    let path = path.as_ref();
+    let contents = read_raw(path)?;
+    let target = Some((section.to_lowercase(), subsection.map(String::from)));
+
+    let raw_lines = raw_lines(&contents);
+    let lines: Vec<&str> = raw_lines
+        .iter()
+        .filter(|line| line.section != target)
+        .map(|line| line.text)
+        .collect();
+    if lines.len() == raw_lines.len() {
+        return Ok(false);
+    }
+
+    write_raw(path, &lines)?;
+    Ok(true)
+}
+
+/// A line of a config file, as needed to edit it in place.
+struct RawLine<'a> {
+    text: &'a str,
+    /// The section and subsection the line is in (or starts, for headers).
+    section: Option<(String, Option<String>)>,
+    is_header: bool,
+    /// The (lowercase) name of the variable the line sets, if it sets one.
+    var: Option<String>,
+}
+
+fn raw_lines(contents: &str) -> Vec<RawLine<'_>> {
+    let mut section = None;
+    contents
+        .lines()
+        .map(|text| {
+            let line = text.trim();
+            let header = line
+                .strip_prefix('[')
+                .and_then(|header| header.split_once(']'));
+            if let Some((header, _)) = header {
+                section = Some(parse_section_header(header));
+            }
+
+            let is_comment = line.is_empty() || line.starts_with(['#', ';']);
+            RawLine {
+                text,
+                section: section.clone(),
+                is_header: header.is_some(),
+                var: (header.is_none() && !is_comment).then(|| {
+                    let var = line.split_once('=').map_or(line, |(var, _)| var);
+                    var.trim().to_lowercase()
+                }),
+            }
+        })
+        .collect()
+}
+
+/// Read a config file's text; a missing file is treated as empty.
+fn read_raw(path: &Path) -> Result<String> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(contents),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(err) => Err(err).context("read config file"),
+    }
+}
+
+fn write_raw(path: &Path, lines: &[&str]) -> Result<()> {
+    let mut contents = lines.join("\n");
+    if !contents.is_empty() {
+        contents.push('\n');
+    }
+    std::fs::write(path, contents).context("write config file")
+}
+
+/// Parse the inside of a section header (without its brackets) into its
+/// section name and optional subsection.
+fn parse_section_header(header: &str) -> (String, Option<String>) {
+    match header.split_once(' ') {
+        // [remote "origin"]
+        Some((name, sub)) => (
+            name.to_lowercase(),
+            Some(sub.trim().trim_matches('"').to_owned()),
+        ),
+        // [branch.main] (deprecated, but still accepted by git)
+        None => match header.split_once('.') {
+            Some((name, sub)) => (name.to_lowercase(), Some(sub.to_owned())),
+            None => (header.to_lowercase(), None),
+        },
+    }
+}
+
+/// Format a section header, like `[remote "origin"]`.
+fn section_header(section: &str, subsection: Option<&str>) -> String {
+    match subsection {
+        Some(subsection) => format!("[{section} \"{subsection}\"]"),
+        None => format!("[{section}]"),
+    }
+}
+
+/// Quote a value for writing to a config file, if it needs it; the inverse
+/// of [`parse_value`].
+fn quote_value(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    if escaped.contains(['#', ';']) || value.trim() != value {
+        format!("\"{escaped}\"")
+    } else {
+        escaped
+    }
+}
+
+/// Split a dotted key into its section, optional subsection, and variable name.
+/// The subsection may itself contain dots (`branch.feat/v1.2.remote`).
+fn split_key(key: &str) -> (String, Option<String>, String) {
+    let (section, rest) = key.split_once('.').unwrap_or((key, ""));
+    let (subsection, name) = match rest.rsplit_once('.') {
+        Some((subsection, name)) => (Some(subsection.to_owned()), name),
+        None => (None, rest),
+    };
+
+    (section.to_lowercase(), subsection, name.to_lowercase())
+}
+
+/// Strip quotes, escapes, and trailing comments from a raw config value.
+fn parse_value(raw: &str) -> String {
+    let mut value = String::with_capacity(raw.len());
+    let mut quoted = false;
+    let mut chars = raw.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some('b') => {
+                    value.pop();
+                }
+                Some(c) => value.push(c),
+                None => (),
+            },
+            '#' | ';' if !quoted => break,
+            c => value.push(c),
+        }
+    }
+
+    // whitespace is only significant inside of quotes
+    if raw.ends_with('"') {
+        value
+    } else {
+        value.trim_end().to_owned()
+    }
+}
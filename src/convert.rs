@@ -0,0 +1,152 @@
+use crate::attributes::{AttributeState, Attributes};
+use crate::config::Config;
+use eyre::Result;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// How line endings are converted between the working tree and the object
+/// store, as chosen by `core.autocrlf`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoCrlf {
+    /// Store and check out files exactly as they are.
+    False,
+    /// Convert CRLF to LF when storing text files, and LF to CRLF when
+    /// checking them out.
+    True,
+    /// Convert CRLF to LF when storing text files, but leave them alone when
+    /// checking them out.
+    Input,
+}
+
+static CURRENT: OnceLock<AutoCrlf> = OnceLock::new();
+
+impl AutoCrlf {
+    /// The setting for the current repository, which is read from
+    /// `.git/config` the first time it's needed.
+    pub fn current() -> Result<Self> {
+        if let Some(autocrlf) = CURRENT.get() {
+            return Ok(*autocrlf);
+        }
+
+        let autocrlf = Self::from_config(&Config::read_default()?);
+        Ok(*CURRENT.get_or_init(|| autocrlf))
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        match config
+            .get("core.autocrlf")
+            .map(str::to_lowercase)
+            .as_deref()
+        {
+            Some("true" | "yes" | "on" | "1") => Self::True,
+            Some("input") => Self::Input,
+            _ => Self::False,
+        }
+    }
+}
+
+/// How a path's line endings are converted between the working tree and the
+/// object store, as decided by its `text` and `eol` attributes (falling back
+/// to `core.autocrlf` when `text` is unspecified).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EolConversion {
+    /// Store and check out the file exactly as it is.
+    None,
+    /// Convert CRLF to LF when storing the file, and check it out as-is.
+    Lf { auto: bool },
+    /// Convert CRLF to LF when storing the file, and LF to CRLF when checking
+    /// it out.
+    Crlf { auto: bool },
+}
+
+impl EolConversion {
+    /// Decide how to convert the file at `path` (relative to the root of the
+    /// repository). When `auto` is set, files that look binary are left alone.
+    pub fn for_path(path: &Path) -> Result<Self> {
+        Ok(Self::for_path_with(
+            path,
+            Attributes::current()?,
+            AutoCrlf::current()?,
+        ))
+    }
+
+    /// Like [`EolConversion::for_path`], but with the given attributes and
+    /// `core.autocrlf` setting rather than the current repository's.
+    pub fn for_path_with(path: &Path, attributes: &Attributes, autocrlf: AutoCrlf) -> Self {
+        let path = path
+            .strip_prefix("./")
+            .unwrap_or(path)
+            .as_os_str()
+            .as_bytes();
+
+        let eol = attributes.get(path, "eol");
+        let auto = match attributes.get(path, "text") {
+            Some(AttributeState::Unset) => return Self::None,
+            Some(AttributeState::Value(value)) if value == "auto" => true,
+            Some(_) => false,
+            // setting `eol` implies `text`
+            None if eol.is_some() => false,
+            None => match autocrlf {
+                AutoCrlf::False => return Self::None,
+                AutoCrlf::True | AutoCrlf::Input => true,
+            },
+        };
+
+        match eol {
+            Some(AttributeState::Value(value)) if value == "crlf" => Self::Crlf { auto },
+            Some(AttributeState::Value(value)) if value == "lf" => Self::Lf { auto },
+            _ if autocrlf == AutoCrlf::True => Self::Crlf { auto },
+            _ => Self::Lf { auto },
+        }
+    }
+
+    /// Convert the contents of a file in the working tree to what should be
+    /// stored in its blob.
+    pub fn to_git(self, contents: Vec<u8>) -> Vec<u8> {
+        match self {
+            Self::None => contents,
+            Self::Lf { auto: true } | Self::Crlf { auto: true } if is_binary(&contents) => contents,
+            Self::Lf { .. } | Self::Crlf { .. } => crlf_to_lf(&contents),
+        }
+    }
+
+    /// Convert the contents of a blob to what should be written to the
+    /// working tree.
+    pub fn to_working_tree(self, contents: Vec<u8>) -> Vec<u8> {
+        match self {
+            Self::None | Self::Lf { .. } => contents,
+            Self::Crlf { auto: true } if is_binary(&contents) => contents,
+            Self::Crlf { .. } => lf_to_crlf(&contents),
+        }
+    }
+}
+
+/// Like git, guess that any file with a NUL byte near its start is binary
+/// (and so shouldn't have its line endings converted).
+pub fn is_binary(contents: &[u8]) -> bool {
+    contents.iter().take(8000).any(|&b| b == b'\0')
+}
+
+fn crlf_to_lf(contents: &[u8]) -> Vec<u8> {
+    let mut converted = Vec::with_capacity(contents.len());
+    for (i, &b) in contents.iter().enumerate() {
+        if b == b'\r' && contents.get(i + 1) == Some(&b'\n') {
+            continue;
+        }
+        converted.push(b);
+    }
+    converted
+}
+
+fn lf_to_crlf(contents: &[u8]) -> Vec<u8> {
+    let mut converted = Vec::with_capacity(contents.len());
+    for (i, &b) in contents.iter().enumerate() {
+        // lines that already end with CRLF are left alone
+        if b == b'\n' && (i == 0 || contents[i - 1] != b'\r') {
+            converted.push(b'\r');
+        }
+        converted.push(b);
+    }
+    converted
+}
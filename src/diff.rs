@@ -0,0 +1,173 @@
+/// A single step in the edit script that transforms one sequence into another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edit {
+    /// The item at `old` is unchanged, and appears at `new` in the new sequence.
+    Equal { old: usize, new: usize },
+    /// The item at `old` was removed.
+    Delete { old: usize },
+    /// The item at `new` was added.
+    Insert { new: usize },
+}
+
+/// The result of merging two sets of changes to a common base.
+#[derive(Debug)]
+pub struct Merge {
+    pub contents: String,
+    /// The number of conflicting regions, each of which is surrounded by
+    /// conflict markers in `contents`.
+    pub conflicts: usize,
+}
+
+/// Compute the shortest edit script between two sequences using Myers' diff
+/// algorithm, as described in "An O(ND) Difference Algorithm and Its
+/// Variations" (the same algorithm git uses by default).
+pub fn diff<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Edit> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m) as usize;
+    let offset = max as isize;
+
+    // `v[k]` holds the furthest-reaching x on diagonal k (where k = x - y);
+    // a copy is kept for each edit distance d so that we can backtrack
+    let mut v = vec![0isize; 2 * max + 2];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max as isize {
+        trace.push(v.clone());
+
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1] // move down (insertion)
+            } else {
+                v[idx - 1] + 1 // move right (deletion)
+            };
+            let mut y = x - k;
+
+            // follow the diagonal for as long as the sequences match
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let idx = (k + offset) as usize;
+
+        let prev_k = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Equal {
+                old: x as usize,
+                new: y as usize,
+            });
+        }
+
+        if d > 0 {
+            if x == prev_x {
+                edits.push(Edit::Insert {
+                    new: prev_y as usize,
+                });
+            } else {
+                edits.push(Edit::Delete {
+                    old: prev_x as usize,
+                });
+            }
+        }
+
+        x = prev_x;
+        y = prev_y;
+    }
+
+    edits.reverse();
+    edits
+}
+
+/// Perform a line-level three-way merge of `ours` and `theirs`, which were
+/// both derived from `base`. Regions changed by only one side are taken from
+/// that side; regions changed by both (in different ways) are written out
+/// with conflict markers labeled by `ours_label` and `theirs_label`.
+pub fn merge3(base: &str, ours: &str, theirs: &str, ours_label: &str, theirs_label: &str) -> Merge {
+    let base: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours: Vec<&str> = ours.split_inclusive('\n').collect();
+    let theirs: Vec<&str> = theirs.split_inclusive('\n').collect();
+
+    let ours_matches = matches(&base, &ours);
+    let theirs_matches = matches(&base, &theirs);
+
+    let mut contents = String::new();
+    let mut conflicts = 0;
+
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    loop {
+        // find the next base line that was left untouched by both sides
+        let stable = (i..base.len()).find_map(|n| Some((n, ours_matches[n]?, theirs_matches[n]?)));
+        let (next_i, next_j, next_k) = stable.unwrap_or((base.len(), ours.len(), theirs.len()));
+
+        // everything between here and there was changed by at least one side
+        let (b, o, t) = (&base[i..next_i], &ours[j..next_j], &theirs[k..next_k]);
+        if o == b {
+            contents.extend(t.iter().copied());
+        } else if t == b || o == t {
+            contents.extend(o.iter().copied());
+        } else {
+            conflicts += 1;
+            push_marker(&mut contents, &format!("<<<<<<< {ours_label}"));
+            contents.extend(o.iter().copied());
+            push_marker(&mut contents, "=======");
+            contents.extend(t.iter().copied());
+            push_marker(&mut contents, &format!(">>>>>>> {theirs_label}"));
+        }
+
+        let Some((next_i, next_j, next_k)) = stable else {
+            break;
+        };
+
+        contents.push_str(base[next_i]);
+        (i, j, k) = (next_i + 1, next_j + 1, next_k + 1);
+    }
+
+    Merge {
+        contents,
+        conflicts,
+    }
+}
+
+/// For each line in `base`, find the index of the matching line in `other`
+/// (if it wasn't changed).
+fn matches(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; base.len()];
+    for edit in diff(base, other) {
+        if let Edit::Equal { old, new } = edit {
+            matches[old] = Some(new);
+        }
+    }
+    matches
+}
+
+/// Conflict markers always start on their own line, even if the preceding
+/// region didn't end with a newline.
+fn push_marker(contents: &mut String, marker: &str) {
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str(marker);
+    contents.push('\n');
+}
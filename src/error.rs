@@ -0,0 +1,78 @@
+use crate::object::ObjectType;
+use std::fmt::Display;
+use std::path::PathBuf;
+
+/// Errors caused by malformed repository data or bad input from the user, as
+/// opposed to I/O failures. These are reported as a one-line message.
+#[derive(Debug)]
+pub enum GitError {
+    /// Neither the current directory nor any of its parents is a repository.
+    NotARepository,
+    /// A name that doesn't refer to any object.
+    BadObject { name: String },
+    /// A revision given on the command line that doesn't refer to a commit.
+    UnknownRevision { name: String },
+    /// A delta instruction that reaches past the end of its base object (for
+    /// a copy) or past the size declared for its result (for either kind).
+    MalformedDelta {
+        offset: usize,
+        size: usize,
+        len: usize,
+    },
+    /// A commit whose parent doesn't exist in the object store.
+    MissingParent { commit: String, parent: String },
+    /// A commit whose parent exists, but isn't a commit.
+    ParentNotACommit {
+        commit: String,
+        parent: String,
+        object_type: ObjectType,
+    },
+    /// A commit that is (through its parents) its own ancestor.
+    CommitCycle { commit: String },
+    /// A loose object whose file can't be decompressed, or whose header
+    /// doesn't make sense once it is.
+    CorruptLooseObject { hash: String, path: PathBuf },
+}
+
+impl Display for GitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotARepository => {
+                write!(
+                    f,
+                    "not a git repository (or any of the parent directories): .git"
+                )
+            }
+            Self::BadObject { name } => write!(f, "not a valid object name {name}"),
+            Self::UnknownRevision { name } => write!(
+                f,
+                "ambiguous argument '{name}': unknown revision or path not in the working tree"
+            ),
+            Self::MalformedDelta { offset, size, len } => write!(
+                f,
+                "malformed delta: {size} bytes at offset {offset} overruns an object of {len} bytes"
+            ),
+            Self::MissingParent { commit, parent } => {
+                write!(f, "commit {commit} has a missing parent {parent}")
+            }
+            Self::ParentNotACommit {
+                commit,
+                parent,
+                object_type,
+            } => write!(
+                f,
+                "parent {parent} of commit {commit} is a {object_type}, not a commit"
+            ),
+            Self::CommitCycle { commit } => {
+                write!(f, "commit {commit} is its own ancestor")
+            }
+            Self::CorruptLooseObject { hash, path } => write!(
+                f,
+                "loose object {hash} (stored in {}) is corrupt",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GitError {}
@@ -0,0 +1,108 @@
+/// Match a path against a glob pattern, like git's `wildmatch`: `*` and `?`
+/// only match within a single path component, `**` matches across them, and
+/// `[...]` matches any one of a set (or range) of bytes.
+pub fn wildmatch(pattern: &[u8], name: &[u8]) -> bool {
+    glob_match(pattern, name, true)
+}
+
+/// Match a path against a glob pattern, like `fnmatch`: unlike [`wildmatch`],
+/// every wildcard can match across path components. This is how pathspecs
+/// are matched.
+pub fn fnmatch(pattern: &[u8], name: &[u8]) -> bool {
+    glob_match(pattern, name, false)
+}
+
+fn glob_match(pattern: &[u8], name: &[u8], components: bool) -> bool {
+    let recurse = |pattern: &[u8], name: &[u8]| glob_match(pattern, name, components);
+
+    match pattern.first() {
+        None => name.is_empty(),
+
+        Some(b'*') if pattern.get(1) == Some(&b'*') && components => {
+            let rest = &pattern[2..];
+
+            // `**/` can match zero directories, too
+            if let Some(rest) = rest.strip_prefix(b"/") {
+                if recurse(rest, name) {
+                    return true;
+                }
+            }
+
+            (0..=name.len()).any(|i| recurse(rest, &name[i..]))
+        }
+
+        Some(b'*') => {
+            let rest = &pattern[1..];
+            for i in 0..=name.len() {
+                if recurse(rest, &name[i..]) {
+                    return true;
+                }
+                if components && name.get(i) == Some(&b'/') {
+                    break;
+                }
+            }
+            false
+        }
+
+        Some(b'?') => {
+            name.first().is_some_and(|&b| !components || b != b'/')
+                && recurse(&pattern[1..], &name[1..])
+        }
+
+        Some(b'[') => {
+            let Some(&b) = name.first() else {
+                return false;
+            };
+
+            let negated = matches!(pattern.get(1), Some(b'!' | b'^'));
+            let start = if negated { 2 } else { 1 };
+            let mut matched = false;
+            let mut i = start;
+            loop {
+                match pattern.get(i) {
+                    // an unterminated set is just a literal `[`
+                    None => return b == b'[' && recurse(&pattern[1..], &name[1..]),
+                    // a `]` at the start of the set is part of it
+                    Some(b']') if i > start => break,
+                    Some(&lo) => match (pattern.get(i + 1), pattern.get(i + 2)) {
+                        (Some(b'-'), Some(&hi)) if hi != b']' => {
+                            matched |= (lo..=hi).contains(&b);
+                            i += 3;
+                        }
+                        _ => {
+                            matched |= lo == b;
+                            i += 1;
+                        }
+                    },
+                }
+            }
+
+            (!components || b != b'/')
+                && matched != negated
+                && recurse(&pattern[i + 1..], &name[1..])
+        }
+
+        Some(b'\\') if pattern.len() > 1 => {
+            name.first() == Some(&pattern[1]) && recurse(&pattern[2..], &name[1..])
+        }
+
+        Some(&c) => name.first() == Some(&c) && recurse(&pattern[1..], &name[1..]),
+    }
+}
+
+/// Match a path against a pattern from a file like `.gitattributes` (or
+/// `.gitignore`). Patterns without a slash match the final component of the
+/// path at any depth; others are anchored to the root of the repository.
+pub fn matches_path(pattern: &str, path: &[u8]) -> bool {
+    let pattern = pattern.as_bytes();
+
+    if !pattern.contains(&b'/') {
+        let name = match path.iter().rposition(|&b| b == b'/') {
+            Some(slash) => &path[slash + 1..],
+            None => path,
+        };
+        return wildmatch(pattern, name);
+    }
+
+    wildmatch(pattern.strip_prefix(b"/").unwrap_or(pattern), path)
+}
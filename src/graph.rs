@@ -0,0 +1,369 @@
+use crate::commit::Commit;
+use crate::error::GitError;
+use crate::object::{ObjectBuf, ObjectHash, ObjectMode, ObjectType};
+use crate::repository::Repository;
+use crate::tree::Tree;
+use eyre::Result;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::time::SystemTime;
+
+/// Determine whether `ancestor` can be reached by walking the parents of
+/// `descendant`. A commit is considered to be its own ancestor.
+pub fn is_ancestor(ancestor: &str, descendant: &str) -> Result<bool> {
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([descendant.to_owned()]);
+
+    while let Some(hash) = queue.pop_front() {
+        if hash == ancestor {
+            return Ok(true);
+        }
+
+        if !seen.insert(hash.clone()) {
+            continue;
+        }
+
+        queue.extend(Commit::read_at_hash(&hash)?.parent_hashes);
+    }
+
+    Ok(false)
+}
+
+/// Find the best common ancestor of two commits, i.e. one that's reachable
+/// from both but isn't an ancestor of any other common ancestor. Returns
+/// `None` if the commits have unrelated histories.
+pub fn merge_base(a: &str, b: &str) -> Result<Option<String>> {
+    let a_ancestors = ancestors(a)?;
+
+    // walk b's history breadth-first, stopping at the first common ancestor
+    // found along each path, so that nearer ancestors are found first
+    let mut common = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([b.to_owned()]);
+
+    while let Some(hash) = queue.pop_front() {
+        if !seen.insert(hash.clone()) {
+            continue;
+        }
+
+        if a_ancestors.contains(&hash) {
+            common.push(hash);
+            continue;
+        }
+
+        queue.extend(Commit::read_at_hash(&hash)?.parent_hashes);
+    }
+
+    // a common ancestor may still be reachable from another one through a
+    // different path, in which case it isn't the best candidate
+    let mut redundant = HashSet::new();
+    for hash in common.iter() {
+        for parent_hash in Commit::read_at_hash(hash)?.parent_hashes {
+            redundant.extend(ancestors(&parent_hash)?);
+        }
+    }
+
+    Ok(common.into_iter().find(|hash| !redundant.contains(hash)))
+}
+
+/// Count the commits reachable from `include` but not from `exclude`, like
+/// `git rev-list --count <exclude>..<include>`.
+pub fn count_commits(include: &str, exclude: &str) -> Result<usize> {
+    let mut seen = ancestors(exclude)?;
+    let mut queue = VecDeque::from([include.to_owned()]);
+    let mut count = 0;
+
+    while let Some(hash) = queue.pop_front() {
+        if seen.insert(hash.clone()) {
+            count += 1;
+            queue.extend(Commit::read_at_hash(&hash)?.parent_hashes);
+        }
+    }
+
+    Ok(count)
+}
+
+/// List the commits reachable from `include` but not from `exclude`, like
+/// `git rev-list <exclude>..<include>`.
+pub fn walk_range(include: &str, exclude: &str, order: WalkOrder) -> Result<Vec<String>> {
+    let excluded = ancestors(exclude)?;
+    let mut commits = walk(&[include.to_owned()], order)?;
+    commits.retain(|hash| !excluded.contains(hash));
+    Ok(commits)
+}
+
+/// Collect every commit reachable from the given commit, including itself.
+pub(crate) fn ancestors(hash: &str) -> Result<HashSet<String>> {
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([hash.to_owned()]);
+
+    while let Some(hash) = queue.pop_front() {
+        if seen.insert(hash.clone()) {
+            queue.extend(Commit::read_at_hash(&hash)?.parent_hashes);
+        }
+    }
+
+    Ok(seen)
+}
+
+/// List every object (commits, trees, and blobs) reachable from the commits in
+/// `include` but not from those in `exclude`, like `git rev-list --objects`.
+/// Excluded commits that don't exist locally are ignored.
+pub fn rev_list_objects(include: &[&str], exclude: &[&str]) -> Result<Vec<String>> {
+    let mut seen = HashSet::new();
+
+    let exclude: Vec<&str> = exclude
+        .iter()
+        .copied()
+        .filter(|hash| ObjectBuf::exists(hash))
+        .collect();
+    walk_objects(&exclude, &mut seen, &mut Vec::new())?;
+
+    let mut objects = Vec::new();
+    walk_objects(include, &mut seen, &mut objects)?;
+
+    Ok(objects)
+}
+
+fn walk_objects(
+    tips: &[&str],
+    seen: &mut HashSet<String>,
+    objects: &mut Vec<String>,
+) -> Result<()> {
+    let mut queue: VecDeque<String> = tips.iter().map(|hash| String::from(*hash)).collect();
+
+    while let Some(hash) = queue.pop_front() {
+        if !seen.insert(hash.clone()) {
+            continue;
+        }
+
+        let commit = Commit::read_at_hash(&hash)?;
+        walk_tree(&commit.tree_hash, seen, objects)?;
+        objects.push(hash);
+        queue.extend(commit.parent_hashes);
+    }
+
+    Ok(())
+}
+
+fn walk_tree(hash: &str, seen: &mut HashSet<String>, objects: &mut Vec<String>) -> Result<()> {
+    if !seen.insert(hash.to_owned()) {
+        return Ok(());
+    }
+    objects.push(hash.to_owned());
+
+    for entry in Tree::read_at_hash(hash)?.entries() {
+        match entry.mode {
+            ObjectMode::Directory => walk_tree(entry.hash.as_hex(), seen, objects)?,
+            // a submodule's commit lives in its own repository, not this one
+            ObjectMode::Gitlink => (),
+            _ => {
+                if seen.insert(entry.hash.to_string()) {
+                    objects.push(entry.hash.to_string());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The order in which [`walk`] lists commits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WalkOrder {
+    /// Newest first, by commit date.
+    #[default]
+    Default,
+    /// Newest first, by commit date, but never show a commit before all of
+    /// its children.
+    Date,
+    /// Never show a commit before all of its children, and avoid interleaving
+    /// commits from separate lines of history.
+    Topo,
+}
+
+impl WalkOrder {
+    /// The order selected by `--topo-order` and `--date-order`; topo order
+    /// wins if both are given.
+    pub fn from_flags(topo_order: bool, date_order: bool) -> Self {
+        match (topo_order, date_order) {
+            (true, _) => Self::Topo,
+            (false, true) => Self::Date,
+            (false, false) => Self::Default,
+        }
+    }
+}
+
+struct WalkCommit {
+    time: SystemTime,
+    parents: Vec<String>,
+}
+
+/// List every commit reachable from `tips` (including the tips themselves),
+/// like `git rev-list`.
+pub fn walk(tips: &[String], order: WalkOrder) -> Result<Vec<String>> {
+    let mut commits: HashMap<String, WalkCommit> = HashMap::new();
+    // each commit to visit, along with the child it was found through
+    let mut queue: VecDeque<(String, Option<String>)> =
+        tips.iter().map(|tip| (tip.clone(), None)).collect();
+
+    while let Some((hash, child)) = queue.pop_front() {
+        if commits.contains_key(&hash) {
+            continue;
+        }
+
+        let commit = match (Commit::read_at_hash(&hash), child) {
+            (Ok(commit), _) => commit,
+            (Err(err), None) => return Err(err),
+            (Err(err), Some(child)) => return Err(bad_parent(child, hash, err)),
+        };
+        queue.extend(
+            commit
+                .parent_hashes
+                .iter()
+                .map(|parent| (parent.clone(), Some(hash.clone()))),
+        );
+        commits.insert(
+            hash,
+            WalkCommit {
+                time: commit.committer.timestamp,
+                parents: commit.parent_hashes,
+            },
+        );
+    }
+
+    check_acyclic(&commits)?;
+
+    // the number of (not yet listed) children of each commit
+    let mut in_degree: HashMap<&str, usize> = HashMap::new();
+    if order != WalkOrder::Default {
+        for commit in commits.values() {
+            for parent in commit.parents.iter() {
+                *in_degree.entry(parent).or_default() += 1;
+            }
+        }
+    }
+
+    // commits that are ready to be listed, newest first (and in the order they
+    // were found when their dates are the same)
+    let mut ready = BinaryHeap::new();
+    let mut seq = 0;
+    let mut push = |ready: &mut BinaryHeap<_>, hash: &str| {
+        ready.push((commits[hash].time, Reverse(seq), hash.to_owned()));
+        seq += 1;
+    };
+
+    let mut seen = HashSet::new();
+    for tip in tips.iter() {
+        if !in_degree.contains_key(tip.as_str()) && seen.insert(tip.as_str()) {
+            push(&mut ready, tip);
+        }
+    }
+
+    let mut listed = Vec::with_capacity(commits.len());
+    // topo order lists each line of history in full before moving on to the
+    // next, so parents that become ready are listed next rather than queued
+    let mut stack: Vec<String> = Vec::new();
+    while let Some(hash) = stack.pop().or_else(|| ready.pop().map(|(_, _, hash)| hash)) {
+        let parents = &commits[&hash].parents;
+        match order {
+            WalkOrder::Default => {
+                for parent in parents.iter() {
+                    if seen.insert(parent) {
+                        push(&mut ready, parent);
+                    }
+                }
+            }
+            WalkOrder::Date | WalkOrder::Topo => {
+                let mut unblocked = Vec::new();
+                for parent in parents.iter() {
+                    let degree = in_degree
+                        .get_mut(parent.as_str())
+                        .expect("parent is counted");
+                    *degree -= 1;
+                    if *degree == 0 {
+                        unblocked.push(parent);
+                    }
+                }
+
+                if order == WalkOrder::Topo {
+                    // the first parent should be the first to be listed
+                    stack.extend(unblocked.into_iter().rev().cloned());
+                } else {
+                    for parent in unblocked {
+                        push(&mut ready, parent);
+                    }
+                }
+            }
+        }
+        listed.push(hash);
+    }
+
+    Ok(listed)
+}
+
+/// Explain why the parent of a commit couldn't be read, preferring a
+/// structured error when the object store is missing (or has the wrong kind
+/// of) object.
+fn bad_parent(commit: String, parent: String, err: eyre::Report) -> eyre::Report {
+    let Ok(repository) = Repository::open() else {
+        return err;
+    };
+    let Ok(hash) = parent.parse::<ObjectHash>() else {
+        return GitError::MissingParent { commit, parent }.into();
+    };
+
+    if !repository.has_object(&hash) {
+        return GitError::MissingParent { commit, parent }.into();
+    }
+    match repository.object_type(&hash) {
+        Ok(object_type) if object_type != ObjectType::Commit => GitError::ParentNotACommit {
+            commit,
+            parent,
+            object_type,
+        }
+        .into(),
+        _ => err.wrap_err(format!("read parent {parent} of commit {commit}")),
+    }
+}
+
+/// Make sure no commit is its own ancestor, which can only happen if the
+/// object store is corrupt (but would otherwise stall a walk that waits for
+/// every child to be listed before its parents).
+fn check_acyclic(commits: &HashMap<String, WalkCommit>) -> Result<()> {
+    // commits are "in progress" while their ancestors are being visited, so
+    // reaching one of them again means there's a cycle
+    let mut in_progress: HashSet<&str> = HashSet::new();
+    let mut done: HashSet<&str> = HashSet::new();
+
+    for start in commits.keys() {
+        if done.contains(start.as_str()) {
+            continue;
+        }
+
+        in_progress.insert(start);
+        let mut stack = vec![(start.as_str(), 0)];
+        while let Some(&(hash, next)) = stack.last() {
+            let Some(parent) = commits[hash].parents.get(next) else {
+                in_progress.remove(hash);
+                done.insert(hash);
+                stack.pop();
+                continue;
+            };
+
+            stack.last_mut().expect("stack is not empty").1 += 1;
+            if in_progress.contains(parent.as_str()) {
+                return Err(GitError::CommitCycle {
+                    commit: parent.clone(),
+                }
+                .into());
+            }
+            if !done.contains(parent.as_str()) {
+                in_progress.insert(parent);
+                stack.push((parent, 0));
+            }
+        }
+    }
+
+    Ok(())
+}
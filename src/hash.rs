@@ -0,0 +1,136 @@
+use crate::config::Config;
+use crate::object::ObjectHash;
+use eyre::Result;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use std::io::Write;
+use std::sync::OnceLock;
+
+/// The hash function a repository uses to name its objects (and checksum its
+/// pack and index files), as chosen by `extensions.objectFormat`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha1,
+    Sha256,
+}
+
+static CURRENT: OnceLock<HashAlgorithm> = OnceLock::new();
+
+impl HashAlgorithm {
+    /// The algorithm used by the current repository, which is read from
+    /// `.git/config` the first time it's needed.
+    pub fn current() -> Result<Self> {
+        if let Some(algorithm) = CURRENT.get() {
+            return Ok(*algorithm);
+        }
+
+        let algorithm = Self::from_config(&Config::read_default()?)?;
+        Ok(*CURRENT.get_or_init(|| algorithm))
+    }
+
+    /// Use the given algorithm for the rest of the process instead of reading
+    /// it from `.git/config`, for commands (like `clone`) that need it before
+    /// the repository exists.
+    pub fn set_current(self) -> Result<()> {
+        let current = *CURRENT.get_or_init(|| self);
+        if current != self {
+            eyre::bail!("object format is already {}", current.name());
+        }
+
+        Ok(())
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self> {
+        match config.get("extensions.objectformat") {
+            None => Ok(Self::Sha1),
+            Some(name) => name.parse(),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+        }
+    }
+
+    /// The id git uses for the algorithm in binary formats (like `.rev` files).
+    pub fn format_id(self) -> u32 {
+        match self {
+            Self::Sha1 => 1,
+            Self::Sha256 => 2,
+        }
+    }
+
+    /// The length of a digest, in bytes.
+    pub fn digest_len(self) -> usize {
+        match self {
+            Self::Sha1 => 20,
+            Self::Sha256 => 32,
+        }
+    }
+
+    /// The length of a digest, in hex characters.
+    pub fn hex_len(self) -> usize {
+        self.digest_len() * 2
+    }
+
+    /// The all-zero hash, used in place of a hash that doesn't exist (like
+    /// the old value of a newly-created ref).
+    pub fn null_hash(self) -> String {
+        "0".repeat(self.hex_len())
+    }
+
+    pub fn hasher(self) -> Hasher {
+        match self {
+            Self::Sha1 => Hasher::Sha1(Sha1::new()),
+            Self::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+}
+
+impl std::str::FromStr for HashAlgorithm {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "sha1" => Ok(Self::Sha1),
+            "sha256" => Ok(Self::Sha256),
+            _ => eyre::bail!("unknown object format '{s}'"),
+        }
+    }
+}
+
+/// An in-progress hash using any of the supported algorithms.
+pub enum Hasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha1(hasher) => hasher.update(data),
+            Self::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    pub fn finalize(self) -> ObjectHash {
+        let digest = match self {
+            Self::Sha1(hasher) => hasher.finalize().to_vec(),
+            Self::Sha256(hasher) => hasher.finalize().to_vec(),
+        };
+        ObjectHash::from_bytes(&digest).expect("digest has a valid length")
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
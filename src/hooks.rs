@@ -0,0 +1,58 @@
+use crate::config::Config;
+use crate::repository;
+use eyre::{Context, Result};
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+/// Run the named hook from `.git/hooks` (or `core.hooksPath`) with the given
+/// arguments, from the top of the working tree (or the git directory, in a bare
+/// repository), which a relative `core.hooksPath` is relative to as well.
+/// Returns whether it succeeded; a hook that isn't installed (or isn't
+/// executable) is skipped, and counts as a success.
+pub(crate) fn run(name: &str, args: &[&str]) -> Result<bool> {
+    let dir = match repository::is_bare() {
+        true => repository::current_git_dir(),
+        false => repository::work_tree(),
+    };
+    let hooks_dir = match Config::read_default()?.get("core.hookspath") {
+        Some(path) => dir.join(path),
+        None => repository::common_dir().join("hooks"),
+    };
+    let path = hooks_dir.join(name);
+
+    let meta = match std::fs::metadata(&path) {
+        Ok(meta) => meta,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(true),
+        Err(err) => return Err(err).with_context(|| format!("stat {}", path.display())),
+    };
+    if !meta.is_file() || meta.permissions().mode() & 0o111 == 0 {
+        eprintln!(
+            "hint: The '{}' hook was ignored because it's not set as executable.",
+            path.display()
+        );
+        return Ok(true);
+    }
+
+    let status = Command::new(&path)
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .with_context(|| format!("run {name} hook"))?;
+    Ok(status.success())
+}
+
+/// Run a hook that can stop the command it's run for (like `pre-merge-commit`),
+/// failing if the hook does.
+pub(crate) fn run_required(name: &str, args: &[&str]) -> Result<()> {
+    if !run(name, args)? {
+        eyre::bail!("{name} hook failed");
+    }
+    Ok(())
+}
+
+/// Run a hook that's only a notification (like `post-commit`), so its exit
+/// status is ignored.
+pub(crate) fn notify(name: &str, args: &[&str]) -> Result<()> {
+    run(name, args)?;
+    Ok(())
+}
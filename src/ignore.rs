@@ -0,0 +1,99 @@
+use crate::glob;
+use crate::repository;
+use eyre::{Context, Result};
+use std::path::Path;
+
+/// Patterns for untracked files that should be ignored, like those in
+/// `.gitignore` files.
+#[derive(Debug, Default)]
+pub struct Ignore {
+    patterns: Vec<IgnorePattern>,
+}
+
+#[derive(Debug)]
+struct IgnorePattern {
+    pattern: String,
+    /// `!pattern`, which re-includes anything matched by an earlier pattern.
+    negated: bool,
+    /// `pattern/`, which only matches directories.
+    dir_only: bool,
+}
+
+impl Ignore {
+    /// The standard exclusions, from the top-level `.gitignore` and
+    /// `.git/info/exclude` (the former takes precedence).
+    // FIXME: read `.gitignore` files in subdirectories and `core.excludesFile`
+    pub fn read_standard() -> Result<Self> {
+        let mut ignore = Self::read(repository::common_dir().join("info/exclude"))?;
+        ignore
+            .patterns
+            .extend(Self::read(repository::work_tree_path(".gitignore"))?.patterns);
+        Ok(ignore)
+    }
+
+    /// Read the ignore file at the given path; a missing file is treated as empty.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        match std::fs::read_to_string(path.as_ref()) {
+            Ok(contents) => Ok(Self::parse(&contents)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err).context("read ignore file"),
+        }
+    }
+
+    pub fn parse(s: &str) -> Self {
+        let mut patterns = Vec::new();
+
+        for line in s.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (line, negated) = match line.strip_prefix('!') {
+                Some(line) => (line, true),
+                None => (line, false),
+            };
+
+            // a leading `!` or `#` can be escaped to match it literally
+            let line = match line.strip_prefix('\\') {
+                Some(rest) if rest.starts_with(['!', '#']) => rest,
+                _ => line,
+            };
+
+            let (line, dir_only) = match line.strip_suffix('/') {
+                Some(line) => (line, true),
+                None => (line, false),
+            };
+
+            patterns.push(IgnorePattern {
+                pattern: line.to_owned(),
+                negated,
+                dir_only,
+            });
+        }
+
+        Self { patterns }
+    }
+
+    /// Whether the file at `path` (relative to the root of the repository) is
+    /// ignored, either by itself or because a directory containing it is.
+    pub fn is_ignored(&self, path: &[u8]) -> bool {
+        // like git, files can't be re-included if their directory is ignored
+        for (i, &b) in path.iter().enumerate() {
+            if b == b'/' && self.matches(&path[..i], true) {
+                return true;
+            }
+        }
+
+        self.matches(path, false)
+    }
+
+    fn matches(&self, path: &[u8], is_dir: bool) -> bool {
+        // the last matching pattern wins
+        self.patterns
+            .iter()
+            .rev()
+            .find(|p| (is_dir || !p.dir_only) && glob::matches_path(&p.pattern, path))
+            .is_some_and(|p| !p.negated)
+    }
+}
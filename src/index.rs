@@ -1,15 +1,88 @@
 use eyre::{Context, Result};
+use std::ffi::OsString;
 use std::fmt::{Debug, Display};
-use std::io::{BufReader, BufWriter, Write};
+use std::io::{BufReader, BufWriter, Cursor, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::os::unix::fs::{MetadataExt, PermissionsExt};
 use std::path::Path;
+use std::sync::OnceLock;
 
-use crate::object::{Object, ObjectHash, ObjectHashable};
+use crate::attributes::Attributes;
+use crate::config::Config;
+use crate::convert::{AutoCrlf, EolConversion};
+use crate::hash::HashAlgorithm;
+use crate::object::{Object, ObjectBuf, ObjectHash, ObjectHashable, ObjectMode, ObjectType};
 use crate::parser::Parser;
+use crate::refs;
+use crate::repository;
+use crate::tree::{Tree, TreeEntry};
 use crate::utils::append_checksum;
 
 pub const INDEX_HEADER: &[u8; 4] = b"DIRC";
 
+/// Set in an entry's flags when it's followed by extended flags (v3+ only).
+const FLAG_EXTENDED: u16 = 0x4000;
+
+/// The bits of an entry's flags that hold its merge stage.
+const FLAG_STAGE_MASK: u16 = 0x3000;
+
+/// Set in an entry's extended flags when it's excluded from a sparse
+/// checkout, and so may be missing from the working tree.
+const FLAG_EXT_SKIP_WORKTREE: u16 = 0x4000;
+
+static WORKING_TREE_CONFIG: OnceLock<WorkingTreeConfig> = OnceLock::new();
+
+/// Whether the executable bit in the working tree is meaningful, as set by
+/// `core.filemode` (true unless the filesystem doesn't preserve it). When it
+/// isn't, files are always recorded as non-executable.
+pub(crate) fn trust_executable_bit() -> Result<bool> {
+    Ok(WorkingTreeConfig::current()?.trust_executable_bit)
+}
+
+/// The settings that decide how files in a working tree are read and
+/// written: their attributes, `core.autocrlf` and `core.filemode`.
+#[derive(Debug)]
+pub(crate) struct WorkingTreeConfig {
+    pub attributes: Attributes,
+    pub autocrlf: AutoCrlf,
+    pub trust_executable_bit: bool,
+}
+
+impl WorkingTreeConfig {
+    /// The settings for the current repository, which are read the first
+    /// time they're needed.
+    pub fn current() -> Result<&'static Self> {
+        if let Some(config) = WORKING_TREE_CONFIG.get() {
+            return Ok(config);
+        }
+
+        let config = Self::read(
+            repository::common_dir(),
+            Attributes::read(repository::work_tree_path(".gitattributes"))?,
+        )?;
+        Ok(WORKING_TREE_CONFIG.get_or_init(|| config))
+    }
+
+    /// Read the settings for the repository at `git_dir`, whose working tree
+    /// has the given top-level attributes.
+    pub fn read(git_dir: &Path, mut attributes: Attributes) -> Result<Self> {
+        let config = Config::read(git_dir.join("config"))?;
+        attributes.extend(Attributes::read(git_dir.join("info/attributes"))?);
+
+        Ok(Self {
+            attributes,
+            autocrlf: AutoCrlf::from_config(&config),
+            trust_executable_bit: config.get_bool("core.filemode")?.unwrap_or(true),
+        })
+    }
+
+    /// How to convert the line endings of the file at `path`, relative to
+    /// the top of the working tree.
+    pub fn eol_conversion(&self, path: &Path) -> EolConversion {
+        EolConversion::for_path_with(path, &self.attributes, self.autocrlf)
+    }
+}
+
 #[derive(Debug)]
 pub struct Index {
     pub version: u8,
@@ -22,12 +95,13 @@ pub struct IndexEntry {
     pub _type: IndexEntryType,
     pub permissions: IndexEntryPermissions,
     pub hash: ObjectHash,
-    pub name: String,
+    /// The entry's path, as raw bytes (which needn't be valid UTF-8).
+    pub name: OsString,
     pub flags: u16,
     pub flags_ext: u16,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct IndexEntryStats {
     pub ctime: u32,
     pub ctime_nsec: u32,
@@ -112,7 +186,20 @@ impl TryFrom<u16> for IndexEntryPermissions {
 
 impl Index {
     pub fn read_default() -> Result<Self> {
-        Self::read(".git/index")
+        Self::read(repository::current_git_dir().join("index"))
+    }
+
+    /// Like [`Index::read_default`], but a missing index (as in a new
+    /// repository, before anything has been added) is treated as empty.
+    pub fn read_default_or_empty() -> Result<Self> {
+        if !repository::current_git_dir().join("index").exists() {
+            return Ok(Self {
+                version: 2,
+                entries: Vec::new(),
+            });
+        }
+
+        Self::read_default()
     }
 
     pub fn read(path: impl AsRef<Path>) -> Result<Self> {
@@ -132,6 +219,7 @@ impl Index {
         }
 
         let (_, mut parser) = parser.verify_checksum(file_size)?;
+        let algorithm = HashAlgorithm::current()?;
 
         let version = parser.parse_usize_exact::<4>().context("parse version")? as u8;
 
@@ -142,41 +230,35 @@ impl Index {
 
         let mut offset = 12; // 4 + 4 + 4
         for _ in 0..num_entries {
-            let ctime = parser.parse_usize_exact::<4>().context("parse ctime")? as u32;
-            let ctime_nsec = parser
-                .parse_usize_exact::<4>()
-                .context("parse ctime_nsec")? as u32;
-            let mtime = parser.parse_usize_exact::<4>().context("parse mtime")? as u32;
-            let mtime_nsec = parser
-                .parse_usize_exact::<4>()
-                .context("parse mtime_nsec")? as u32;
-            let dev = parser.parse_usize_exact::<4>().context("parse dev")? as u32;
-            let ino = parser.parse_usize_exact::<4>().context("parse ino")? as u32;
+            // the stats and mode are a fixed 40 bytes, so they're read in one go:
+            // ctime, ctime_nsec, mtime, mtime_nsec, dev, ino, mode, uid, gid, size
+            let prefix = parser.read_bytes::<40>().context("parse entry stats")?;
+            let field = |i: usize| u32::from_be_bytes(prefix[i * 4..i * 4 + 4].try_into().unwrap());
+            let (ctime, ctime_nsec, mtime, mtime_nsec, dev, ino) =
+                (field(0), field(1), field(2), field(3), field(4), field(5));
 
             // mode is documented as a 32 byte value, but no definition is only given for
             // the first 16 bits; only the lower 16 bits are documented...
-            parser.skip(2);
-            let mode = parser.parse_usize_exact::<2>().context("parse mode")? as u16;
+            let mode = field(6) as u16;
 
-            let uid = parser.parse_usize_exact::<4>().context("parse uid")? as u32;
-            let gid = parser.parse_usize_exact::<4>().context("parse gid")? as u32;
-            let size = parser.parse_usize_exact::<4>().context("parse size")? as u32;
+            let (uid, gid, size) = (field(7), field(8), field(9));
 
-            let hash = parser.read_bytes::<20>().context("parse object hash")?;
+            let hash = parser.read_hash(algorithm).context("parse object hash")?;
 
             let flags = parser.parse_usize_exact::<2>().context("parse flags")? as u16;
 
-            let mut entry_len = 62;
-            let flags_ext = if version >= 3
-            /* && flags["extended"] */
-            {
+            // 40 bytes of stats, the hash, and 2 bytes of flags
+            let mut entry_len = 40 + algorithm.digest_len() + 2;
+            let flags_ext = if version >= 3 && flags & FLAG_EXTENDED != 0 {
                 entry_len += 2;
-                todo!("parse extended flags");
+                parser
+                    .parse_usize_exact::<2>()
+                    .context("parse extended flags")? as u16
             } else {
                 0
             };
 
-            let name = parser.parse_str(b'\0').context("parse name")?;
+            let name = OsString::from_vec(parser.parse_bytes(b'\0').context("parse name")?);
             let name_len = flags & 0x0fff;
 
             if name.len() <= 0x0fff && name.len() != name_len as usize {
@@ -215,7 +297,7 @@ impl Index {
                     .context("parse entry type")?,
                 permissions: IndexEntryPermissions::try_from(mode & 0x01ff)
                     .context("parse entry permissions")?,
-                hash: ObjectHash::from_bytes(&hash),
+                hash,
                 name,
                 flags,
                 flags_ext,
@@ -223,14 +305,17 @@ impl Index {
         }
 
         loop {
-            // the final 20 bytes of a packfile contain a hash of its contents,
+            // the final bytes of the index contain a hash of its contents,
             // which we've already verified to be correct earlier
-            if offset == file_size - 20 {
+            if offset == file_size - algorithm.digest_len() {
                 break;
             }
 
             let ext_header = parser.read_bytes::<4>().context("parse extension header")?;
-            dbg!(std::string::String::from_utf8_lossy(&ext_header));
+            tracing::debug!(
+                "skipping index extension {}",
+                String::from_utf8_lossy(&ext_header)
+            );
             let ext_size = parser
                 .parse_usize_exact::<4>()
                 .context("parse extension size")? as u32;
@@ -243,12 +328,28 @@ impl Index {
     }
 
     pub fn working_tree() -> Result<Self> {
-        fn entries_in_dir(path: &Path) -> Result<Vec<IndexEntry>> {
+        Self::working_tree_in(repository::work_tree(), WorkingTreeConfig::current()?)
+    }
+
+    /// Build an index of every file in the working tree at `root`, with paths
+    /// relative to it, reading files according to `config`.
+    pub(crate) fn working_tree_in(root: &Path, config: &WorkingTreeConfig) -> Result<Self> {
+        fn entries_in_dir(
+            root: &Path,
+            path: &Path,
+            config: &WorkingTreeConfig,
+        ) -> Result<Vec<IndexEntry>> {
             let mut entries: Vec<IndexEntry> = Vec::new();
 
             // FIXME: actually read .gitignore
-            let path_str = format!("{}", path.display());
-            if path_str.contains(".git") || path_str.contains("target") {
+            let path_bytes = path
+                .strip_prefix(root)
+                .unwrap_or(path)
+                .as_os_str()
+                .as_bytes();
+            if path_bytes.windows(4).any(|w| w == b".git")
+                || path_bytes.windows(6).any(|w| w == b"target")
+            {
                 return Ok(Vec::new());
             }
 
@@ -256,16 +357,26 @@ impl Index {
                 let dir_entry = dir_entry?;
 
                 if dir_entry.metadata()?.is_file() {
-                    entries.push(IndexEntry::from_path(dir_entry.path())?);
+                    entries.push(IndexEntry::from_path_with(root, dir_entry.path(), config)?);
+                } else if dir_entry.path().join(".git").exists() {
+                    // a nested repository is a submodule, which is recorded by
+                    // the commit it has checked out rather than by its files
+                    if let Some(hash) = refs::resolve_submodule_head(&dir_entry.path())? {
+                        entries.push(IndexEntry::from_submodule(
+                            root,
+                            dir_entry.path(),
+                            hash.parse()?,
+                        )?);
+                    }
                 } else {
-                    entries.extend(entries_in_dir(&dir_entry.path())?.into_iter());
+                    entries.extend(entries_in_dir(root, &dir_entry.path(), config)?);
                 }
             }
 
             Ok(entries)
         }
 
-        let mut entries = entries_in_dir(Path::new("."))?;
+        let mut entries = entries_in_dir(root, root, config)?;
         entries.sort_unstable_by(|a, b| a.name.cmp(&b.name));
 
         Ok(Self {
@@ -274,11 +385,74 @@ impl Index {
         })
     }
 
+    /// Write the contents of the index as a tree object (along with any
+    /// subtrees), returning the hash of the root tree. Blobs that haven't
+    /// been written to the object store yet are written from the working tree.
+    pub fn write_tree(&self) -> Result<ObjectHash> {
+        let mut entries = Vec::with_capacity(self.entries.len());
+
+        for entry in self.entries.iter() {
+            // submodule commits live in the submodule's repository, not ours
+            let mode = entry.mode()?;
+            if mode != ObjectMode::Gitlink && !ObjectBuf::exists(entry.hash.as_hex()) {
+                let hash = Object::blob(&entry.name)
+                    .hash(true)
+                    .with_context(|| format!("write blob for {}", entry.name.to_string_lossy()))?;
+                if hash != entry.hash {
+                    eyre::bail!(
+                        "{} has been modified since it was staged",
+                        entry.name.to_string_lossy()
+                    );
+                }
+            }
+
+            entries.push(TreeEntry {
+                name: entry.name.clone(),
+                mode,
+                hash: entry.hash.clone(),
+            });
+        }
+
+        Tree::write_flat(entries)
+    }
+
+    /// Stage the file at `path` (relative to the top of the working tree),
+    /// writing its blob to the object store and replacing any existing entry.
+    pub fn add(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let entry =
+            IndexEntry::from_path(repository::work_tree(), repository::work_tree_path(path))?;
+        Object::blob(path)
+            .hash(true)
+            .with_context(|| format!("write blob for {}", path.display()))?;
+
+        match self
+            .entries
+            .binary_search_by(|existing| existing.name.cmp(&entry.name))
+        {
+            Ok(i) => self.entries[i] = entry,
+            Err(i) => self.entries.insert(i, entry),
+        }
+
+        Ok(())
+    }
+
+    /// Remove the entry for `path` (relative to the top of the working tree),
+    /// if there is one.
+    pub fn remove(&mut self, path: impl AsRef<Path>) {
+        let name = path.as_ref().as_os_str();
+        self.entries.retain(|entry| entry.name != name);
+    }
+
     pub fn write_default(&self) -> Result<()> {
-        self.write(".git/index")
+        self.write(repository::current_git_dir().join("index"))
     }
 
+    /// Write the index to `path`. Entries are written sorted by name and
+    /// stage however they're ordered in memory, since git relies on that.
     pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let entries = self.normalized_entries()?;
+
         let f = std::fs::File::options()
             .read(true)
             .write(true)
@@ -291,14 +465,21 @@ impl Index {
         // 1. header
         writer.write_all(INDEX_HEADER)?;
 
-        // 2. version
-        writer.write_all(&2u32.to_be_bytes())?;
+        // 2. version (extended flags need at least version 3)
+        let version: u32 = if entries.iter().any(|entry| entry.is_extended()) {
+            3
+        } else {
+            2
+        };
+        writer.write_all(&version.to_be_bytes())?;
 
         // 3. entry count
-        writer.write_all(&(self.entries.len() as u32).to_be_bytes())?;
+        writer.write_all(&(entries.len() as u32).to_be_bytes())?;
+
+        let hash_len = HashAlgorithm::current()?.digest_len();
 
         // 4. entries
-        for entry in self.entries.iter() {
+        for entry in entries {
             // 4a. ctime
             writer.write_all(&entry.stats.ctime.to_be_bytes())?;
             // 4b. ctime_nsec
@@ -314,7 +495,9 @@ impl Index {
             // 4g. 2 bytes padding
             writer.write_all(&[0, 0])?;
             // 4h. mode
-            writer.write_all(&((entry._type as u16) << 12).to_be_bytes())?;
+            writer.write_all(
+                &(((entry._type as u16) << 12) | entry.permissions as u16).to_be_bytes(),
+            )?;
             // 4i. uid
             writer.write_all(&entry.stats.uid.to_be_bytes())?;
             // 4j. gid
@@ -322,15 +505,20 @@ impl Index {
             // 4k. size
             writer.write_all(&entry.stats.size.to_be_bytes())?;
             // 4l. hash
-            writer.write_all(&entry.hash.as_bytes())?;
+            writer.write_all(entry.hash.as_bytes())?;
             // 4m. flags
             writer.write_all(&entry.flags.to_be_bytes())?;
-            // 4n. flags_ext (v3+, skipped for version 2)
+            // 4n. flags_ext (v3+, only if the extended flag is set)
+            let mut entry_len = 40 + hash_len + 2;
+            if entry.is_extended() {
+                writer.write_all(&entry.flags_ext.to_be_bytes())?;
+                entry_len += 2;
+            }
             // 4o. name
             writer.write_all(entry.name.as_bytes())?;
             writer.write_all(&[0])?;
             // 4p. padding
-            let overflow = (62 + entry.name.len() + 1) % 8;
+            let overflow = (entry_len + entry.name.len() + 1) % 8;
             if overflow > 0 {
                 writer.write_all(&vec![0; 8 - overflow])?;
             }
@@ -343,10 +531,121 @@ impl Index {
 
         Ok(())
     }
+
+    /// The entries in the order they're written: by name, then by stage.
+    /// There can only be one entry for each name and stage.
+    fn normalized_entries(&self) -> Result<Vec<&IndexEntry>> {
+        let mut entries: Vec<&IndexEntry> = self.entries.iter().collect();
+        entries.sort_by(|a, b| (&a.name, a.stage()).cmp(&(&b.name, b.stage())));
+
+        for pair in entries.windows(2) {
+            if pair[0].name == pair[1].name && pair[0].stage() == pair[1].stage() {
+                eyre::bail!(
+                    "duplicate index entry for {} at stage {}",
+                    pair[0].name.to_string_lossy(),
+                    pair[0].stage()
+                );
+            }
+        }
+
+        Ok(entries)
+    }
 }
 
 impl IndexEntry {
-    fn from_path(path: impl AsRef<Path>) -> Result<IndexEntry> {
+    fn is_extended(&self) -> bool {
+        self.flags & FLAG_EXTENDED != 0
+    }
+
+    /// The merge stage of this entry (zero unless it's part of a conflict).
+    fn stage(&self) -> u16 {
+        (self.flags & FLAG_STAGE_MASK) >> 12
+    }
+
+    /// Whether this entry is outside of a sparse checkout, in which case it's
+    /// treated as present and unmodified whatever's in the working tree.
+    pub fn skip_worktree(&self) -> bool {
+        self.is_extended() && self.flags_ext & FLAG_EXT_SKIP_WORKTREE != 0
+    }
+
+    /// The mode this entry would have in a tree object.
+    pub fn mode(&self) -> Result<ObjectMode> {
+        Ok(match (self._type, self.permissions) {
+            (IndexEntryType::SymbolicLink, _) => ObjectMode::Symlink,
+            (IndexEntryType::RegularFile, IndexEntryPermissions::ExecutableFile) => {
+                ObjectMode::Executable
+            }
+            (IndexEntryType::RegularFile, _) => ObjectMode::Normal,
+            (IndexEntryType::GitLink, _) => ObjectMode::Gitlink,
+        })
+    }
+
+    /// Build an index entry for a (flattened) tree entry. Since the entry
+    /// didn't come from the working tree, its stats are all zero, so it'll
+    /// look modified until it's refreshed.
+    pub fn from_tree_entry(entry: &TreeEntry) -> Result<IndexEntry> {
+        let (_type, permissions) = match entry.mode {
+            ObjectMode::Normal => (
+                IndexEntryType::RegularFile,
+                IndexEntryPermissions::RegularFile,
+            ),
+            ObjectMode::Executable => (
+                IndexEntryType::RegularFile,
+                IndexEntryPermissions::ExecutableFile,
+            ),
+            ObjectMode::Symlink => (IndexEntryType::SymbolicLink, IndexEntryPermissions::None),
+            ObjectMode::Gitlink => (IndexEntryType::GitLink, IndexEntryPermissions::None),
+            ObjectMode::Directory => eyre::bail!(
+                "can't add directory {} to the index",
+                entry.name.to_string_lossy()
+            ),
+        };
+
+        Ok(Self {
+            stats: IndexEntryStats::default(),
+            _type,
+            permissions,
+            hash: entry.hash.clone(),
+            name: entry.name.clone(),
+            flags: entry.name.len().min(0xfff) as u16,
+            flags_ext: 0,
+        })
+    }
+
+    /// Build an index entry for the submodule checked out at `path` in the
+    /// working tree at `root`, which has `hash` checked out.
+    fn from_submodule(root: &Path, path: impl AsRef<Path>, hash: ObjectHash) -> Result<IndexEntry> {
+        let path: &Path = path.as_ref();
+        let metadata = path.metadata()?;
+        let name = path
+            .strip_prefix(root)
+            .unwrap_or(path)
+            .as_os_str()
+            .to_owned();
+
+        Ok(Self {
+            stats: IndexEntryStats::from_metadata(&metadata),
+            _type: IndexEntryType::GitLink,
+            permissions: IndexEntryPermissions::None,
+            hash,
+            flags: name.len().min(0xfff) as u16,
+            name,
+            flags_ext: 0,
+        })
+    }
+
+    /// Build an index entry for a file in the current working tree.
+    fn from_path(root: &Path, path: impl AsRef<Path>) -> Result<IndexEntry> {
+        Self::from_path_with(root, path, WorkingTreeConfig::current()?)
+    }
+
+    /// Build an index entry for a file in the working tree at `root`, reading
+    /// it according to `config`.
+    fn from_path_with(
+        root: &Path,
+        path: impl AsRef<Path>,
+        config: &WorkingTreeConfig,
+    ) -> Result<IndexEntry> {
         let path: &Path = path.as_ref();
         let f = std::fs::File::open(path)?;
         let metadata = f.metadata()?;
@@ -359,16 +658,31 @@ impl IndexEntry {
 
         let permissions = if metadata.file_type().is_symlink() {
             IndexEntryPermissions::None
-        } else if metadata.permissions().mode() & 0o111 != 0 {
+        } else if metadata.permissions().mode() & 0o111 != 0 && config.trust_executable_bit {
             IndexEntryPermissions::ExecutableFile
         } else {
             IndexEntryPermissions::RegularFile
         };
 
-        let hash = Object::blob(path).hash(false)?;
-        let name = format!("{}", path.display())
-            .trim_start_matches("./")
-            .to_owned();
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let hash = match config.eol_conversion(relative) {
+            EolConversion::None => ObjectBuf {
+                object_type: ObjectType::Blob,
+                content_len: metadata.len() as usize,
+                contents: Parser::new(BufReader::new(f)),
+            }
+            .hash(false)?,
+            conversion => {
+                let contents = conversion.to_git(std::fs::read(path).context("read file")?);
+                ObjectBuf {
+                    object_type: ObjectType::Blob,
+                    content_len: contents.len(),
+                    contents: Parser::new(Cursor::new(contents)),
+                }
+                .hash(false)?
+            }
+        };
+        let name = relative.as_os_str().to_owned();
         // FIXME: assume-valid, extended, stage
         let flags = if name.len() < 0xfff {
             name.len() as u16
@@ -1,9 +1,27 @@
+mod attributes;
+mod color;
 mod commit;
+mod config;
+mod convert;
+mod diff;
+pub mod error;
+mod glob;
+mod graph;
+mod hash;
+mod hooks;
+mod ignore;
 mod index;
 mod object;
+mod object_store;
+pub mod output;
 mod pack;
 mod packet_line;
 mod parser;
+mod patch;
+mod pathspec;
+mod refs;
+mod remote_url;
+pub mod repository;
 pub mod subcommand;
 mod tag;
 mod tree;
@@ -1,5 +1,6 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use eyre::Result;
+use rusty_git::error::GitError;
 use rusty_git::subcommand;
 
 #[derive(Parser, Debug)]
@@ -7,6 +8,10 @@ use rusty_git::subcommand;
 struct App {
     #[command(subcommand)]
     command: Command,
+
+    /// Only print errors (and the output of commands that query the repository)
+    #[arg(short, long, global = true)]
+    quiet: bool,
 }
 
 #[derive(Subcommand, Debug)]
@@ -16,6 +21,15 @@ enum Command {
         #[arg(short)]
         pretty: bool,
 
+        #[arg(short = 't')]
+        show_type: bool,
+
+        #[arg(short = 'e')]
+        exists: bool,
+
+        #[arg(long)]
+        format: Option<String>,
+
         #[arg(value_name = "object")]
         object_hash: String,
     },
@@ -23,6 +37,14 @@ enum Command {
         #[arg(short)]
         write: bool,
 
+        /// Convert line endings as if the file were stored at this path
+        #[arg(long = "path", value_name = "path", conflicts_with = "no_filters")]
+        filter_path: Option<String>,
+
+        /// Hash the file exactly as it is, without converting line endings
+        #[arg(long)]
+        no_filters: bool,
+
         path: String,
     },
     LsTree {
@@ -32,7 +54,17 @@ enum Command {
         #[arg(long)]
         name_only: bool,
     },
-    WriteTree,
+    WriteTree {
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+    ReadTree {
+        #[arg(value_name = "tree-ish", required = true)]
+        trees: Vec<String>,
+
+        #[arg(short)]
+        merge: bool,
+    },
     CommitTree {
         #[arg(value_name = "tree_sha")]
         object_hash: String,
@@ -41,7 +73,7 @@ enum Command {
         parent_hash: Vec<String>,
 
         #[arg(short)]
-        message: String,
+        message: Vec<String>,
     },
     Clone {
         #[arg(value_name = "repo_url")]
@@ -49,27 +81,315 @@ enum Command {
 
         #[arg(value_name = "dir")]
         output_dir: Option<String>,
+
+        #[arg(long)]
+        keep_pack: bool,
+
+        /// Clone without a working tree
+        #[arg(long)]
+        bare: bool,
+
+        /// Like `--bare`, but also copy every ref (not just branches) as is
+        #[arg(long)]
+        mirror: bool,
     },
     IndexPack {
         #[arg(value_name = "packfile")]
         pack_file: String,
+
+        #[arg(long)]
+        strict: bool,
+
+        #[arg(long)]
+        rev_index: bool,
+
+        /// Show progress, and how many objects and deltas were indexed
+        #[arg(short, long, visible_alias = "progress")]
+        verbose: bool,
     },
     VerifyPack {
         #[arg(value_name = "index_file")]
         index_file: String,
+
+        #[arg(short, long)]
+        verbose: bool,
+
+        #[arg(short, long)]
+        stat_only: bool,
+
+        #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+        format: OutputFormat,
     },
     UnpackObjects,
     Checkout {
         branch: String,
     },
+    CheckoutIndex {
+        #[arg(short, long)]
+        all: bool,
+
+        #[arg(short, long)]
+        force: bool,
+
+        #[arg(value_name = "file")]
+        paths: Vec<String>,
+    },
     LsFiles {
         #[arg(short, long)]
         cached: bool,
 
         #[arg(short, long = "stage")]
         staged: bool,
+
+        #[arg(short, long)]
+        others: bool,
+
+        #[arg(short, long)]
+        modified: bool,
+
+        #[arg(short, long)]
+        deleted: bool,
+
+        #[arg(long)]
+        exclude_standard: bool,
+
+        #[arg(value_name = "file")]
+        paths: Vec<String>,
+    },
+    Status {
+        #[arg(value_name = "pathspec")]
+        paths: Vec<String>,
+    },
+    Diff {
+        #[arg(long)]
+        name_status: bool,
+
+        #[arg(long)]
+        stat: bool,
+
+        #[arg(
+            short = 'M',
+            long,
+            value_name = "n",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "50"
+        )]
+        find_renames: Option<u8>,
+
+        #[arg(value_name = "commit", num_args = 0..=2)]
+        commits: Vec<String>,
+    },
+    Describe {
+        #[arg(value_name = "commit-ish")]
+        commit: Option<String>,
+
+        #[arg(long)]
+        tags: bool,
+
+        #[arg(long)]
+        always: bool,
+    },
+    Fetch {
+        #[arg(value_name = "repository")]
+        remote: Option<String>,
+    },
+    Pull,
+    Push {
+        #[arg(value_name = "repository")]
+        remote: String,
+
+        branch: String,
+    },
+    Merge {
+        branch: String,
+    },
+    ShowBranch {
+        #[arg(value_name = "rev")]
+        revs: Vec<String>,
+    },
+    MergeBase {
+        #[arg(value_name = "commit")]
+        a: String,
+
+        #[arg(value_name = "commit")]
+        b: String,
+
+        #[arg(long)]
+        is_ancestor: bool,
+    },
+    CherryPick {
+        commit: String,
+    },
+    Revert {
+        commit: String,
+    },
+    Reflog {
+        #[arg(value_name = "ref")]
+        name: Option<String>,
+    },
+    RevParse {
+        /// Print the absolute path of the top of the working tree
+        #[arg(long)]
+        show_toplevel: bool,
+
+        /// Print the path of the `.git` directory
+        #[arg(long)]
+        git_dir: bool,
+
+        /// Print whether the current directory is inside the working tree
+        #[arg(long)]
+        is_inside_work_tree: bool,
+
+        #[arg(value_name = "rev")]
+        revisions: Vec<String>,
+    },
+    RevList {
+        #[arg(value_name = "commit", required = true)]
+        commits: Vec<String>,
+
+        #[arg(long)]
+        topo_order: bool,
+
+        #[arg(long)]
+        date_order: bool,
+    },
+    Log {
+        #[arg(value_name = "revision")]
+        revisions: Vec<String>,
+
+        #[arg(long)]
+        topo_order: bool,
+
+        #[arg(long)]
+        date_order: bool,
+
+        /// Only show commits by authors whose name or email contains this
+        #[arg(long, value_name = "pattern")]
+        author: Vec<String>,
+
+        /// Only show commits whose message contains this
+        #[arg(long, value_name = "pattern")]
+        grep: Vec<String>,
+
+        /// Only show commits made at or after this date
+        #[arg(long, visible_alias = "after", value_name = "date")]
+        since: Option<String>,
+
+        /// Only show commits made at or before this date
+        #[arg(long, visible_alias = "before", value_name = "date")]
+        until: Option<String>,
+    },
+    PackRefs {
+        #[arg(long)]
+        all: bool,
+    },
+    Stash {
+        #[command(subcommand)]
+        action: Option<StashAction>,
     },
-    Status,
+    Notes {
+        #[command(subcommand)]
+        action: NotesAction,
+    },
+    Worktree {
+        #[command(subcommand)]
+        action: WorktreeAction,
+    },
+    Remote {
+        /// Show each remote's URLs too
+        #[arg(short, long)]
+        verbose: bool,
+
+        #[command(subcommand)]
+        action: Option<RemoteAction>,
+    },
+    Am {
+        /// Restore the branch to where it was before the `am` in progress
+        #[arg(long)]
+        abort: bool,
+
+        #[arg(value_name = "mbox", required_unless_present = "abort")]
+        mboxes: Vec<String>,
+    },
+    Apply {
+        /// Only check whether the patch applies, without changing anything
+        #[arg(long)]
+        check: bool,
+
+        /// Also stage the changes
+        #[arg(long)]
+        index: bool,
+
+        patch: String,
+    },
+    FormatPatch {
+        /// `<since>..<until>`, or just `<since>` for everything since it
+        range: String,
+    },
+    Archive {
+        /// Defaults to zip when the output file ends in `.zip`, and tar otherwise
+        #[arg(long, value_enum)]
+        format: Option<ArchiveFormat>,
+
+        /// Prepended to every path in the archive (e.g. `project/`)
+        #[arg(long, default_value = "")]
+        prefix: String,
+
+        #[arg(short, long)]
+        output: Option<String>,
+
+        #[arg(value_name = "tree-ish")]
+        tree_ish: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum StashAction {
+    Save,
+    Pop,
+}
+
+#[derive(Subcommand, Debug)]
+enum NotesAction {
+    Show {
+        #[arg(value_name = "object", default_value = "HEAD")]
+        object: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum WorktreeAction {
+    Add { path: String, branch: String },
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+enum RemoteAction {
+    Add {
+        name: String,
+        url: String,
+    },
+    #[command(alias = "rm")]
+    Remove {
+        name: String,
+    },
+    SetUrl {
+        name: String,
+        url: String,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ArchiveFormat {
+    Tar,
+    Zip,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum OutputFormat {
+    Human,
+    Json,
 }
 
 fn main() -> Result<()> {
@@ -77,18 +397,60 @@ fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
 
     let app = App::parse();
-    match app.command {
+    rusty_git::output::set_quiet(app.quiet);
+
+    if let Err(err) = run(app.command) {
+        // errors the user is expected to run into (like a bad object name) get
+        // a one-line message and git's exit status for fatal errors, unless a
+        // backtrace was asked for; anything else gets the full report
+        let expected = err.chain().any(|cause| cause.is::<GitError>());
+        if expected && std::env::var_os("RUST_BACKTRACE").is_none() {
+            let causes: Vec<String> = err.chain().map(ToString::to_string).collect();
+            eprintln!("fatal: {}", causes.join(": "));
+            std::process::exit(128);
+        }
+
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+fn run(command: Command) -> Result<()> {
+    // everything but creating a repository needs to run inside of one
+    if !matches!(command, Command::Init | Command::Clone { .. }) {
+        rusty_git::repository::enter()?;
+    }
+    match command {
         Command::Init => subcommand::init::run(),
+        Command::CatFile {
+            exists: true,
+            object_hash,
+            ..
+        } => exit_status(subcommand::cat_file::exists(&object_hash)?),
+        Command::CatFile {
+            format: Some(format),
+            object_hash,
+            ..
+        } => subcommand::cat_file::format(&format, &object_hash),
         Command::CatFile {
             pretty,
+            show_type,
             object_hash,
-        } => subcommand::cat_file::run(pretty, &object_hash),
-        Command::HashObject { write, path } => subcommand::hash_object::run(write, &path),
+            ..
+        } => subcommand::cat_file::run(pretty, show_type, &object_hash),
+        Command::HashObject {
+            write,
+            filter_path,
+            no_filters,
+            path,
+        } => subcommand::hash_object::run(write, &path, filter_path.as_deref(), no_filters),
         Command::LsTree {
             object_hash,
             name_only,
         } => subcommand::ls_tree::run(name_only, &object_hash),
-        Command::WriteTree => subcommand::write_tree::run(),
+        Command::WriteTree { dry_run } => subcommand::write_tree::run(dry_run),
+        Command::ReadTree { trees, merge } => subcommand::read_tree::run(&trees, merge),
         Command::CommitTree {
             object_hash,
             parent_hash,
@@ -97,12 +459,154 @@ fn main() -> Result<()> {
         Command::Clone {
             repo_url,
             output_dir,
-        } => subcommand::clone::run(&repo_url, output_dir.as_deref()),
-        Command::IndexPack { pack_file } => subcommand::index_pack::run(pack_file),
-        Command::VerifyPack { index_file } => subcommand::verify_pack::run(&index_file),
+            keep_pack,
+            bare,
+            mirror,
+        } => subcommand::clone::run(&repo_url, output_dir.as_deref(), keep_pack, bare, mirror),
+        Command::IndexPack {
+            pack_file,
+            strict,
+            rev_index,
+            verbose,
+        } => subcommand::index_pack::run(pack_file, strict, rev_index, verbose),
+        Command::VerifyPack {
+            index_file,
+            verbose,
+            stat_only,
+            format,
+        } => subcommand::verify_pack::run(
+            &index_file,
+            verbose,
+            stat_only,
+            matches!(format, OutputFormat::Json),
+        ),
         Command::UnpackObjects => subcommand::unpack_objects::run(),
         Command::Checkout { branch } => subcommand::checkout::run(&branch),
-        Command::LsFiles { cached, staged } => subcommand::ls_files::run(cached, staged),
-        Command::Status => subcommand::status::run(),
+        Command::CheckoutIndex { all, force, paths } => {
+            subcommand::checkout_index::run(all, force, &paths)
+        }
+        Command::LsFiles {
+            cached,
+            staged,
+            others,
+            modified,
+            deleted,
+            exclude_standard,
+            paths,
+        } => subcommand::ls_files::run(
+            cached,
+            staged,
+            others,
+            modified,
+            deleted,
+            exclude_standard,
+            &paths,
+        ),
+        Command::Status { paths } => subcommand::status::run(&paths),
+        Command::Diff {
+            name_status,
+            stat,
+            find_renames,
+            commits,
+        } => subcommand::diff::run(&commits, name_status, stat, find_renames),
+        Command::Describe {
+            commit,
+            tags,
+            always,
+        } => subcommand::describe::run(commit.as_deref(), tags, always),
+        Command::Fetch { remote } => subcommand::fetch::run(remote.as_deref().unwrap_or("origin")),
+        Command::Pull => subcommand::pull::run(),
+        Command::Push { remote, branch } => subcommand::push::run(&remote, &branch),
+        Command::Merge { branch } => subcommand::merge::run(&branch),
+        Command::ShowBranch { revs } => subcommand::show_branch::run(&revs),
+        Command::MergeBase {
+            a,
+            b,
+            is_ancestor: true,
+        } => exit_status(subcommand::merge_base::is_ancestor(&a, &b)?),
+        Command::MergeBase { a, b, .. } => exit_status(subcommand::merge_base::run(&a, &b)?),
+        Command::CherryPick { commit } => subcommand::cherry_pick::run(&commit),
+        Command::Revert { commit } => subcommand::revert::run(&commit),
+        Command::Reflog { name } => subcommand::reflog::run(name.as_deref().unwrap_or("HEAD")),
+        Command::RevParse {
+            show_toplevel,
+            git_dir,
+            is_inside_work_tree,
+            revisions,
+        } => subcommand::rev_parse::run(&revisions, show_toplevel, git_dir, is_inside_work_tree),
+        Command::RevList {
+            commits,
+            topo_order,
+            date_order,
+        } => subcommand::rev_list::run(&commits, topo_order, date_order),
+        Command::Log {
+            revisions,
+            topo_order,
+            date_order,
+            author,
+            grep,
+            since,
+            until,
+        } => subcommand::log::run(
+            &revisions,
+            topo_order,
+            date_order,
+            &subcommand::log::LogFilter {
+                authors: author,
+                greps: grep,
+                since,
+                until,
+            },
+        ),
+        Command::PackRefs { all } => subcommand::pack_refs::run(all),
+        Command::Stash { action } => match action.unwrap_or(StashAction::Save) {
+            StashAction::Save => subcommand::stash::save(),
+            StashAction::Pop => subcommand::stash::pop(),
+        },
+        Command::Notes { action } => match action {
+            NotesAction::Show { object } => subcommand::notes::show(&object),
+        },
+        Command::Worktree { action } => match action {
+            WorktreeAction::Add { path, branch } => subcommand::worktree::add(&path, &branch),
+            WorktreeAction::List => subcommand::worktree::list(),
+        },
+        Command::Remote { verbose, action } => match action {
+            None => subcommand::remote::list(verbose),
+            Some(RemoteAction::Add { name, url }) => subcommand::remote::add(&name, &url),
+            Some(RemoteAction::Remove { name }) => subcommand::remote::remove(&name),
+            Some(RemoteAction::SetUrl { name, url }) => subcommand::remote::set_url(&name, &url),
+        },
+        Command::Am { abort, mboxes } => match abort {
+            true => subcommand::am::abort(),
+            false => subcommand::am::run(&mboxes),
+        },
+        Command::Apply {
+            check,
+            index,
+            patch,
+        } => subcommand::apply::run(&patch, check, index),
+        Command::FormatPatch { range } => subcommand::format_patch::run(&range),
+        Command::Archive {
+            format,
+            prefix,
+            output,
+            tree_ish,
+        } => {
+            let zip = match format {
+                Some(format) => matches!(format, ArchiveFormat::Zip),
+                None => output.as_deref().is_some_and(|path| path.ends_with(".zip")),
+            };
+            subcommand::archive::run(&tree_ish, zip, &prefix, output.as_deref())
+        }
     }
 }
+
+/// For commands that answer a yes/no question (like `cat-file -e`), which
+/// report the answer only through the exit status.
+fn exit_status(success: bool) -> Result<()> {
+    if !success {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
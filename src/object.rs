@@ -1,18 +1,27 @@
 use crate::commit::Commit;
+use crate::config::Config;
+use crate::convert::EolConversion;
+use crate::error::GitError;
+use crate::hash::HashAlgorithm;
+use crate::index::trust_executable_bit;
+use crate::object_store::{FsObjectStore, ObjectRead, ObjectStore};
 use crate::parser::{ParseError, Parser};
+use crate::repository;
 use crate::tag::Tag;
+use crate::tree::base_name_compare;
+use crate::utils::compression_level;
 use eyre::{Context, Result};
-use flate2::read::ZlibDecoder;
-use flate2::write::ZlibEncoder;
+use flate2::read::{DeflateDecoder, ZlibDecoder};
+use flate2::Compression;
 use io_tee::TeeWriter;
-use sha1::{Digest, Sha1};
 use std::fmt::{Debug, Display};
 use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::fs::MetadataExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use tempfile::NamedTempFile;
+use std::sync::OnceLock;
 
 #[derive(Debug)]
 pub enum Object {
@@ -22,53 +31,168 @@ pub enum Object {
     Tag(Tag),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ObjectMode {
     Symlink,
     Directory,
     Executable,
     Normal,
+    /// A submodule, whose hash names a commit in another repository.
+    Gitlink,
 }
 
 #[derive(Clone)]
 pub struct ObjectHash {
     hex: String,
-    bin: [u8; 20],
+    bin: HashBytes,
+}
+
+/// The raw digest backing an [`ObjectHash`], whose length depends on the
+/// algorithm that produced it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum HashBytes {
+    Sha1([u8; 20]),
+    Sha256([u8; 32]),
 }
 
 impl ObjectHash {
-    pub fn from_hasher(hasher: Sha1) -> Self {
-        let digest = hasher.finalize();
-        Self {
-            hex: format!("{:x}", digest),
-            bin: digest.into(),
-        }
-    }
+    /// Build a hash from a raw SHA-1 (20 byte) or SHA-256 (32 byte) digest.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let bin = match bytes.len() {
+            20 => HashBytes::Sha1(bytes.try_into()?),
+            32 => HashBytes::Sha256(bytes.try_into()?),
+            len => eyre::bail!("invalid object hash length ({len} bytes)"),
+        };
 
-    pub fn from_bytes(bytes: &[u8; 20]) -> Self {
-        let mut hex = String::with_capacity(40);
+        let mut hex = String::with_capacity(bytes.len() * 2);
         use std::fmt::Write;
         for byte in bytes.iter() {
             write!(hex, "{:02x}", byte).unwrap();
         }
-        Self { hex, bin: *bytes }
+        Ok(Self { hex, bin })
     }
 
     pub fn as_hex(&self) -> &str {
         &self.hex
     }
 
-    pub fn as_bytes(&self) -> [u8; 20] {
-        self.bin
+    pub fn as_bytes(&self) -> &[u8] {
+        match &self.bin {
+            HashBytes::Sha1(bytes) => bytes,
+            HashBytes::Sha256(bytes) => bytes,
+        }
+    }
+
+    pub fn algorithm(&self) -> HashAlgorithm {
+        match self.bin {
+            HashBytes::Sha1(_) => HashAlgorithm::Sha1,
+            HashBytes::Sha256(_) => HashAlgorithm::Sha256,
+        }
+    }
+
+    /// The hash of the tree with no entries, which (like git) we treat as
+    /// always existing, even if it's never been written.
+    pub fn empty_tree(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha1 => "4b825dc642cb6eb9a060e54bf8d69288fbee4904",
+            HashAlgorithm::Sha256 => {
+                "6ef19b41225c5369f1c104d45d8d85efa9b057b53b14b4b9b939dd74decc5321"
+            }
+        }
+        .parse()
+        .expect("well-known hash is valid")
+    }
+
+    /// The hash of the blob with no contents, which (like git) we treat as
+    /// always existing, even if it's never been written.
+    pub fn empty_blob(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha1 => "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391",
+            HashAlgorithm::Sha256 => {
+                "473a0f4c3be8a93681a267e3b1e9a7dcda1185436fe141f7749120a303721813"
+            }
+        }
+        .parse()
+        .expect("well-known hash is valid")
+    }
+
+    /// The type of the well-known empty object with this hash, if it is one.
+    fn empty_object_type(&self) -> Option<ObjectType> {
+        if *self == Self::empty_tree(self.algorithm()) {
+            Some(ObjectType::Tree)
+        } else if *self == Self::empty_blob(self.algorithm()) {
+            Some(ObjectType::Blob)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseObjectHashError(String);
+
+impl Display for ParseObjectHashError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid object hash '{}' (expected 40 or 64 lowercase hex characters)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseObjectHashError {}
+
+impl FromStr for ObjectHash {
+    type Err = ParseObjectHashError;
+
+    fn from_str(s: &str) -> std::prelude::v1::Result<Self, Self::Err> {
+        let err = || ParseObjectHashError(s.to_owned());
+
+        if !matches!(s.len(), 40 | 64) || !s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        {
+            return Err(err());
+        }
+
+        let bytes = (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
+            .collect::<std::prelude::v1::Result<Vec<u8>, _>>()
+            .map_err(|_| err())?;
+
+        Self::from_bytes(&bytes).map_err(|_| err())
     }
 }
 
+// equality, hashing, and ordering only need to look at the raw digest, since
+// the hex representation is derived from it
+
 impl PartialEq for ObjectHash {
     fn eq(&self, other: &Self) -> bool {
         self.bin == other.bin
     }
 }
 
+impl Eq for ObjectHash {}
+
+impl std::hash::Hash for ObjectHash {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.bin.hash(state);
+    }
+}
+
+impl PartialOrd for ObjectHash {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ObjectHash {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_bytes().cmp(other.as_bytes())
+    }
+}
+
 impl Display for ObjectHash {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(&self.hex)
@@ -88,6 +212,18 @@ impl Display for ObjectMode {
             Self::Directory => write!(f, "40000"),
             Self::Executable => write!(f, "100755"),
             Self::Normal => write!(f, "100644"),
+            Self::Gitlink => write!(f, "160000"),
+        }
+    }
+}
+
+impl ObjectMode {
+    /// The type of object an entry with this mode points at.
+    pub fn object_type(&self) -> ObjectType {
+        match self {
+            Self::Directory => ObjectType::Tree,
+            Self::Gitlink => ObjectType::Commit,
+            Self::Symlink | Self::Executable | Self::Normal => ObjectType::Blob,
         }
     }
 }
@@ -101,6 +237,7 @@ impl FromStr for ObjectMode {
             "40000" => Ok(Self::Directory),
             "100755" => Ok(Self::Executable),
             "100644" => Ok(Self::Normal),
+            "160000" => Ok(Self::Gitlink),
             _ => Err(String::from(s)),
         }
     }
@@ -128,13 +265,13 @@ impl Object {
     }
 
     pub fn mode(&self) -> Result<ObjectMode> {
-        let meta = self.path().metadata()?;
+        let meta = repository::work_tree_path(self.path()).metadata()?;
 
         Ok(if meta.is_dir() {
             ObjectMode::Directory
         } else if meta.is_symlink() {
             ObjectMode::Symlink
-        } else if meta.mode() & 0o111 != 0 {
+        } else if meta.mode() & 0o111 != 0 && trust_executable_bit()? {
             ObjectMode::Executable
         } else {
             ObjectMode::Normal
@@ -142,50 +279,152 @@ impl Object {
     }
 }
 
+/// Where loose objects (and packs) are stored. Linked working trees share the
+/// main one's.
+pub fn objects_dir() -> PathBuf {
+    repository::common_dir().join("objects")
+}
+
 pub trait ObjectHashable {
-    fn write<W: Write>(&mut self, w: W) -> Result<()>;
+    /// Write the object's header and contents. Objects that refer to others
+    /// (like trees) hash those first, writing them into `store` if one is
+    /// given.
+    fn write<W: Write>(&mut self, w: W, store: Option<&dyn ObjectStore>) -> Result<()>;
 
-    /// Consume the inner reader to determine the hash of this object.
+    /// Consume the inner reader to determine the hash of this object. Unless
+    /// `write` is set, nothing is written to the object store.
     fn hash(&mut self, write: bool) -> Result<ObjectHash>
     where
         Self: Sized,
     {
-        fn write_hash<O: ObjectHashable, W: Write>(object: &mut O, mut w: W) -> Result<ObjectHash> {
-            let mut hasher = Sha1::new();
-            let mut writer = TeeWriter::new(&mut hasher, &mut w);
-            object.write(&mut writer)?;
-            Ok(ObjectHash::from_hasher(hasher))
+        if write {
+            self.hash_to(&FsObjectStore::default())
+        } else {
+            write_hash(self, std::io::sink(), None)
         }
+    }
 
-        if write {
-            let mut temp = NamedTempFile::new().context("create temp file")?;
-            let encoder = ZlibEncoder::new(&mut temp, flate2::Compression::default());
+    /// Consume the inner reader to determine the hash of this object, writing
+    /// it (and any objects it refers to) into the given objects directory.
+    fn hash_into(&mut self, objects_dir: &Path) -> Result<ObjectHash>
+    where
+        Self: Sized,
+    {
+        self.hash_to(&FsObjectStore::new(objects_dir))
+    }
 
-            let hash = write_hash(self, encoder)?;
+    /// Consume the inner reader to determine the hash of this object, writing
+    /// it (and any objects it refers to) into the given store.
+    fn hash_to(&mut self, store: &dyn ObjectStore) -> Result<ObjectHash>
+    where
+        Self: Sized,
+    {
+        store.insert(&mut |w| write_hash(self, w, Some(store)))
+    }
+}
 
-            let prefix_dir = format!(".git/objects/{}", &hash.as_hex()[..2]);
-            match std::fs::create_dir(&prefix_dir) {
-                Ok(_) => (),
-                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => (),
-                err @ Err(_) => err?,
-            };
+fn write_hash<O: ObjectHashable, W: Write>(
+    object: &mut O,
+    mut w: W,
+    store: Option<&dyn ObjectStore>,
+) -> Result<ObjectHash> {
+    let algorithm = match store {
+        Some(store) => store.algorithm()?,
+        None => HashAlgorithm::current()?,
+    };
+    let mut hasher = algorithm.hasher();
+    let mut writer = TeeWriter::new(&mut hasher, &mut w);
+    object.write(&mut writer, store)?;
+    Ok(hasher.finalize())
+}
 
-            std::fs::rename(temp, format!("{}/{}", prefix_dir, &hash.as_hex()[2..]))
-                .context("move temp file to .git/objects")?;
+static LOOSE_COMPRESSION: OnceLock<Compression> = OnceLock::new();
 
-            Ok(hash)
-        } else {
-            write_hash(self, std::io::sink())
+/// The zlib level for loose objects, set by `core.looseCompression` (or
+/// `core.compression`), which is read the first time it's needed.
+pub(crate) fn loose_compression() -> Result<Compression> {
+    if let Some(level) = LOOSE_COMPRESSION.get() {
+        return Ok(*level);
+    }
+
+    let level = compression_level(
+        &Config::read_default()?,
+        &["core.loosecompression", "core.compression"],
+    )?;
+    Ok(*LOOSE_COMPRESSION.get_or_init(|| level))
+}
+
+/// The path of the loose object with the given hash.
+pub(crate) fn loose_path(objects_dir: &Path, object_hash: &str) -> PathBuf {
+    objects_dir.join(&object_hash[..2]).join(&object_hash[2..])
+}
+
+/// Like [`loose_path`], but falls back to the path in the first alternate
+/// objects directory that has the object (if any).
+fn find_loose_path(objects_dir: &Path, object_hash: &str) -> Result<PathBuf> {
+    let path = loose_path(objects_dir, object_hash);
+    if path.exists() {
+        return Ok(path);
+    }
+
+    Ok(alternates(objects_dir)?
+        .into_iter()
+        .map(|dir| loose_path(&dir, object_hash))
+        .find(|path| path.exists())
+        .unwrap_or(path))
+}
+
+/// The other objects directories that objects may be borrowed from, listed
+/// one per line in `objects/info/alternates`, along with any alternates of
+/// theirs. Relative paths are relative to the objects directory listing them.
+pub(crate) fn alternates(objects_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    let mut pending = vec![objects_dir.to_owned()];
+    while let Some(dir) = pending.pop() {
+        let path = dir.join("info/alternates");
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                return Err(err).with_context(|| format!("read {}", path.display()));
+            }
+        };
+
+        for line in contents.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let alternate = dir.join(line);
+            if alternate != objects_dir && !dirs.contains(&alternate) {
+                dirs.push(alternate.clone());
+                pending.push(alternate);
+            }
         }
     }
+
+    Ok(dirs)
 }
 
 impl ObjectHashable for Object {
-    fn write<W: Write>(&mut self, mut w: W) -> Result<()> {
+    fn write<W: Write>(&mut self, mut w: W, store: Option<&dyn ObjectStore>) -> Result<()> {
         match self {
             Self::Blob(ref path) => {
-                let meta = std::fs::metadata(path).context("stat file")?;
-                let mut f = File::open(path).context("open file")?;
+                let conversion = EolConversion::for_path(path)?;
+
+                // line endings can only be converted once the whole file is
+                // in memory, since the header needs the converted length
+                let path = repository::work_tree_path(path);
+                if conversion != EolConversion::None {
+                    let contents = conversion.to_git(std::fs::read(path).context("read file")?);
+                    write!(w, "blob {}\0", contents.len())?;
+                    w.write_all(&contents).context("hash file contents")?;
+                    return Ok(());
+                }
+
+                let meta = std::fs::metadata(&path).context("stat file")?;
+                let mut f = File::open(&path).context("open file")?;
                 write!(w, "blob {}\0", meta.len())?;
                 std::io::copy(&mut f, &mut w).context("hash file contents")?;
 
@@ -194,7 +433,7 @@ impl ObjectHashable for Object {
             Self::Tree(root) => {
                 let mut objects: Vec<Object> = Vec::new();
 
-                for f in std::fs::read_dir(root)? {
+                for f in std::fs::read_dir(repository::work_tree_path(&root))? {
                     let f = f?;
 
                     // exclude .git directory
@@ -213,34 +452,36 @@ impl ObjectHashable for Object {
                             continue;
                         }
 
-                        objects.push(Object::tree(f.path()));
+                        objects.push(Object::tree(root.join(f.file_name())));
                     } else {
-                        objects.push(Object::blob(f.path()));
+                        objects.push(Object::blob(root.join(f.file_name())));
                     }
                 }
 
-                // TODO: figure out a more performant way to do this
-                objects.sort_unstable_by_key(|obj| match &obj {
-                    Object::Blob(path) => path.as_os_str().to_owned(),
-                    Object::Tree(path) => {
-                        let mut str = path.as_os_str().to_owned();
-                        str.push("/");
-                        str
-                    }
-                    _ => unreachable!(),
+                // every path shares the same parent, so comparing whole paths
+                // orders entries by name
+                objects.sort_unstable_by(|a, b| {
+                    let is_dir = |obj: &Object| matches!(obj, Object::Tree(_));
+                    base_name_compare(
+                        a.path().as_os_str().as_bytes(),
+                        is_dir(a),
+                        b.path().as_os_str().as_bytes(),
+                        is_dir(b),
+                    )
                 });
 
                 let mut buf = Vec::new();
 
                 for mut obj in objects {
-                    write!(
-                        buf,
-                        "{} {}\0",
-                        obj.mode()?,
-                        // TODO: figure out how git handles non-UTF8 filenames
-                        obj.path().file_name().unwrap().to_string_lossy()
-                    )?;
-                    buf.write_all(&obj.hash(true)?.as_bytes())?;
+                    // git stores names as raw bytes, which needn't be valid UTF-8
+                    write!(buf, "{} ", obj.mode()?)?;
+                    buf.write_all(obj.path().file_name().unwrap().as_bytes())?;
+                    buf.write_all(b"\0")?;
+                    let hash = match store {
+                        Some(store) => obj.hash_to(store)?,
+                        None => obj.hash(false)?,
+                    };
+                    buf.write_all(hash.as_bytes())?;
                 }
 
                 write!(w, "tree {}\0", buf.len())?;
@@ -255,9 +496,13 @@ impl ObjectHashable for Object {
                 for parent_hash in commit.parent_hashes.iter() {
                     writeln!(buf, "parent {parent_hash}")?;
                 }
-                writeln!(buf, "author {}", commit.author)?;
-                writeln!(buf, "committer {}", commit.committer)?;
-                writeln!(buf, "\n{}", commit.message)?;
+                write_commit_header(&mut buf, "author", &commit.author.to_bytes())?;
+                write_commit_header(&mut buf, "committer", &commit.committer.to_bytes())?;
+                for (key, value) in commit.extra_headers.iter() {
+                    write_commit_header(&mut buf, key, value)?;
+                }
+                writeln!(buf)?;
+                buf.write_all(&commit.message)?;
 
                 write!(w, "commit {}\0", buf.len()).unwrap();
                 w.write_all(&buf).context("commit contents")?;
@@ -277,6 +522,20 @@ impl ObjectHashable for Object {
     }
 }
 
+/// Write a commit header, continuing a value that spans multiple lines on
+/// lines starting with a single space.
+fn write_commit_header(w: &mut impl Write, key: &str, value: &[u8]) -> Result<()> {
+    write!(w, "{key} ")?;
+    for (i, line) in value.split(|&b| b == b'\n').enumerate() {
+        if i > 0 {
+            w.write_all(b"\n ")?;
+        }
+        w.write_all(line)?;
+    }
+    writeln!(w)?;
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct ObjectBuf<R: BufRead + Debug> {
     pub object_type: ObjectType,
@@ -284,30 +543,80 @@ pub struct ObjectBuf<R: BufRead + Debug> {
     pub contents: Parser<R>,
 }
 
-impl ObjectBuf<BufReader<ZlibDecoder<File>>> {
+impl ObjectBuf<Box<dyn ObjectRead>> {
+    /// Check whether an object with the given hash exists in the object store,
+    /// without reading it.
+    pub fn exists(object_hash: &str) -> bool {
+        FsObjectStore::current().exists(object_hash)
+    }
+
     pub fn read_at_hash(object_hash: &str) -> Result<Self> {
-        let f = File::open(format!(
-            ".git/objects/{}/{}",
-            &object_hash[..2],
-            &object_hash[2..]
-        ))
-        .with_context(|| format!("read object file at {object_hash}"))?;
-
-        let decoder = ZlibDecoder::new(f);
+        FsObjectStore::current().read(object_hash)
+    }
+
+    /// Like [`ObjectBuf::read_at_hash`], but from the given objects directory.
+    pub fn read_from(objects_dir: &Path, object_hash: &str) -> Result<Self> {
+        FsObjectStore::new(objects_dir).read(object_hash)
+    }
+}
+
+impl ObjectBuf<BufReader<LooseDecoder>> {
+    /// Check whether a loose object with the given hash exists in the given
+    /// objects directory (or an alternate), without reading it. This backs
+    /// [`FsObjectStore`]; everything else should go through the store.
+    pub(crate) fn exists_loose(objects_dir: &Path, object_hash: &str) -> bool {
+        match object_hash.parse::<ObjectHash>() {
+            Err(_) => return false,
+            Ok(hash) if hash.empty_object_type().is_some() => return true,
+            Ok(_) => (),
+        }
+
+        find_loose_path(objects_dir, object_hash).is_ok_and(|path| path.exists())
+    }
+
+    /// Read a loose object from the given objects directory (or an alternate).
+    /// Like [`ObjectBuf::exists_loose`], this backs [`FsObjectStore`].
+    pub(crate) fn read_loose(objects_dir: &Path, object_hash: &str) -> Result<Self> {
+        let hash = object_hash.parse::<ObjectHash>()?;
+        let path = find_loose_path(objects_dir, object_hash)?;
+
+        // the empty tree and blob may not have been written yet, but they're
+        // treated as always present (without writing anything to the store)
+        if let Some(object_type) = hash.empty_object_type() {
+            if !path.exists() {
+                return Ok(ObjectBuf {
+                    object_type,
+                    content_len: 0,
+                    contents: Parser::new(BufReader::new(LooseDecoder::Empty)),
+                });
+            }
+        }
+
+        let f = File::open(&path).with_context(|| format!("read object file at {object_hash}"))?;
+        let corrupt = || GitError::CorruptLooseObject {
+            hash: object_hash.to_owned(),
+            path: path.clone(),
+        };
+
+        let decoder = LooseDecoder::new(f).wrap_err_with(corrupt)?;
         let reader = BufReader::new(decoder);
         let mut parser = Parser::new(reader);
 
         let object_type = match parser.parse::<ObjectType>(b' ') {
             Ok(object_type) => object_type,
             Err(ParseError::Parse(object_type)) => {
-                return Err(eyre::eyre!("unrecognized object type {object_type}"));
+                return Err(eyre::eyre!("unrecognized object type {object_type}"))
+                    .wrap_err_with(corrupt);
             }
             Err(ParseError::Read(err)) => {
-                return Err(err);
+                return Err(err).wrap_err_with(corrupt);
             }
         };
 
-        let content_len = parser.parse_usize(b'\0').context("content length")?;
+        let content_len = parser
+            .parse_usize(b'\0')
+            .context("content length")
+            .wrap_err_with(corrupt)?;
 
         Ok(Self {
             object_type,
@@ -317,8 +626,101 @@ impl ObjectBuf<BufReader<ZlibDecoder<File>>> {
     }
 }
 
+/// Decompresses a loose object. Git always writes loose objects as zlib
+/// streams (with any window size), but a raw deflate stream with no zlib
+/// header is accepted too.
+#[derive(Debug)]
+pub enum LooseDecoder {
+    Zlib(ZlibDecoder<File>),
+    Deflate(DeflateDecoder<File>),
+    /// The contents of the empty tree or blob, which needn't exist on disk.
+    Empty,
+}
+
+impl LooseDecoder {
+    /// Pick a decoder by looking at the start of the file, failing (rather
+    /// than partway through decoding) if it isn't either kind of stream.
+    fn new(mut f: File) -> Result<Self> {
+        let mut header = [0; 2];
+        f.read_exact(&mut header)
+            .context("object file is too short to be compressed")?;
+        f.seek(SeekFrom::Start(0))?;
+
+        // a zlib header names the deflate method (8) and a window of at most
+        // 32K, and its two bytes are a multiple of 31 when read as a u16
+        let [cmf, flg] = header;
+        if cmf & 0x0f == 8 && cmf >> 4 <= 7 && u16::from_be_bytes(header) % 31 == 0 {
+            return Ok(Self::Zlib(ZlibDecoder::new(f)));
+        }
+
+        // otherwise, the first deflate block can't use the reserved type (3)
+        if (cmf >> 1) & 0b11 != 0b11 {
+            return Ok(Self::Deflate(DeflateDecoder::new(f)));
+        }
+
+        eyre::bail!("unrecognized compression header {cmf:02x}{flg:02x}")
+    }
+}
+
+impl Read for LooseDecoder {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Zlib(decoder) => decoder.read(buf),
+            Self::Deflate(decoder) => decoder.read(buf),
+            Self::Empty => Ok(0),
+        }
+    }
+}
+
+impl<R: BufRead + Debug> ObjectBuf<R> {
+    /// Stream the contents of this object, without loading them all into memory.
+    pub fn reader(&mut self) -> ObjectReader<'_, R> {
+        ObjectReader {
+            remaining: self.content_len,
+            contents: &mut self.contents,
+        }
+    }
+
+    /// Read the full contents of this object into memory, making sure that
+    /// the object is exactly as long as its header says it is.
+    pub fn read_to_vec(&mut self) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(self.content_len);
+        self.reader().read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// Reads an object's contents, which must be exactly as long as its header
+/// says they are (otherwise, reading fails with an error).
+pub struct ObjectReader<'a, R: BufRead + Debug> {
+    contents: &'a mut Parser<R>,
+    remaining: usize,
+}
+
+impl<R: BufRead + Debug> Read for ObjectReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mismatch = || std::io::Error::other("object content length mismatch");
+
+        if self.remaining == 0 {
+            if !self.contents.inner_mut().fill_buf()?.is_empty() {
+                return Err(mismatch());
+            }
+            return Ok(0);
+        }
+
+        let max = buf.len().min(self.remaining);
+        let n = self.contents.inner_mut().read(&mut buf[..max])?;
+        if n == 0 && max > 0 {
+            return Err(mismatch());
+        }
+
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
 impl<R: BufRead + Debug> ObjectHashable for ObjectBuf<R> {
-    fn write<W: Write>(&mut self, mut w: W) -> Result<()> {
+    fn write<W: Write>(&mut self, mut w: W, _store: Option<&dyn ObjectStore>) -> Result<()> {
         write!(w, "{} {}\0", self.object_type, self.content_len)?;
         let _ = std::io::copy(self.contents.inner_mut(), &mut w)?;
         Ok(())
@@ -357,3 +759,42 @@ impl FromStr for ObjectType {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    fn assert_corrupt(contents: &[u8]) {
+        let objects_dir = tempfile::tempdir().unwrap();
+        let path = loose_path(objects_dir.path(), HASH);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+
+        let err = ObjectBuf::read_from(objects_dir.path(), HASH).unwrap_err();
+        match err.downcast_ref::<GitError>() {
+            Some(GitError::CorruptLooseObject { hash, path: at }) => {
+                assert_eq!(hash, HASH);
+                assert_eq!(*at, path);
+            }
+            _ => panic!("expected a corrupt loose object, got: {err:#}"),
+        }
+    }
+
+    #[test]
+    fn unrecognized_compression_is_corrupt() {
+        assert_corrupt(&[0xff, 0xff, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn truncated_object_is_corrupt() {
+        // a zlib header, but nothing after it
+        assert_corrupt(&[0x78, 0x9c]);
+    }
+
+    #[test]
+    fn empty_file_is_corrupt() {
+        assert_corrupt(&[]);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::hash::HashAlgorithm;
+use crate::object::{loose_compression, loose_path, objects_dir, ObjectBuf, ObjectHash};
+use crate::pack::PackIndex;
+use crate::parser::Parser;
+use crate::repository::read_pack_indexes;
+use eyre::{Context, Result};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::fmt::Debug;
+use std::io::{BufRead, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tempfile::NamedTempFile;
+
+/// A reader over an object's contents, whatever store it came from.
+pub trait ObjectRead: BufRead + Debug {}
+
+impl<R: BufRead + Debug> ObjectRead for R {}
+
+/// Somewhere objects can be read from and written to, by hash.
+pub trait ObjectStore {
+    /// Check whether the object with the given hash exists, without reading it.
+    fn exists(&self, hash: &str) -> bool;
+
+    fn read(&self, hash: &str) -> Result<ObjectBuf<Box<dyn ObjectRead>>>;
+
+    /// The hash function that objects in this store are named with.
+    fn algorithm(&self) -> Result<HashAlgorithm>;
+
+    /// Store a new object. Its header and contents are streamed to the writer
+    /// given to `write`, which returns the object's hash once it's done (since
+    /// that isn't known until the whole object has been written).
+    fn insert(
+        &self,
+        write: &mut dyn FnMut(&mut dyn Write) -> Result<ObjectHash>,
+    ) -> Result<ObjectHash>;
+}
+
+static CURRENT: OnceLock<FsObjectStore> = OnceLock::new();
+
+/// The objects in an objects directory (like `.git/objects`), loose or in
+/// packs, falling back to any alternates when reading. New objects are always
+/// written loose.
+#[derive(Debug)]
+pub struct FsObjectStore {
+    objects_dir: PathBuf,
+    /// The hash function and zlib level for new objects, if they shouldn't
+    /// come from the current repository's config.
+    algorithm: Option<HashAlgorithm>,
+    compression: Option<Compression>,
+    /// The indexes of the packs in the objects directory and its alternates,
+    /// which are only read once an object isn't found loose.
+    packs: OnceLock<Vec<PackIndex>>,
+}
+
+impl FsObjectStore {
+    pub fn new(objects_dir: impl AsRef<Path>) -> Self {
+        Self {
+            objects_dir: objects_dir.as_ref().to_owned(),
+            algorithm: None,
+            compression: None,
+            packs: OnceLock::new(),
+        }
+    }
+
+    /// Hash and compress new objects as given, rather than as the current
+    /// repository is configured to.
+    #[cfg(test)]
+    pub fn with_format(self, algorithm: HashAlgorithm, compression: Compression) -> Self {
+        Self {
+            algorithm: Some(algorithm),
+            compression: Some(compression),
+            ..self
+        }
+    }
+
+    /// The store for the current repository, so that its packs are only
+    /// looked for once.
+    pub fn current() -> &'static Self {
+        CURRENT.get_or_init(Self::default)
+    }
+
+    pub fn objects_dir(&self) -> &Path {
+        &self.objects_dir
+    }
+
+    fn packs(&self) -> Result<&[PackIndex]> {
+        if let Some(packs) = self.packs.get() {
+            return Ok(packs);
+        }
+
+        let packs = read_pack_indexes(&self.objects_dir)?;
+        Ok(self.packs.get_or_init(|| packs))
+    }
+
+    /// Look for an object in the packs, returning the first copy found.
+    fn read_packed(&self, hash: &str) -> Result<Option<ObjectBuf<Box<dyn ObjectRead>>>> {
+        let Ok(hash) = hash.parse::<ObjectHash>() else {
+            return Ok(None);
+        };
+
+        for pack in self.packs()? {
+            if let Some(offset) = pack.find_offset(&hash) {
+                let (object_type, contents) = pack
+                    .read_object(offset)
+                    .with_context(|| format!("read {hash} from {}", pack.pack_path.display()))?;
+                return Ok(Some(ObjectBuf {
+                    object_type,
+                    content_len: contents.len(),
+                    contents: Parser::new(Box::new(Cursor::new(contents))),
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl Default for FsObjectStore {
+    fn default() -> Self {
+        Self::new(objects_dir())
+    }
+}
+
+impl ObjectStore for FsObjectStore {
+    fn exists(&self, hash: &str) -> bool {
+        if ObjectBuf::exists_loose(&self.objects_dir, hash) {
+            return true;
+        }
+
+        let Ok(hash) = hash.parse::<ObjectHash>() else {
+            return false;
+        };
+        self.packs()
+            .is_ok_and(|packs| packs.iter().any(|pack| pack.find_offset(&hash).is_some()))
+    }
+
+    fn read(&self, hash: &str) -> Result<ObjectBuf<Box<dyn ObjectRead>>> {
+        if !ObjectBuf::exists_loose(&self.objects_dir, hash) {
+            if let Some(object) = self.read_packed(hash)? {
+                return Ok(object);
+            }
+        }
+
+        let object = ObjectBuf::read_loose(&self.objects_dir, hash)?;
+        Ok(ObjectBuf {
+            object_type: object.object_type,
+            content_len: object.content_len,
+            contents: Parser::new(Box::new(object.contents.into_inner())),
+        })
+    }
+
+    fn algorithm(&self) -> Result<HashAlgorithm> {
+        self.algorithm.map_or_else(HashAlgorithm::current, Ok)
+    }
+
+    fn insert(
+        &self,
+        write: &mut dyn FnMut(&mut dyn Write) -> Result<ObjectHash>,
+    ) -> Result<ObjectHash> {
+        // objects are compressed into a temp file first, then moved into place
+        // once their hash is known, so a partly-written object is never seen
+        let mut temp = NamedTempFile::new_in(&self.objects_dir).context("create temp file")?;
+        let compression = self.compression.map_or_else(loose_compression, Ok)?;
+        let mut encoder = ZlibEncoder::new(&mut temp, compression);
+
+        let hash = write(&mut encoder)?;
+        encoder.finish().context("compress object")?;
+
+        let path = loose_path(&self.objects_dir, hash.as_hex());
+        let prefix_dir = path
+            .parent()
+            .expect("loose objects are in a prefix directory");
+        match std::fs::create_dir(prefix_dir) {
+            Ok(_) => (),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => (),
+            err @ Err(_) => err?,
+        };
+
+        std::fs::rename(temp, &path).context("move temp file to objects directory")?;
+
+        Ok(hash)
+    }
+}
+
+/// Objects kept in memory (uncompressed) rather than on disk, so tests can
+/// read and write objects without a repository.
+#[cfg(test)]
+#[derive(Debug)]
+pub struct MemoryObjectStore {
+    algorithm: HashAlgorithm,
+    objects: std::cell::RefCell<std::collections::HashMap<String, Vec<u8>>>,
+}
+
+#[cfg(test)]
+impl MemoryObjectStore {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        Self {
+            algorithm,
+            objects: Default::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl ObjectStore for MemoryObjectStore {
+    fn exists(&self, hash: &str) -> bool {
+        self.objects.borrow().contains_key(hash)
+    }
+
+    fn read(&self, hash: &str) -> Result<ObjectBuf<Box<dyn ObjectRead>>> {
+        let object = self
+            .objects
+            .borrow()
+            .get(hash)
+            .cloned()
+            .ok_or_else(|| eyre::eyre!("object {hash} not found"))?;
+
+        let mut parser = Parser::new(std::io::Cursor::new(object));
+        let object_type = parser
+            .parse_str(b' ')?
+            .parse()
+            .map_err(|object_type| eyre::eyre!("unrecognized object type {object_type}"))?;
+        let content_len = parser.parse_usize(b'\0').context("content length")?;
+
+        Ok(ObjectBuf {
+            object_type,
+            content_len,
+            contents: Parser::new(Box::new(parser.into_inner())),
+        })
+    }
+
+    fn algorithm(&self) -> Result<HashAlgorithm> {
+        Ok(self.algorithm)
+    }
+
+    fn insert(
+        &self,
+        write: &mut dyn FnMut(&mut dyn Write) -> Result<ObjectHash>,
+    ) -> Result<ObjectHash> {
+        let mut buf = Vec::new();
+        let hash = write(&mut buf)?;
+        self.objects.borrow_mut().insert(hash.to_string(), buf);
+        Ok(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{ObjectHashable, ObjectType};
+    use std::io::Cursor;
+
+    /// Write a blob to the store, then read it back.
+    fn round_trip(store: &dyn ObjectStore) {
+        let contents = b"hello\n".to_vec();
+        let mut blob = ObjectBuf {
+            object_type: ObjectType::Blob,
+            content_len: contents.len(),
+            contents: Parser::new(Cursor::new(contents)),
+        };
+
+        let hash = blob.hash_to(store).unwrap();
+        assert_eq!(hash.as_hex(), "ce013625030ba8dba906f756967f9e9ca394464a");
+        assert!(store.exists(hash.as_hex()));
+        assert!(!store.exists("ce013625030ba8dba906f756967f9e9ca394464b"));
+
+        let mut object = store.read(hash.as_hex()).unwrap();
+        assert_eq!(object.object_type, ObjectType::Blob);
+        assert_eq!(object.content_len, 6);
+        assert_eq!(object.read_to_vec().unwrap(), b"hello\n");
+    }
+
+    #[test]
+    fn fs_store_round_trip() {
+        let objects_dir = tempfile::tempdir().unwrap();
+        let store = FsObjectStore::new(objects_dir.path())
+            .with_format(HashAlgorithm::Sha1, Compression::default());
+        round_trip(&store);
+    }
+
+    #[test]
+    fn memory_store_round_trip() {
+        round_trip(&MemoryObjectStore::new(HashAlgorithm::Sha1));
+    }
+}
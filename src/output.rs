@@ -0,0 +1,35 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Suppress informational output (like the hash printed by `hash-object` or
+/// the summary printed by `merge`) and progress for the rest of the process.
+/// Commands whose output is the point of running them (like `cat-file` or
+/// `ls-files`) print regardless, and errors are always reported.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub(crate) fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Like `print!`, but prints nothing when running with `--quiet`.
+macro_rules! out {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_quiet() {
+            print!($($arg)*);
+        }
+    };
+}
+
+/// Like `println!`, but prints nothing when running with `--quiet`.
+macro_rules! outln {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use {out, outln};
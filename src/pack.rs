@@ -1,15 +1,28 @@
+use crate::config::Config;
+use crate::error::GitError;
+use crate::hash::{HashAlgorithm, Hasher};
 use crate::object::{ObjectBuf, ObjectHash, ObjectHashable, ObjectType};
 use crate::parser::{InMemoryReader, Parser};
-use crate::utils::append_checksum;
+use crate::utils::{append_checksum, compression_level};
 use eyre::{Context, Result};
+use flate2::write::ZlibEncoder;
+use io_tee::TeeWriter;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fmt::Debug;
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
-use std::path::Path;
+use std::io::{BufRead, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 pub const PACK_HEADER: &[u8; 4] = b"PACK";
 pub const IDX_MAGIC_NUM: [u8; 4] = [0xff, 0x74, 0x4f, 0x63];
 pub const IDX_VERSION: u32 = 2;
+pub const RIDX_MAGIC_NUM: &[u8; 4] = b"RIDX";
+pub const RIDX_VERSION: u32 = 1;
+
+/// How much memory to spend caching delta bases when reading a pack, unless
+/// `core.deltaBaseCacheLimit` says otherwise (the same default as git).
+pub const DEFAULT_DELTA_BASE_CACHE_LIMIT: usize = 96 * 1024 * 1024;
 
 #[derive(Debug)]
 pub struct Pack {
@@ -17,7 +30,13 @@ pub struct Pack {
     pub obj_count: u32,
     pub checksum: ObjectHash,
 
-    /// Compressed contents of the pack file; these are kept in order by their hashes.
+    /// The number of entries in `contents` whose hash begins with a byte less
+    /// than or equal to each index (see [`FanOutTable`]).
+    pub fan_out: [u32; 256],
+
+    /// Compressed contents of the pack file; these are kept in order by their
+    /// hashes, and an object stored in the pack more than once appears here
+    /// once for each copy.
     pub contents: Vec<PackedObject>,
 }
 
@@ -27,313 +46,259 @@ pub struct PackedObject {
     pub hash: ObjectHash,
     /// The cyclic redundancy check value for this object's contents.
     pub crc32: u32,
-    /// The decompressed size of this object's content.
-    pub size: usize,
+    /// The size of this object's content, once decompressed (and, for deltas,
+    /// reconstructed).
+    pub inflated_size: usize,
+    /// The number of bytes this entry takes up in the pack, from its header
+    /// (and, for deltas, the reference to its base) to the end of its
+    /// compressed data.
+    pub packed_size: usize,
     /// The byte offset of this pack in the containing file.
     pub offset: usize,
-    /// The contents of the object.
-    pub inner: ObjectBuf<InMemoryReader>,
+    /// The length of the chain of deltas needed to reconstruct this object
+    /// (zero if it's stored whole).
+    pub depth: usize,
+    /// The type of the object (for deltas, the type of their base).
+    pub object_type: ObjectType,
+    contents: PackedContents,
 }
 
+/// The contents of a [`PackedObject`], which may still be compressed in the
+/// pack if they weren't needed when it was read.
 #[derive(Debug)]
-pub enum DeltaInstruction {
-    /// Copy `size` bytes from the base object, starting at `offset`.
-    Copy { offset: usize, size: usize },
+enum PackedContents {
+    Loaded(ObjectBuf<InMemoryReader>),
+    /// A whole (non-delta) object whose compressed data starts at the given
+    /// offset in the pack.
+    Deferred {
+        pack_path: Rc<PathBuf>,
+        data_offset: usize,
+    },
+    /// An object (possibly a delta) that's only been found through the pack's
+    /// index, which is read back from the pack at its offset when needed.
+    Indexed(Rc<PackIndex>),
+}
 
-    /// Append the contained bytes to the end of the object.
-    Add(Vec<u8>),
+/// The outcome of unpacking a pack into loose objects (see
+/// [`PackStream::unpack_into`]).
+#[derive(Debug, Default)]
+pub struct UnpackReport {
+    /// The objects that were written to the object store.
+    pub unpacked: Vec<ObjectHash>,
+    /// The objects (or parts of the pack) that couldn't be unpacked.
+    pub failed: Vec<UnpackFailure>,
 }
 
-impl Pack {
-    /// Open a packfile that does *not* have an index.
-    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
-        let f = File::open(path.as_ref()).context("open packfile")?;
-        let file_size = f.metadata()?.len() as usize;
-        let reader = BufReader::new(f);
-        let mut parser = Parser::new(reader);
+#[derive(Debug)]
+pub struct UnpackFailure {
+    /// The object that couldn't be written, if it got far enough to be named.
+    pub hash: Option<ObjectHash>,
+    pub error: eyre::Report,
+}
 
-        // first, verify that the magic header is present and well-formed
-        let header = parser.read_bytes::<4>()?;
-        if &header != PACK_HEADER {
-            eyre::bail!(
-                "invalid header; expected {:?}, got {:?}",
-                PACK_HEADER,
-                header
-            );
+impl UnpackReport {
+    /// Print each failure, and fail if there were any.
+    pub fn check(self) -> Result<()> {
+        if self.failed.is_empty() {
+            return Ok(());
         }
 
-        // second, verify that the checksum at the end of the packfile
-        let (checksum, mut parser) = parser.verify_checksum(file_size)?;
-
-        let version = parser
-            .parse_usize_exact::<4>()
-            .context("parse packfile version")? as u32;
-
-        let obj_count = parser
-            .parse_usize_exact::<4>()
-            .context("parse packfile object count")? as u32;
-
-        let mut pack_contents = Vec::new();
-
-        let mut offset: usize = 12; // 4 + 4 + 4
-        loop {
-            // the final 20 bytes of a packfile contain a hash of its contents,
-            // which we've already verified to be correct earlier
-            if offset == file_size - 20 {
-                break;
+        for failure in self.failed.iter() {
+            match &failure.hash {
+                Some(hash) => eprintln!("error: {hash}: {:#}", failure.error),
+                None => eprintln!("error: {:#}", failure.error),
             }
+        }
+        eyre::bail!(
+            "unpacked {} objects, but {} failed",
+            self.unpacked.len(),
+            self.failed.len()
+        )
+    }
+}
 
-            // 1 0 0 1 1 1 1 0   0 0 0 0 1 1 1 1
-            // ^ |-t-| |--A--|   ^ |-----B-----|
-            //
-            // the MSB of each byte tells whether to continue parsing (variable len encoding)
-            //
-            // the first 3 bits of the result indicate the type (see below); the remaining
-            // bits should be concatenated, in reverse order (A is the low bits, B is high),
-            // to form the actual value: 0b1111_1110
-            let size_bytes = parser.parse_size_enc_bytes()?;
-
-            // Valid object types are:
-            //
-            //   - OBJ_COMMIT (1)
-            //   - OBJ_TREE (2)
-            //   - OBJ_BLOB (3)
-            //   - OBJ_TAG (4)
-            //   - OBJ_OFS_DELTA (6)
-            //   - OBJ_REF_DELTA (7)
-            //
-            // Type 5 is reserved for future expansion. Type 0 is invalid.
-            let obj_type = (size_bytes[0] & 0b0111_0000) >> 4;
-
-            let mut size: usize = (size_bytes[0] & 0b0000_1111) as usize;
-            size = size_enc_init(&size_bytes[1..], size, 4);
-
-            let consumed = match obj_type {
-                0 => eyre::bail!("invalid object type (invalid)"),
-
-                1..=4 => {
-                    let (consumed, contents) = parser.split_off_decode(size)?;
-
-                    let mut object = ObjectBuf {
-                        object_type: match obj_type {
-                            1 => ObjectType::Commit,
-                            2 => ObjectType::Tree,
-                            3 => ObjectType::Blob,
-                            4 => ObjectType::Tag,
-                            _ => unreachable!("only 1..=3 available in parent match"),
-                        },
-                        content_len: size,
-                        contents,
-                    };
-
-                    let hash = object.hash(false).context("hash object contents")?;
-                    object.contents.reset();
-
-                    let mut hasher = crc32fast::Hasher::new();
-                    parser.seek(SeekFrom::Start(offset as _)).unwrap();
-                    std::io::copy(
-                        &mut parser.inner_mut().take(consumed + size_bytes.len() as u64),
-                        &mut hasher,
-                    )?;
-                    let crc32 = hasher.finalize();
-                    object.contents.reset();
-
-                    pack_contents.push(PackedObject {
-                        hash,
-                        crc32,
-                        size,
-                        offset,
-                        inner: object,
-                    });
-
-                    consumed as usize
-                }
-
-                5 => eyre::bail!("invalid object type (reserved)"),
-
-                // TODO: figure out OFS encoding
-                // OFS delta encodes the offset of the object in the pack
-                6 => todo!("OFS delta encoding"),
-
-                // REF delta uses the object's hash
-                7 => {
-                    let base_hash = parser.read_bytes::<20>()?;
-
-                    let (consumed, mut contents) = parser.split_off_decode(size)?;
-
-                    // we don't need to know this but we do need to parse over it
-                    let size_base_bytes = contents.parse_size_enc_bytes()?;
-                    let _size_base = size_enc(&size_base_bytes);
-
-                    let size_new_bytes = contents.parse_size_enc_bytes()?;
-                    let size_new = size_enc(&size_new_bytes);
-
-                    let mut instructions = Vec::new();
-                    while !contents.at_eof()? {
-                        let instr = contents.read_byte()?;
-
-                        if instr & 0x80 == 0 {
-                            let size = instr /* & 0x7f */;
-                            let mut data = vec![0; size as _];
-                            contents.read_exact(&mut data)?;
-                            instructions.push(DeltaInstruction::Add(data));
-                        } else {
-                            // TODO: not really sure what is meant by the zero value exception
-                            // here?
-                            //
-                            // > In its most compact form, this instruction only takes up one byte (0x80)
-                            // > with both offset and size omitted, which will have default values zero.
-                            // > There is another exception: size zero is automatically converted to 0x10000.
-
-                            let mut offset: u32 = 0;
-                            for (cond, shift) in [
-                                (instr & 0b0001, 0),
-                                (instr & 0b0010, 8),
-                                (instr & 0b0100, 16),
-                                (instr & 0b1000, 24),
-                            ] {
-                                if cond != 0 {
-                                    offset |= (contents.read_byte()? as u32) << shift;
-                                }
-                            }
-
-                            let mut size: u32 = 0;
-                            for (cond, shift) in [
-                                (instr & 0b0001_0000, 0),
-                                (instr & 0b0010_0000, 8),
-                                (instr & 0b0100_0000, 16),
-                            ] {
-                                if cond != 0 {
-                                    size |= (contents.read_byte()? as u32) << shift;
-                                }
-                            }
-
-                            instructions.push(DeltaInstruction::Copy {
-                                offset: offset as _,
-                                size: size as _,
-                            });
-                        }
-                    }
-
-                    let mut obj_buf = Vec::with_capacity(size_new);
-                    let base_obj = pack_contents
-                        .iter_mut()
-                        .find(|obj| obj.hash.as_bytes() == base_hash)
-                        .expect("base object should exist");
-
-                    for instr in instructions {
-                        match instr {
-                            DeltaInstruction::Copy { offset, size } => obj_buf.extend_from_slice(
-                                &base_obj.inner.contents.get_ref()[offset..][..size],
-                            ),
-                            DeltaInstruction::Add(data) => obj_buf.extend(data),
-                        }
-                    }
-
-                    base_obj.inner.contents.reset();
-
-                    let mut object = ObjectBuf {
-                        object_type: base_obj.inner.object_type,
-                        content_len: size_new,
-                        contents: Parser::new(Cursor::new(obj_buf)),
-                    };
+/// A callback for reporting progress through a long-running operation, given
+/// the name of the current stage along with how many of its items are done
+/// out of the total.
+pub type ProgressFn<'a> = dyn FnMut(&str, usize, usize) + 'a;
 
-                    let hash = object.hash(false).context("hash object contents")?;
-                    object.contents.reset();
+/// Options for reading a packfile with [`Pack::open`] or [`PackStream::open`].
+#[derive(Debug, Clone, Copy)]
+pub struct OpenOptions {
+    /// Read the trailing checksum but don't verify it, for packs that are
+    /// already trusted.
+    pub skip_checksum: bool,
 
-                    let mut hasher = crc32fast::Hasher::new();
-                    parser.seek(SeekFrom::Start(offset as _)).unwrap();
-                    std::io::copy(
-                        &mut parser
-                            .inner_mut()
-                            .take(consumed + size_bytes.len() as u64 + 20),
-                        &mut hasher,
-                    )?;
-                    let crc32 = hasher.finalize();
-                    object.contents.reset();
+    /// How many bytes of delta bases to cache (see [`delta_base_cache_limit`]).
+    pub cache_limit: usize,
 
-                    pack_contents.push(PackedObject {
-                        hash,
-                        crc32,
-                        size: size_new,
-                        offset,
-                        inner: object,
-                    });
+    /// Don't keep the contents of whole objects in memory, for passes that
+    /// only need each object's hash, type, and size.
+    pub lazy: bool,
+}
 
-                    (consumed as usize) + 20 // hash length
-                }
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self {
+            skip_checksum: false,
+            cache_limit: DEFAULT_DELTA_BASE_CACHE_LIMIT,
+            lazy: false,
+        }
+    }
+}
 
-                _ => eyre::bail!("invalid object type (out of range)"),
-            };
+#[derive(Debug, PartialEq, Eq)]
+pub enum DeltaInstruction {
+    /// Copy `size` bytes from the base object, starting at `offset`.
+    Copy { offset: usize, size: usize },
 
-            offset += size_bytes.len();
-            offset += consumed;
+    /// Append the contained bytes to the end of the object.
+    Add(Vec<u8>),
+}
 
-            // Reset the file offset to the start of the next entry, or the checksum
-            // if we've just finished parsing the final object entry. This is required
-            // because `ZlibDecoder` is greedy and will pull in more bytes than it needs
-            // to decode the contents, including some of the subsequent entry.
-            parser
-                .seek(SeekFrom::Start(offset as _))
-                .expect("valid offset");
+impl Pack {
+    /// Open a packfile that does *not* have an index, reporting progress as
+    /// objects are indexed and deltas are resolved (see [`PackStream::open`]).
+    pub fn open<'a>(
+        path: impl AsRef<Path>,
+        progress: Option<&'a mut ProgressFn<'a>>,
+        options: OpenOptions,
+    ) -> Result<Self> {
+        let mut stream = PackStream::open(path, progress, options)?;
+        let mut pack_contents = Vec::with_capacity(stream.obj_count as _);
+        for object in stream.by_ref() {
+            pack_contents.push(object?);
         }
+        let checksum = stream
+            .checksum()
+            .cloned()
+            .expect("the whole pack has been read");
 
         // make sure pack contents are kept in ascending order by object hash
-        pack_contents.sort_by_key(|obj| obj.hash.as_bytes());
+        pack_contents.sort_by(|a, b| a.hash.cmp(&b.hash));
+
+        let mut fan_out = FanOutTable::new();
+        for obj in pack_contents.iter() {
+            fan_out.add(&obj.hash);
+        }
 
         Ok(Self {
-            version,
-            obj_count,
+            version: stream.version,
+            obj_count: stream.obj_count,
             checksum,
+            fan_out: fan_out.to_array(),
             contents: pack_contents,
         })
     }
 
-    /// Open the packfile pointed to by the given index.
+    /// Open the packfile pointed to by the given index. Only the pack's
+    /// checksum and the header of each object are read up front; contents
+    /// are read back from their offset in the pack when they're needed (see
+    /// [`PackedObject::inner`]).
     pub fn open_index(path: impl AsRef<Path>) -> Result<Self> {
         let path = path.as_ref();
+        let index = Rc::new(PackIndex::read(path)?);
+        let obj_count = index.names.len() as u32;
 
-        let mut parser = {
-            let f = File::open(path).context("open index file")?;
+        // make sure the idx file actually belongs with its pack
+        let (file_size, mut pack_parser) = {
+            let f = File::open(&index.pack_path).context("open pack file")?;
+            let file_size = f.metadata()?.len() as usize;
             let reader = BufReader::new(f);
-            Parser::new(reader)
+            (file_size, Parser::new(reader))
         };
-
-        let mut pack_parser = {
-            let f = File::open(path.with_extension("pack")).context("open pack file")?;
-            let reader = BufReader::new(f);
-            Parser::new(reader)
-        };
-
-        let header = parser.read_bytes::<4>()?;
-        if header != IDX_MAGIC_NUM {
-            eyre::bail!("invalid idx file header");
+        let pack_header = pack_parser.read_bytes::<4>().context("read pack header")?;
+        if &pack_header != PACK_HEADER {
+            eyre::bail!("invalid pack file header for {}", path.display());
         }
-
-        let version = parser.parse_usize_exact::<4>()?;
-        if version != 2 {
-            eyre::bail!("only version 2 idx files are supported");
+        let (checksum, mut pack_parser) = pack_parser
+            .verify_checksum(file_size)
+            .context("verify pack checksum")?;
+        if checksum != index.pack_checksum {
+            eyre::bail!(
+                "idx file is for pack {}, but found pack {checksum}",
+                index.pack_checksum,
+            );
+        }
+        let pack_version = pack_parser
+            .parse_usize_exact::<4>()
+            .context("parse packfile version")?;
+        if !matches!(pack_version, 2 | 3) {
+            eyre::bail!("unsupported pack version {pack_version}");
+        }
+        let pack_obj_count = pack_parser
+            .parse_usize_exact::<4>()
+            .context("parse packfile object count")?;
+        if pack_obj_count != obj_count as usize {
+            eyre::bail!(
+                "idx file doesn't match its pack: idx has {obj_count} objects, but pack has {pack_obj_count}"
+            );
         }
 
-        // fan-out table (except last entry)
-        let _ = parser.read_bytes::<1020>()?;
+        // each entry runs up to the start of the next one (or, for the last,
+        // the pack's trailing checksum)
+        let mut ends: Vec<usize> = index.offsets.clone();
+        ends.sort_unstable();
+        let data_end = file_size - checksum.algorithm().digest_len();
+
+        let mut known = HashMap::new();
+        let mut contents = Vec::with_capacity(index.names.len());
+        for (i, (name, &offset)) in index.names.iter().zip(&index.offsets).enumerate() {
+            let end = match ends.binary_search(&offset) {
+                Ok(j) => ends.get(j + 1).copied().unwrap_or(data_end),
+                Err(_) => unreachable!("every offset is in the list"),
+            };
+            if offset < 12 || end > data_end {
+                eyre::bail!("idx entry for {name} points outside the pack");
+            }
 
-        // TODO: verify pack header and version
+            let crc32 = read_entry_crc32(&mut pack_parser, offset, (end - offset) as u64, None)?;
+            if index.crcs.as_ref().is_some_and(|crcs| crcs[i] != crc32) {
+                eyre::bail!("idx entry for {name} doesn't match the pack");
+            }
 
-        let obj_count = parser.parse_usize_exact::<4>()? as u32;
-        dbg!(obj_count);
+            let (object_type, depth) = index
+                .resolve_type(offset, &mut known)
+                .with_context(|| format!("read header of {name}"))?;
+            contents.push(PackedObject {
+                hash: name.clone(),
+                crc32,
+                inflated_size: index
+                    .inflated_size(offset)
+                    .with_context(|| format!("read size of {name}"))?,
+                packed_size: end - offset,
+                offset,
+                depth,
+                object_type,
+                contents: PackedContents::Indexed(Rc::clone(&index)),
+            });
+        }
 
         Ok(Self {
-            // FIXME: use actual pack version
-            version: 2,
+            version: pack_version as u32,
             obj_count,
-            // FIXME: use actual pack hash
-            checksum: ObjectHash::from_bytes(&[0; 20]),
-            // FIXME: use actual contents
-            contents: Vec::new(),
+            checksum,
+            // the contents are in the same order as the index's names
+            fan_out: index.fan_out,
+            contents,
         })
     }
 
+    /// Look up an object by hash, using the fan-out table to narrow down the
+    /// range of candidates before binary searching them.
+    pub fn find(&self, hash: &ObjectHash) -> Option<&PackedObject> {
+        let first_byte = *hash.as_bytes().first()? as usize;
+        let start = match first_byte {
+            0 => 0,
+            _ => self.fan_out[first_byte - 1] as usize,
+        };
+        let end = self.fan_out[first_byte] as usize;
+
+        let candidates = self.contents.get(start..end)?;
+        let idx = candidates.binary_search_by(|obj| obj.hash.cmp(hash)).ok()?;
+        Some(&candidates[idx])
+    }
+
     pub fn write_index(&self, path: impl AsRef<Path>) -> Result<()> {
         let f = File::options()
             .read(true)
@@ -343,6 +308,7 @@ impl Pack {
             .open(path.as_ref())
             .context("create index file")?;
         let mut writer = BufWriter::new(f);
+        let contents = self.unique_contents()?;
 
         // We'll always write a version 2 [index][1] file.
         //
@@ -361,29 +327,29 @@ impl Pack {
         // corresponding pack, the first byte of whose object name is less
         // than or equal to N. This is called the first-level fan-out table.
         let mut fan_out = FanOutTable::new();
-        for obj in self.contents.iter() {
+        for obj in contents.iter() {
             fan_out.add(&obj.hash);
         }
-        for freq in fan_out.cum_freq() {
+        for freq in fan_out.to_array() {
             writer.write_all(&freq.to_be_bytes())?;
         }
 
         // 4. (layer 2) table of sorted object names
-        for obj in self.contents.iter() {
-            writer.write_all(&obj.hash.as_bytes())?;
+        for obj in contents.iter() {
+            writer.write_all(obj.hash.as_bytes())?;
         }
 
         // 5. (layer 3) table of cyclic redundancy check (CRC32) values
         //
         // Since packfiles are optimized for usage across a network, these
         // check values allow us to verify that the pack's contents are valid.
-        for obj in self.contents.iter() {
+        for obj in contents.iter() {
             writer.write_all(&obj.crc32.to_be_bytes())?;
         }
 
         // 6. (layer 4) packfile offsets
         let mut large_offsets: Vec<u64> = Vec::new();
-        for obj in self.contents.iter() {
+        for obj in contents.iter() {
             // MSB is reserved for indicating whether this is an offset value
             // in the packfile (MSB = 0), or an offset into layer 5 (MSB = 1)
             if obj.offset <= 0x7f_ff_ff_ff {
@@ -395,7 +361,7 @@ impl Pack {
                     0x7f_ff_ff_ff
                 );
 
-                let layer_5_index = 0x80_00_00_00 & (large_offsets.len() as u32);
+                let layer_5_index = 0x80_00_00_00 | (large_offsets.len() as u32);
                 large_offsets.push(obj.offset as u64);
                 writer.write_all(&layer_5_index.to_be_bytes())?;
             }
@@ -407,7 +373,7 @@ impl Pack {
         }
 
         // 8. packfile checksum
-        writer.write_all(&self.checksum.as_bytes())?;
+        writer.write_all(self.checksum.as_bytes())?;
 
         // 9. index file checksum
         append_checksum(writer.into_inner()?)?;
@@ -415,18 +381,1194 @@ impl Pack {
         Ok(())
     }
 
-    pub fn unpack(&mut self) -> Result<()> {
-        for object in self.contents.iter_mut() {
-            object.inner.hash(true)?;
+    /// Write a [reverse index][1] for the pack, which lists the position (in
+    /// the idx file) of each object in the order they appear in the pack, so
+    /// an offset can be mapped back to its object with a binary search.
+    ///
+    /// [1]: https://git-scm.com/docs/gitformat-pack#_pack_rev_files_have_the_format
+    pub fn write_reverse_index(&self, path: impl AsRef<Path>) -> Result<()> {
+        let f = File::options()
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(path.as_ref())
+            .context("create reverse index file")?;
+        let mut writer = BufWriter::new(f);
+        let contents = self.unique_contents()?;
+
+        // 1. header (magic number, version, and hash function id)
+        writer.write_all(RIDX_MAGIC_NUM)?;
+        writer.write_all(&RIDX_VERSION.to_be_bytes())?;
+        writer.write_all(&self.checksum.algorithm().format_id().to_be_bytes())?;
+
+        // 2. idx positions, sorted by the offset of their object in the pack
+        let mut positions: Vec<u32> = (0..contents.len() as u32).collect();
+        positions.sort_by_key(|&i| contents[i as usize].offset);
+        for position in positions {
+            writer.write_all(&position.to_be_bytes())?;
+        }
+
+        // 3. packfile checksum
+        writer.write_all(self.checksum.as_bytes())?;
+
+        // 4. reverse index checksum
+        append_checksum(writer.into_inner()?)?;
+
+        Ok(())
+    }
+
+    /// The objects to list in the pack's index. The same object may
+    /// legitimately appear in a pack more than once, but the index should only
+    /// list it once (keeping the first occurrence); contents that haven't been
+    /// read can't be compared, but the hashes already agree.
+    fn unique_contents(&self) -> Result<Vec<&PackedObject>> {
+        let mut unique_contents: Vec<&PackedObject> = Vec::with_capacity(self.contents.len());
+        for obj in self.contents.iter() {
+            if let Some(prev) = unique_contents.last() {
+                if prev.hash == obj.hash {
+                    let differ = match (&prev.contents, &obj.contents) {
+                        (PackedContents::Loaded(a), PackedContents::Loaded(b)) => {
+                            a.contents.get_ref() != b.contents.get_ref()
+                        }
+                        _ => false,
+                    };
+                    if prev.object_type != obj.object_type || differ {
+                        eyre::bail!("hash collision: found different objects named {}", obj.hash);
+                    }
+                    continue;
+                }
+            }
+            unique_contents.push(obj);
+        }
+
+        Ok(unique_contents)
+    }
+}
+
+impl PackedObject {
+    /// The contents of the object, decompressing them from the pack first if
+    /// they weren't kept when it was read.
+    pub fn inner(&mut self) -> Result<&mut ObjectBuf<InMemoryReader>> {
+        let contents = match &self.contents {
+            PackedContents::Loaded(_) => None,
+            PackedContents::Deferred {
+                pack_path,
+                data_offset,
+            } => {
+                let f = File::open(pack_path.as_path()).context("open packfile")?;
+                let mut parser = Parser::new(BufReader::new(f));
+                parser
+                    .seek(SeekFrom::Start(*data_offset as _))
+                    .context("seek to object contents")?;
+                let (_, contents) = parser
+                    .split_off_decode(self.inflated_size)
+                    .with_context(|| format!("decompress object {}", self.hash))?;
+                Some(contents)
+            }
+            PackedContents::Indexed(index) => {
+                let (_, contents) = index
+                    .read_object(self.offset)
+                    .with_context(|| format!("read object {}", self.hash))?;
+                Some(Parser::new(Cursor::new(contents)))
+            }
+        };
+        if let Some(contents) = contents {
+            self.contents = PackedContents::Loaded(ObjectBuf {
+                object_type: self.object_type,
+                content_len: self.inflated_size,
+                contents,
+            });
+        }
+
+        match &mut self.contents {
+            PackedContents::Loaded(object) => Ok(object),
+            _ => unreachable!("contents were just loaded"),
+        }
+    }
+
+    /// Like [`PackedObject::inner`], but take ownership of the contents.
+    pub fn into_inner(mut self) -> Result<ObjectBuf<InMemoryReader>> {
+        self.inner()?;
+        match self.contents {
+            PackedContents::Loaded(object) => Ok(object),
+            _ => unreachable!("contents were just loaded"),
+        }
+    }
+}
+
+/// Reads the objects out of a packfile one at a time, rather than holding the
+/// whole pack in memory like [`Pack::open`] does.
+///
+/// Objects are yielded in the order they appear in the pack, except that a
+/// delta is only yielded once its base has been. Deltas whose base comes later
+/// in the pack are held until it's reached, and bases that have already been
+/// yielded are read back from the pack when they're needed.
+pub struct PackStream<'a> {
+    parser: Parser<BufReader<File>>,
+    path: Rc<PathBuf>,
+    /// Whether to skip keeping the contents of whole objects.
+    lazy: bool,
+    file_size: usize,
+    algorithm: HashAlgorithm,
+    pack_hasher: Option<Hasher>,
+    progress: Option<&'a mut ProgressFn<'a>>,
+
+    pub version: u32,
+    pub obj_count: u32,
+    checksum: Option<ObjectHash>,
+
+    /// The offset of the next entry to read.
+    offset: usize,
+    indexed: usize,
+    deltas: usize,
+    resolved: usize,
+
+    /// The offset of every object yielded so far, so it can be read back.
+    seen: HashMap<ObjectHash, usize>,
+    base_cache: DeltaBaseCache,
+    /// Deltas that are waiting for their base, by the base's hash.
+    pending: HashMap<ObjectHash, Vec<PendingDelta>>,
+    /// Objects that are ready to be yielded, along with any deltas that
+    /// couldn't be resolved (which are reported without stopping the stream).
+    ready: VecDeque<Result<PackedObject>>,
+    done: bool,
+}
+
+impl<'a> PackStream<'a> {
+    /// Open a packfile for streaming, reading just its header.
+    ///
+    /// Delta bases that are read back from the pack are cached, up to the
+    /// options' `cache_limit` bytes, so that bases shared by many deltas don't
+    /// need to be rebuilt for each one.
+    ///
+    /// With the `lazy` option, whole objects are hashed as they're
+    /// decompressed but their contents aren't kept; [`PackedObject::inner`]
+    /// reads them back from the pack if they turn out to be needed. Deltas
+    /// still have to be applied (and kept) to find their hashes.
+    pub fn open(
+        path: impl AsRef<Path>,
+        progress: Option<&'a mut ProgressFn<'a>>,
+        options: OpenOptions,
+    ) -> Result<Self> {
+        let f = File::open(path.as_ref()).context("open packfile")?;
+        let file_size = f.metadata()?.len() as usize;
+        let reader = BufReader::new(f);
+        let mut parser = Parser::new(reader);
+
+        // first, verify that the magic header is present and well-formed
+        let header = parser.read_bytes::<4>()?;
+        if &header != PACK_HEADER {
+            eyre::bail!(
+                "invalid header; expected {:?}, got {:?}",
+                PACK_HEADER,
+                header
+            );
+        }
+
+        // the checksum at the end of the packfile is a hash of everything
+        // before it (20 bytes for SHA-1, 32 for SHA-256); rather than reading
+        // the whole file up front to verify it, each entry's bytes are hashed
+        // as they're read back for their CRC32
+        let algorithm = HashAlgorithm::current()?;
+        let mut pack_hasher = (!options.skip_checksum).then(|| algorithm.hasher());
+
+        let version_bytes = parser.read_bytes::<4>().context("parse packfile version")?;
+        let version = u32::from_be_bytes(version_bytes);
+
+        let obj_count_bytes = parser
+            .read_bytes::<4>()
+            .context("parse packfile object count")?;
+        let obj_count = u32::from_be_bytes(obj_count_bytes);
+
+        if let Some(hasher) = pack_hasher.as_mut() {
+            hasher.update(&header);
+            hasher.update(&version_bytes);
+            hasher.update(&obj_count_bytes);
+        }
+
+        Ok(Self {
+            parser,
+            path: Rc::new(path.as_ref().to_owned()),
+            lazy: options.lazy,
+            file_size,
+            algorithm,
+            pack_hasher,
+            progress,
+            version,
+            obj_count,
+            checksum: None,
+            offset: 12, // 4 + 4 + 4
+            indexed: 0,
+            deltas: 0,
+            resolved: 0,
+            seen: HashMap::new(),
+            base_cache: DeltaBaseCache::new(options.cache_limit),
+            pending: HashMap::new(),
+            ready: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    /// Write every object in the pack as a loose object in the given objects
+    /// directory. An object that can't be read or written doesn't stop the
+    /// rest from being unpacked; it's listed in the report instead, so a pack
+    /// that's only partly corrupt still yields whatever can be recovered.
+    pub fn unpack_into(self, objects_dir: &Path) -> UnpackReport {
+        let mut report = UnpackReport::default();
+        for object in self {
+            let mut object = match object {
+                Ok(object) => object,
+                Err(error) => {
+                    report.failed.push(UnpackFailure { hash: None, error });
+                    continue;
+                }
+            };
+
+            match object
+                .inner()
+                .and_then(|object| object.hash_into(objects_dir))
+            {
+                Ok(hash) => report.unpacked.push(hash),
+                Err(error) => report.failed.push(UnpackFailure {
+                    hash: Some(object.hash),
+                    error,
+                }),
+            }
+        }
+        report
+    }
+
+    /// The pack's checksum, once every object has been read.
+    pub fn checksum(&self) -> Option<&ObjectHash> {
+        self.checksum.as_ref()
+    }
+
+    /// Read entries until there's something ready to be yielded, or the end
+    /// of the pack is reached.
+    fn fill_ready(&mut self) -> Result<()> {
+        let hash_len = self.algorithm.digest_len();
+        loop {
+            if !self.ready.is_empty() || self.done {
+                return Ok(());
+            }
+
+            // the final bytes of a packfile contain a hash of its contents
+            if self.offset + hash_len >= self.file_size {
+                self.finish()?;
+                continue;
+            }
+
+            let offset = self.offset;
+            self.parser
+                .seek(SeekFrom::Start(offset as _))
+                .context("seek to next entry")?;
+            let (entry, len) = parse_entry(&mut self.parser, self.algorithm, self.lazy)
+                .with_context(|| format!("read object at offset {offset}"))?;
+            let crc32 = read_entry_crc32(
+                &mut self.parser,
+                offset,
+                len as u64,
+                self.pack_hasher.as_mut(),
+            )?;
+            self.offset += len;
+
+            self.indexed += 1;
+            if let Some(progress) = self.progress.as_mut() {
+                progress("Indexing objects", self.indexed, self.obj_count as _);
+            }
+
+            match entry {
+                PackEntry::Whole(mut object) => {
+                    let hash = object.hash(false).context("hash object contents")?;
+                    object.contents.reset();
+
+                    self.push_ready(PackedObject {
+                        hash,
+                        crc32,
+                        inflated_size: object.content_len,
+                        packed_size: len,
+                        offset,
+                        depth: 0,
+                        object_type: object.object_type,
+                        contents: PackedContents::Loaded(object),
+                    })?;
+                }
+                PackEntry::Hashed {
+                    object_type,
+                    size,
+                    hash,
+                    header_len,
+                } => {
+                    self.push_ready(PackedObject {
+                        hash,
+                        crc32,
+                        inflated_size: size,
+                        packed_size: len,
+                        offset,
+                        depth: 0,
+                        object_type,
+                        contents: PackedContents::Deferred {
+                            pack_path: self.path.clone(),
+                            data_offset: offset + header_len,
+                        },
+                    })?;
+                }
+                PackEntry::RefDelta {
+                    base_hash,
+                    instructions,
+                    size_new,
+                } => {
+                    self.deltas += 1;
+                    let delta = PendingDelta {
+                        base_hash,
+                        instructions,
+                        size_new,
+                        crc32,
+                        offset,
+                        packed_size: len,
+                    };
+
+                    match self.seen.get(&delta.base_hash) {
+                        Some(&base_offset) => {
+                            let (base_type, base_contents, base_depth) =
+                                self.read_base(base_offset)?;
+                            if let Some(object) =
+                                self.resolve(delta, base_type, &base_contents, base_depth)
+                            {
+                                self.push_ready(object)?;
+                            }
+                        }
+                        None => self
+                            .pending
+                            .entry(delta.base_hash.clone())
+                            .or_default()
+                            .push(delta),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Queue an object to be yielded, along with any pending deltas that can
+    /// now be resolved against it.
+    fn push_ready(&mut self, object: PackedObject) -> Result<()> {
+        let mut queue = vec![object];
+        while let Some(object) = queue.pop() {
+            self.seen
+                .entry(object.hash.clone())
+                .or_insert(object.offset);
+
+            let pending = self.pending.remove(&object.hash).unwrap_or_default();
+            if !pending.is_empty() {
+                let deferred_base;
+                let base_contents: &[u8] = match &object.contents {
+                    PackedContents::Loaded(inner) => inner.contents.get_ref(),
+                    PackedContents::Deferred { .. } | PackedContents::Indexed(_) => {
+                        deferred_base = self.read_base(object.offset)?.1;
+                        &deferred_base
+                    }
+                };
+                for delta in pending {
+                    queue.extend(self.resolve(
+                        delta,
+                        object.object_type,
+                        base_contents,
+                        object.depth,
+                    ));
+                }
+            }
+
+            self.ready.push_back(Ok(object));
+        }
+
+        Ok(())
+    }
+
+    /// Apply a delta to its base. A delta that can't be applied is queued to
+    /// be reported in its place, rather than ending the stream, since the
+    /// rest of the pack may still be fine.
+    fn resolve(
+        &mut self,
+        delta: PendingDelta,
+        base_type: ObjectType,
+        base_contents: &[u8],
+        base_depth: usize,
+    ) -> Option<PackedObject> {
+        let offset = delta.offset;
+        match delta.resolve(base_type, base_contents, base_depth) {
+            Ok(object) => {
+                self.resolved += 1;
+                Some(object)
+            }
+            Err(err) => {
+                let err = err.wrap_err(format!("resolve delta at offset {offset}"));
+                self.ready.push_back(Err(err));
+                None
+            }
+        }
+    }
+
+    /// Read back an object that's already been yielded, to use as a delta
+    /// base, from the cache if it's there.
+    fn read_base(&mut self, offset: usize) -> Result<DeltaBase> {
+        if let Some(base) = self.base_cache.get(offset) {
+            return Ok(base);
+        }
+
+        self.parser
+            .seek(SeekFrom::Start(offset as _))
+            .context("seek to delta base")?;
+        let base = match parse_entry(&mut self.parser, self.algorithm, false)?.0 {
+            PackEntry::Whole(object) => (
+                object.object_type,
+                Rc::new(object.contents.into_inner().into_inner()),
+                0,
+            ),
+            PackEntry::Hashed { .. } => unreachable!("base contents are always kept"),
+            PackEntry::RefDelta {
+                base_hash,
+                instructions,
+                size_new,
+            } => {
+                let (base_type, base_contents, base_depth) = match self.seen.get(&base_hash) {
+                    Some(&base_offset) => self.read_base(base_offset)?,
+                    None => read_local_base(&base_hash)?,
+                };
+                let contents = apply_delta(&instructions, &base_contents, size_new)?;
+                (base_type, Rc::new(contents), base_depth + 1)
+            }
+        };
+
+        self.base_cache.insert(offset, base.clone());
+        Ok(base)
+    }
+
+    /// Check the pack's trailing checksum, then resolve any deltas whose base
+    /// wasn't in the pack.
+    fn finish(&mut self) -> Result<()> {
+        if self.offset + self.algorithm.digest_len() != self.file_size {
+            eyre::bail!("packfile is truncated");
+        }
+
+        self.parser
+            .seek(SeekFrom::Start(self.offset as _))
+            .context("seek to pack checksum")?;
+        let checksum = self
+            .parser
+            .read_hash(self.algorithm)
+            .context("read pack checksum")?;
+        if let Some(hasher) = self.pack_hasher.take() {
+            let actual = hasher.finalize();
+            if actual != checksum {
+                eyre::bail!(
+                    "checksums don't match (wanted {}, got {})",
+                    checksum,
+                    actual
+                );
+            }
+        }
+        self.checksum = Some(checksum);
+
+        // thin packs (sent in response to a fetch) may use objects we already
+        // have as delta bases without including them, so fall back to the
+        // local object store; the resolved objects are stored in full, so a
+        // pack written from them won't be thin
+        let local_bases: Vec<ObjectHash> = self
+            .pending
+            .keys()
+            .filter(|hash| ObjectBuf::exists(hash.as_hex()))
+            .cloned()
+            .collect();
+        for base_hash in local_bases {
+            let (base_type, base_contents, _) = read_local_base(&base_hash)?;
+            for delta in self.pending.remove(&base_hash).unwrap_or_default() {
+                if let Some(object) = self.resolve(delta, base_type, &base_contents, 0) {
+                    self.push_ready(object)?;
+                }
+            }
+        }
+        if let Some(base_hash) = self.pending.keys().next() {
+            eyre::bail!("find delta base {base_hash} (not in pack)");
+        }
+
+        if self.deltas > 0 {
+            if let Some(progress) = self.progress.as_mut() {
+                progress("Resolving deltas", self.resolved, self.deltas);
+            }
         }
 
+        self.done = true;
         Ok(())
     }
 }
 
+impl Iterator for PackStream<'_> {
+    type Item = Result<PackedObject>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.fill_ready() {
+            Ok(()) => self.ready.pop_front(),
+            Err(err) => {
+                // there's no picking back up after a malformed entry, but
+                // anything that was already read is still yielded after it
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// A pack entry as it's stored, before any delta has been applied.
+enum PackEntry {
+    Whole(ObjectBuf<InMemoryReader>),
+    /// A whole object whose contents were hashed but not kept; they start
+    /// `header_len` bytes into the entry.
+    Hashed {
+        object_type: ObjectType,
+        size: usize,
+        hash: ObjectHash,
+        header_len: usize,
+    },
+    RefDelta {
+        base_hash: ObjectHash,
+        instructions: Vec<DeltaInstruction>,
+        size_new: usize,
+    },
+}
+
+/// Parse the pack entry at the parser's current position, returning it along
+/// with its length in the pack. With `lazy`, whole objects are only hashed.
+fn parse_entry(
+    parser: &mut Parser<BufReader<File>>,
+    algorithm: HashAlgorithm,
+    lazy: bool,
+) -> Result<(PackEntry, usize)> {
+    // 1 0 0 1 1 1 1 0   0 0 0 0 1 1 1 1
+    // ^ |-t-| |--A--|   ^ |-----B-----|
+    //
+    // the MSB of each byte tells whether to continue parsing (variable len encoding)
+    //
+    // the first 3 bits of the result indicate the type (see below); the remaining
+    // bits should be concatenated, in reverse order (A is the low bits, B is high),
+    // to form the actual value: 0b1111_1110
+    let size_bytes = parser.parse_size_enc_bytes()?;
+
+    // Valid object types are:
+    //
+    //   - OBJ_COMMIT (1)
+    //   - OBJ_TREE (2)
+    //   - OBJ_BLOB (3)
+    //   - OBJ_TAG (4)
+    //   - OBJ_OFS_DELTA (6)
+    //   - OBJ_REF_DELTA (7)
+    //
+    // Type 5 is reserved for future expansion. Type 0 is invalid.
+    let obj_type = (size_bytes[0] & 0b0111_0000) >> 4;
+
+    let mut size: usize = (size_bytes[0] & 0b0000_1111) as usize;
+    size = size_enc_init(&size_bytes[1..], size, 4);
+
+    match obj_type {
+        0 => eyre::bail!("invalid object type (invalid)"),
+
+        1..=4 => {
+            let object_type = match obj_type {
+                1 => ObjectType::Commit,
+                2 => ObjectType::Tree,
+                3 => ObjectType::Blob,
+                4 => ObjectType::Tag,
+                _ => unreachable!("only 1..=3 available in parent match"),
+            };
+
+            if lazy {
+                let mut hasher = algorithm.hasher();
+                write!(hasher, "{object_type} {size}\0")?;
+                let consumed = parser
+                    .decode_into(size, &mut hasher)
+                    .context("hash object contents")?;
+
+                return Ok((
+                    PackEntry::Hashed {
+                        object_type,
+                        size,
+                        hash: hasher.finalize(),
+                        header_len: size_bytes.len(),
+                    },
+                    size_bytes.len() + consumed as usize,
+                ));
+            }
+
+            let (consumed, contents) = parser.split_off_decode(size)?;
+            let object = ObjectBuf {
+                object_type,
+                content_len: size,
+                contents,
+            };
+
+            Ok((
+                PackEntry::Whole(object),
+                size_bytes.len() + consumed as usize,
+            ))
+        }
+
+        5 => eyre::bail!("invalid object type (reserved)"),
+
+        // TODO: figure out OFS encoding
+        // OFS delta encodes the offset of the object in the pack
+        6 => todo!("OFS delta encoding"),
+
+        // REF delta uses the object's hash
+        7 => {
+            let base_hash = parser.read_hash(algorithm)?;
+
+            let (consumed, mut contents) = parser.split_off_decode(size)?;
+
+            // we don't need to know this but we do need to parse over it
+            let size_base_bytes = contents.parse_size_enc_bytes()?;
+            let _size_base = size_enc(&size_base_bytes);
+
+            let size_new_bytes = contents.parse_size_enc_bytes()?;
+            let size_new = size_enc(&size_new_bytes);
+
+            let instructions = parse_delta_instructions(&mut contents)?;
+
+            Ok((
+                PackEntry::RefDelta {
+                    base_hash,
+                    instructions,
+                    size_new,
+                },
+                size_bytes.len() + algorithm.digest_len() + consumed as usize,
+            ))
+        }
+
+        _ => eyre::bail!("invalid object type (out of range)"),
+    }
+}
+
+/// Parse the copy and add instructions that make up the rest of a delta, once
+/// the sizes of its base and result have been read.
+fn parse_delta_instructions(
+    contents: &mut Parser<InMemoryReader>,
+) -> Result<Vec<DeltaInstruction>> {
+    let mut instructions = Vec::new();
+    while !contents.at_eof()? {
+        let instr = contents.read_byte()?;
+
+        if instr & 0x80 == 0 {
+            let size = instr /* & 0x7f */;
+            let mut data = vec![0; size as _];
+            contents.read_exact(&mut data)?;
+            instructions.push(DeltaInstruction::Add(data));
+        } else {
+            // > In its most compact form, this instruction only takes up one byte (0x80)
+            // > with both offset and size omitted, which will have default values zero.
+            // > There is another exception: size zero is automatically converted to 0x10000.
+
+            let mut offset: u32 = 0;
+            for (cond, shift) in [
+                (instr & 0b0001, 0),
+                (instr & 0b0010, 8),
+                (instr & 0b0100, 16),
+                (instr & 0b1000, 24),
+            ] {
+                if cond != 0 {
+                    offset |= (contents.read_byte()? as u32) << shift;
+                }
+            }
+
+            let mut size: u32 = 0;
+            for (cond, shift) in [
+                (instr & 0b0001_0000, 0),
+                (instr & 0b0010_0000, 8),
+                (instr & 0b0100_0000, 16),
+            ] {
+                if cond != 0 {
+                    size |= (contents.read_byte()? as u32) << shift;
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+
+            instructions.push(DeltaInstruction::Copy {
+                offset: offset as _,
+                size: size as _,
+            });
+        }
+    }
+
+    Ok(instructions)
+}
+
+/// Read a delta base that isn't in the pack from the local object store.
+fn read_local_base(hash: &ObjectHash) -> Result<DeltaBase> {
+    let mut base = ObjectBuf::read_at_hash(hash.as_hex())
+        .with_context(|| format!("find delta base {hash} (not in pack)"))?;
+    let contents = base.read_to_vec()?;
+    Ok((base.object_type, Rc::new(contents), 0))
+}
+
+/// The size of the cache of delta bases to keep when reading a pack, as set
+/// by `core.deltaBaseCacheLimit` (which may use a `k`, `m`, or `g` suffix).
+pub fn delta_base_cache_limit(config: &Config) -> Result<usize> {
+    Ok(config
+        .get_usize("core.deltabasecachelimit")?
+        .unwrap_or(DEFAULT_DELTA_BASE_CACHE_LIMIT))
+}
+
+/// An object's type, contents, and delta depth, for use as a delta base.
+type DeltaBase = (ObjectType, Rc<Vec<u8>>, usize);
+
+/// Delta bases that have been read back from a pack, by their offset in it,
+/// up to a total size in bytes. The least recently used are evicted first.
+struct DeltaBaseCache {
+    limit: usize,
+    used: usize,
+    /// Incremented on each access, to order entries by when they were used.
+    clock: u64,
+    entries: HashMap<usize, (DeltaBase, u64)>,
+    /// The offset of each cached base, by when it was last used.
+    by_use: BTreeMap<u64, usize>,
+}
+
+impl DeltaBaseCache {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            used: 0,
+            clock: 0,
+            entries: HashMap::new(),
+            by_use: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, offset: usize) -> Option<DeltaBase> {
+        let (base, last_used) = self.entries.get_mut(&offset)?;
+        self.by_use.remove(last_used);
+        self.clock += 1;
+        *last_used = self.clock;
+        self.by_use.insert(self.clock, offset);
+        Some(base.clone())
+    }
+
+    fn insert(&mut self, offset: usize, base: DeltaBase) {
+        let size = base.1.len();
+        if size > self.limit || self.entries.contains_key(&offset) {
+            return;
+        }
+
+        while self.used + size > self.limit {
+            let Some((_, evicted)) = self.by_use.pop_first() else {
+                break;
+            };
+            if let Some(((_, contents, _), _)) = self.entries.remove(&evicted) {
+                self.used -= contents.len();
+            }
+        }
+
+        self.clock += 1;
+        self.by_use.insert(self.clock, offset);
+        self.entries.insert(offset, (base, self.clock));
+        self.used += size;
+    }
+}
+
+/// The contents of a pack's `.idx` file, which maps the hash of each object in
+/// the pack to its offset, without reading the pack itself.
+#[derive(Debug)]
+pub struct PackIndex {
+    /// The path of the pack this index belongs to.
+    pub pack_path: PathBuf,
+    pub fan_out: [u32; 256],
+    /// The hash of every object in the pack, in ascending order.
+    pub names: Vec<ObjectHash>,
+    /// The CRC32 of each object's packed data, in the same order as `names`
+    /// (version 1 indexes don't include these).
+    pub crcs: Option<Vec<u32>>,
+    /// The offset of each object in the pack, in the same order as `names`.
+    pub offsets: Vec<usize>,
+    pub pack_checksum: ObjectHash,
+}
+
+/// The object names, CRCs, and offsets read from an idx file.
+type IdxEntries = (Vec<ObjectHash>, Option<Vec<u32>>, Vec<usize>);
+
+/// The header of an object in a pack, which says either what type of object
+/// it is or where to find the base it's a delta against.
+#[derive(Debug)]
+pub enum PackedHeader {
+    Whole(ObjectType),
+    /// A delta against the object at the given offset in the same pack.
+    OfsDelta(usize),
+    /// A delta against the object with the given hash.
+    RefDelta(ObjectHash),
+}
+
+impl PackIndex {
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+
+        let (file_size, mut parser) = {
+            let f = File::open(path).context("open index file")?;
+            let file_size = f.metadata()?.len() as usize;
+            let reader = BufReader::new(f);
+            (file_size, Parser::new(reader))
+        };
+
+        let header = parser.read_bytes::<4>()?;
+
+        let (_, mut parser) = parser.verify_checksum(file_size)?;
+        let algorithm = HashAlgorithm::current()?;
+        let hash_len = algorithm.digest_len();
+
+        // version 1 idx files don't have a header at all, and instead start
+        // right away with the fan-out table
+        let version = match header {
+            IDX_MAGIC_NUM => parser.parse_usize_exact::<4>()? as u32,
+            _ => 1,
+        };
+        if !matches!(version, 1 | 2) {
+            eyre::bail!("unsupported idx file version {version}");
+        }
+
+        // fan-out table; since each entry is a cumulative count, they can
+        // never decrease, and the last one is the total number of objects
+        let mut fan_out = [0; 256];
+        let first_entry = match version {
+            1 => {
+                fan_out[0] = u32::from_be_bytes(header);
+                1
+            }
+            _ => 0,
+        };
+        for entry in fan_out[first_entry..].iter_mut() {
+            *entry = parser
+                .parse_usize_exact::<4>()
+                .context("read fan-out table")? as u32;
+        }
+        if fan_out.windows(2).any(|pair| pair[0] > pair[1]) {
+            eyre::bail!("corrupt idx file: fan-out table entries decrease");
+        }
+
+        let obj_count = fan_out[255];
+        tracing::debug!("reading version {version} idx file with {obj_count} objects");
+
+        // each object needs (at least) a name and offset (plus a CRC, from
+        // version 2 on), followed by the pack and idx checksums
+        let min_size = match version {
+            1 => 256 * 4 + obj_count as usize * (4 + hash_len) + 2 * hash_len,
+            _ => 8 + 256 * 4 + obj_count as usize * (hash_len + 4 + 4) + 2 * hash_len,
+        };
+        if file_size < min_size {
+            eyre::bail!("corrupt idx file: too short to hold {obj_count} objects");
+        }
+
+        let (names, crcs, offsets) = match version {
+            1 => Self::read_v1_entries(&mut parser, obj_count, algorithm)?,
+            _ => Self::read_v2_entries(&mut parser, obj_count, algorithm)?,
+        };
+        if names.windows(2).any(|pair| pair[0] >= pair[1]) {
+            eyre::bail!("corrupt idx file: object names aren't sorted");
+        }
+
+        // only the last fan-out entry was needed to know how many names to
+        // read, so make sure the rest agree with the names that were read
+        let mut expected = FanOutTable::new();
+        for name in names.iter() {
+            expected.add(name);
+        }
+        if expected.to_array() != fan_out {
+            eyre::bail!("corrupt idx file: fan-out table doesn't match object names");
+        }
+
+        let pack_checksum = parser.read_hash(algorithm).context("read pack checksum")?;
+
+        Ok(Self {
+            pack_path: path.with_extension("pack"),
+            fan_out,
+            names,
+            crcs,
+            offsets,
+            pack_checksum,
+        })
+    }
+
+    /// Read the entries of a version 1 idx file, which are each an offset
+    /// followed by an object name (and don't include a CRC).
+    fn read_v1_entries<R: BufRead + Debug>(
+        parser: &mut Parser<R>,
+        obj_count: u32,
+        algorithm: HashAlgorithm,
+    ) -> Result<IdxEntries> {
+        let mut names = Vec::with_capacity(obj_count as usize);
+        let mut offsets = Vec::with_capacity(obj_count as usize);
+        for _ in 0..obj_count {
+            offsets.push(parser.parse_usize_exact::<4>().context("read offset")?);
+            names.push(parser.read_hash(algorithm).context("read object name")?);
+        }
+
+        Ok((names, None, offsets))
+    }
+
+    /// Read the entries of a version 2 idx file, which are stored as separate
+    /// tables of object names, CRCs, and offsets.
+    fn read_v2_entries<R: BufRead + Debug>(
+        parser: &mut Parser<R>,
+        obj_count: u32,
+        algorithm: HashAlgorithm,
+    ) -> Result<IdxEntries> {
+        // (layer 2) sorted object names
+        let mut names = Vec::with_capacity(obj_count as usize);
+        for _ in 0..obj_count {
+            names.push(parser.read_hash(algorithm).context("read object name")?);
+        }
+
+        // (layer 3) CRC32 values
+        let mut crcs = Vec::with_capacity(obj_count as usize);
+        for _ in 0..obj_count {
+            crcs.push(parser.parse_usize_exact::<4>().context("read CRC32")? as u32);
+        }
+
+        // (layer 4) packfile offsets, where a set MSB means the rest of the
+        // value is an index into the table of large offsets (layer 5)
+        let mut offsets = Vec::with_capacity(obj_count as usize);
+        for _ in 0..obj_count {
+            offsets.push(parser.parse_usize_exact::<4>().context("read offset")? as u32);
+        }
+        let large_count = offsets.iter().filter(|&&o| o & 0x80_00_00_00 != 0).count();
+        let mut large_offsets = Vec::with_capacity(large_count);
+        for _ in 0..large_count {
+            large_offsets.push(
+                parser
+                    .parse_usize_exact::<8>()
+                    .context("read large offset")?,
+            );
+        }
+        let offsets = offsets
+            .into_iter()
+            .map(|offset| match offset & 0x80_00_00_00 {
+                0 => Ok(offset as usize),
+                _ => large_offsets
+                    .get((offset & 0x7f_ff_ff_ff) as usize)
+                    .copied()
+                    .ok_or_else(|| eyre::eyre!("corrupt idx file: large offset out of range")),
+            })
+            .collect::<Result<Vec<usize>>>()?;
+
+        Ok((names, Some(crcs), offsets))
+    }
+
+    /// Find the offset of an object in the pack, using the fan-out table to
+    /// narrow down the range of candidates before binary searching them.
+    pub fn find_offset(&self, hash: &ObjectHash) -> Option<usize> {
+        let first_byte = *hash.as_bytes().first()? as usize;
+        let start = match first_byte {
+            0 => 0,
+            _ => self.fan_out[first_byte - 1] as usize,
+        };
+        let end = self.fan_out[first_byte] as usize;
+
+        let idx = self.names.get(start..end)?.binary_search(hash).ok()?;
+        Some(self.offsets[start + idx])
+    }
+
+    /// Read just the header of the object at the given offset in the pack,
+    /// without decompressing its contents.
+    pub fn read_header(&self, offset: usize) -> Result<PackedHeader> {
+        Ok(self.read_entry_header(offset)?.0)
+    }
+
+    /// Like [`PackIndex::read_header`], but also return the size recorded in
+    /// the header (for deltas, the size of the delta itself, rather than the
+    /// object it produces) along with a parser positioned at the start of the
+    /// entry's compressed data.
+    fn read_entry_header(
+        &self,
+        offset: usize,
+    ) -> Result<(PackedHeader, usize, Parser<BufReader<File>>)> {
+        let f = File::open(&self.pack_path).context("open pack file")?;
+        let mut parser = Parser::new(BufReader::new(f));
+        parser
+            .seek(SeekFrom::Start(offset as _))
+            .context("seek to object")?;
+
+        let size_bytes = parser.parse_size_enc_bytes()?;
+        let size = size_enc_init(&size_bytes[1..], (size_bytes[0] & 0b0000_1111) as usize, 4);
+        let header = match (size_bytes[0] & 0b0111_0000) >> 4 {
+            1 => PackedHeader::Whole(ObjectType::Commit),
+            2 => PackedHeader::Whole(ObjectType::Tree),
+            3 => PackedHeader::Whole(ObjectType::Blob),
+            4 => PackedHeader::Whole(ObjectType::Tag),
+            6 => {
+                // the base's offset is encoded relative to this object, with
+                // each continuation byte adding one before shifting
+                let mut byte = parser.read_byte()?;
+                let mut relative = (byte & 0x7f) as usize;
+                while byte & 0x80 != 0 {
+                    byte = parser.read_byte()?;
+                    relative = ((relative + 1) << 7) | (byte & 0x7f) as usize;
+                }
+                let base_offset = offset.checked_sub(relative).filter(|_| relative > 0);
+                PackedHeader::OfsDelta(base_offset.ok_or_else(|| {
+                    eyre::eyre!("corrupt pack: delta base offset out of range at {offset}")
+                })?)
+            }
+            7 => PackedHeader::RefDelta(parser.read_hash(self.pack_checksum.algorithm())?),
+            obj_type => eyre::bail!("invalid object type {obj_type} at offset {offset}"),
+        };
+
+        Ok((header, size, parser))
+    }
+
+    /// Find the type of the object at the given offset, along with the length
+    /// of the chain of deltas leading to it, by following delta headers back
+    /// to a whole object. Results are remembered in `known`, by offset, so
+    /// chains that share bases are only followed once.
+    fn resolve_type(
+        &self,
+        offset: usize,
+        known: &mut HashMap<usize, (ObjectType, usize)>,
+    ) -> Result<(ObjectType, usize)> {
+        if let Some(&resolved) = known.get(&offset) {
+            return Ok(resolved);
+        }
+
+        let resolved = match self.read_header(offset)? {
+            PackedHeader::Whole(object_type) => (object_type, 0),
+            PackedHeader::OfsDelta(base_offset) => {
+                let (object_type, depth) = self.resolve_type(base_offset, known)?;
+                (object_type, depth + 1)
+            }
+            PackedHeader::RefDelta(base) => match self.find_offset(&base) {
+                Some(base_offset) => {
+                    let (object_type, depth) = self.resolve_type(base_offset, known)?;
+                    (object_type, depth + 1)
+                }
+                None => {
+                    let base = ObjectBuf::read_at_hash(base.as_hex())
+                        .with_context(|| format!("find delta base {base} (not in pack)"))?;
+                    (base.object_type, 1)
+                }
+            },
+        };
+        known.insert(offset, resolved);
+
+        Ok(resolved)
+    }
+
+    /// The size of the object at the given offset, once decompressed (and,
+    /// for deltas, reconstructed), without decompressing any more of it than
+    /// its header.
+    fn inflated_size(&self, offset: usize) -> Result<usize> {
+        let (header, size, mut parser) = self.read_entry_header(offset)?;
+        if let PackedHeader::Whole(_) = header {
+            return Ok(size);
+        }
+
+        // a delta starts with the sizes of its base and of the object it
+        // produces, which only take up the first few bytes once decompressed
+        let decoder = flate2::bufread::ZlibDecoder::new(parser.inner_mut());
+        let mut delta = Parser::new(BufReader::new(decoder));
+        delta
+            .parse_size_enc_bytes()
+            .context("read delta base size")?;
+        let size_new_bytes = delta.parse_size_enc_bytes().context("read delta size")?;
+        Ok(size_enc(&size_new_bytes))
+    }
+
+    /// Read the object at the given offset in the pack, applying any deltas
+    /// needed to reconstruct it.
+    pub(crate) fn read_object(&self, offset: usize) -> Result<(ObjectType, Vec<u8>)> {
+        let (header, size, mut parser) = self.read_entry_header(offset)?;
+        let (_, mut contents) = parser
+            .split_off_decode(size)
+            .with_context(|| format!("decompress object at offset {offset}"))?;
+
+        let (base_type, base_contents) = match header {
+            PackedHeader::Whole(object_type) => {
+                return Ok((object_type, contents.into_inner().into_inner()));
+            }
+            PackedHeader::OfsDelta(base_offset) => self.read_object(base_offset)?,
+            PackedHeader::RefDelta(base) => match self.find_offset(&base) {
+                Some(base_offset) => self.read_object(base_offset)?,
+                None => {
+                    let (base_type, base_contents, _) = read_local_base(&base)?;
+                    (base_type, base_contents.to_vec())
+                }
+            },
+        };
+
+        contents
+            .parse_size_enc_bytes()
+            .context("read delta base size")?;
+        let size_new_bytes = contents.parse_size_enc_bytes().context("read delta size")?;
+        let instructions = parse_delta_instructions(&mut contents)?;
+        let object = apply_delta(&instructions, &base_contents, size_enc(&size_new_bytes))
+            .with_context(|| format!("resolve delta at offset {offset}"))?;
+
+        Ok((base_type, object))
+    }
+}
+
+/// Write a version 2 packfile containing the objects at the given hashes,
+/// followed by its checksum. Objects are always stored whole (no deltas).
+/// Objects are compressed at the level set by `pack.compression` (or
+/// `core.compression`).
+pub fn write_pack<W: Write>(object_hashes: &[String], mut w: W) -> Result<ObjectHash> {
+    let mut hasher = HashAlgorithm::current()?.hasher();
+    let compression = compression_level(
+        &Config::read_default()?,
+        &["pack.compression", "core.compression"],
+    )?;
+
+    {
+        let mut writer = TeeWriter::new(&mut hasher, &mut w);
+
+        // 1. header
+        writer.write_all(PACK_HEADER)?;
+
+        // 2. version
+        writer.write_all(&2u32.to_be_bytes())?;
+
+        // 3. object count
+        writer.write_all(&(object_hashes.len() as u32).to_be_bytes())?;
+
+        // 4. objects
+        for hash in object_hashes {
+            let mut object = ObjectBuf::read_at_hash(hash)?;
+
+            // see `Pack::open` for the list of valid object types
+            let obj_type = match object.object_type {
+                ObjectType::Commit => 1,
+                ObjectType::Tree => 2,
+                ObjectType::Blob => 3,
+                ObjectType::Tag => 4,
+            };
+            writer.write_all(&size_enc_header(obj_type, object.content_len))?;
+
+            let mut encoder = ZlibEncoder::new(&mut writer, compression);
+            std::io::copy(
+                &mut object.contents.inner_mut().take(object.content_len as u64),
+                &mut encoder,
+            )
+            .with_context(|| format!("compress object {hash}"))?;
+            encoder.finish()?;
+        }
+    }
+
+    // 5. checksum
+    let checksum = hasher.finalize();
+    w.write_all(checksum.as_bytes())?;
+
+    Ok(checksum)
+}
+
 /// A table storing the cumulative frequency of hashes in a set that begin
 /// with a byte less than or equal to the current index. Hashes are assumed
-/// to be unique; this must be enforced by the caller.
+/// to be unique; this must be enforced by the caller (`Pack::write_index`
+/// removes any duplicates).
 struct FanOutTable {
     inner: [u32; 256],
     size: u32,
@@ -452,6 +1594,15 @@ impl FanOutTable {
         self.size += 1;
     }
 
+    /// Return the cumulative frequencies as an array, as stored in an idx file.
+    pub fn to_array(&self) -> [u32; 256] {
+        let mut array = [0; 256];
+        for (entry, freq) in array.iter_mut().zip(self.cum_freq()) {
+            *entry = freq;
+        }
+        array
+    }
+
     /// Return the cumulative frequency of all hashes added to the set.
     pub fn cum_freq(&self) -> impl Iterator<Item = u32> + '_ {
         let mut i = 0;
@@ -468,6 +1619,43 @@ impl FanOutTable {
     }
 }
 
+/// Read back the `len` raw bytes of the entry at `offset` to compute its CRC32,
+/// also feeding them to `pack_hasher` (if any) to build up the pack checksum.
+fn read_entry_crc32(
+    parser: &mut Parser<BufReader<File>>,
+    offset: usize,
+    len: u64,
+    pack_hasher: Option<&mut Hasher>,
+) -> Result<u32> {
+    let mut hasher = crc32fast::Hasher::new();
+    parser.seek(SeekFrom::Start(offset as _)).unwrap();
+
+    let mut entry = parser.inner_mut().take(len);
+    match pack_hasher {
+        Some(pack_hasher) => {
+            std::io::copy(&mut entry, &mut TeeWriter::new(&mut hasher, pack_hasher))?
+        }
+        None => std::io::copy(&mut entry, &mut hasher)?,
+    };
+
+    Ok(hasher.finalize())
+}
+
+/// Encode an object's type and decompressed size as a packfile entry header;
+/// this is the inverse of the parsing done in `Pack::open`.
+fn size_enc_header(obj_type: u8, size: usize) -> Vec<u8> {
+    let mut bytes = vec![(obj_type << 4) | (size & 0b0000_1111) as u8];
+    let mut size = size >> 4;
+
+    while size > 0 {
+        *bytes.last_mut().unwrap() |= 0b1000_0000;
+        bytes.push((size & 0b0111_1111) as u8);
+        size >>= 7;
+    }
+
+    bytes
+}
+
 fn size_enc(size_bytes: &[u8]) -> usize {
     size_enc_init(size_bytes, 0, 0)
 }
@@ -483,3 +1671,282 @@ fn size_enc_init(size_bytes: &[u8], init_n: usize, init_shift: usize) -> usize {
 
     n
 }
+
+/// A delta that's been read from the pack, but not yet applied to its base.
+struct PendingDelta {
+    base_hash: ObjectHash,
+    instructions: Vec<DeltaInstruction>,
+    size_new: usize,
+    crc32: u32,
+    offset: usize,
+    packed_size: usize,
+}
+
+impl PendingDelta {
+    fn resolve(
+        self,
+        base_type: ObjectType,
+        base_contents: &[u8],
+        base_depth: usize,
+    ) -> Result<PackedObject> {
+        let size_new = self.size_new;
+        let obj_buf = apply_delta(&self.instructions, base_contents, size_new)?;
+
+        let mut object = ObjectBuf {
+            object_type: base_type,
+            content_len: size_new,
+            contents: Parser::new(Cursor::new(obj_buf)),
+        };
+
+        let hash = object.hash(false).context("hash object contents")?;
+        object.contents.reset();
+
+        Ok(PackedObject {
+            hash,
+            crc32: self.crc32,
+            inflated_size: size_new,
+            packed_size: self.packed_size,
+            offset: self.offset,
+            depth: base_depth + 1,
+            object_type: base_type,
+            contents: PackedContents::Loaded(object),
+        })
+    }
+}
+
+/// Apply a delta's instructions to the contents of its base, producing an
+/// object of `size_new` bytes.
+fn apply_delta(
+    instructions: &[DeltaInstruction],
+    base_contents: &[u8],
+    size_new: usize,
+) -> Result<Vec<u8>> {
+    let mut obj_buf = Vec::with_capacity(size_new);
+    for instr in instructions {
+        let data = match instr {
+            DeltaInstruction::Copy { offset, size } => {
+                check_delta_bounds(*offset, *size, base_contents.len())?;
+                &base_contents[*offset..][..*size]
+            }
+            DeltaInstruction::Add(data) => data.as_slice(),
+        };
+        check_delta_bounds(obj_buf.len(), data.len(), size_new)?;
+        obj_buf.extend_from_slice(data);
+    }
+    if obj_buf.len() != size_new {
+        eyre::bail!(
+            "malformed delta: expected {size_new} bytes but only got {}",
+            obj_buf.len()
+        );
+    }
+
+    Ok(obj_buf)
+}
+
+/// Make sure that `size` bytes starting at `offset` fit within `len` bytes.
+fn check_delta_bounds(offset: usize, size: usize, len: usize) -> Result<()> {
+    if offset.checked_add(size).is_none_or(|end| end > len) {
+        return Err(GitError::MalformedDelta { offset, size, len }.into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write a pack containing each of the given entries, in order. Each is
+    /// the entry's type, the base it's a delta against (for ref deltas), and
+    /// its uncompressed data.
+    fn write_test_pack(path: &Path, entries: &[(u8, Option<&ObjectHash>, &[u8])]) {
+        let mut pack = Vec::new();
+        pack.extend_from_slice(PACK_HEADER);
+        pack.extend_from_slice(&2u32.to_be_bytes());
+        pack.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+        for (obj_type, base, data) in entries {
+            pack.extend_from_slice(&size_enc_header(*obj_type, data.len()));
+            if let Some(base) = base {
+                pack.extend_from_slice(base.as_bytes());
+            }
+            let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).unwrap();
+            pack.extend_from_slice(&encoder.finish().unwrap());
+        }
+        let mut hasher = HashAlgorithm::Sha1.hasher();
+        hasher.update(&pack);
+        pack.extend_from_slice(hasher.finalize().as_bytes());
+
+        std::fs::write(path, pack).unwrap();
+    }
+
+    #[test]
+    fn index_lists_duplicate_objects_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let pack_path = dir.path().join("pack-test.pack");
+        let idx_path = dir.path().join("pack-test.idx");
+        write_test_pack(&pack_path, &[(3, None, b"hello\n"), (3, None, b"hello\n")]);
+
+        let pack = Pack::open(&pack_path, None, OpenOptions::default()).unwrap();
+        assert_eq!(pack.obj_count, 2);
+        assert_eq!(pack.contents.len(), 2);
+
+        pack.write_index(&idx_path).unwrap();
+        let index = PackIndex::read(&idx_path).unwrap();
+        assert_eq!(index.names, [pack.contents[0].hash.clone()]);
+        assert_eq!(index.offsets, [12]);
+        assert_eq!(index.fan_out[255], 1);
+    }
+
+    #[test]
+    fn open_index_reads_objects_lazily() {
+        let dir = tempfile::tempdir().unwrap();
+        let pack_path = dir.path().join("pack-test.pack");
+        let idx_path = dir.path().join("pack-test.idx");
+
+        let mut hasher = HashAlgorithm::Sha1.hasher();
+        hasher.update(b"blob 12\0hello world\n");
+        let base = hasher.finalize();
+        // copy "hello " from the base, then add "there\n"
+        let delta = b"\x0c\x0c\x90\x06\x06there\n";
+        write_test_pack(
+            &pack_path,
+            &[(3, None, b"hello world\n"), (7, Some(&base), delta)],
+        );
+        let options = OpenOptions {
+            lazy: true,
+            ..Default::default()
+        };
+        Pack::open(&pack_path, None, options)
+            .unwrap()
+            .write_index(&idx_path)
+            .unwrap();
+
+        let mut pack = Pack::open_index(&idx_path).unwrap();
+        assert_eq!(pack.obj_count, 2);
+        let delta_obj = pack
+            .contents
+            .iter_mut()
+            .find(|obj| obj.hash != base)
+            .unwrap();
+        assert!(matches!(delta_obj.contents, PackedContents::Indexed(_)));
+        assert_eq!(delta_obj.object_type, ObjectType::Blob);
+        assert_eq!(delta_obj.depth, 1);
+        assert_eq!(delta_obj.inflated_size, 12);
+        assert_eq!(
+            delta_obj.inner().unwrap().contents.get_ref(),
+            b"hello there\n"
+        );
+    }
+
+    #[test]
+    fn packed_only_objects_are_found_and_read() {
+        use crate::object_store::{FsObjectStore, ObjectStore};
+
+        let objects_dir = tempfile::tempdir().unwrap();
+        let pack_dir = objects_dir.path().join("pack");
+        std::fs::create_dir(&pack_dir).unwrap();
+        let pack_path = pack_dir.join("pack-test.pack");
+        write_test_pack(&pack_path, &[(3, None, b"hello\n"), (3, None, b"world\n")]);
+
+        let pack = Pack::open(&pack_path, None, OpenOptions::default()).unwrap();
+        pack.write_index(pack_dir.join("pack-test.idx")).unwrap();
+
+        let hash: ObjectHash = "ce013625030ba8dba906f756967f9e9ca394464a".parse().unwrap();
+        assert_eq!(pack.find(&hash).unwrap().hash, hash);
+        let missing: ObjectHash = "ce013625030ba8dba906f756967f9e9ca394464b".parse().unwrap();
+        assert!(pack.find(&missing).is_none());
+
+        let store = FsObjectStore::new(objects_dir.path());
+        assert!(store.exists(hash.as_hex()));
+        assert!(!store.exists(missing.as_hex()));
+
+        let mut object = store.read(hash.as_hex()).unwrap();
+        assert_eq!(object.object_type, ObjectType::Blob);
+        assert_eq!(object.content_len, 6);
+        assert_eq!(object.read_to_vec().unwrap(), b"hello\n");
+    }
+
+    #[test]
+    fn fan_out_must_match_object_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let pack_path = dir.path().join("pack-test.pack");
+        let idx_path = dir.path().join("pack-test.idx");
+        write_test_pack(&pack_path, &[(3, None, b"hello\n"), (3, None, b"world\n")]);
+        Pack::open(&pack_path, None, OpenOptions::default())
+            .unwrap()
+            .write_index(&idx_path)
+            .unwrap();
+        PackIndex::read(&idx_path).unwrap();
+
+        // the objects start with 0xcc and 0xce, so claiming both start with
+        // 0xcd or less still leaves the table in order
+        let mut idx = std::fs::read(&idx_path).unwrap();
+        let entry = 8 + 0xcd * 4;
+        idx[entry..entry + 4].copy_from_slice(&2u32.to_be_bytes());
+        let data_len = idx.len() - 20;
+        let mut hasher = HashAlgorithm::Sha1.hasher();
+        hasher.update(&idx[..data_len]);
+        idx.truncate(data_len);
+        idx.extend_from_slice(hasher.finalize().as_bytes());
+        std::fs::write(&idx_path, idx).unwrap();
+
+        let err = PackIndex::read(&idx_path).unwrap_err();
+        assert!(err.to_string().contains("fan-out table doesn't match"));
+    }
+
+    #[test]
+    fn zero_size_copy_is_0x10000_bytes() {
+        // copy with an offset of 0x20 and no size bytes, then with a size
+        // byte that's explicitly zero
+        let mut contents = Parser::new(Cursor::new(vec![0x81, 0x20, 0x91, 0x20, 0x00]));
+
+        let instructions = parse_delta_instructions(&mut contents).unwrap();
+        assert_eq!(
+            instructions,
+            [
+                DeltaInstruction::Copy {
+                    offset: 0x20,
+                    size: 0x10000
+                },
+                DeltaInstruction::Copy {
+                    offset: 0x20,
+                    size: 0x10000
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn copy_past_end_of_base_is_malformed() {
+        let instructions = [DeltaInstruction::Copy { offset: 2, size: 4 }];
+
+        let err = apply_delta(&instructions, b"abc", 4).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GitError>(),
+            Some(GitError::MalformedDelta {
+                offset: 2,
+                size: 4,
+                len: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn add_past_end_of_result_is_malformed() {
+        let instructions = [
+            DeltaInstruction::Copy { offset: 0, size: 3 },
+            DeltaInstruction::Add(b"de".to_vec()),
+        ];
+
+        let err = apply_delta(&instructions, b"abc", 4).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GitError>(),
+            Some(GitError::MalformedDelta {
+                offset: 3,
+                size: 2,
+                len: 4
+            })
+        ));
+    }
+}
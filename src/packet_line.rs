@@ -1,98 +1,156 @@
 use bytes::Bytes;
+use eyre::{Context as _, Result};
 use futures_core::Stream;
 use pin_project_lite::pin_project;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
 #[derive(Debug)]
-pub struct PacketLine(String);
+pub enum PacketLine {
+    /// A so-called "flush" packet (`0000`), which should be treated
+    /// differently than an empty packet (`0004`); the latter should
+    /// never be sent over the wire.
+    Flush,
+
+    Data(Vec<u8>),
+}
 
 impl PacketLine {
     pub fn flush() -> Self {
-        Self("".into())
+        Self::Flush
     }
 
+    /// A line of text, which (by convention) ends with a newline.
     pub fn new(s: impl Into<String>) -> Self {
-        Self(s.into())
+        let mut bytes = s.into().into_bytes();
+        bytes.push(b'\n');
+        Self::Data(bytes)
     }
 
-    pub fn repr(&self) -> String {
-        // so-called "flush" packets should be treated differently
-        // than an empty packet (`0004`), which should never be sent
-        // over the wire
-        if self.0.is_empty() {
-            "0000".into()
-        } else {
-            // # of bytes in line + 4 bytes for length + 1 byte for newline
-            format!("{:04x}{}\n", self.0.len() + 5, self.0)
+    /// Arbitrary bytes, sent exactly as given.
+    pub fn bytes(bytes: impl Into<Vec<u8>>) -> Self {
+        Self::Data(bytes.into())
+    }
+
+    pub fn repr(&self) -> Vec<u8> {
+        match self {
+            Self::Flush => b"0000".to_vec(),
+            Self::Data(bytes) => {
+                // # of bytes in payload + 4 bytes for length
+                let mut repr = format!("{:04x}", bytes.len() + 4).into_bytes();
+                repr.extend_from_slice(bytes);
+                repr
+            }
         }
     }
 }
 
-pub fn pkt_line_str(pkt: &[u8]) -> &str {
-    pkt_line_str_keep_newline(pkt).trim_end_matches('\n')
+pub fn pkt_line_str(pkt: &[u8]) -> Result<&str> {
+    Ok(pkt_line_str_keep_newline(pkt)?.trim_end_matches('\n'))
 }
 
-pub fn pkt_line_str_keep_newline(pkt: &[u8]) -> &str {
-    std::str::from_utf8(pkt).expect("valid utf-8")
+pub fn pkt_line_str_keep_newline(pkt: &[u8]) -> Result<&str> {
+    std::str::from_utf8(pkt).context("malformed response from remote: packet isn't valid utf-8")
+}
+
+/// A packet read from the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Packet<'a> {
+    Data(&'a [u8]),
+
+    /// `0000`, which ends a message (or a section of one, in protocol v1).
+    Flush,
+
+    /// `0001`, which separates the sections of a protocol v2 message.
+    Delim,
+
+    /// `0002`, which ends a protocol v2 response.
+    ResponseEnd,
+}
+
+impl<'a> Packet<'a> {
+    /// The packet's payload, if it's a data packet.
+    pub fn data(self) -> Option<&'a [u8]> {
+        match self {
+            Self::Data(data) => Some(data),
+            _ => None,
+        }
+    }
 }
 
 /// Attempt to parse the next available packet line, returning the
 /// number of bytes to advance the cursor (how many bytes were consumed
-/// to read the full packet) and the parsed packet. If the packet was
-/// a flush, the parsed packet will be `None`. If no full packet was
-/// available, returns `(0, None)`.
-fn pkt_line_next(input: &[u8]) -> (usize, Option<&[u8]>) {
+/// to read the full packet) and the parsed packet. If no full packet was
+/// available, returns `None`.
+fn pkt_line_next(input: &[u8]) -> Result<Option<(usize, Packet<'_>)>> {
     if input.len() < 4 {
         // we don't have enough input to parse a full packet
-        return (0, None);
+        return Ok(None);
     }
 
-    let len_str = std::str::from_utf8(&input[..4]).expect("pkt len is valid utf-8");
-    let len = usize::from_str_radix(len_str, 16).expect("parse pkt len");
+    let len = std::str::from_utf8(&input[..4])
+        .ok()
+        .and_then(|len_str| usize::from_str_radix(len_str, 16).ok())
+        .ok_or_else(|| {
+            eyre::eyre!(
+                "malformed response from remote: invalid packet length {:?}",
+                String::from_utf8_lossy(&input[..4])
+            )
+        })?;
+
+    let packet = match len {
+        0 => Packet::Flush,
+        1 => Packet::Delim,
+        2 => Packet::ResponseEnd,
+        3 => eyre::bail!("malformed response from remote: invalid packet length {len:04x}"),
 
-    if len == 0 {
-        // we got a flush packet
-        return (4, None);
-    }
-
-    if input.len() < len {
         // we know the packet's size, but don't have enough input
         // to parse the packet's contents
-        return (0, None);
-    }
+        _ if input.len() < len => return Ok(None),
 
-    // we got a full packet!
-    (len, Some(&input[..len][4..]))
+        // we got a full packet!
+        _ => return Ok(Some((len, Packet::Data(&input[..len][4..])))),
+    };
+
+    Ok(Some((4, packet)))
 }
 
-pub fn pkt_line_iter(mut input: &[u8]) -> impl Iterator<Item = &[u8]> {
+/// Iterate over the packets in `input`. If a malformed or truncated
+/// packet is found, an error is yielded and iteration stops.
+pub fn pkt_line_iter(mut input: &[u8]) -> impl Iterator<Item = Result<Packet<'_>>> {
     std::iter::from_fn(move || {
-        // skip all flush pkts
-        loop {
-            if input.is_empty() {
-                return None;
-            }
-
-            match pkt_line_next(input) {
-                // only partial packet available
-                (0, None) => panic!("malformed partial packet!"),
+        if input.is_empty() {
+            return None;
+        }
 
-                // flush packet
-                (4, None) => input = &input[4..],
+        match pkt_line_next(input) {
+            Ok(Some((n, packet))) => {
+                input = &input[n..];
+                Some(Ok(packet))
+            }
 
-                // standard packet
-                (n, Some(packet)) => {
-                    input = &input[n..];
-                    return Some(packet);
-                }
+            // only partial packet available
+            Ok(None) => {
+                input = &[];
+                Some(Err(eyre::eyre!(
+                    "malformed response from remote: truncated packet"
+                )))
+            }
 
-                _ => unreachable!(),
+            Err(err) => {
+                input = &[];
+                Some(Err(err))
             }
         }
     })
 }
 
+/// Iterate over the payloads of the data packets in `input`, skipping
+/// over flush (and other special) packets.
+pub fn pkt_line_data_iter(input: &[u8]) -> impl Iterator<Item = Result<&[u8]>> {
+    pkt_line_iter(input).filter_map(|packet| packet.map(Packet::data).transpose())
+}
+
 pin_project! {
     pub struct PacketLineStream<S> where S: Stream<Item = reqwest::Result<Bytes>> {
         #[pin]
@@ -106,35 +164,41 @@ impl<S> Stream for PacketLineStream<S>
 where
     S: Stream<Item = reqwest::Result<Bytes>>,
 {
-    type Item = reqwest::Result<Vec<u8>>;
+    type Item = Result<Vec<u8>>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let mut this = self.project();
 
         loop {
-            match this.inner.as_mut().poll_next(cx) {
-                Poll::Ready(Some(Ok(new_bytes))) => {
-                    this.buf.extend(new_bytes);
-
-                    match pkt_line_next(&this.buf[*this.cursor..]) {
-                        // only partial packet available
-                        (0, None) => continue,
-
-                        // flush packet
-                        (4, None) => continue,
+            // drain any packets we've already received before waiting for more
+            match pkt_line_next(&this.buf[*this.cursor..]) {
+                Err(err) => return Poll::Ready(Some(Err(err))),
 
-                        // standard packet
-                        (n, Some(packet)) => {
-                            *this.cursor += n;
-                            return std::task::Poll::Ready(Some(Ok(packet.to_vec())));
-                        }
+                // standard packet
+                Ok(Some((n, Packet::Data(packet)))) => {
+                    let packet = packet.to_vec();
+                    *this.cursor += n;
+                    return Poll::Ready(Some(Ok(packet)));
+                }
 
-                        _ => unreachable!(),
-                    };
+                // flush (or other special) packet
+                Ok(Some((n, _))) => {
+                    *this.cursor += n;
+                    continue;
                 }
 
-                Poll::Ready(Some(Err(err))) => {
-                    return Poll::Ready(Some(Err::<Vec<u8>, reqwest::Error>(err)))
+                // only partial packet available
+                Ok(None) => (),
+            }
+
+            match this.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(new_bytes))) => this.buf.extend(new_bytes),
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err.into()))),
+                Poll::Ready(None) if *this.cursor < this.buf.len() => {
+                    *this.cursor = this.buf.len();
+                    return Poll::Ready(Some(Err(eyre::eyre!(
+                        "malformed response from remote: truncated packet"
+                    ))));
                 }
                 Poll::Ready(None) => return Poll::Ready(None),
                 Poll::Pending => return Poll::Pending,
@@ -1,10 +1,10 @@
+use crate::hash::HashAlgorithm;
 use crate::object::ObjectHash;
 use eyre::{Context, Result};
 use flate2::read::ZlibDecoder;
-use sha1::{Digest, Sha1};
 use std::fmt::Debug;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write};
 use std::str::FromStr;
 
 pub struct Parser<R: BufRead> {
@@ -66,14 +66,18 @@ impl<R: BufRead + Debug> Parser<R> {
         self.inner
     }
 
-    pub fn parse_str(&mut self, delim: u8) -> Result<String> {
+    /// Read up to (and including) the next `delim`, returning everything before it.
+    pub fn parse_bytes(&mut self, delim: u8) -> Result<Vec<u8>> {
         let mut buf = Vec::new();
         self.inner
             .read_until(delim, &mut buf)
             .context("fill string from inner BufRead")?;
-        let mut s = String::from_utf8(buf).context("parse string as UTF-8")?;
-        let _ = s.pop(); // remove trailing delimiter
-        Ok(s)
+        let _ = buf.pop(); // remove trailing delimiter
+        Ok(buf)
+    }
+
+    pub fn parse_str(&mut self, delim: u8) -> Result<String> {
+        String::from_utf8(self.parse_bytes(delim)?).context("parse string as UTF-8")
     }
 
     pub fn parse_str_exact<const N: usize>(&mut self) -> Result<String> {
@@ -94,9 +98,7 @@ impl<R: BufRead + Debug> Parser<R> {
         assert!(N <= USIZE_BYTES, "must fit in usize");
 
         let mut buf = [0; USIZE_BYTES];
-        self.inner
-            .read_exact(&mut buf[(USIZE_BYTES - N)..])
-            .context(format!("read {N} bytes from inner BufRead"))?;
+        buf[(USIZE_BYTES - N)..].copy_from_slice(&self.read_bytes::<N>()?);
         Ok(usize::from_be_bytes(buf))
     }
 
@@ -135,21 +137,44 @@ impl<R: BufRead + Debug> Parser<R> {
     }
 
     pub fn read_byte(&mut self) -> Result<u8> {
-        let mut buf = [0; 1];
-        self.inner
-            .read_exact(&mut buf)
-            .context("read byte from inner BufRead")?;
-        Ok(buf[0])
+        Ok(self.read_bytes::<1>()?[0])
     }
 
     pub fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N]> {
         let mut buf = [0; N];
+
+        // fast path: when the bytes are already buffered (always, for
+        // in-memory readers), copy them straight out of the buffer
+        let available = self.inner.fill_buf().context("fill inner BufRead")?;
+        if available.len() >= N {
+            buf.copy_from_slice(&available[..N]);
+            self.inner.consume(N);
+            return Ok(buf);
+        }
+
         self.inner
             .read_exact(&mut buf)
-            .context(format!("read {N} bytes from inner BufRead"))?;
+            .with_context(|| format!("read {N} bytes from inner BufRead"))?;
         Ok(buf)
     }
 
+    /// Read a raw object hash of the given algorithm's length.
+    pub fn read_hash(&mut self, algorithm: HashAlgorithm) -> Result<ObjectHash> {
+        let len = algorithm.digest_len();
+        let available = self.inner.fill_buf().context("fill inner BufRead")?;
+        if available.len() >= len {
+            let hash = ObjectHash::from_bytes(&available[..len]);
+            self.inner.consume(len);
+            return hash;
+        }
+
+        let mut buf = vec![0; len];
+        self.inner
+            .read_exact(&mut buf)
+            .context("read object hash from inner BufRead")?;
+        ObjectHash::from_bytes(&buf)
+    }
+
     pub fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
         Ok(self.inner.read_exact(buf)?)
     }
@@ -162,6 +187,18 @@ impl<R: BufRead + Debug> Parser<R> {
         Ok((consumed, Parser::new(Cursor::new(buf))))
     }
 
+    /// Like [`Parser::split_off_decode`], but write the decompressed bytes to
+    /// `w` as they're read instead of collecting them, returning how many
+    /// compressed bytes were consumed.
+    pub fn decode_into<W: Write>(&mut self, size: usize, mut w: W) -> Result<u64> {
+        let mut decoder = ZlibDecoder::new(&mut self.inner);
+        let copied = std::io::copy(&mut (&mut decoder).take(size as u64), &mut w)?;
+        if copied != size as u64 {
+            eyre::bail!("expected {size} bytes of compressed data, found {copied}");
+        }
+        Ok(decoder.total_in())
+    }
+
     pub fn at_eof(&mut self) -> Result<bool> {
         Ok(self.inner.fill_buf().context("peek contents")?.is_empty())
     }
@@ -169,26 +206,32 @@ impl<R: BufRead + Debug> Parser<R> {
 
 impl Parser<BufReader<File>> {
     pub fn verify_checksum(mut self, file_size: usize) -> Result<(ObjectHash, Self)> {
-        // verify that the 20-byte SHA-1 checksum at the end of the file
-        // matches the SHA-1 of the remaining file contents (older git
-        // implementations used a SHA-1 hash of the object names contained
-        // within the packfile, see [here][so-packfile]).
+        // verify that the checksum at the end of the file (20 bytes for
+        // SHA-1, 32 for SHA-256) matches the hash of the remaining file
+        // contents (older git implementations used a SHA-1 hash of the object
+        // names contained within the packfile, see [here][so-packfile]).
         //
         // [so-packfile]: https://stackoverflow.com/questions/5469978/git-pack-filenames-what-is-the-digest
-        self.skip(file_size - 4 - 20);
-        let checksum = ObjectHash::from_bytes(&self.read_bytes::<20>()?);
+        let algorithm = HashAlgorithm::current()?;
+        let hash_len = algorithm.digest_len();
+        self.skip(file_size - 4 - hash_len);
+        let checksum = self.read_hash(algorithm)?;
 
         let mut f = self.into_inner().into_inner();
         f.seek(SeekFrom::Start(0)).unwrap();
-        let mut hasher = Sha1::new();
+        let mut hasher = algorithm.hasher();
         std::io::copy(
-            &mut f.try_clone().unwrap().take(file_size as u64 - 20),
+            &mut f.try_clone().unwrap().take((file_size - hash_len) as u64),
             &mut hasher,
         )?;
-        let sha1 = ObjectHash::from_hasher(hasher);
-
-        if sha1 != checksum {
-            eyre::bail!("checksums don't match (wanted {}, got {})", checksum, sha1);
+        let actual = hasher.finalize();
+
+        if actual != checksum {
+            eyre::bail!(
+                "checksums don't match (wanted {}, got {})",
+                checksum,
+                actual
+            );
         }
 
         f.seek(SeekFrom::Start(4)).unwrap();
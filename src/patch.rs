@@ -0,0 +1,421 @@
+use crate::diff::{self, Edit};
+use crate::object::ObjectMode;
+use eyre::{Context, Result};
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+
+/// How many lines of context at either end of a hunk may be ignored when it
+/// doesn't apply as-is (like `patch`'s default fuzz factor).
+const MAX_FUZZ: usize = 2;
+
+/// How many lines of unchanged context to show around each change.
+const CONTEXT_LINES: usize = 3;
+
+/// The changes to a single file in a unified diff.
+#[derive(Debug, Default)]
+pub struct FilePatch {
+    /// The path before the change, or `None` if the file is being created.
+    pub old_path: Option<OsString>,
+    /// The path after the change, or `None` if the file is being deleted.
+    pub new_path: Option<OsString>,
+    /// The file's mode after the change, if the patch gives one.
+    pub new_mode: Option<ObjectMode>,
+    pub hunks: Vec<Hunk>,
+}
+
+/// A contiguous run of changed lines, along with the context around them.
+#[derive(Debug)]
+pub struct Hunk {
+    /// The (1-based) line the hunk starts on in the old file, or 0 if the old
+    /// side is empty.
+    pub old_start: usize,
+    pub new_start: usize,
+    pub lines: Vec<HunkLine>,
+}
+
+/// A line of a hunk, including its trailing newline (if it has one).
+#[derive(Debug, Clone, PartialEq)]
+pub enum HunkLine {
+    Context(Vec<u8>),
+    Delete(Vec<u8>),
+    Insert(Vec<u8>),
+}
+
+impl FilePatch {
+    /// The path to show for this file (the new path, unless it's deleted).
+    pub fn path(&self) -> &OsStr {
+        self.new_path
+            .as_deref()
+            .or(self.old_path.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Apply each hunk to `contents` in turn, returning the result along with
+    /// the (0-based) indices of any hunks that couldn't be applied.
+    ///
+    /// A hunk is applied where its old lines are found closest to where it
+    /// says they should be (adjusted by how far previous hunks were moved).
+    /// If they aren't found anywhere, up to [`MAX_FUZZ`] lines of context at
+    /// either end are ignored and the search is tried again.
+    pub fn apply(&self, contents: &[u8]) -> (Vec<u8>, Vec<usize>) {
+        let lines: Vec<&[u8]> = contents.split_inclusive(|&b| b == b'\n').collect();
+        let mut result: Vec<&[u8]> = Vec::with_capacity(lines.len());
+        let mut rejected = Vec::new();
+
+        // the next line of `lines` that hasn't been copied to `result` yet, and
+        // how far the last hunk was from where it was expected
+        let mut cursor = 0;
+        let mut drift: isize = 0;
+        'hunks: for (i, hunk) in self.hunks.iter().enumerate() {
+            for fuzz in 0..=MAX_FUZZ {
+                let hunk_lines = hunk.trim_context(fuzz);
+                let old: Vec<&[u8]> = old_lines(hunk_lines).collect();
+
+                let trimmed = hunk.lines.len() - hunk.trim_context_start(fuzz).len();
+                let expected = hunk.old_start.saturating_sub(1) + trimmed;
+                let Some(at) = find_lines(&lines, &old, cursor, expected as isize + drift) else {
+                    continue;
+                };
+
+                result.extend_from_slice(&lines[cursor..at]);
+                result.extend(new_lines(hunk_lines));
+                cursor = at + old.len();
+                drift = at as isize - expected as isize;
+                continue 'hunks;
+            }
+
+            rejected.push(i);
+        }
+        result.extend_from_slice(&lines[cursor..]);
+
+        (result.concat(), rejected)
+    }
+}
+
+impl Hunk {
+    /// The hunk's lines, without up to `fuzz` lines of context at either end.
+    fn trim_context(&self, fuzz: usize) -> &[HunkLine] {
+        let lines = self.trim_context_start(fuzz);
+        let trailing = lines
+            .iter()
+            .rev()
+            .take(fuzz)
+            .take_while(|line| matches!(line, HunkLine::Context(_)))
+            .count();
+        &lines[..lines.len() - trailing]
+    }
+
+    fn trim_context_start(&self, fuzz: usize) -> &[HunkLine] {
+        let leading = self
+            .lines
+            .iter()
+            .take(fuzz)
+            .take_while(|line| matches!(line, HunkLine::Context(_)))
+            .count();
+        &self.lines[leading..]
+    }
+
+    /// The hunk in unified diff format, starting with its `@@` header.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let old_len = old_lines(&self.lines).count();
+        let new_len = new_lines(&self.lines).count();
+        let mut out = format!(
+            "@@ -{} +{} @@\n",
+            range(self.old_start, old_len),
+            range(self.new_start, new_len)
+        )
+        .into_bytes();
+
+        for line in self.lines.iter() {
+            let (prefix, text) = match line {
+                HunkLine::Context(text) => (b' ', text),
+                HunkLine::Delete(text) => (b'-', text),
+                HunkLine::Insert(text) => (b'+', text),
+            };
+            out.push(prefix);
+            out.extend_from_slice(text);
+            if !text.ends_with(b"\n") {
+                out.extend_from_slice(b"\n\\ No newline at end of file\n");
+            }
+        }
+
+        out
+    }
+}
+
+/// A hunk header range: `start,len`, or just `start` when there's one line.
+fn range(start: usize, len: usize) -> String {
+    match len {
+        1 => start.to_string(),
+        len => format!("{start},{len}"),
+    }
+}
+
+fn old_lines(lines: &[HunkLine]) -> impl Iterator<Item = &[u8]> {
+    lines.iter().filter_map(|line| match line {
+        HunkLine::Context(text) | HunkLine::Delete(text) => Some(text.as_slice()),
+        HunkLine::Insert(_) => None,
+    })
+}
+
+fn new_lines(lines: &[HunkLine]) -> impl Iterator<Item = &[u8]> {
+    lines.iter().filter_map(|line| match line {
+        HunkLine::Context(text) | HunkLine::Insert(text) => Some(text.as_slice()),
+        HunkLine::Delete(_) => None,
+    })
+}
+
+/// Find where `needle` appears in `lines` (no earlier than `from`), choosing
+/// the match closest to `expected`.
+fn find_lines(lines: &[&[u8]], needle: &[&[u8]], from: usize, expected: isize) -> Option<usize> {
+    let last = lines.len().checked_sub(needle.len())?;
+    if from > last {
+        return None;
+    }
+
+    let expected = expected.clamp(from as isize, last as isize) as usize;
+    let matches = |at: usize| lines[at..at + needle.len()] == *needle;
+    let max_distance = (expected - from).max(last - expected);
+    (0..=max_distance).find_map(|distance| {
+        [
+            expected.checked_add(distance),
+            expected.checked_sub(distance),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|&at| at >= from && at <= last && matches(at))
+    })
+}
+
+/// Compute the hunks that turn `old` into `new`, each with up to
+/// [`CONTEXT_LINES`] lines of context around its changes. Changes that are
+/// close enough for their context to touch are kept in the same hunk.
+pub fn hunks(old: &[u8], new: &[u8]) -> Vec<Hunk> {
+    let old: Vec<&[u8]> = old.split_inclusive(|&b| b == b'\n').collect();
+    let new: Vec<&[u8]> = new.split_inclusive(|&b| b == b'\n').collect();
+    let edits = diff::diff(&old, &new);
+
+    // the ranges of `edits` covered by each hunk, context included
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (i, edit) in edits.iter().enumerate() {
+        if matches!(edit, Edit::Equal { .. }) {
+            continue;
+        }
+        let start = i.saturating_sub(CONTEXT_LINES);
+        let end = (i + 1 + CONTEXT_LINES).min(edits.len());
+        match ranges.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    let (mut old_line, mut new_line) = (0, 0);
+    let mut cursor = 0;
+    let mut hunks = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        for edit in edits[cursor..start].iter() {
+            if let Edit::Equal { .. } = edit {
+                old_line += 1;
+                new_line += 1;
+            }
+        }
+
+        let lines: Vec<HunkLine> = edits[start..end]
+            .iter()
+            .map(|edit| match *edit {
+                Edit::Equal { old: i, .. } => HunkLine::Context(old[i].to_vec()),
+                Edit::Delete { old: i } => HunkLine::Delete(old[i].to_vec()),
+                Edit::Insert { new: i } => HunkLine::Insert(new[i].to_vec()),
+            })
+            .collect();
+
+        // like git, an empty side starts at the line before the hunk
+        let old_len = old_lines(&lines).count();
+        let new_len = new_lines(&lines).count();
+        hunks.push(Hunk {
+            old_start: old_line + usize::from(old_len > 0),
+            new_start: new_line + usize::from(new_len > 0),
+            lines,
+        });
+        old_line += old_len;
+        new_line += new_len;
+        cursor = end;
+    }
+
+    hunks
+}
+
+/// Parse the unified diffs in `patch`, in either git's format (`diff --git`
+/// headers, with extended headers for new, deleted, and renamed files) or the
+/// plain one (just `---`/`+++` headers). Anything that isn't part of a diff,
+/// like the commit message in a mailbox patch, is skipped over.
+pub fn parse(patch: &[u8]) -> Result<Vec<FilePatch>> {
+    let mut patches: Vec<FilePatch> = Vec::new();
+    // whether the current patch has had its `---` line, so a plain diff's
+    // headers can start a new one
+    let mut has_old_header = false;
+
+    let mut lines = patch
+        .split_inclusive(|&b| b == b'\n')
+        .enumerate()
+        .peekable();
+    while let Some((lineno, line)) = lines.next() {
+        let text = line.strip_suffix(b"\n").unwrap_or(line);
+
+        if let Some(paths) = text.strip_prefix(b"diff --git ") {
+            let (old_path, new_path) = split_git_paths(paths);
+            patches.push(FilePatch {
+                old_path,
+                new_path,
+                ..Default::default()
+            });
+            has_old_header = false;
+        } else if let Some(mode) = text.strip_prefix(b"new file mode ") {
+            let patch = current(&mut patches, lineno)?;
+            patch.old_path = None;
+            patch.new_mode = Some(parse_mode(mode)?);
+        } else if text.starts_with(b"deleted file mode ") {
+            current(&mut patches, lineno)?.new_path = None;
+        } else if let Some(mode) = text.strip_prefix(b"new mode ") {
+            current(&mut patches, lineno)?.new_mode = Some(parse_mode(mode)?);
+        } else if let Some(path) = text.strip_prefix(b"rename from ") {
+            current(&mut patches, lineno)?.old_path = Some(OsStr::from_bytes(path).to_owned());
+        } else if let Some(path) = text.strip_prefix(b"rename to ") {
+            current(&mut patches, lineno)?.new_path = Some(OsStr::from_bytes(path).to_owned());
+        } else if let Some(path) = text.strip_prefix(b"--- ") {
+            if patches.is_empty() || has_old_header {
+                patches.push(FilePatch::default());
+            }
+            has_old_header = true;
+            current(&mut patches, lineno)?.old_path = header_path(path);
+        } else if let Some(path) = text.strip_prefix(b"+++ ") {
+            current(&mut patches, lineno)?.new_path = header_path(path);
+        } else if let Some(header) = text.strip_prefix(b"@@ -") {
+            let ((old_start, mut old_left), (new_start, mut new_left)) = hunk_ranges(header)
+                .with_context(|| format!("malformed hunk header on line {}", lineno + 1))?;
+            let mut hunk = Hunk {
+                old_start,
+                new_start,
+                lines: Vec::new(),
+            };
+
+            // the header says how many lines are on each side, which is
+            // what tells us where the hunk ends
+            while old_left > 0 || new_left > 0 {
+                let Some((lineno, line)) = lines.next() else {
+                    eyre::bail!("patch ends in the middle of a hunk");
+                };
+                let text = line[1..].to_vec();
+                match line[0] {
+                    b' ' if old_left > 0 && new_left > 0 => {
+                        hunk.lines.push(HunkLine::Context(text));
+                        old_left -= 1;
+                        new_left -= 1;
+                    }
+                    // some editors strip the space from empty context lines
+                    b'\n' if old_left > 0 && new_left > 0 => {
+                        hunk.lines.push(HunkLine::Context(b"\n".to_vec()));
+                        old_left -= 1;
+                        new_left -= 1;
+                    }
+                    b'-' if old_left > 0 => {
+                        hunk.lines.push(HunkLine::Delete(text));
+                        old_left -= 1;
+                    }
+                    b'+' if new_left > 0 => {
+                        hunk.lines.push(HunkLine::Insert(text));
+                        new_left -= 1;
+                    }
+                    _ => eyre::bail!("malformed hunk line {}", lineno + 1),
+                }
+
+                strip_missing_newline(&mut lines, &mut hunk);
+            }
+
+            current(&mut patches, lineno)?.hunks.push(hunk);
+        }
+    }
+
+    Ok(patches)
+}
+
+/// The patch that headers on the given line belong to.
+fn current(patches: &mut [FilePatch], lineno: usize) -> Result<&mut FilePatch> {
+    patches
+        .last_mut()
+        .ok_or_else(|| eyre::eyre!("patch fragment without a header on line {}", lineno + 1))
+}
+
+/// If the next line is `\ No newline at end of file`, consume it and remove
+/// the newline from the last line of the hunk.
+fn strip_missing_newline<'a>(
+    lines: &mut std::iter::Peekable<impl Iterator<Item = (usize, &'a [u8])>>,
+    hunk: &mut Hunk,
+) {
+    if lines.next_if(|(_, line)| line.starts_with(b"\\")).is_some() {
+        if let Some(HunkLine::Context(text) | HunkLine::Delete(text) | HunkLine::Insert(text)) =
+            hunk.lines.last_mut()
+        {
+            if text.ends_with(b"\n") {
+                text.pop();
+            }
+        }
+    }
+}
+
+/// Split the `a/<old> b/<new>` paths of a `diff --git` line.
+fn split_git_paths(paths: &[u8]) -> (Option<OsString>, Option<OsString>) {
+    let split = paths
+        .windows(3)
+        .position(|w| w == b" b/")
+        .filter(|_| paths.starts_with(b"a/"));
+    match split {
+        Some(i) => (
+            Some(OsStr::from_bytes(&paths[2..i]).to_owned()),
+            Some(OsStr::from_bytes(&paths[i + 3..]).to_owned()),
+        ),
+        None => (None, None),
+    }
+}
+
+/// The path in a `---` or `+++` header, with its first component (`a/` or
+/// `b/`) and any trailing timestamp removed. `/dev/null` means there's no file
+/// on that side.
+fn header_path(path: &[u8]) -> Option<OsString> {
+    let path = path.split(|&b| b == b'\t').next().unwrap_or(path);
+    if path == b"/dev/null" {
+        return None;
+    }
+
+    let path = match path.iter().position(|&b| b == b'/') {
+        Some(i) => &path[i + 1..],
+        None => path,
+    };
+    Some(OsStr::from_bytes(path).to_owned())
+}
+
+fn parse_mode(mode: &[u8]) -> Result<ObjectMode> {
+    let mode = std::str::from_utf8(mode).context("mode isn't valid UTF-8")?;
+    mode.parse()
+        .map_err(|mode| eyre::eyre!("invalid file mode {mode}"))
+}
+
+/// Parse the `(start, len)` ranges of a `@@ -<old> +<new> @@` hunk header
+/// (following the `@@ -`), where each is `start,len` or just `start`.
+fn hunk_ranges(header: &[u8]) -> Result<((usize, usize), (usize, usize))> {
+    let header = std::str::from_utf8(header).context("hunk header isn't valid UTF-8")?;
+    let (old, rest) = header
+        .split_once(" +")
+        .ok_or_else(|| eyre::eyre!("missing new range"))?;
+    let (new, _) = rest
+        .split_once(" @@")
+        .ok_or_else(|| eyre::eyre!("missing closing @@"))?;
+
+    let parse_range = |range: &str| -> Result<(usize, usize)> {
+        Ok(match range.split_once(',') {
+            Some((start, len)) => (start.parse()?, len.parse()?),
+            None => (range.parse()?, 1),
+        })
+    };
+    Ok((parse_range(old)?, parse_range(new)?))
+}
@@ -0,0 +1,46 @@
+use crate::glob;
+use crate::repository;
+
+/// A list of paths (or globs) given on the command line, which restrict a
+/// command to the files that match any of them. An empty list matches
+/// everything.
+#[derive(Debug, Default)]
+pub struct Pathspec(Vec<String>);
+
+impl Pathspec {
+    /// Build a pathspec from paths relative to where the command was run.
+    pub fn new(specs: &[String]) -> Self {
+        Self(
+            specs
+                .iter()
+                .map(|spec| {
+                    let mut resolved = repository::from_prefix(spec).to_string_lossy().into_owned();
+                    // a trailing slash means the spec only matches a directory
+                    if spec.ends_with('/') && !resolved.is_empty() {
+                        resolved.push('/');
+                    }
+                    resolved
+                })
+                .collect(),
+        )
+    }
+
+    /// Whether the path (relative to the root of the repository) matches.
+    pub fn matches(&self, path: &[u8]) -> bool {
+        self.0.is_empty() || self.0.iter().any(|spec| matches_one(spec, path))
+    }
+}
+
+fn matches_one(spec: &str, path: &[u8]) -> bool {
+    let dir = spec.trim_end_matches('/').as_bytes();
+
+    // `.` (or an empty spec) matches everything, a directory matches everything
+    // inside of it, and anything else is either an exact path or a glob
+    dir.is_empty()
+        || dir == b"."
+        || (path == dir && !spec.ends_with('/'))
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with(b"/"))
+        || glob::fnmatch(spec.as_bytes(), path)
+}
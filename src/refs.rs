@@ -0,0 +1,707 @@
+use crate::commit::{Commit, CommitAttribution};
+use crate::config::Config;
+use crate::hash::HashAlgorithm;
+use crate::object::ObjectType;
+use crate::repository::{self, Repository};
+use crate::tag::Tag;
+use eyre::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+/// Where `HEAD` currently points.
+#[derive(Debug)]
+pub enum Head {
+    /// `HEAD` is a symbolic ref to `refs/heads/<branch>`.
+    Branch(String),
+    /// `HEAD` points directly at a commit.
+    Detached(String),
+}
+
+pub fn read_head() -> Result<Head> {
+    read_head_in(repository::current_git_dir())
+}
+
+pub(crate) fn read_head_in(git_dir: &Path) -> Result<Head> {
+    let head = std::fs::read_to_string(git_dir.join("HEAD")).context("read HEAD")?;
+    let head = head.trim_end();
+
+    match head.strip_prefix("ref: ") {
+        Some(target) => {
+            let branch = target
+                .strip_prefix("refs/heads/")
+                .ok_or_else(|| eyre::eyre!("HEAD points outside of refs/heads ({target})"))?;
+            Ok(Head::Branch(branch.to_owned()))
+        }
+        None => Ok(Head::Detached(head.to_owned())),
+    }
+}
+
+/// Resolve a fully-qualified ref name (`HEAD`, `refs/heads/main`) to the hash
+/// it points at, following symbolic refs. Returns `None` if the ref doesn't
+/// exist (e.g. a branch that doesn't have any commits yet).
+pub fn resolve(name: &str) -> Result<Option<String>> {
+    resolve_in(repository::current_git_dir(), name)
+}
+
+fn resolve_in(git_dir: &Path, name: &str) -> Result<Option<String>> {
+    let path = ref_path(git_dir, name)?;
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        // a loose ref takes precedence over a packed one, so only fall back
+        // to packed-refs when there isn't one
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound || path.is_dir() => {
+            return Ok(read_packed_in(git_dir)?
+                .into_iter()
+                .find(|packed| packed.name == name)
+                .map(|packed| packed.hash));
+        }
+        Err(err) => return Err(err).with_context(|| format!("read ref {name}")),
+    };
+    let contents = contents.trim_end();
+
+    match contents.strip_prefix("ref: ") {
+        Some(target) => resolve_in(git_dir, target),
+        None => Ok(Some(contents.to_owned())),
+    }
+}
+
+/// Resolve `HEAD` in the submodule checked out at `path`, whose `.git` is
+/// either a directory or a `gitdir: <dir>` file pointing at one. Returns
+/// `None` if the submodule doesn't have any commits yet.
+pub(crate) fn resolve_submodule_head(path: &Path) -> Result<Option<String>> {
+    resolve_in(&repository::read_gitfile(&path.join(".git"))?, "HEAD")
+}
+
+/// The directory that the given fully-qualified ref (and its log) is kept in
+/// for the working tree whose git directory is `git_dir`. `HEAD` and the other
+/// pseudo-refs (like `MERGE_HEAD`) belong to the working tree, but everything
+/// below `refs/` is shared by all of them.
+fn ref_dir(git_dir: &Path, name: &str) -> Result<PathBuf> {
+    match name.starts_with("refs/") {
+        true => repository::common_dir_of(git_dir),
+        false => Ok(git_dir.to_owned()),
+    }
+}
+
+fn ref_path(git_dir: &Path, name: &str) -> Result<PathBuf> {
+    Ok(ref_dir(git_dir, name)?.join(name))
+}
+
+/// Expand a ref name given on the command line to the fully-qualified ref it
+/// refers to the way git does, trying it as `<name>`, `refs/<name>`,
+/// `refs/tags/<name>`, `refs/heads/<name>`, and `refs/remotes/<name>` in turn.
+pub fn expand(name: &str) -> Result<Option<String>> {
+    for candidate in [
+        name.to_owned(),
+        format!("refs/{name}"),
+        format!("refs/tags/{name}"),
+        format!("refs/heads/{name}"),
+        format!("refs/remotes/{name}"),
+    ] {
+        if resolve(&candidate)?.is_some() {
+            return Ok(Some(candidate));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Resolve a name given on the command line to a hash, either as a ref (see
+/// [`expand`]), a reflog entry (`HEAD@{2}`, `main@{yesterday}`), or a full
+/// object hash. Any of these may be followed by `^{<type>}` (or `^{}`) to
+/// peel it, see [`lookup_peeled`].
+pub fn lookup(name: &str) -> Result<Option<String>> {
+    if let Some((name, peel_type)) = name.strip_suffix('}').and_then(|n| n.rsplit_once("^{")) {
+        return lookup_peeled(name, peel_type);
+    }
+
+    if let Some((name, selector)) = name.strip_suffix('}').and_then(|n| n.split_once("@{")) {
+        return lookup_reflog(name, selector);
+    }
+
+    if let Some(name) = expand(name)? {
+        return resolve(&name);
+    }
+
+    if name.len() == HashAlgorithm::current()?.hex_len()
+        && name.bytes().all(|b| b.is_ascii_hexdigit())
+        && Repository::open()?.has_object(&name.parse()?)
+    {
+        return Ok(Some(name.to_owned()));
+    }
+
+    Ok(None)
+}
+
+/// Resolve a name to the commit it refers to, peeling annotated tags (which
+/// may point at other tags) along the way.
+pub fn lookup_commit(name: &str) -> Result<Option<String>> {
+    lookup_peeled(name, "commit")
+}
+
+/// Resolve `<name>^{<type>}`: follow tags until reaching an object of the
+/// given type (or, for `commit`s, their trees). An empty type peels tags down
+/// to whatever they point at.
+fn lookup_peeled(name: &str, peel_type: &str) -> Result<Option<String>> {
+    let Some(hash) = lookup(name)? else {
+        return Ok(None);
+    };
+    let (peeled, object_type) = Tag::peel(&hash)?;
+
+    Ok(Some(match (peel_type, object_type) {
+        ("", _) => peeled,
+        ("tag", _) if peeled != hash => hash,
+        (peel_type, object_type) if peel_type == object_type.to_string() => peeled,
+        ("tree", ObjectType::Commit) => Commit::read_at_hash(&peeled)?.tree_hash,
+        ("commit" | "tree" | "blob" | "tag", _) => {
+            eyre::bail!("{name} is a {object_type}, not a {peel_type}")
+        }
+        _ => eyre::bail!("unknown object type '{peel_type}' in {name}^{{{peel_type}}}"),
+    }))
+}
+
+/// Resolve `<name>@{<selector>}`, where the selector is either the number of
+/// updates to go back, or a date like `yesterday` or `2.hours.ago`. An empty
+/// name refers to the current branch.
+fn lookup_reflog(name: &str, selector: &str) -> Result<Option<String>> {
+    let full_name = if name.is_empty() {
+        match read_head()? {
+            Head::Branch(branch) => format!("refs/heads/{branch}"),
+            Head::Detached(_) => String::from("HEAD"),
+        }
+    } else {
+        match expand(name)? {
+            Some(full_name) => full_name,
+            None => return Ok(None),
+        }
+    };
+
+    let entries = read_reflog(&full_name)?;
+    let Some(oldest) = entries.first() else {
+        eyre::bail!("log for '{name}' is empty");
+    };
+
+    if let Ok(n) = selector.parse::<usize>() {
+        return match entries.len().checked_sub(n + 1) {
+            Some(idx) => Ok(Some(entries[idx].new_hash.clone())),
+            None => eyre::bail!("log for '{name}' only has {} entries", entries.len()),
+        };
+    }
+
+    // the ref's value at a point in time is the one set by the last update
+    // before then; before the first update, it's whatever that update replaced
+    let time = parse_approxidate(selector)?;
+    match entries
+        .iter()
+        .rev()
+        .find(|entry| entry.committer.timestamp <= time)
+    {
+        Some(entry) => Ok(Some(entry.new_hash.clone())),
+        None if oldest.old_hash != HashAlgorithm::current()?.null_hash() => {
+            Ok(Some(oldest.old_hash.clone()))
+        }
+        None => eyre::bail!("log for '{name}' doesn't go back that far"),
+    }
+}
+
+/// Parse the subset of git's "approxidate" formats that are handy for reflog
+/// lookups: `now`, `yesterday`, and `<n>.<unit>.ago` (or `<n> <unit> ago`).
+fn parse_approxidate(s: &str) -> Result<SystemTime> {
+    let s = s.trim().to_lowercase();
+    let ago = match s.as_str() {
+        "now" => 0,
+        "yesterday" => 24 * 60 * 60,
+        _ => {
+            let words: Vec<&str> = s.split(['.', ' ']).filter(|w| !w.is_empty()).collect();
+            let [n, unit, "ago"] = words.as_slice() else {
+                eyre::bail!("unsupported date expression '{s}'");
+            };
+            let n: u64 = n
+                .parse()
+                .with_context(|| format!("unsupported date expression '{s}'"))?;
+            let unit = match unit.trim_end_matches('s') {
+                "second" => 1,
+                "minute" => 60,
+                "hour" => 60 * 60,
+                "day" => 24 * 60 * 60,
+                "week" => 7 * 24 * 60 * 60,
+                "month" => 30 * 24 * 60 * 60,
+                "year" => 365 * 24 * 60 * 60,
+                _ => eyre::bail!("unsupported date expression '{s}'"),
+            };
+            n * unit
+        }
+    };
+
+    Ok(SystemTime::now() - Duration::from_secs(ago))
+}
+
+/// Point the given fully-qualified ref at a new hash, creating it if necessary,
+/// and record the update in its reflog (and `HEAD`'s, if `HEAD` points at it).
+pub fn update(name: &str, hash: &str, message: &str) -> Result<()> {
+    update_in(repository::current_git_dir(), name, hash, message)
+}
+
+/// Like [`update`], but in the repository at the given `.git` directory
+/// rather than the current one.
+pub fn update_in(git_dir: &Path, name: &str, hash: &str, message: &str) -> Result<()> {
+    check_ref_format(name)?;
+
+    let old_hash = resolve_in(git_dir, name)?;
+    write(git_dir, name, hash)?;
+
+    if logs_updates(git_dir, name)? {
+        append_reflog(git_dir, name, old_hash.as_deref(), hash, message)?;
+    }
+    if let Ok(Head::Branch(branch)) = read_head_in(git_dir) {
+        if name == format!("refs/heads/{branch}") && logs_updates(git_dir, "HEAD")? {
+            append_reflog(git_dir, "HEAD", old_hash.as_deref(), hash, message)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Make sure that the given fully-qualified ref name is one git would accept
+/// (see `git check-ref-format`), saying which rule it breaks if not.
+pub fn check_ref_format(name: &str) -> Result<()> {
+    let invalid = |reason: &str| eyre::eyre!("'{name}' is not a valid ref name: {reason}");
+
+    if name.is_empty() {
+        return Err(invalid("it is empty"));
+    }
+    if name == "@" {
+        return Err(invalid("it is '@'"));
+    }
+    if name.starts_with('/') || name.ends_with('/') {
+        return Err(invalid("it begins or ends with '/'"));
+    }
+    if name.ends_with('.') {
+        return Err(invalid("it ends with '.'"));
+    }
+    if name.contains("..") {
+        return Err(invalid("it contains '..'"));
+    }
+    if name.contains("@{") {
+        return Err(invalid("it contains '@{'"));
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|&c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+    {
+        return Err(invalid(&format!("it contains {c:?}")));
+    }
+
+    for component in name.split('/') {
+        if component.is_empty() {
+            return Err(invalid("it contains '//'"));
+        }
+        if component.starts_with('.') {
+            return Err(invalid("a component begins with '.'"));
+        }
+        if component.ends_with(".lock") {
+            return Err(invalid("a component ends with '.lock'"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Like [`check_ref_format`], for the short name of a branch (which also
+/// can't begin with `-`, so it isn't mistaken for an option).
+pub fn check_branch_name(branch: &str) -> Result<()> {
+    if branch.starts_with('-') {
+        eyre::bail!("'{branch}' is not a valid branch name: it begins with '-'");
+    }
+    check_ref_format(&format!("refs/heads/{branch}"))
+}
+
+fn write(git_dir: &Path, name: &str, hash: &str) -> Result<()> {
+    let path = ref_path(git_dir, name)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).with_context(|| format!("create parent of ref {name}"))?;
+    }
+
+    std::fs::write(&path, format!("{hash}\n")).with_context(|| format!("write ref {name}"))
+}
+
+/// Whether updates to the given ref should be logged, according to
+/// `core.logAllRefUpdates`. By default, only `HEAD`, branches, remote-tracking
+/// branches, notes, and the stash keep a reflog; with `always`, every ref
+/// does. Refs that already have a reflog always keep it up to date.
+fn logs_updates(git_dir: &Path, name: &str) -> Result<bool> {
+    if reflog_path(git_dir, name)?.exists() {
+        return Ok(true);
+    }
+
+    let config = Config::read(repository::common_dir_of(git_dir)?.join("config"))?;
+    Ok(
+        match config
+            .get("core.logallrefupdates")
+            .map(str::to_lowercase)
+            .as_deref()
+        {
+            Some("always") => true,
+            Some("false" | "no" | "off" | "0") => false,
+            _ => {
+                name == "HEAD"
+                    || name == "refs/stash"
+                    || ["refs/heads/", "refs/remotes/", "refs/notes/"]
+                        .iter()
+                        .any(|prefix| name.starts_with(prefix))
+            }
+        },
+    )
+}
+
+/// Detach `HEAD` at the given commit, recording the move in `HEAD`'s reflog.
+pub fn set_head_detached(hash: &str, message: &str) -> Result<()> {
+    let git_dir = repository::current_git_dir();
+    let old_hash = resolve("HEAD")?;
+    std::fs::write(git_dir.join("HEAD"), format!("{hash}\n")).context("write HEAD")?;
+
+    append_reflog(git_dir, "HEAD", old_hash.as_deref(), hash, message)
+}
+
+/// Point `HEAD` at the given branch (like `git symbolic-ref`), recording the
+/// move in `HEAD`'s reflog.
+pub fn set_head_branch(branch: &str, message: &str) -> Result<()> {
+    check_branch_name(branch)?;
+
+    let git_dir = repository::current_git_dir();
+    let old_hash = resolve("HEAD")?;
+    std::fs::write(git_dir.join("HEAD"), format!("ref: refs/heads/{branch}\n"))
+        .context("write HEAD")?;
+
+    if let Some(new_hash) = resolve("HEAD")? {
+        append_reflog(git_dir, "HEAD", old_hash.as_deref(), &new_hash, message)?;
+    }
+
+    Ok(())
+}
+
+/// Delete the given fully-qualified ref (whether it's loose or packed), along
+/// with its reflog.
+pub fn delete(name: &str) -> Result<()> {
+    let mut packed = read_packed()?;
+    if packed.iter().any(|packed| packed.name == name) {
+        packed.retain(|packed| packed.name != name);
+        write_packed(&packed)?;
+    }
+
+    let git_dir = repository::current_git_dir();
+    for path in [ref_path(git_dir, name)?, reflog_path(git_dir, name)?] {
+        match std::fs::remove_file(&path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                return Err(err).with_context(|| format!("delete {}", path.display()))
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+/// Point the current branch at a new commit (or `HEAD` itself, if detached).
+pub fn update_head(hash: &str, message: &str) -> Result<()> {
+    match read_head()? {
+        Head::Branch(branch) => update(&format!("refs/heads/{branch}"), hash, message),
+        Head::Detached(_) => update("HEAD", hash, message),
+    }
+}
+
+/// List every ref below the given prefix (e.g. `refs/heads`) as
+/// `(name, hash)` pairs, sorted by name. Loose refs take precedence over
+/// packed ones with the same name.
+pub fn list(prefix: &str) -> Result<Vec<(String, String)>> {
+    let prefix = prefix.trim_end_matches('/');
+    let mut refs: BTreeMap<String, String> = read_packed()?
+        .into_iter()
+        .filter(|packed| is_below(&packed.name, prefix))
+        .map(|packed| (packed.name, packed.hash))
+        .collect();
+
+    for (name, _) in list_loose(prefix)? {
+        if let Some(hash) = resolve(&name)? {
+            refs.insert(name, hash);
+        }
+    }
+
+    Ok(refs.into_iter().collect())
+}
+
+/// List every loose ref below the given prefix as `(name, contents)` pairs,
+/// without following symbolic refs.
+fn list_loose(prefix: &str) -> Result<Vec<(String, String)>> {
+    fn list_in(dir: &Path, name: &str, refs: &mut Vec<(String, String)>) -> Result<()> {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err).with_context(|| format!("list refs in {name}")),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            let child = format!("{name}/{}", entry.file_name().to_string_lossy());
+
+            if entry.file_type()?.is_dir() {
+                list_in(&entry.path(), &child, refs)?;
+            } else {
+                let contents = std::fs::read_to_string(entry.path())
+                    .with_context(|| format!("read ref {child}"))?;
+                refs.push((child, contents.trim_end().to_owned()));
+            }
+        }
+
+        Ok(())
+    }
+
+    let mut refs = Vec::new();
+    list_in(&repository::common_dir().join(prefix), prefix, &mut refs)?;
+    refs.sort_unstable();
+
+    Ok(refs)
+}
+
+fn is_below(name: &str, prefix: &str) -> bool {
+    name.strip_prefix(prefix)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// A ref stored in `.git/packed-refs` rather than in its own file.
+#[derive(Debug, Clone)]
+pub struct PackedRef {
+    pub name: String,
+    pub hash: String,
+    /// For annotated tags, the object the tag (eventually) points at.
+    pub peeled: Option<String>,
+}
+
+/// Read every ref in `.git/packed-refs`, sorted by name.
+pub fn read_packed() -> Result<Vec<PackedRef>> {
+    read_packed_in(repository::current_git_dir())
+}
+
+fn read_packed_in(git_dir: &Path) -> Result<Vec<PackedRef>> {
+    let path = repository::common_dir_of(git_dir)?.join("packed-refs");
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).context("read packed-refs"),
+    };
+
+    let mut refs: Vec<PackedRef> = Vec::new();
+    for line in contents.lines() {
+        // the header lists the traits the file was written with
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+
+        if let Some(peeled) = line.strip_prefix('^') {
+            let packed = refs
+                .last_mut()
+                .ok_or_else(|| eyre::eyre!("peeled line before the first ref in packed-refs"))?;
+            packed.peeled = Some(peeled.to_owned());
+            continue;
+        }
+
+        let (hash, name) = line
+            .split_once(' ')
+            .ok_or_else(|| eyre::eyre!("unexpected line in packed-refs \"{line}\""))?;
+        refs.push(PackedRef {
+            name: name.to_owned(),
+            hash: hash.to_owned(),
+            peeled: None,
+        });
+    }
+    refs.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(refs)
+}
+
+fn write_packed(refs: &[PackedRef]) -> Result<()> {
+    let mut contents = String::from("# pack-refs with: peeled fully-peeled sorted \n");
+    for packed in refs {
+        contents.push_str(&format!("{} {}\n", packed.hash, packed.name));
+        if let Some(peeled) = &packed.peeled {
+            contents.push_str(&format!("^{peeled}\n"));
+        }
+    }
+
+    std::fs::write(repository::common_dir().join("packed-refs"), contents)
+        .context("write packed-refs")
+}
+
+/// Move loose refs into `.git/packed-refs` (like `git pack-refs`). Only tags
+/// are packed unless `all` is set, in which case every ref below `refs/` is.
+/// Symbolic refs are always left loose.
+pub fn pack(all: bool) -> Result<()> {
+    let prefix = if all { "refs" } else { "refs/tags" };
+    let loose: Vec<(String, String)> = list_loose(prefix)?
+        .into_iter()
+        .filter(|(_, contents)| !contents.starts_with("ref: "))
+        .collect();
+
+    let mut refs: BTreeMap<String, PackedRef> = read_packed()?
+        .into_iter()
+        .map(|packed| (packed.name.clone(), packed))
+        .collect();
+    for (name, hash) in loose.iter() {
+        let peeled = match Tag::peel(hash)? {
+            (peeled, _) if peeled != *hash => Some(peeled),
+            _ => None,
+        };
+        refs.insert(
+            name.clone(),
+            PackedRef {
+                name: name.clone(),
+                hash: hash.clone(),
+                peeled,
+            },
+        );
+    }
+    write_packed(&refs.into_values().collect::<Vec<_>>())?;
+
+    // only remove the loose refs once they're safely packed
+    for (name, _) in loose.iter() {
+        let path = repository::common_dir().join(name);
+        std::fs::remove_file(&path).with_context(|| format!("delete loose ref {name}"))?;
+        remove_empty_parents(name);
+    }
+
+    Ok(())
+}
+
+/// Remove the directories that held a deleted loose ref if they're now empty,
+/// like `refs/heads/feature/` after packing `refs/heads/feature/x`. The
+/// top-level directories like `refs/heads` are always kept.
+fn remove_empty_parents(name: &str) {
+    let mut dir = Path::new(name).parent();
+    while let Some(parent) = dir.filter(|dir| dir.components().count() > 2) {
+        // this fails (and stops) as soon as a directory isn't empty
+        if std::fs::remove_dir(repository::common_dir().join(parent)).is_err() {
+            break;
+        }
+        dir = parent.parent();
+    }
+}
+
+/// A single line from a ref's log, recording one update to the ref.
+#[derive(Debug)]
+pub struct ReflogEntry {
+    pub old_hash: String,
+    pub new_hash: String,
+    pub committer: CommitAttribution,
+    pub message: String,
+}
+
+impl Display for ReflogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {}\t{}",
+            self.old_hash, self.new_hash, self.committer, self.message
+        )
+    }
+}
+
+impl FromStr for ReflogEntry {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // <old> <new> <name> <<email>> <timestamp> <tz>\t<message>
+        let (header, message) = s.split_once('\t').unwrap_or((s, ""));
+        let mut parts = header.splitn(3, ' ');
+        let (Some(old_hash), Some(new_hash), Some(committer)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            eyre::bail!("malformed reflog entry: {s}");
+        };
+
+        Ok(Self {
+            old_hash: old_hash.to_owned(),
+            new_hash: new_hash.to_owned(),
+            committer: committer.parse().context("parse reflog committer")?,
+            message: message.to_owned(),
+        })
+    }
+}
+
+fn reflog_path(git_dir: &Path, name: &str) -> Result<PathBuf> {
+    Ok(ref_dir(git_dir, name)?.join("logs").join(name))
+}
+
+/// Read the log for the given fully-qualified ref, oldest entry first. A ref
+/// without a log has no entries.
+pub fn read_reflog(name: &str) -> Result<Vec<ReflogEntry>> {
+    let contents = match std::fs::read_to_string(reflog_path(repository::current_git_dir(), name)?)
+    {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("read reflog for {name}")),
+    };
+
+    contents.lines().map(str::parse).collect()
+}
+
+/// Remove `<name>@{n}` from the given fully-qualified ref's log. Removing the
+/// newest entry (`n = 0`) also moves the ref back to where it pointed before,
+/// deleting it if there are no entries left.
+pub fn delete_reflog_entry(name: &str, n: usize) -> Result<()> {
+    let mut entries = read_reflog(name)?;
+    let Some(idx) = entries.len().checked_sub(n + 1) else {
+        eyre::bail!("{name}@{{{n}}}: no such reflog entry");
+    };
+    entries.remove(idx);
+
+    if n == 0 {
+        match entries.last() {
+            Some(entry) => write(repository::current_git_dir(), name, &entry.new_hash)?,
+            None => return delete(name),
+        }
+    }
+
+    write_reflog(name, &entries)
+}
+
+fn write_reflog(name: &str, entries: &[ReflogEntry]) -> Result<()> {
+    let contents: String = entries.iter().map(|entry| format!("{entry}\n")).collect();
+    std::fs::write(reflog_path(repository::current_git_dir(), name)?, contents)
+        .with_context(|| format!("write reflog for {name}"))
+}
+
+/// Record an update to the given fully-qualified ref in its log.
+fn append_reflog(
+    git_dir: &Path,
+    name: &str,
+    old_hash: Option<&str>,
+    new_hash: &str,
+    message: &str,
+) -> Result<()> {
+    let path = reflog_path(git_dir, name)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("create parent of reflog {name}"))?;
+    }
+
+    let entry = ReflogEntry {
+        old_hash: match old_hash {
+            Some(old_hash) => old_hash.to_owned(),
+            None => HashAlgorithm::current()?.null_hash(),
+        },
+        new_hash: new_hash.to_owned(),
+        committer: CommitAttribution::committer()?,
+        message: message.to_owned(),
+    };
+
+    let mut f = std::fs::File::options()
+        .append(true)
+        .create(true)
+        .open(&path)
+        .with_context(|| format!("open reflog for {name}"))?;
+    writeln!(f, "{entry}").with_context(|| format!("append to reflog for {name}"))
+}
@@ -0,0 +1,192 @@
+use eyre::Result;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// How to talk to a remote repository.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Http,
+    Https,
+    Ssh,
+    Git,
+    File,
+}
+
+impl Transport {
+    fn scheme(self) -> &'static str {
+        match self {
+            Self::Http => "http",
+            Self::Https => "https",
+            Self::Ssh => "ssh",
+            Self::Git => "git",
+            Self::File => "file",
+        }
+    }
+}
+
+/// The location of a remote repository, in any of the forms git accepts:
+/// `<scheme>://[<user>@]<host>[:<port>]/<path>`, scp-like
+/// `[<user>@]<host>:<path>` (which is ssh), or a local path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteUrl {
+    pub transport: Transport,
+    pub user: Option<String>,
+    /// Empty for local repositories.
+    pub host: String,
+    pub port: Option<u16>,
+    /// The path on the host, without any query string or fragment (or
+    /// trailing slashes).
+    pub path: String,
+    /// Whether the URL was given in the scp-like form.
+    scp_like: bool,
+}
+
+impl RemoteUrl {
+    /// The base URL for git's smart HTTP protocol, which is the only transport
+    /// supported so far.
+    pub fn http_base(&self) -> Result<String> {
+        match self.transport {
+            Transport::Http | Transport::Https => Ok(self.to_string()),
+            transport => eyre::bail!(
+                "unsupported transport '{}' for {self}; only http(s) remotes are supported",
+                transport.scheme()
+            ),
+        }
+    }
+
+    /// The directory `clone` creates by default, like git: the last component
+    /// of the path without any `.git` suffix (so both `foo.git` and `foo/.git`
+    /// become `foo`), or the host if there's no path.
+    pub fn default_dir(&self) -> String {
+        let path = self.path.trim_end_matches('/');
+        let path = path.strip_suffix("/.git").unwrap_or(path);
+        let name = path.rsplit(['/', ':']).next().unwrap_or_default();
+        let name = name.strip_suffix(".git").unwrap_or(name);
+
+        if name.is_empty() {
+            self.host.clone()
+        } else {
+            name.to_owned()
+        }
+    }
+}
+
+impl FromStr for RemoteUrl {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() {
+            eyre::bail!("empty remote url");
+        }
+
+        let Some((scheme, rest)) = s.split_once("://") else {
+            return Ok(parse_scp_like(s).unwrap_or_else(|| Self {
+                transport: Transport::File,
+                user: None,
+                host: String::new(),
+                port: None,
+                path: trim_path(s).to_owned(),
+                scp_like: false,
+            }));
+        };
+
+        let transport = match scheme.to_lowercase().as_str() {
+            "http" => Transport::Http,
+            "https" => Transport::Https,
+            "ssh" | "git+ssh" | "ssh+git" => Transport::Ssh,
+            "git" => Transport::Git,
+            "file" => Transport::File,
+            _ => eyre::bail!("unsupported url scheme '{scheme}' in {s}"),
+        };
+
+        let rest = rest.split(['?', '#']).next().unwrap_or_default();
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => rest.split_at(idx),
+            None => (rest, ""),
+        };
+        let (user, host_port) = match authority.rsplit_once('@') {
+            Some((user, host_port)) => (Some(user.to_owned()), host_port),
+            None => (None, authority),
+        };
+        let (host, port) = match host_port.rsplit_once(':') {
+            // brackets are only used around IPv6 addresses, which contain colons
+            Some((host, port)) if !port.contains(']') => (
+                host,
+                Some(
+                    port.parse()
+                        .map_err(|_| eyre::eyre!("invalid port '{port}' in {s}"))?,
+                ),
+            ),
+            _ => (host_port, None),
+        };
+
+        if host.is_empty() && transport != Transport::File {
+            eyre::bail!("no host in {s}");
+        }
+
+        Ok(Self {
+            transport,
+            user,
+            host: host.to_owned(),
+            port,
+            path: trim_path(path).to_owned(),
+            scp_like: false,
+        })
+    }
+}
+
+/// Parse `[<user>@]<host>:<path>`. Like git, this only applies if there's a
+/// colon before the first slash, so local paths containing colons still work
+/// (e.g. `./foo:bar`).
+fn parse_scp_like(s: &str) -> Option<RemoteUrl> {
+    let (authority, path) = s.split_once(':')?;
+    if authority.is_empty() || authority.contains('/') {
+        return None;
+    }
+
+    let (user, host) = match authority.rsplit_once('@') {
+        Some((user, host)) => (Some(user.to_owned()), host),
+        None => (None, authority),
+    };
+
+    Some(RemoteUrl {
+        transport: Transport::Ssh,
+        user,
+        host: host.to_owned(),
+        port: None,
+        path: trim_path(path).to_owned(),
+        scp_like: true,
+    })
+}
+
+fn trim_path(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" if path.starts_with('/') => "/",
+        trimmed => trimmed,
+    }
+}
+
+impl Display for RemoteUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.scp_like {
+            if let Some(user) = &self.user {
+                write!(f, "{user}@")?;
+            }
+            return write!(f, "{}:{}", self.host, self.path);
+        }
+
+        if self.transport == Transport::File && self.host.is_empty() {
+            return f.write_str(&self.path);
+        }
+
+        write!(f, "{}://", self.transport.scheme())?;
+        if let Some(user) = &self.user {
+            write!(f, "{user}@")?;
+        }
+        f.write_str(&self.host)?;
+        if let Some(port) = self.port {
+            write!(f, ":{port}")?;
+        }
+        f.write_str(&self.path)
+    }
+}
@@ -0,0 +1,421 @@
+use crate::error::GitError;
+use crate::hash::HashAlgorithm;
+use crate::object::{alternates, objects_dir, ObjectBuf, ObjectHash, ObjectType};
+use crate::object_store::FsObjectStore;
+use crate::pack::{PackIndex, PackedHeader};
+use eyre::{Context, Result};
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+static WORK_TREE: OnceLock<PathBuf> = OnceLock::new();
+static PREFIX: OnceLock<PathBuf> = OnceLock::new();
+static GIT_DIR: OnceLock<PathBuf> = OnceLock::new();
+static COMMON_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// A repository's object store, made up of loose objects and packs.
+#[derive(Debug)]
+pub struct Repository {
+    loose: FsObjectStore,
+    packs: Vec<PackIndex>,
+}
+
+impl Repository {
+    /// Open the repository in the current directory, reading the index of
+    /// every pack in `.git/objects/pack` (but not the packs themselves).
+    pub fn open() -> Result<Self> {
+        Self::open_in(objects_dir())
+    }
+
+    /// Open the object store in the given objects directory, which needn't be
+    /// in the current directory. Packs in any alternate objects directories
+    /// are included too.
+    pub fn open_in(objects_dir: impl AsRef<Path>) -> Result<Self> {
+        let objects_dir = objects_dir.as_ref();
+        Ok(Self {
+            loose: FsObjectStore::new(objects_dir),
+            packs: read_pack_indexes(objects_dir)?,
+        })
+    }
+
+    /// Check whether an object exists, either loose or in a pack.
+    pub fn has_object(&self, hash: &ObjectHash) -> bool {
+        ObjectBuf::exists_loose(self.loose.objects_dir(), hash.as_hex())
+            || self
+                .packs
+                .iter()
+                .any(|pack| pack.find_offset(hash).is_some())
+    }
+
+    /// Find the type of an object by reading just its header. For deltas in
+    /// a pack, that means following the chain of bases back to a whole object.
+    pub fn object_type(&self, hash: &ObjectHash) -> Result<ObjectType> {
+        let objects_dir = self.loose.objects_dir();
+        if ObjectBuf::exists_loose(objects_dir, hash.as_hex()) {
+            return Ok(ObjectBuf::read_loose(objects_dir, hash.as_hex())?.object_type);
+        }
+
+        for pack in self.packs.iter() {
+            let Some(mut offset) = pack.find_offset(hash) else {
+                continue;
+            };
+
+            loop {
+                match pack.read_header(offset).with_context(|| {
+                    format!("read header of {hash} in {}", pack.pack_path.display())
+                })? {
+                    PackedHeader::Whole(object_type) => return Ok(object_type),
+                    PackedHeader::OfsDelta(base_offset) => offset = base_offset,
+                    PackedHeader::RefDelta(base) => match pack.find_offset(&base) {
+                        Some(base_offset) => offset = base_offset,
+                        None => return self.object_type(&base),
+                    },
+                }
+            }
+        }
+
+        eyre::bail!("object {hash} not found")
+    }
+
+    /// List every loose object in the objects directory (but not in any of
+    /// its alternates), sorted by hash so the order doesn't depend on the
+    /// filesystem. Each hash is put back together from the `xx/yyyy...`
+    /// layout; anything else in the objects directory is skipped.
+    pub fn loose_objects(&self) -> Result<impl Iterator<Item = ObjectHash>> {
+        let rest_len = HashAlgorithm::current()?.hex_len() - 2;
+        let objects_dir = self.loose.objects_dir();
+        let entries = std::fs::read_dir(objects_dir)
+            .with_context(|| format!("read {}", objects_dir.display()))?;
+
+        let mut hashes = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let dir_name = entry.file_name();
+            let Some(prefix) = dir_name
+                .to_str()
+                .filter(|name| name.len() == 2 && name.bytes().all(|b| b.is_ascii_hexdigit()))
+            else {
+                continue;
+            };
+
+            for object in std::fs::read_dir(entry.path())? {
+                let file_name = object?.file_name();
+                let Some(rest) = file_name.to_str().filter(|name| name.len() == rest_len) else {
+                    continue;
+                };
+                // this also makes sure the name is all (lowercase) hex
+                if let Ok(hash) = format!("{prefix}{rest}").parse::<ObjectHash>() {
+                    hashes.push(hash);
+                }
+            }
+        }
+        hashes.sort();
+
+        Ok(hashes.into_iter())
+    }
+}
+
+/// Read the index of every pack in the given objects directory and in any of
+/// its alternates.
+pub(crate) fn read_pack_indexes(objects_dir: &Path) -> Result<Vec<PackIndex>> {
+    let mut packs = Vec::new();
+    read_packs(objects_dir, &mut packs)?;
+    for alternate in alternates(objects_dir)? {
+        read_packs(&alternate, &mut packs)?;
+    }
+    Ok(packs)
+}
+
+/// Read the index of every pack in the given objects directory.
+fn read_packs(objects_dir: &Path, packs: &mut Vec<PackIndex>) -> Result<()> {
+    let entries = match std::fs::read_dir(objects_dir.join("pack")) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err).context("read pack directory"),
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "idx") {
+            packs.push(
+                PackIndex::read(&path)
+                    .with_context(|| format!("read pack index {}", path.display()))?,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Find the repository that the current directory is in by walking up until a
+/// directory containing `.git` is found, returning the top of its working
+/// tree. Paths in the working tree are relative to that (see
+/// [`work_tree_path`]), and the directory we started in is remembered as the
+/// [`prefix`].
+///
+/// In a linked working tree, `.git` is a file pointing at its own git
+/// directory instead, which in turn points at the [`common_dir`] it shares
+/// with the main working tree. A bare repository (like one made by
+/// `clone --bare`) is found by its `HEAD`, `objects` and `refs`, and has no
+/// working tree, so the git directory itself is returned instead.
+pub fn enter() -> Result<PathBuf> {
+    let cwd = std::env::current_dir().context("get current directory")?;
+    for dir in cwd.ancestors() {
+        let dot_git = dir.join(".git");
+        if dot_git.exists() {
+            let git_dir = read_gitfile(&dot_git)?;
+            let _ = COMMON_DIR.set(common_dir_of(&git_dir)?);
+            let _ = GIT_DIR.set(git_dir);
+            let _ = WORK_TREE.set(dir.to_owned());
+
+            let prefix = cwd.strip_prefix(dir).expect("dir is an ancestor of cwd");
+            tracing::debug!(
+                "found repository at {} (prefix {})",
+                dir.display(),
+                prefix.display()
+            );
+            let _ = PREFIX.set(prefix.to_owned());
+            return Ok(dir.to_owned());
+        }
+
+        // a `.git` directory looks like a bare repository too, but belongs to
+        // the working tree above it
+        if dir.file_name() != Some(OsStr::new(".git")) && is_bare_repository(dir) {
+            tracing::debug!("found bare repository at {}", dir.display());
+            let _ = COMMON_DIR.set(common_dir_of(dir)?);
+            let _ = GIT_DIR.set(dir.to_owned());
+            return Ok(dir.to_owned());
+        }
+    }
+
+    Err(GitError::NotARepository.into())
+}
+
+/// Whether `dir` is the git directory of a repository itself, rather than a
+/// working tree.
+fn is_bare_repository(dir: &Path) -> bool {
+    dir.join("HEAD").is_file() && dir.join("objects").is_dir() && dir.join("refs").is_dir()
+}
+
+/// Whether the repository that was [`enter`]ed is bare.
+pub(crate) fn is_bare() -> bool {
+    GIT_DIR.get().is_some() && WORK_TREE.get().is_none()
+}
+
+/// The absolute path of the top of the working tree (once [`enter`]ed).
+pub(crate) fn toplevel() -> Result<PathBuf> {
+    match WORK_TREE.get() {
+        Some(root) => Ok(root.to_owned()),
+        None if is_bare() => eyre::bail!("this operation must be run in a work tree"),
+        None => std::env::current_dir().context("get current directory"),
+    }
+}
+
+/// The top of the working tree (once [`enter`]ed), or the current directory
+/// before then.
+pub(crate) fn work_tree() -> &'static Path {
+    WORK_TREE.get().map_or(Path::new("."), PathBuf::as_path)
+}
+
+/// Where to find a path that's relative to the top of the working tree (like
+/// an index entry's name), given that the command may have been run from
+/// somewhere else.
+pub(crate) fn work_tree_path(path: impl AsRef<Path>) -> PathBuf {
+    match WORK_TREE.get() {
+        Some(root) => root.join(path),
+        None => path.as_ref().to_owned(),
+    }
+}
+
+/// The path of the git directory as git shows it: relative when the command
+/// was run from the top of the working tree (or of a bare repository), and
+/// absolute otherwise.
+pub(crate) fn git_dir() -> Result<PathBuf> {
+    let cwd = std::env::current_dir().context("get current directory")?;
+    let git_dir = current_git_dir();
+    match git_dir.strip_prefix(&cwd) {
+        Ok(relative) if relative.as_os_str().is_empty() => Ok(PathBuf::from(".")),
+        Ok(relative) if prefix().as_os_str().is_empty() => Ok(relative.to_owned()),
+        _ => Ok(git_dir.to_owned()),
+    }
+}
+
+/// The git directory of the current working tree (once [`enter`]ed), which
+/// holds its `HEAD`, index and other state of its own. This is `.git` itself
+/// in the main working tree.
+pub(crate) fn current_git_dir() -> &'static Path {
+    GIT_DIR.get().map_or(Path::new(".git"), PathBuf::as_path)
+}
+
+/// The directory holding what every working tree of the current repository
+/// shares, like its objects, branches and config. This is `.git` itself in
+/// the main working tree.
+pub(crate) fn common_dir() -> &'static Path {
+    COMMON_DIR.get().map_or(Path::new(".git"), PathBuf::as_path)
+}
+
+/// Find the git directory a `.git` entry stands for: either the entry itself,
+/// or the directory a `gitdir: <dir>` file points at (relative to the file).
+pub(crate) fn read_gitfile(dot_git: &Path) -> Result<PathBuf> {
+    if dot_git.is_dir() {
+        return Ok(dot_git.to_owned());
+    }
+
+    let contents =
+        std::fs::read_to_string(dot_git).with_context(|| format!("read {}", dot_git.display()))?;
+    let target = contents
+        .trim_end()
+        .strip_prefix("gitdir: ")
+        .ok_or_else(|| eyre::eyre!("invalid gitfile format: {}", dot_git.display()))?;
+
+    Ok(dot_git.parent().unwrap_or(Path::new("")).join(target))
+}
+
+/// The common directory of the working tree whose git directory is `git_dir`:
+/// wherever its `commondir` file points (relative to `git_dir`), or `git_dir`
+/// itself if it doesn't have one.
+pub(crate) fn common_dir_of(git_dir: &Path) -> Result<PathBuf> {
+    match std::fs::read_to_string(git_dir.join("commondir")) {
+        Ok(contents) => Ok(git_dir.join(contents.trim_end())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(git_dir.to_owned()),
+        Err(err) => Err(err).with_context(|| format!("read {}/commondir", git_dir.display())),
+    }
+}
+
+/// Whether the command was run from inside the working tree, rather than
+/// from inside the `.git` directory (or a bare repository).
+pub(crate) fn is_inside_work_tree() -> bool {
+    !is_bare() && !prefix().starts_with(".git")
+}
+
+/// The directory the command was run from, relative to the top of the working
+/// tree (empty when run from the top, or outside of a repository).
+pub(crate) fn prefix() -> &'static Path {
+    PREFIX.get().map_or(Path::new(""), PathBuf::as_path)
+}
+
+/// Resolve a path given on the command line (relative to where the command
+/// was run) to one relative to the top of the working tree.
+pub(crate) fn from_prefix(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    if path.is_absolute() {
+        return path.to_owned();
+    }
+
+    let mut resolved = PathBuf::new();
+    for component in prefix().join(path).components() {
+        match component {
+            Component::CurDir => (),
+            // paths outside of the working tree keep their leading `..`
+            Component::ParentDir if !resolved.ends_with("..") && resolved.pop() => (),
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+/// The inverse of [`from_prefix`]: show a path relative to the top of the
+/// working tree relative to where the command was run instead.
+pub(crate) fn to_prefix(path: impl AsRef<OsStr>) -> PathBuf {
+    let path = Path::new(path.as_ref());
+    let mut prefix = prefix().components().peekable();
+    let mut rest = path.components().peekable();
+    while prefix.peek().is_some() && prefix.peek() == rest.peek() {
+        prefix.next();
+        rest.next();
+    }
+
+    let relative: PathBuf = prefix.map(|_| Component::ParentDir).chain(rest).collect();
+    match relative.as_os_str().is_empty() {
+        true => PathBuf::from("."),
+        false => relative,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loose_objects_are_sorted_and_skip_other_files() {
+        let objects_dir = tempfile::tempdir().unwrap();
+        let hashes = [
+            "ff00000000000000000000000000000000000000",
+            "0a00000000000000000000000000000000000001",
+            "0a00000000000000000000000000000000000000",
+        ];
+        let others = [
+            "info/alternates",
+            "pack/pack-0000000000000000000000000000000000000000.pack",
+            // not hex, uppercase, and the wrong length
+            "zz/00000000000000000000000000000000000000",
+            "0A/00000000000000000000000000000000000000",
+            "0b/0000",
+            "0b/00000000000000000000000000000000000000.tmp",
+        ];
+        let loose = hashes.map(|hash| format!("{}/{}", &hash[..2], &hash[2..]));
+        for name in loose.iter().map(String::as_str).chain(others) {
+            let path = objects_dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"").unwrap();
+        }
+
+        let repo = Repository::open_in(objects_dir.path()).unwrap();
+        let found: Vec<String> = repo
+            .loose_objects()
+            .unwrap()
+            .map(|hash| hash.to_string())
+            .collect();
+        assert_eq!(found, [hashes[2], hashes[1], hashes[0]]);
+    }
+
+    #[test]
+    fn linked_work_tree_shares_refs_with_common_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let common_dir = dir.path().join("main/.git");
+        let git_dir = common_dir.join("worktrees/linked");
+        let work_tree = dir.path().join("linked");
+        let hash = "0a00000000000000000000000000000000000000";
+
+        std::fs::create_dir_all(common_dir.join("refs/heads")).unwrap();
+        std::fs::create_dir_all(&git_dir).unwrap();
+        std::fs::create_dir_all(&work_tree).unwrap();
+        std::fs::write(common_dir.join("refs/heads/main"), format!("{hash}\n")).unwrap();
+        std::fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        std::fs::write(git_dir.join("commondir"), "../..\n").unwrap();
+        std::fs::write(
+            work_tree.join(".git"),
+            "gitdir: ../main/.git/worktrees/linked\n",
+        )
+        .unwrap();
+
+        let found = read_gitfile(&work_tree.join(".git")).unwrap();
+        assert_eq!(
+            found.canonicalize().unwrap(),
+            git_dir.canonicalize().unwrap()
+        );
+        assert_eq!(
+            common_dir_of(&found).unwrap().canonicalize().unwrap(),
+            common_dir.canonicalize().unwrap()
+        );
+        // `HEAD` is the working tree's own, but the branch it points at isn't
+        assert_eq!(
+            crate::refs::resolve_submodule_head(&work_tree).unwrap(),
+            Some(hash.to_owned())
+        );
+    }
+
+    #[test]
+    fn bare_repositories_need_head_objects_and_refs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("objects")).unwrap();
+        std::fs::create_dir(dir.path().join("refs")).unwrap();
+        assert!(!is_bare_repository(dir.path()));
+
+        std::fs::write(dir.path().join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        assert!(is_bare_repository(dir.path()));
+
+        std::fs::remove_dir(dir.path().join("refs")).unwrap();
+        assert!(!is_bare_repository(dir.path()));
+    }
+}
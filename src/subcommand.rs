@@ -1,13 +1,38 @@
+pub mod am;
+pub mod apply;
+pub mod archive;
 pub mod cat_file;
 pub mod checkout;
+pub mod checkout_index;
+pub mod cherry_pick;
 pub mod clone;
 pub mod commit_tree;
+pub mod describe;
+pub mod diff;
+pub mod fetch;
+pub mod format_patch;
 pub mod hash_object;
 pub mod index_pack;
 pub mod init;
+pub mod log;
 pub mod ls_files;
 pub mod ls_tree;
+pub mod merge;
+pub mod merge_base;
+pub mod notes;
+pub mod pack_refs;
+pub mod pull;
+pub mod push;
+pub mod read_tree;
+pub mod reflog;
+pub mod remote;
+pub mod rev_list;
+pub mod rev_parse;
+pub mod revert;
+pub mod show_branch;
+pub mod stash;
 pub mod status;
 pub mod unpack_objects;
 pub mod verify_pack;
+pub mod worktree;
 pub mod write_tree;
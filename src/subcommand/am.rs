@@ -0,0 +1,213 @@
+use crate::commit::{self, Commit, CommitAttribution};
+use crate::hooks;
+use crate::index::Index;
+use crate::object::{Object, ObjectHashable};
+use crate::output::outln;
+use crate::patch;
+use crate::refs;
+use crate::repository;
+use crate::subcommand::apply::apply;
+use crate::subcommand::checkout::update_working_tree;
+use eyre::{Context, Result};
+use std::path::PathBuf;
+
+/// Where the state of an `am` in progress is kept, so it can be aborted.
+fn state_dir() -> PathBuf {
+    repository::current_git_dir().join("rebase-apply")
+}
+
+/// A patch from a mailbox, as written by `format-patch`.
+struct Message {
+    author: CommitAttribution,
+    subject: String,
+    body: String,
+    diff: Vec<u8>,
+}
+
+/// Apply each patch in the given mailboxes to the working tree and index, and
+/// commit it with its original author, date, and message.
+///
+/// If a patch doesn't apply, its rejected hunks are left behind (like
+/// `apply`) and nothing else is committed; `--abort` goes back to where the
+/// branch was before.
+// FIXME: make sure that working directory is clean first
+pub fn run(mboxes: &[String]) -> Result<()> {
+    if state_dir().exists() {
+        eyre::bail!("previous am still in progress; use --abort to restore the original branch");
+    }
+
+    let mut messages = Vec::new();
+    for mbox in mboxes.iter() {
+        let contents = std::fs::read(mbox).with_context(|| format!("read mailbox {mbox}"))?;
+        for message in split_mbox(&contents) {
+            messages.push(parse_message(message).with_context(|| format!("parse {mbox}"))?);
+        }
+    }
+    if messages.is_empty() {
+        eyre::bail!("no patches in input");
+    }
+
+    let mut head =
+        refs::resolve("HEAD")?.ok_or_else(|| eyre::eyre!("HEAD does not point to a commit yet"))?;
+    std::fs::create_dir_all(state_dir()).context("create rebase-apply")?;
+    std::fs::write(state_dir().join("orig-head"), format!("{head}\n"))
+        .context("write original HEAD")?;
+
+    for message in messages {
+        outln!("Applying: {}", message.subject);
+
+        let patches = patch::parse(&message.diff)?;
+        apply(&patches, false, true).with_context(|| {
+            format!(
+                "patch failed at '{}'; use --abort to restore the original branch",
+                message.subject
+            )
+        })?;
+
+        // like git, the hook sees the patch already applied, but can still
+        // stop it from being committed
+        hooks::run_required("pre-applypatch", &[]).with_context(|| {
+            format!(
+                "not committing '{}'; use --abort to restore the original branch",
+                message.subject
+            )
+        })?;
+        let tree_hash = Index::read_default()?.write_tree()?;
+        let new_commit = Commit {
+            tree_hash: tree_hash.to_string(),
+            parent_hashes: vec![head],
+            author: message.author,
+            committer: CommitAttribution::committer()?,
+            extra_headers: Vec::new(),
+            message: match message.body.is_empty() {
+                true => format!("{}\n", message.subject),
+                false => format!("{}\n\n{}\n", message.subject, message.body),
+            }
+            .into_bytes(),
+        };
+        let new_hash = Object::commit(new_commit).hash(true)?;
+        refs::update_head(new_hash.as_hex(), &format!("am: {}", message.subject))?;
+        hooks::notify("post-applypatch", &[])?;
+        head = new_hash.to_string();
+    }
+
+    std::fs::remove_dir_all(state_dir()).context("remove rebase-apply")
+}
+
+/// Give up on the `am` in progress, moving the branch, index, and working tree
+/// back to where they were before it started.
+pub fn abort() -> Result<()> {
+    let orig_head = match std::fs::read_to_string(state_dir().join("orig-head")) {
+        Ok(orig_head) => orig_head.trim().to_owned(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            eyre::bail!("no am in progress")
+        }
+        Err(err) => return Err(err).context("read original HEAD"),
+    };
+
+    // the index includes anything a failed patch staged, so it's removed too
+    let current_tree = Index::read_default_or_empty()?.write_tree()?;
+    let orig_tree = Commit::read_at_hash(&orig_head)?.tree_hash;
+    update_working_tree(Some(current_tree.as_hex()), &orig_tree).context("update working tree")?;
+    refs::update_head(&orig_head, "am --abort")?;
+
+    std::fs::remove_dir_all(state_dir()).context("remove rebase-apply")
+}
+
+/// Split a mailbox into its messages, each of which starts with a `From `
+/// line.
+fn split_mbox(contents: &[u8]) -> Vec<&[u8]> {
+    let mut messages = Vec::new();
+    let mut start = None;
+    let mut offset = 0;
+    for line in contents.split_inclusive(|&b| b == b'\n') {
+        if line.starts_with(b"From ") {
+            if let Some(start) = start {
+                messages.push(&contents[start..offset]);
+            }
+            start = Some(offset);
+        }
+        offset += line.len();
+    }
+    if let Some(start) = start {
+        messages.push(&contents[start..]);
+    }
+    messages
+}
+
+/// Pull the author, subject, and body out of a message's headers and commit
+/// message, leaving the diff that follows the `---` line.
+fn parse_message(message: &[u8]) -> Result<Message> {
+    // the diff may not be valid UTF-8, but the headers and message must be
+    let split = find_diff(message);
+    let (text, diff) = message.split_at(split);
+    let text = std::str::from_utf8(text).context("message should contain valid UTF-8")?;
+    let (headers, body) = text.split_once("\n\n").unwrap_or((text, ""));
+
+    let (mut from, mut date, mut subject) = (None, None, None);
+    let mut unfolded: Vec<String> = Vec::new();
+    for line in headers.lines().skip(1) {
+        // long headers are continued on lines starting with whitespace
+        match (line.starts_with([' ', '\t']), unfolded.last_mut()) {
+            (true, Some(header)) => header.push_str(line),
+            _ => unfolded.push(line.to_owned()),
+        }
+    }
+    for header in unfolded.iter() {
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "from" => from = Some(value.trim()),
+            "date" => date = Some(value.trim()),
+            "subject" => subject = Some(value.trim()),
+            _ => (),
+        }
+    }
+
+    let from = from.ok_or_else(|| eyre::eyre!("patch is missing a From header"))?;
+    let (name, email) = from
+        .split_once('<')
+        .and_then(|(name, rest)| Some((name.trim(), rest.split_once('>')?.0)))
+        .ok_or_else(|| eyre::eyre!("invalid From header: {from}"))?;
+    let (timestamp, tz_offset) = match date {
+        Some(date) => {
+            commit::parse_date(date).ok_or_else(|| eyre::eyre!("invalid Date header: {date}"))?
+        }
+        None => (std::time::SystemTime::now(), 0),
+    };
+
+    let subject = subject.ok_or_else(|| eyre::eyre!("patch is missing a Subject header"))?;
+    // drop the `[PATCH n/m]` prefix added by `format-patch`
+    let subject = match subject.strip_prefix('[') {
+        Some(rest) => rest
+            .split_once(']')
+            .map_or(subject, |(_, rest)| rest.trim_start()),
+        None => subject,
+    };
+
+    Ok(Message {
+        author: CommitAttribution {
+            name: name.as_bytes().to_vec(),
+            email: email.as_bytes().to_vec(),
+            timestamp,
+            tz_offset,
+        },
+        subject: subject.to_owned(),
+        body: body.trim().to_owned(),
+        diff: diff.to_vec(),
+    })
+}
+
+/// The offset where a message's diff starts: the `---` line that separates
+/// it from the commit message, or else the first `diff --git` header.
+fn find_diff(message: &[u8]) -> usize {
+    let mut offset = 0;
+    for line in message.split_inclusive(|&b| b == b'\n') {
+        if line == b"---\n" || line.starts_with(b"diff --git ") {
+            return offset;
+        }
+        offset += line.len();
+    }
+    message.len()
+}
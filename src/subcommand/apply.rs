@@ -0,0 +1,158 @@
+use crate::index::Index;
+use crate::patch::{self, FilePatch};
+use crate::repository;
+use crate::subcommand::checkout;
+use eyre::{Context, Result};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Apply a patch (a unified diff, like the ones `format-patch` writes) to the
+/// working tree. With `check`, only report whether it would apply; with
+/// `index`, also stage the changes.
+///
+/// Hunks that don't apply (even with some fuzz) are written to a `.rej` file
+/// next to the file they're for, and the whole patch is reported as failed.
+pub fn run(patch: &str, check: bool, index: bool) -> Result<()> {
+    let contents = std::fs::read(patch).with_context(|| format!("read patch {patch}"))?;
+
+    let patches = patch::parse(&contents)?;
+    if patches.is_empty() {
+        eyre::bail!("no valid patches in input");
+    }
+
+    apply(&patches, check, index)
+}
+
+/// The result of applying one file's patch.
+struct Applied<'a> {
+    patch: &'a FilePatch,
+    contents: Vec<u8>,
+    rejected: Vec<usize>,
+}
+
+/// Apply each file's changes to the working tree (and with `index`, stage
+/// them), or with `check`, just make sure they'd apply. Nothing is written
+/// unless every file exists (or doesn't) as the patch expects.
+pub(crate) fn apply(patches: &[FilePatch], check: bool, index: bool) -> Result<()> {
+    let mut results = Vec::with_capacity(patches.len());
+    for patch in patches.iter() {
+        let original = match (&patch.old_path, &patch.new_path) {
+            (Some(old_path), _) => std::fs::read(repository::work_tree_path(old_path))
+                .with_context(|| {
+                    format!(
+                        "{}: does not exist in working tree",
+                        old_path.to_string_lossy()
+                    )
+                })?,
+            (None, Some(new_path)) if repository::work_tree_path(new_path).exists() => eyre::bail!(
+                "{}: already exists in working directory",
+                new_path.to_string_lossy()
+            ),
+            (None, _) => Vec::new(),
+        };
+
+        let (contents, rejected) = patch.apply(&original);
+        results.push(Applied {
+            patch,
+            contents,
+            rejected,
+        });
+    }
+
+    let failed = results.iter().any(|result| !result.rejected.is_empty());
+    if check {
+        for result in results.iter() {
+            for &i in result.rejected.iter() {
+                eprintln!(
+                    "error: patch failed: {}:{}",
+                    result.patch.path().to_string_lossy(),
+                    result.patch.hunks[i].old_start
+                );
+            }
+        }
+        if failed {
+            eyre::bail!("patch does not apply");
+        }
+        return Ok(());
+    }
+
+    let mut staged = index
+        .then(Index::read_default_or_empty)
+        .transpose()
+        .context("read index")?;
+    for result in results.iter() {
+        write_result(result)?;
+
+        if let Some(staged) = staged.as_mut() {
+            if let Some(old_path) = &result.patch.old_path {
+                staged.remove(old_path);
+            }
+            if let Some(new_path) = &result.patch.new_path {
+                staged.add(new_path)?;
+            }
+        }
+    }
+    if let Some(staged) = staged {
+        staged.write_default().context("write index")?;
+    }
+
+    if failed {
+        eyre::bail!("patch does not apply cleanly; see the .rej files for the rejected hunks");
+    }
+
+    Ok(())
+}
+
+/// Write a file's patched contents (or remove it, if it was deleted), along
+/// with a `.rej` file for any rejected hunks.
+fn write_result(result: &Applied) -> Result<()> {
+    let patch = result.patch;
+
+    if let Some(old_path) = &patch.old_path {
+        if patch.new_path.as_ref() != Some(old_path) {
+            std::fs::remove_file(repository::work_tree_path(old_path))
+                .with_context(|| format!("remove {}", old_path.to_string_lossy()))?;
+        }
+    }
+
+    if let Some(new_path) = &patch.new_path {
+        let path = repository::work_tree_path(new_path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, &result.contents)
+            .with_context(|| format!("write {}", new_path.to_string_lossy()))?;
+
+        if let Some(mode) = patch.new_mode {
+            checkout::set_permissions(Path::new(new_path), mode)?;
+        }
+    }
+
+    if result.rejected.is_empty() {
+        return Ok(());
+    }
+
+    let path = patch.path();
+    eprintln!(
+        "Applying patch {} with {} reject{}...",
+        path.to_string_lossy(),
+        result.rejected.len(),
+        if result.rejected.len() == 1 { "" } else { "s" }
+    );
+
+    let mut rej = format!(
+        "diff a/{0} b/{0}\t(rejected hunks)\n",
+        path.to_string_lossy()
+    )
+    .into_bytes();
+    for &i in result.rejected.iter() {
+        eprintln!("Rejected hunk #{}.", i + 1);
+        rej.extend(patch.hunks[i].to_bytes());
+    }
+
+    let mut rej_path = OsString::from(path);
+    rej_path.push(".rej");
+    let rej_path = PathBuf::from(rej_path);
+    std::fs::write(repository::work_tree_path(&rej_path), rej)
+        .with_context(|| format!("write {}", rej_path.display()))
+}
@@ -0,0 +1,350 @@
+use crate::attributes::{AttributeState, Attributes};
+use crate::commit::{civil_from_days, Commit};
+use crate::error::GitError;
+use crate::object::{ObjectBuf, ObjectMode, ObjectType};
+use crate::refs;
+use crate::tag::Tag;
+use crate::tree::Tree;
+use eyre::{Context, Result};
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What an archived path is, along with its contents (empty for directories).
+enum EntryKind {
+    Directory,
+    File { executable: bool },
+    Symlink,
+}
+
+struct Entry {
+    path: Vec<u8>,
+    kind: EntryKind,
+    contents: Vec<u8>,
+}
+
+/// Write an archive of the given tree (or the tree of the given commit) to
+/// `output`, or stdout. It's a tar file unless `zip` is set. Every path is
+/// prefixed with `prefix` (e.g. `project-1.0/`), and anything with the
+/// `export-ignore` attribute is left out.
+///
+/// Files are timestamped with the commit time, or the current time when
+/// archiving a bare tree.
+pub fn run(tree_ish: &str, zip: bool, prefix: &str, output: Option<&str>) -> Result<()> {
+    let hash = refs::lookup(tree_ish)?.ok_or_else(|| GitError::BadObject {
+        name: tree_ish.to_owned(),
+    })?;
+    let (hash, object_type) = Tag::peel(&hash)?;
+
+    // (seconds since the epoch, offset from UTC in minutes)
+    let (tree, mtime) = match object_type {
+        ObjectType::Commit => {
+            let commit = Commit::read_at_hash(&hash)?;
+            let time = commit
+                .committer
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let tz_offset = commit.committer.tz_offset;
+            (Tree::read_at_hash(&commit.tree_hash)?, (time, tz_offset))
+        }
+        ObjectType::Tree => {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+            (Tree::read_at_hash(&hash)?, (now.as_secs(), 0))
+        }
+        object_type => eyre::bail!("{tree_ish} is a {object_type}, not a tree"),
+    };
+
+    let mut entries = Vec::new();
+    if !prefix.is_empty() && prefix.ends_with('/') {
+        entries.push(Entry {
+            path: prefix.as_bytes().to_vec(),
+            kind: EntryKind::Directory,
+            contents: Vec::new(),
+        });
+    }
+    collect_entries(
+        &tree,
+        prefix.as_bytes(),
+        b"",
+        Attributes::current()?,
+        &mut entries,
+    )?;
+
+    let mut out: Box<dyn Write> = match output {
+        Some(path) => Box::new(std::io::BufWriter::new(
+            std::fs::File::create(path).with_context(|| format!("create {path}"))?,
+        )),
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    if zip {
+        write_zip(&mut out, &entries, mtime)?;
+    } else {
+        write_tar(&mut out, &entries, mtime.0)?;
+    }
+    out.flush().context("write archive")
+}
+
+/// Recursively gather every entry of `tree` (whose path in the repository is
+/// `dir`), in tree order.
+fn collect_entries(
+    tree: &Tree,
+    prefix: &[u8],
+    dir: &[u8],
+    attributes: &Attributes,
+    entries: &mut Vec<Entry>,
+) -> Result<()> {
+    for entry in tree.entries() {
+        let mut repo_path = dir.to_vec();
+        repo_path.extend_from_slice(entry.name.as_bytes());
+        if attributes.get(&repo_path, "export-ignore") == Some(&AttributeState::Set) {
+            continue;
+        }
+
+        let mut path = prefix.to_vec();
+        path.extend_from_slice(&repo_path);
+
+        let read_blob = || -> Result<Vec<u8>> {
+            ObjectBuf::read_at_hash(entry.hash.as_hex())
+                .and_then(|mut blob| blob.read_to_vec())
+                .with_context(|| format!("read {}", entry.name.to_string_lossy()))
+        };
+        match entry.mode {
+            ObjectMode::Directory => {
+                path.push(b'/');
+                repo_path.push(b'/');
+                entries.push(Entry {
+                    path,
+                    kind: EntryKind::Directory,
+                    contents: Vec::new(),
+                });
+
+                let subtree = Tree::read_at_hash(entry.hash.as_hex())?;
+                collect_entries(&subtree, prefix, &repo_path, attributes, entries)?;
+            }
+            // like git, submodules are archived as empty directories
+            ObjectMode::Gitlink => {
+                path.push(b'/');
+                entries.push(Entry {
+                    path,
+                    kind: EntryKind::Directory,
+                    contents: Vec::new(),
+                });
+            }
+            ObjectMode::Normal | ObjectMode::Executable => entries.push(Entry {
+                path,
+                kind: EntryKind::File {
+                    executable: entry.mode == ObjectMode::Executable,
+                },
+                contents: read_blob()?,
+            }),
+            ObjectMode::Symlink => entries.push(Entry {
+                path,
+                kind: EntryKind::Symlink,
+                contents: read_blob()?,
+            }),
+        }
+    }
+
+    Ok(())
+}
+
+/// Write a POSIX (ustar) tar file, using pax extended headers for paths and
+/// link targets that don't fit in the fixed-size header fields.
+fn write_tar(out: &mut impl Write, entries: &[Entry], mtime: u64) -> Result<()> {
+    let mut written = 0;
+    let mut write_block = |out: &mut dyn Write, data: &[u8]| -> Result<()> {
+        out.write_all(data)?;
+        let padding = (512 - data.len() % 512) % 512;
+        out.write_all(&vec![0; padding])?;
+        written += data.len() + padding;
+        Ok(())
+    };
+
+    for entry in entries.iter() {
+        let (mode, typeflag, link, contents): (u32, u8, &[u8], &[u8]) = match entry.kind {
+            EntryKind::Directory => (0o775, b'5', b"", b""),
+            EntryKind::File { executable: true } => (0o775, b'0', b"", &entry.contents),
+            EntryKind::File { executable: false } => (0o664, b'0', b"", &entry.contents),
+            EntryKind::Symlink => (0o777, b'2', &entry.contents, b""),
+        };
+
+        let mut pax = Vec::new();
+        if entry.path.len() > 100 {
+            pax.extend(pax_record("path", &entry.path));
+        }
+        if link.len() > 100 {
+            pax.extend(pax_record("linkpath", link));
+        }
+        if !pax.is_empty() {
+            let header = tar_header(b"././@PaxHeader", 0o666, pax.len(), mtime, b'x', b"");
+            write_block(out, &header)?;
+            write_block(out, &pax)?;
+        }
+
+        let header = tar_header(
+            &entry.path[..entry.path.len().min(100)],
+            mode,
+            contents.len(),
+            mtime,
+            typeflag,
+            &link[..link.len().min(100)],
+        );
+        write_block(out, &header)?;
+        if !contents.is_empty() {
+            write_block(out, contents)?;
+        }
+    }
+
+    // the end of the archive is marked by two empty blocks, and (like git) the
+    // whole thing is padded out to a multiple of the default 10KiB record size
+    write_block(out, &[0; 1024])?;
+    let padding = (10240 - written % 10240) % 10240;
+    out.write_all(&vec![0; padding])?;
+
+    Ok(())
+}
+
+/// A single 512-byte ustar header block.
+fn tar_header(
+    name: &[u8],
+    mode: u32,
+    size: usize,
+    mtime: u64,
+    typeflag: u8,
+    link: &[u8],
+) -> [u8; 512] {
+    let mut header = [0; 512];
+    let mut field = |offset: usize, value: &[u8]| {
+        header[offset..offset + value.len()].copy_from_slice(value);
+    };
+
+    field(0, name);
+    field(100, format!("{mode:07o}").as_bytes());
+    field(108, b"0000000"); // uid
+    field(116, b"0000000"); // gid
+    field(124, format!("{size:011o}").as_bytes());
+    field(136, format!("{mtime:011o}").as_bytes());
+    field(148, b"        "); // checksum, which is computed as if it were spaces
+    field(156, &[typeflag]);
+    field(157, link);
+    field(257, b"ustar\x0000");
+    field(265, b"root"); // uname
+    field(297, b"root"); // gname
+
+    let checksum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+
+    header
+}
+
+/// A pax extended header record, `<length> <key>=<value>\n`, where the length
+/// includes itself.
+fn pax_record(key: &str, value: &[u8]) -> Vec<u8> {
+    let rest = key.len() + value.len() + 3; // space, `=`, and newline
+    let mut len = rest + 1;
+    while len != rest + len.to_string().len() {
+        len = rest + len.to_string().len();
+    }
+
+    let mut record = format!("{len} {key}=").into_bytes();
+    record.extend_from_slice(value);
+    record.push(b'\n');
+    record
+}
+
+/// Write a zip file, deflating regular files and storing everything else.
+fn write_zip(out: &mut impl Write, entries: &[Entry], mtime: (u64, i16)) -> Result<()> {
+    let (dos_time, dos_date) = dos_date_time(mtime.0, mtime.1);
+
+    let mut offset: u32 = 0;
+    let mut central_directory = Vec::new();
+    for entry in entries.iter() {
+        let (mode, deflate): (u32, bool) = match entry.kind {
+            EntryKind::Directory => (0o040755, false),
+            EntryKind::File { executable: true } => (0o100755, true),
+            EntryKind::File { executable: false } => (0o100644, true),
+            EntryKind::Symlink => (0o120777, false),
+        };
+
+        let data = if deflate {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&entry.contents)?;
+            encoder.finish().context("compress file")?
+        } else {
+            entry.contents.clone()
+        };
+        let method: u16 = if deflate { 8 } else { 0 };
+        let crc32 = crc32fast::hash(&entry.contents);
+
+        // the fields shared by the local header and the central directory:
+        // version needed, flags, method, time, date, CRC, sizes, name length
+        let mut common = Vec::new();
+        common.extend_from_slice(&20u16.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        common.extend_from_slice(&method.to_le_bytes());
+        common.extend_from_slice(&dos_time.to_le_bytes());
+        common.extend_from_slice(&dos_date.to_le_bytes());
+        common.extend_from_slice(&crc32.to_le_bytes());
+        common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(entry.contents.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(entry.path.len() as u16).to_le_bytes());
+
+        // local file header
+        out.write_all(&0x04034b50u32.to_le_bytes())?;
+        out.write_all(&common)?;
+        out.write_all(&0u16.to_le_bytes())?; // extra field length
+        out.write_all(&entry.path)?;
+        out.write_all(&data)?;
+
+        // central directory entry (written once every file has been)
+        central_directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        central_directory.extend_from_slice(&(3u16 << 8 | 20).to_le_bytes()); // made by unix
+        central_directory.extend_from_slice(&common);
+        central_directory.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+        central_directory.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        central_directory.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        central_directory.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        let dos_directory = if matches!(entry.kind, EntryKind::Directory) {
+            0x10
+        } else {
+            0
+        };
+        central_directory.extend_from_slice(&(mode << 16 | dos_directory).to_le_bytes());
+        central_directory.extend_from_slice(&offset.to_le_bytes());
+        central_directory.extend_from_slice(&entry.path);
+
+        offset += (30 + entry.path.len() + data.len()) as u32;
+    }
+
+    out.write_all(&central_directory)?;
+
+    // end of central directory record
+    out.write_all(&0x06054b50u32.to_le_bytes())?;
+    out.write_all(&0u16.to_le_bytes())?; // this disk
+    out.write_all(&0u16.to_le_bytes())?; // disk with the central directory
+    out.write_all(&(entries.len() as u16).to_le_bytes())?;
+    out.write_all(&(entries.len() as u16).to_le_bytes())?;
+    out.write_all(&(central_directory.len() as u32).to_le_bytes())?;
+    out.write_all(&offset.to_le_bytes())?;
+    out.write_all(&0u16.to_le_bytes())?; // comment length
+
+    Ok(())
+}
+
+/// Convert a timestamp to the MS-DOS time and date used by zip files, which
+/// are in local time (here, the commit's timezone) with 2-second precision.
+fn dos_date_time(timestamp: u64, tz_offset: i16) -> (u16, u16) {
+    let seconds = timestamp as i64 + i64::from(tz_offset) * 60;
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+    let time = seconds.rem_euclid(86400);
+
+    let dos_time = ((time / 3600) << 11) | ((time / 60 % 60) << 5) | ((time % 60) / 2);
+    // DOS dates start in 1980
+    let dos_date = ((year.clamp(1980, 2107) - 1980) << 9) | (month << 5) | day;
+    (dos_time as u16, dos_date as u16)
+}
@@ -1,47 +1,52 @@
-use crate::object::{ObjectBuf, ObjectType};
+use crate::commit::Commit;
+use crate::object::{ObjectBuf, ObjectHash, ObjectType};
+use crate::refs;
+use crate::repository::Repository;
 use eyre::{Context, Result};
 use std::fmt::Debug;
 use std::io::BufRead;
 
-pub fn run(pretty: bool, object_hash: &str) -> Result<()> {
+pub fn run(pretty: bool, show_type: bool, object_hash: &str) -> Result<()> {
+    let object_hash: ObjectHash = object_hash.parse()?;
+
+    if show_type {
+        println!("{}", Repository::open()?.object_type(&object_hash)?);
+        return Ok(());
+    }
+
     eyre::ensure!(pretty, "only pretty-printing is supported for now");
 
-    let object = ObjectBuf::read_at_hash(object_hash)?;
+    let object = ObjectBuf::read_at_hash(object_hash.as_hex())?;
     print_obj(object)?;
 
     Ok(())
 }
 
-pub fn print_obj<R: BufRead + Debug>(mut object: ObjectBuf<R>) -> Result<()> {
-    match &object.object_type {
-        // FIXME: move object parsing into object.rs
-        ObjectType::Blob => {
-            let mut buf = vec![0; object.content_len];
-
-            object
-                .contents
-                .read_exact(&mut buf)
-                .context("read blob contents")?;
-
-            if !object.contents.at_eof()? {
-                eyre::bail!("blob contains more bytes than its content length specified");
-            }
-
-            let mut stdout = std::io::stdout().lock();
-            let mut cursor = std::io::Cursor::new(buf);
-            std::io::copy(&mut cursor, &mut stdout).context("write contents to stdout")?;
+/// Check whether the named object exists, without reading it (for `-e`).
+pub fn exists(object: &str) -> Result<bool> {
+    Ok(match refs::lookup(object)? {
+        Some(hash) => Repository::open()?.has_object(&hash.parse()?),
+        None => false,
+    })
+}
 
-            Ok(())
-        }
+/// Print the named commit's fields according to `format` (for `--format`),
+/// so that scripts needn't parse the raw commit themselves.
+pub fn format(format: &str, object: &str) -> Result<()> {
+    let hash = refs::lookup_commit(object)?
+        .ok_or_else(|| eyre::eyre!("not a valid commit name {object}"))?;
+    println!("{}", Commit::read_at_hash(&hash)?.format(format)?);
+    Ok(())
+}
 
+pub fn print_obj<R: BufRead + Debug>(mut object: ObjectBuf<R>) -> Result<()> {
+    match &object.object_type {
         // tree objects delegate to `ls-tree`
         ObjectType::Tree => crate::subcommand::ls_tree::print_tree(false, object),
 
-        ObjectType::Commit | ObjectType::Tag => {
-            let mut buf = vec![0; object.content_len];
-            object.contents.read_exact(&mut buf)?;
-            println!("{}", String::from_utf8_lossy(&buf));
-
+        ObjectType::Blob | ObjectType::Commit | ObjectType::Tag => {
+            std::io::copy(&mut object.reader(), &mut std::io::stdout().lock())
+                .context("write contents to stdout")?;
             Ok(())
         }
     }
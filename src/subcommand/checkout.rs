@@ -1,18 +1,39 @@
+use crate::attributes::Attributes;
 use crate::commit::Commit;
-use crate::index::Index;
-use crate::object::{ObjectBuf, ObjectType};
-use crate::tree::Tree;
+use crate::convert::EolConversion;
+use crate::hash::HashAlgorithm;
+use crate::hooks;
+use crate::index::{Index, IndexEntry, WorkingTreeConfig};
+use crate::object::{objects_dir, ObjectBuf, ObjectHash, ObjectMode};
+use crate::object_store::{FsObjectStore, ObjectStore};
+use crate::refs::{self, Head};
+use crate::repository;
+use crate::tree::{Tree, TreeEntry};
 use eyre::{Context, Result};
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{BufRead, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 
+/// Check out a branch, or (detaching `HEAD`) any other commit, such as one
+/// named by a tag. The `post-checkout` hook is run afterwards, and if it
+/// fails, so does the checkout (although it's still done).
 // FIXME: make sure that working directory is clean first
 pub fn run(branch: &str) -> Result<()> {
-    let commit_hash =
-        std::fs::read_to_string(format!(".git/refs/heads/{branch}")).context("read branch ref")?;
-    let commit_hash = commit_hash.trim_end();
+    let branch_hash = refs::resolve(&format!("refs/heads/{branch}"))?;
+    let commit_hash = match &branch_hash {
+        Some(hash) => hash.clone(),
+        None => refs::lookup_commit(branch)?.ok_or_else(|| {
+            eyre::eyre!("pathspec '{branch}' did not match any file(s) known to git")
+        })?,
+    };
 
     let commit = {
-        let obj = ObjectBuf::read_at_hash(commit_hash).context("read object at branch hash")?;
+        let obj = ObjectBuf::read_at_hash(&commit_hash).context("read object at branch hash")?;
         Commit::from_buf(obj)?
     };
 
@@ -21,33 +42,363 @@ pub fn run(branch: &str) -> Result<()> {
         Tree::from_buf(obj)?
     };
 
-    unpack_in(PathBuf::from("."), &tree).context("check out file contents")?;
+    check_out_tree(
+        &objects_dir(),
+        repository::work_tree(),
+        &tree,
+        WorkingTreeConfig::current()?,
+    )?
+    .write_default()
+    .context("write working tree to index")?;
+
+    let from = match refs::read_head()? {
+        Head::Branch(from) => from,
+        Head::Detached(hash) => hash,
+    };
+    let from_hash = match refs::resolve("HEAD")? {
+        Some(hash) => hash,
+        None => HashAlgorithm::current()?.null_hash(),
+    };
+    let message = format!("checkout: moving from {from} to {branch}");
+    if branch_hash.is_none() {
+        refs::set_head_detached(&commit_hash, &message)?;
+    } else if from != branch {
+        refs::set_head_branch(branch, &message)?;
+    }
 
-    Index::working_tree()
-        .context("read working tree")?
-        .write_default()
-        .context("write working tree to index")?;
+    // the last argument says that a branch was checked out, rather than files
+    if !hooks::run("post-checkout", &[&from_hash, &commit_hash, "1"])? {
+        eyre::bail!("post-checkout hook failed");
+    }
 
     Ok(())
 }
 
-fn unpack_in(root: PathBuf, tree: &Tree) -> Result<()> {
-    for entry in tree.entries() {
-        let mut obj = ObjectBuf::read_at_hash(entry.hash.as_hex())?;
-        match obj.object_type {
-            ObjectType::Blob => {
-                let mut f = std::fs::File::create(root.join(&entry.name))?;
-                std::io::copy(obj.contents.inner_mut(), &mut f)?;
+/// Check out a commit into the new (empty) repository at `root`, whose `HEAD`
+/// should already point at it, writing its files and index.
+pub(crate) fn check_out_fresh(root: &Path, commit_hash: &str) -> Result<()> {
+    let git_dir = root.join(".git");
+    check_out_into(&git_dir, root, &git_dir.join("index"), commit_hash)
+}
+
+/// Check out a commit from the repository at `git_dir` into the empty
+/// directory `root`, writing its index to `index_path`. Files are written
+/// according to that repository's config and the commit's own
+/// `.gitattributes`, since there's no working tree to read them from yet.
+pub(crate) fn check_out_into(
+    git_dir: &Path,
+    root: &Path,
+    index_path: &Path,
+    commit_hash: &str,
+) -> Result<()> {
+    let objects_dir = git_dir.join("objects");
+    let store = FsObjectStore::new(&objects_dir);
+    let commit = Commit::read_in(&store, commit_hash)?;
+    let tree = Tree::read_in(&store, &commit.tree_hash)?;
+
+    let attributes = match tree
+        .entries()
+        .iter()
+        .find(|entry| entry.name == ".gitattributes" && entry.mode != ObjectMode::Gitlink)
+    {
+        Some(entry) => {
+            let contents = store.read(entry.hash.as_hex())?.read_to_vec()?;
+            Attributes::parse(&String::from_utf8_lossy(&contents))
+        }
+        None => Attributes::default(),
+    };
+    let config = WorkingTreeConfig::read(git_dir, attributes).context("read config")?;
+
+    check_out_tree(&objects_dir, root, &tree, &config)?
+        .write(index_path)
+        .context("write working tree to index")
+}
+
+/// Move the working tree and index from the contents of one tree to another,
+/// removing any files tracked by `from` that no longer exist in `to`.
+pub(crate) fn update_working_tree(from: Option<&str>, to: &str) -> Result<()> {
+    let to = Tree::read_at_hash(to)?;
+
+    if let Some(from) = from {
+        let keep: HashSet<OsString> = to.flatten()?.into_iter().map(|e| e.name).collect();
+
+        for entry in Tree::read_at_hash(from)?.flatten()? {
+            if keep.contains(&entry.name) {
+                continue;
             }
-            ObjectType::Tree => {
-                let tree = Tree::from_buf(obj)?;
-                let sub_root = root.join(&entry.name);
-                std::fs::create_dir(&sub_root)?;
-                unpack_in(sub_root, &tree)?;
+
+            // submodules are left alone unless they were never checked out
+            if entry.mode == ObjectMode::Gitlink {
+                let _ = std::fs::remove_dir(repository::work_tree_path(&entry.name));
+                continue;
             }
-            _ => unreachable!("trees can only contain blobs and trees"),
+
+            remove_file(Path::new(&entry.name))?;
+        }
+    }
+
+    check_out_tree(
+        &objects_dir(),
+        repository::work_tree(),
+        &to,
+        WorkingTreeConfig::current()?,
+    )?
+    .write_default()
+    .context("write working tree to index")?;
+
+    Ok(())
+}
+
+/// Remove a file from the working tree (if it's still there), along with any
+/// directories left empty by its removal.
+pub(crate) fn remove_file(path: &Path) -> Result<()> {
+    match std::fs::remove_file(repository::work_tree_path(path)) {
+        Ok(_) => (),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+        Err(err) => return Err(err).with_context(|| format!("remove {}", path.display())),
+    }
+
+    let mut dir = path.parent();
+    while let Some(parent) = dir {
+        if parent.as_os_str().is_empty()
+            || std::fs::remove_dir(repository::work_tree_path(parent)).is_err()
+        {
+            break;
         }
+        dir = parent.parent();
     }
 
     Ok(())
 }
+
+/// Write the contents of the blob at the given hash to a file in the working
+/// tree, creating any missing parent directories.
+pub(crate) fn write_blob(path: &Path, hash: &str) -> Result<()> {
+    let conversion = WorkingTreeConfig::current()?.eol_conversion(path);
+    write_blob_from(
+        &objects_dir(),
+        &repository::work_tree_path(path),
+        conversion,
+        hash,
+    )
+}
+
+fn write_blob_from(
+    objects_dir: &Path,
+    path: &Path,
+    conversion: EolConversion,
+    hash: &str,
+) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut obj = ObjectBuf::read_from(objects_dir, hash)?;
+    let f = std::fs::File::create(path).with_context(|| format!("create {}", path.display()))?;
+    write_contents(conversion, &mut obj, f).with_context(|| format!("write {}", path.display()))?;
+
+    Ok(())
+}
+
+/// Write a non-tree entry to the working tree: blobs become files with the
+/// entry's permissions (or symlinks), and submodules become empty directories.
+pub(crate) fn write_entry(path: &Path, mode: ObjectMode, hash: &str) -> Result<()> {
+    let conversion = WorkingTreeConfig::current()?.eol_conversion(path);
+    write_entry_from(
+        &objects_dir(),
+        &repository::work_tree_path(path),
+        conversion,
+        mode,
+        hash,
+    )
+}
+
+/// Write a non-tree entry to `path`, converting a blob's line endings with
+/// `conversion`.
+fn write_entry_from(
+    objects_dir: &Path,
+    path: &Path,
+    conversion: EolConversion,
+    mode: ObjectMode,
+    hash: &str,
+) -> Result<()> {
+    match mode {
+        ObjectMode::Normal | ObjectMode::Executable => {
+            write_blob_from(objects_dir, path, conversion, hash)?;
+            set_file_mode(path, mode)?;
+        }
+        ObjectMode::Symlink => {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            let target = ObjectBuf::read_from(objects_dir, hash)?.read_to_vec()?;
+            match std::fs::remove_file(path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+                _ => (),
+            }
+            std::os::unix::fs::symlink(OsStr::from_bytes(&target), path)
+                .with_context(|| format!("create symlink {}", path.display()))?;
+        }
+        ObjectMode::Gitlink => std::fs::create_dir_all(path)?,
+        ObjectMode::Directory => eyre::bail!("{} is a directory", path.display()),
+    }
+
+    Ok(())
+}
+
+/// Make a file in the working tree executable (or not) to match the given
+/// mode.
+pub(crate) fn set_permissions(path: &Path, mode: ObjectMode) -> Result<()> {
+    set_file_mode(&repository::work_tree_path(path), mode)
+}
+
+fn set_file_mode(path: &Path, mode: ObjectMode) -> Result<()> {
+    let mut permissions = path.metadata()?.permissions();
+    permissions.set_mode(match mode {
+        ObjectMode::Executable => permissions.mode() | 0o111,
+        _ => permissions.mode() & !0o111,
+    });
+    std::fs::set_permissions(path, permissions)
+        .with_context(|| format!("set permissions of {}", path.display()))
+}
+
+/// Write the contents of `tree` to the working tree at `root`, then build an
+/// index of the result. Submodules are only created as empty directories, so
+/// they're indexed by the commit `tree` has for them rather than by whatever
+/// (if anything) is checked out in them.
+fn check_out_tree(
+    objects_dir: &Path,
+    root: &Path,
+    tree: &Tree,
+    config: &WorkingTreeConfig,
+) -> Result<Index> {
+    let mut gitlinks = Vec::new();
+    unpack_in(
+        objects_dir,
+        root,
+        PathBuf::new(),
+        tree,
+        config,
+        &mut gitlinks,
+    )
+    .context("check out file contents")?;
+
+    let mut index = Index::working_tree_in(root, config).context("read working tree")?;
+    for (name, hash) in gitlinks {
+        let name = name.into_os_string();
+        index.entries.retain(|entry| entry.name != name);
+        index.entries.push(IndexEntry::from_tree_entry(&TreeEntry {
+            name,
+            mode: ObjectMode::Gitlink,
+            hash,
+        })?);
+    }
+    index.entries.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(index)
+}
+
+/// Write the contents of `tree`, found at `dir` (relative to `root`) in the
+/// commit being checked out, collecting the repo-relative paths of any
+/// submodules into `gitlinks`.
+fn unpack_in(
+    objects_dir: &Path,
+    root: &Path,
+    dir: PathBuf,
+    tree: &Tree,
+    config: &WorkingTreeConfig,
+    gitlinks: &mut Vec<(PathBuf, ObjectHash)>,
+) -> Result<()> {
+    for entry in tree.entries() {
+        let name = dir.join(&entry.name);
+        let path = root.join(&name);
+        if entry.mode == ObjectMode::Directory {
+            let tree = Tree::from_buf(ObjectBuf::read_from(objects_dir, entry.hash.as_hex())?)?;
+            std::fs::create_dir_all(&path)?;
+            unpack_in(objects_dir, root, name, &tree, config, gitlinks)?;
+        } else {
+            let conversion = config.eol_conversion(&name);
+            write_entry_from(
+                objects_dir,
+                &path,
+                conversion,
+                entry.mode,
+                entry.hash.as_hex(),
+            )?;
+            if entry.mode == ObjectMode::Gitlink {
+                gitlinks.push((name, entry.hash.clone()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Write the contents of a blob to a file, converting line endings if needed.
+fn write_contents<R: BufRead + Debug>(
+    conversion: EolConversion,
+    obj: &mut ObjectBuf<R>,
+    mut f: File,
+) -> Result<()> {
+    match conversion {
+        conversion @ EolConversion::Crlf { .. } => {
+            f.write_all(&conversion.to_working_tree(obj.read_to_vec()?))?
+        }
+        EolConversion::None | EolConversion::Lf { .. } => {
+            std::io::copy(&mut obj.reader(), &mut f)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{ObjectHashable, ObjectType};
+    use crate::parser::Parser;
+    use std::io::Cursor;
+
+    fn insert(store: &FsObjectStore, object_type: ObjectType, contents: Vec<u8>) -> ObjectHash {
+        ObjectBuf {
+            object_type,
+            content_len: contents.len(),
+            contents: Parser::new(Cursor::new(contents)),
+        }
+        .hash_to(store)
+        .unwrap()
+    }
+
+    #[test]
+    fn check_out_into_uses_attributes_from_commit() {
+        let root = tempfile::tempdir().unwrap();
+        let git_dir = root.path().join(".git");
+        std::fs::create_dir_all(git_dir.join("objects")).unwrap();
+        let store = FsObjectStore::new(git_dir.join("objects"));
+
+        let attributes = insert(&store, ObjectType::Blob, b"*.txt eol=crlf\n".to_vec());
+        let blob = insert(&store, ObjectType::Blob, b"one\ntwo\n".to_vec());
+        let mut tree = Vec::new();
+        for (name, hash) in [(".gitattributes", &attributes), ("a.txt", &blob)] {
+            tree.extend_from_slice(format!("100644 {name}\0").as_bytes());
+            tree.extend_from_slice(hash.as_bytes());
+        }
+        let tree = insert(&store, ObjectType::Tree, tree);
+        let commit = format!(
+            "tree {tree}\nauthor A <a@example.com> 0 +0000\ncommitter A <a@example.com> 0 +0000\n\nm\n"
+        );
+        let commit = insert(&store, ObjectType::Commit, commit.into_bytes());
+
+        check_out_fresh(root.path(), commit.as_hex()).unwrap();
+
+        assert_eq!(
+            std::fs::read(root.path().join("a.txt")).unwrap(),
+            b"one\r\ntwo\r\n"
+        );
+        // the index records the blob as committed, so the file isn't modified
+        let index = Index::read(git_dir.join("index")).unwrap();
+        let entry = index.entries.iter().find(|e| e.name == "a.txt").unwrap();
+        assert_eq!(entry.hash, blob);
+    }
+}
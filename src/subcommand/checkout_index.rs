@@ -0,0 +1,40 @@
+use crate::index::Index;
+use crate::repository;
+use crate::subcommand::checkout;
+use eyre::{Context, Result};
+use std::path::Path;
+
+/// Write the staged contents of the given paths (or every path, with `all`)
+/// to the working tree. Existing files are left alone unless `force` is set.
+pub fn run(all: bool, force: bool, paths: &[String]) -> Result<()> {
+    let index = Index::read_default().context("read index")?;
+
+    let entries = if all {
+        index.entries.iter().collect::<Vec<_>>()
+    } else {
+        paths
+            .iter()
+            .map(|path| {
+                let path = repository::from_prefix(path);
+                index
+                    .entries
+                    .iter()
+                    .find(|entry| entry.name == path.as_os_str())
+                    .ok_or_else(|| eyre::eyre!("{} is not in the cache", path.display()))
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    for entry in entries {
+        let path = Path::new(&entry.name);
+        if !force && std::fs::symlink_metadata(repository::work_tree_path(path)).is_ok() {
+            eprintln!("{} already exists, no checkout", path.display());
+            continue;
+        }
+
+        checkout::write_entry(path, entry.mode()?, entry.hash.as_hex())
+            .with_context(|| format!("check out {}", path.display()))?;
+    }
+
+    Ok(())
+}
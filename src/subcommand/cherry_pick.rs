@@ -0,0 +1,96 @@
+use crate::commit::{Commit, CommitAttribution};
+use crate::hooks;
+use crate::object::{Object, ObjectHash, ObjectHashable};
+use crate::output::outln;
+use crate::refs;
+use crate::repository;
+use crate::subcommand::merge::{merge_trees, report_conflicts};
+use eyre::{Context, Result};
+
+// FIXME: make sure that working directory is clean first
+pub fn run(commit: &str) -> Result<()> {
+    let head =
+        refs::resolve("HEAD")?.ok_or_else(|| eyre::eyre!("HEAD does not point to a commit yet"))?;
+    let hash =
+        refs::lookup_commit(commit)?.ok_or_else(|| eyre::eyre!("bad revision '{commit}'"))?;
+    let commit = Commit::read_at_hash(&hash)?;
+
+    // the changes introduced by the commit are the difference between its
+    // tree and its parent's, so use the parent as the merge base
+    let parent_tree = match commit.parent_hashes.as_slice() {
+        [] => None,
+        [parent_hash] => Some(Commit::read_at_hash(parent_hash)?.tree_hash),
+        _ => eyre::bail!("commit {hash} is a merge but no -m option was given"),
+    };
+
+    let subject = commit.summary();
+    let tree_hash = apply_changes(
+        &head,
+        parent_tree.as_deref(),
+        &commit.tree_hash,
+        &format!("{} ({subject})", &hash[..7]),
+        "CHERRY_PICK_HEAD",
+        &hash,
+    )?;
+
+    let new_commit = Commit {
+        tree_hash: tree_hash.to_string(),
+        parent_hashes: vec![head],
+        author: commit.author,
+        committer: CommitAttribution::committer()?,
+        // the message (and author) are copied as they are, so they're still
+        // in the original's encoding; the original signature doesn't cover
+        // the new commit, so it's dropped
+        extra_headers: commit
+            .extra_headers
+            .into_iter()
+            .filter(|(key, _)| key == "encoding")
+            .collect(),
+        message: commit.message,
+    };
+    let new_hash = Object::commit(new_commit).hash(true)?;
+    refs::update_head(new_hash.as_hex(), &format!("cherry-pick: {subject}"))?;
+    hooks::notify("post-commit", &[])?;
+
+    outln!("[{}] {subject}", &new_hash.as_hex()[..7]);
+
+    Ok(())
+}
+
+/// Apply the changes needed to get from the `base` tree to the `target` tree
+/// on top of the `head` commit, returning the resulting tree. If there are
+/// any conflicts, they're left in the working tree for the user to resolve,
+/// and `state_file` (in the working tree's git directory) is written with the
+/// hash of the commit being applied.
+pub(crate) fn apply_changes(
+    head: &str,
+    base: Option<&str>,
+    target: &str,
+    target_label: &str,
+    state_file: &str,
+    commit_hash: &str,
+) -> Result<ObjectHash> {
+    let merge = merge_trees(
+        base,
+        &Commit::read_at_hash(head)?.tree_hash,
+        target,
+        "HEAD",
+        target_label,
+    )?;
+
+    let Some(tree_hash) = merge.tree else {
+        std::fs::write(
+            repository::current_git_dir().join(state_file),
+            format!("{commit_hash}\n"),
+        )
+        .with_context(|| format!("write {state_file}"))?;
+
+        report_conflicts(&merge.conflicts);
+        eyre::bail!(
+            "could not apply {}; fix conflicts and then commit the result",
+            &commit_hash[..7]
+        );
+    };
+
+    Ok(tree_hash)
+}
@@ -1,21 +1,43 @@
-use crate::pack::Pack;
+use crate::config;
+use crate::hash::HashAlgorithm;
+use crate::output::{out, outln};
+use crate::pack::{OpenOptions, Pack, PackStream};
 use crate::packet_line::{
-    pkt_line_iter, pkt_line_str, pkt_line_str_keep_newline, PacketLine, PacketLineStream,
+    pkt_line_data_iter, pkt_line_str, pkt_line_str_keep_newline, PacketLine, PacketLineStream,
 };
+use crate::remote_url::RemoteUrl;
+use crate::utils::print_progress;
 use eyre::{Context, Result};
 use futures_util::StreamExt;
 use std::io::Write;
+use std::path::Path;
+use tempfile::NamedTempFile;
 
 #[derive(Debug)]
-struct Ref {
-    hash: String,
-    name: String,
+pub(crate) struct Ref {
+    pub hash: String,
+    pub name: String,
 }
 
-pub fn run(repo_url: &str, output_dir: Option<&str>) -> Result<()> {
-    let repo_url = repo_url.trim_end_matches('/');
-
-    let (refs, extras) = fetch_refs(repo_url)?;
+/// Clone the repository at `repo_url` into `output_dir` (named after the
+/// repository by default). With `keep_pack`, the downloaded pack is kept in
+/// `.git/objects/pack` (along with an index) after it's unpacked.
+///
+/// With `bare`, the repository is cloned without a working tree, straight
+/// into `output_dir` (`<name>.git` by default), with every branch copied as
+/// it is on the remote. With `mirror`, so is every other ref (like tags and
+/// notes), and the clone is marked as a mirror of the remote.
+pub fn run(
+    repo_url: &str,
+    output_dir: Option<&str>,
+    keep_pack: bool,
+    bare: bool,
+    mirror: bool,
+) -> Result<()> {
+    let bare = bare || mirror;
+    let repo_url: RemoteUrl = repo_url.parse()?;
+
+    let (refs, extras) = fetch_refs(&repo_url, "git-upload-pack")?;
 
     let head_ref = refs
         .iter()
@@ -23,71 +45,183 @@ pub fn run(repo_url: &str, output_dir: Option<&str>) -> Result<()> {
         .expect("HEAD ref must exist");
 
     let default_branch = find_default_branch(&extras);
-    let packfile = fetch_packfile(repo_url, head_ref)?;
 
-    if packfile.is_empty() {
-        eyre::bail!("oops! looks like we didn't receive anything in the packfile");
+    // the pack's hashes (and its checksum) are computed with whichever
+    // algorithm the remote uses, so that needs to be settled before reading it
+    let object_format = find_object_format(&extras)?;
+    object_format.set_current()?;
+
+    // a bare clone copies every branch as is (and a mirror every ref), but
+    // otherwise only the default branch is created
+    let copied_refs: Vec<&Ref> = refs
+        .iter()
+        .filter(|_ref| match (bare, mirror) {
+            (_, true) => _ref.name.starts_with("refs/"),
+            (true, false) => _ref.name.starts_with("refs/heads/"),
+            (false, false) => false,
+        })
+        .collect();
+    let mut wants: Vec<&str> = vec![&head_ref.hash];
+    for _ref in copied_refs.iter() {
+        if !wants.contains(&_ref.hash.as_str()) {
+            wants.push(&_ref.hash);
+        }
     }
 
-    let mut f = std::fs::File::create("repo.pack")?;
-    f.write_all(&packfile)?;
-    drop(f);
+    let packfile = fetch_packfile(&repo_url, &wants, &[])?;
 
-    let mut pack = Pack::open("repo.pack").context("read packfile")?;
+    if packfile.is_empty() {
+        eyre::bail!("oops! looks like we didn't receive anything in the packfile");
+    }
 
-    let output_dir = output_dir.unwrap_or_else(|| {
-        let (_, repo_name) = repo_url.rsplit_once('/').expect("repo url contains slash");
-        repo_name.trim_end_matches(".git")
-    });
+    let default_dir = match bare {
+        true => format!("{}.git", repo_url.default_dir()),
+        false => repo_url.default_dir(),
+    };
+    let output_dir = Path::new(output_dir.unwrap_or(&default_dir));
+    let git_dir = match bare {
+        true => output_dir.to_owned(),
+        false => output_dir.join(".git"),
+    };
 
     std::fs::create_dir(output_dir).context("create directory to clone into")?;
-    std::env::set_current_dir(output_dir).unwrap();
 
-    crate::subcommand::init::with_default_branch(default_branch)
+    crate::subcommand::init::init_at(output_dir, default_branch, object_format, bare)
         .context("initialize empty repository")?;
+    configure_origin(&git_dir, &repo_url, default_branch, bare, mirror)
+        .context("configure origin remote")?;
+
+    // the pack is written alongside the packs it may be kept with, and is
+    // removed when this returns (successfully or not) unless it's kept
+    let pack_dir = git_dir.join("objects/pack");
+    std::fs::create_dir_all(&pack_dir).context("create pack directory")?;
+    let mut tmp_pack = NamedTempFile::new_in(&pack_dir).context("create packfile")?;
+    tmp_pack.write_all(&packfile).context("write packfile")?;
+
+    // there's no repository to read `core.deltaBaseCacheLimit` from yet, so
+    // the default limit is used; a corrupt object doesn't stop the rest of
+    // the pack from being unpacked, so whatever arrived intact is kept
+    PackStream::open(
+        tmp_pack.path(),
+        Some(&mut print_progress),
+        OpenOptions::default(),
+    )
+    .context("read packfile")?
+    .unpack_into(&git_dir.join("objects"))
+    .check()
+    .context("unpack packfile contents")?;
+
+    if keep_pack {
+        // the objects are all unpacked, so only their hashes are needed
+        // (and the checksum has already been verified)
+        let pack = Pack::open(
+            tmp_pack.path(),
+            None,
+            OpenOptions {
+                skip_checksum: true,
+                lazy: true,
+                ..Default::default()
+            },
+        )
+        .context("read packfile")?;
 
-    pack.unpack().context("unpack packfile contents")?;
-    drop(pack);
+        let pack_path = pack_dir.join(format!("pack-{}.pack", pack.checksum));
+        tmp_pack.persist(&pack_path).context("keep packfile")?;
+        pack.write_index(pack_path.with_extension("idx"))?;
+        outln!("Kept pack at {}", pack_path.display());
+    }
 
-    let git_dir = std::path::Path::new(".git");
-    let ref_file = git_dir.join(format!("refs/heads/{default_branch}"));
-    std::fs::create_dir_all(ref_file.parent().unwrap()).context("create default ref parent")?;
-    std::fs::write(ref_file, format!("{}\n", head_ref.hash).as_bytes())
+    if bare {
+        for _ref in copied_refs {
+            crate::refs::update_in(
+                &git_dir,
+                &_ref.name,
+                &_ref.hash,
+                &format!("clone: from {repo_url}"),
+            )
+            .with_context(|| format!("create {}", _ref.name))?;
+        }
+    } else {
+        crate::refs::update_in(
+            &git_dir,
+            &format!("refs/heads/{default_branch}"),
+            &head_ref.hash,
+            &format!("clone: from {repo_url}"),
+        )
         .context(format!("create .git/refs/heads/{}", default_branch))?;
 
-    crate::subcommand::checkout::run(default_branch)?;
+        crate::subcommand::checkout::check_out_fresh(output_dir, &head_ref.hash)?;
+    }
 
-    std::env::set_current_dir("..").unwrap();
-    std::fs::remove_file("repo.pack").context("remove packfile")?;
+    outln!("Done!");
 
-    println!("Done!");
+    Ok(())
+}
+
+/// Record where the clone came from as the `origin` remote, so that `fetch`
+/// and `pull` know where to go. Unless the clone is bare, the default branch
+/// is also set up to track its counterpart on the remote.
+fn configure_origin(
+    git_dir: &Path,
+    repo_url: &RemoteUrl,
+    default_branch: &str,
+    bare: bool,
+    mirror: bool,
+) -> Result<()> {
+    let config_path = git_dir.join("config");
+    config::set_value(&config_path, "remote.origin.url", &repo_url.to_string())?;
+    if mirror {
+        config::set_value(&config_path, "remote.origin.fetch", "+refs/*:refs/*")?;
+        config::set_value(&config_path, "remote.origin.mirror", "true")?;
+    } else {
+        config::set_value(
+            &config_path,
+            "remote.origin.fetch",
+            "+refs/heads/*:refs/remotes/origin/*",
+        )?;
+    }
+
+    if !bare {
+        config::set_value(
+            &config_path,
+            &format!("branch.{default_branch}.remote"),
+            "origin",
+        )?;
+        config::set_value(
+            &config_path,
+            &format!("branch.{default_branch}.merge"),
+            &format!("refs/heads/{default_branch}"),
+        )?;
+    }
 
     Ok(())
 }
 
-fn fetch_refs(repo_url: &str) -> Result<(Vec<Ref>, Vec<String>)> {
-    let refs_url = format!("{}/info/refs?service=git-upload-pack", repo_url);
+/// Fetch the refs advertised by the remote for the given service (either
+/// `git-upload-pack` for fetching or `git-receive-pack` for pushing).
+pub(crate) fn fetch_refs(repo_url: &RemoteUrl, service: &str) -> Result<(Vec<Ref>, Vec<String>)> {
+    let refs_url = format!("{}/info/refs?service={}", repo_url.http_base()?, service);
     let resp = reqwest::blocking::get(refs_url)?;
 
-    const ADV_CONTENT_TYPE: &str = "application/x-git-upload-pack-advertisement";
+    let adv_content_type = format!("application/x-{service}-advertisement");
     let content_type = resp
         .headers()
         .get(reqwest::header::CONTENT_TYPE)
         .and_then(|h| h.to_str().ok())
         .unwrap_or("");
-    if content_type != ADV_CONTENT_TYPE {
+    if content_type != adv_content_type {
         tracing::warn!(
             "bad remote: unexpected content type (wanted \"{}\", got \"{}\")",
-            ADV_CONTENT_TYPE,
+            adv_content_type,
             content_type
         );
     }
 
     let bytes = resp.bytes()?;
-    let mut line_iter = pkt_line_iter(&bytes);
-    let announce = line_iter.next().unwrap_or(b"");
-    if announce != b"# service=git-upload-pack\n" {
-        tracing::debug!("bad remote: first line from git-upload-pack should announce service");
+    let mut line_iter = pkt_line_data_iter(&bytes);
+    let announce = line_iter.next().transpose()?.unwrap_or(b"");
+    if announce != format!("# service={service}\n").as_bytes() {
+        tracing::debug!("bad remote: first line from {service} should announce service");
         tracing::debug!("{}", String::from_utf8_lossy(announce));
         eyre::bail!("bad remote");
     }
@@ -96,7 +230,7 @@ fn fetch_refs(repo_url: &str) -> Result<(Vec<Ref>, Vec<String>)> {
     let mut extras: Vec<String> = Vec::new();
 
     for (index, line) in line_iter.enumerate() {
-        let line = pkt_line_str(line);
+        let line = pkt_line_str(line?)?;
         let (hash, line) = line
             .split_once(' ')
             .ok_or_else(|| eyre::eyre!("read ref hash"))?;
@@ -161,15 +295,33 @@ fn find_default_branch(extras: &[String]) -> &str {
     default_branch
 }
 
-// TODO: fetch more than just HEAD?
-fn fetch_packfile(repo_url: &str, head_ref: &Ref) -> Result<Vec<u8>> {
+/// The hash algorithm the remote uses, as advertised by the `object-format`
+/// capability. Servers that don't advertise it only support SHA-1.
+pub(crate) fn find_object_format(extras: &[String]) -> Result<HashAlgorithm> {
+    match extras
+        .iter()
+        .find_map(|ex| ex.strip_prefix("object-format="))
+    {
+        Some(name) => name.parse(),
+        None => Ok(HashAlgorithm::Sha1),
+    }
+}
+
+/// Request a packfile containing every object reachable from `wants`, excluding
+/// any objects reachable from `haves` (which we already have locally).
+pub(crate) fn fetch_packfile(
+    repo_url: &RemoteUrl,
+    wants: &[&str],
+    haves: &[&str],
+) -> Result<Vec<u8>> {
     use tokio::runtime::Runtime;
 
+    let repo_url = repo_url.http_base()?;
     let rt = Runtime::new().unwrap();
-    rt.block_on(fetch_packfile_inner(repo_url, head_ref))
+    rt.block_on(fetch_packfile_inner(&repo_url, wants, haves))
 }
 
-async fn fetch_packfile_inner(repo_url: &str, head_ref: &Ref) -> Result<Vec<u8>> {
+async fn fetch_packfile_inner(repo_url: &str, wants: &[&str], haves: &[&str]) -> Result<Vec<u8>> {
     // side-band, side-band-64k
     //
     // This capability means that server can send, and client understand multiplexed progress
@@ -188,10 +340,32 @@ async fn fetch_packfile_inner(repo_url: &str, head_ref: &Ref) -> Result<Vec<u8>>
     //   2 - progress messages
     //   3 - fatal error message just before stream aborts
     //
-    let mut body = String::new();
-    body.push_str(&PacketLine::new(format!("want {} side-band-64k", head_ref.hash)).repr());
-    body.push_str(&PacketLine::flush().repr());
-    body.push_str(&PacketLine::new("done").repr());
+    // thin-pack
+    //
+    // The server may send REF deltas against objects that we told it we `have`,
+    // without including those base objects in the pack.
+    //
+    // servers assume SHA-1 unless told otherwise
+    let mut capabilities = String::from("side-band-64k thin-pack");
+    let object_format = HashAlgorithm::current()?;
+    if object_format != HashAlgorithm::Sha1 {
+        capabilities.push_str(&format!(" object-format={}", object_format.name()));
+    }
+
+    let mut body = Vec::new();
+    for (index, want) in wants.iter().enumerate() {
+        // capabilities are only sent along with the first want
+        if index == 0 {
+            body.extend(PacketLine::new(format!("want {want} {capabilities}")).repr());
+        } else {
+            body.extend(PacketLine::new(format!("want {want}")).repr());
+        }
+    }
+    body.extend(PacketLine::flush().repr());
+    for have in haves {
+        body.extend(PacketLine::new(format!("have {have}")).repr());
+    }
+    body.extend(PacketLine::new("done").repr());
 
     let client = reqwest::Client::new();
     let url = format!("{}/git-upload-pack", repo_url);
@@ -208,7 +382,14 @@ async fn fetch_packfile_inner(repo_url: &str, head_ref: &Ref) -> Result<Vec<u8>>
 
     let mut line_stream = PacketLineStream::new(resp_stream);
 
-    if pkt_line_str(line_stream.next().await.unwrap()?.as_ref()) != "NAK" {
+    // since we don't request `multi_ack`, the server responds with a single
+    // `ACK <hash>` if it found an object in common with our haves, or `NAK`
+    let ack = line_stream
+        .next()
+        .await
+        .ok_or_else(|| eyre::eyre!("malformed response from remote: expected ACK or NAK"))??;
+    let ack = pkt_line_str(ack.as_ref())?;
+    if ack != "NAK" && !ack.starts_with("ACK ") {
         eyre::bail!("expected server to respond");
     }
 
@@ -224,10 +405,10 @@ async fn fetch_packfile_inner(repo_url: &str, head_ref: &Ref) -> Result<Vec<u8>>
             1 => packfile.extend_from_slice(line),
             2 | 3 => {
                 // TODO: switch away from reqwest blocking to display this in real time
-                print!("remote: {}", pkt_line_str_keep_newline(line));
+                out!("remote: {}", pkt_line_str_keep_newline(line)?);
             }
             other => {
-                panic!("unrecognized channel {other}");
+                eyre::bail!("malformed response from remote: unrecognized channel {other}");
             }
         }
     }
@@ -1,19 +1,47 @@
 use crate::commit::{Commit, CommitAttribution};
-use crate::object::{Object, ObjectHashable};
-use eyre::Result;
+use crate::object::{Object, ObjectHash, ObjectHashable};
+use crate::output::outln;
+use eyre::{Context, Result};
+use std::io::Read;
+
+/// Write a commit object for the given tree. Each of the `messages` is a
+/// paragraph of the commit message; if there aren't any, the message is read
+/// from stdin instead.
+pub fn run(tree_hash: String, parent_hashes: Vec<String>, messages: Vec<String>) -> Result<()> {
+    // make sure we were given real hashes before writing them into the commit
+    tree_hash.parse::<ObjectHash>()?;
+    for parent_hash in parent_hashes.iter() {
+        parent_hash.parse::<ObjectHash>()?;
+    }
+
+    let message = if messages.is_empty() {
+        let mut message = String::new();
+        std::io::stdin()
+            .read_to_string(&mut message)
+            .context("read commit message from stdin")?;
+        message
+    } else {
+        // like git, separate paragraphs with a blank line and make sure the
+        // message ends with a newline
+        let mut message = messages.join("\n\n");
+        if !message.ends_with('\n') {
+            message.push('\n');
+        }
+        message
+    };
 
-pub fn run(tree_hash: String, parent_hashes: Vec<String>, message: String) -> Result<()> {
     let commit = Commit {
         tree_hash,
         parent_hashes,
-        author: CommitAttribution::yours_truly(),
-        committer: CommitAttribution::yours_truly(),
-        message,
+        author: CommitAttribution::author()?,
+        committer: CommitAttribution::committer()?,
+        extra_headers: Vec::new(),
+        message: message.into_bytes(),
     };
 
     let hash = Object::commit(commit).hash(true)?;
 
-    println!("{hash}");
+    outln!("{hash}");
 
     Ok(())
 }
@@ -0,0 +1,111 @@
+use crate::commit::Commit;
+use crate::error::GitError;
+use crate::graph;
+use crate::object::{ObjectBuf, ObjectType};
+use crate::refs;
+use crate::tag::Tag;
+use eyre::Result;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::SystemTime;
+
+/// A tag that could be used to describe a commit.
+struct Candidate {
+    name: String,
+    annotated: bool,
+    /// When the tag was created (lightweight tags don't record this).
+    date: Option<SystemTime>,
+}
+
+pub fn run(commit: Option<&str>, tags: bool, always: bool) -> Result<()> {
+    let commit = commit.unwrap_or("HEAD");
+    let hash = refs::lookup_commit(commit)?.ok_or_else(|| GitError::BadObject {
+        name: commit.to_owned(),
+    })?;
+
+    let candidates = candidates(tags)?;
+
+    // walk back through history (nearest commits first) until we reach a
+    // commit that has been tagged
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([hash.clone()]);
+    while let Some(ancestor) = queue.pop_front() {
+        if !seen.insert(ancestor.clone()) {
+            continue;
+        }
+
+        if let Some(candidate) = candidates.get(&ancestor) {
+            if ancestor == hash {
+                println!("{}", candidate.name);
+            } else {
+                let depth = graph::count_commits(&hash, &ancestor)?;
+                println!("{}-{depth}-g{}", candidate.name, &hash[..7]);
+            }
+            return Ok(());
+        }
+
+        queue.extend(Commit::read_at_hash(&ancestor)?.parent_hashes);
+    }
+
+    if always {
+        println!("{}", &hash[..7]);
+        return Ok(());
+    }
+
+    if tags {
+        eyre::bail!("no tags can describe '{hash}'");
+    }
+    eyre::bail!("no annotated tags can describe '{hash}'; try --tags");
+}
+
+/// Map each tagged commit to the best tag pointing at it, preferring
+/// annotated tags over lightweight ones, and newer tags over older ones.
+/// Lightweight tags are only considered if `include_lightweight` is set.
+fn candidates(include_lightweight: bool) -> Result<HashMap<String, Candidate>> {
+    let mut candidates: HashMap<String, Candidate> = HashMap::new();
+
+    for (ref_name, mut hash) in refs::list("refs/tags")? {
+        let name = ref_name.trim_start_matches("refs/tags/").to_owned();
+
+        // peel annotated tags (which may themselves point at other tags)
+        // down to the object they describe
+        let mut annotated = false;
+        let mut date = None;
+        let mut object_type = ObjectBuf::read_at_hash(&hash)?.object_type;
+        while object_type == ObjectType::Tag {
+            let tag = Tag::read_at_hash(&hash)?;
+            if !annotated {
+                if tag.name != name {
+                    eprintln!(
+                        "warning: tag '{}' is externally known as '{name}'",
+                        tag.name
+                    );
+                }
+                annotated = true;
+                date = tag.tagger.map(|tagger| tagger.timestamp);
+            }
+            hash = tag.object_hash;
+            object_type = tag.object_type;
+        }
+
+        // tags of trees and blobs can't describe a commit
+        if object_type != ObjectType::Commit || (!annotated && !include_lightweight) {
+            continue;
+        }
+
+        let better = candidates
+            .get(&hash)
+            .is_none_or(|existing| (annotated, date) > (existing.annotated, existing.date));
+        if better {
+            candidates.insert(
+                hash,
+                Candidate {
+                    name,
+                    annotated,
+                    date,
+                },
+            );
+        }
+    }
+
+    Ok(candidates)
+}
@@ -0,0 +1,422 @@
+use crate::color::Colors;
+use crate::convert::is_binary;
+use crate::diff::{self, Edit};
+use crate::index::Index;
+use crate::object::{ObjectBuf, ObjectHash, ObjectMode};
+use crate::repository;
+use crate::tree::Tree;
+use ansi_term::{Color, Style};
+use eyre::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ffi::OsString;
+use std::fmt::Display;
+
+/// How a file differs between the two sides of a diff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ChangeStatus {
+    Added,
+    Deleted,
+    Modified,
+    /// Moved from `old_path`, with the given similarity (as a percentage).
+    Renamed(u8),
+}
+
+impl Display for ChangeStatus {
+    /// The letter `--name-status` uses for this kind of change (followed by
+    /// the similarity, for renames).
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Added => f.write_str("A"),
+            Self::Deleted => f.write_str("D"),
+            Self::Modified => f.write_str("M"),
+            Self::Renamed(similarity) => write!(f, "R{similarity:03}"),
+        }
+    }
+}
+
+/// A file that differs between the two sides of a diff. `old` is missing for
+/// added files, and `new` for deleted ones.
+#[derive(Debug)]
+pub(crate) struct FileChange {
+    pub path: OsString,
+    /// Where the file was renamed from, if it was.
+    pub old_path: Option<OsString>,
+    pub status: ChangeStatus,
+    pub old: Option<(ObjectMode, ObjectHash)>,
+    pub new: Option<(ObjectMode, ObjectHash)>,
+}
+
+/// The files on one side of a diff, by path.
+type Side = BTreeMap<OsString, (ObjectMode, ObjectHash)>;
+
+/// Show which files changed, either between the index and the working tree,
+/// a commit and the working tree, or two commits. Only the summary formats
+/// (`--name-status` and `--stat`) are supported for now.
+///
+/// Files that were moved without changing are always shown as renames. With
+/// `find_renames`, so are files that changed a little along the way, as long
+/// as they're at least that similar (as a percentage).
+pub fn run(
+    commits: &[String],
+    name_status: bool,
+    stat: bool,
+    find_renames: Option<u8>,
+) -> Result<()> {
+    eyre::ensure!(
+        name_status || stat,
+        "only --name-status and --stat are supported for now"
+    );
+
+    let (old, new, new_is_working_tree) = match commits {
+        [] => (index_side()?, working_tree_side()?, true),
+        [commit] => (tree_side(commit)?, working_tree_side()?, true),
+        [old, new] => (tree_side(old)?, tree_side(new)?, false),
+        _ => eyre::bail!("too many revisions; expected at most two"),
+    };
+
+    let changes = detect_renames(
+        compare(&old, &new, new_is_working_tree),
+        find_renames,
+        new_is_working_tree,
+    )?;
+
+    if name_status {
+        for change in changes.iter() {
+            match &change.old_path {
+                Some(old_path) => println!(
+                    "{}\t{}\t{}",
+                    change.status,
+                    old_path.to_string_lossy(),
+                    change.path.to_string_lossy()
+                ),
+                None => println!("{}\t{}", change.status, change.path.to_string_lossy()),
+            }
+        }
+    } else {
+        print_stat(&changes, new_is_working_tree)?;
+    }
+
+    Ok(())
+}
+
+fn index_side() -> Result<Side> {
+    let index = Index::read_default_or_empty().context("read index")?;
+    let mut side = Side::new();
+    for entry in index.entries {
+        // entries outside of a sparse checkout are expected to be missing
+        if !entry.skip_worktree() {
+            side.insert(entry.name.clone(), (entry.mode()?, entry.hash));
+        }
+    }
+    Ok(side)
+}
+
+fn working_tree_side() -> Result<Side> {
+    let working_tree = Index::working_tree().context("read working tree")?;
+    let mut side = Side::new();
+    for entry in working_tree.entries {
+        side.insert(entry.name.clone(), (entry.mode()?, entry.hash));
+    }
+    Ok(side)
+}
+
+pub(crate) fn tree_side(name: &str) -> Result<Side> {
+    Ok(Tree::read_tree_ish(name)?
+        .flatten()?
+        .into_iter()
+        .map(|entry| (entry.name, (entry.mode, entry.hash)))
+        .collect())
+}
+
+/// List the files that differ between `old` and `new`, sorted by path. Files
+/// that only exist in the working tree are untracked, so they're left out.
+pub(crate) fn compare(old: &Side, new: &Side, new_is_working_tree: bool) -> Vec<FileChange> {
+    let paths: BTreeSet<&OsString> = old.keys().chain(new.keys()).collect();
+
+    let mut changes = Vec::new();
+    for path in paths {
+        let (old, new) = (old.get(path), new.get(path));
+        let status = match (old, new) {
+            (Some(old), Some(new)) if old == new => continue,
+            (Some(_), Some(_)) => ChangeStatus::Modified,
+            (Some(_), None) => ChangeStatus::Deleted,
+            (None, Some(_)) if new_is_working_tree => continue,
+            (None, Some(_)) => ChangeStatus::Added,
+            (None, None) => unreachable!("path came from one of the sides"),
+        };
+
+        changes.push(FileChange {
+            path: path.clone(),
+            old_path: None,
+            status,
+            old: old.cloned(),
+            new: new.cloned(),
+        });
+    }
+
+    changes
+}
+
+/// Pair up deleted and added files whose contents are identical, returning
+/// the `(deleted, added)` indices of each pair. Each file is paired at most
+/// once, in order.
+pub(crate) fn exact_renames(deleted: &[&ObjectHash], added: &[&ObjectHash]) -> Vec<(usize, usize)> {
+    let mut by_hash: HashMap<&ObjectHash, Vec<usize>> = HashMap::new();
+    for (i, hash) in deleted.iter().enumerate().rev() {
+        by_hash.entry(hash).or_default().push(i);
+    }
+
+    added
+        .iter()
+        .enumerate()
+        .filter_map(|(j, hash)| Some((by_hash.get_mut(hash)?.pop()?, j)))
+        .collect()
+}
+
+/// Replace deleted and added files that are really the same file moved
+/// elsewhere with a single rename: always if their contents are identical,
+/// and with `min_similarity` if they're at least that similar.
+fn detect_renames(
+    changes: Vec<FileChange>,
+    min_similarity: Option<u8>,
+    new_is_working_tree: bool,
+) -> Result<Vec<FileChange>> {
+    fn hash(side: &Option<(ObjectMode, ObjectHash)>) -> &ObjectHash {
+        &side
+            .as_ref()
+            .expect("side exists for added or deleted file")
+            .1
+    }
+
+    let (deleted, added): (Vec<usize>, Vec<usize>) = (
+        (0..changes.len())
+            .filter(|&i| changes[i].status == ChangeStatus::Deleted)
+            .collect(),
+        (0..changes.len())
+            .filter(|&i| changes[i].status == ChangeStatus::Added)
+            .collect(),
+    );
+
+    // (deleted index, added index, similarity)
+    let mut pairs: Vec<(usize, usize, u8)> = exact_renames(
+        &deleted
+            .iter()
+            .map(|&i| hash(&changes[i].old))
+            .collect::<Vec<_>>(),
+        &added
+            .iter()
+            .map(|&i| hash(&changes[i].new))
+            .collect::<Vec<_>>(),
+    )
+    .into_iter()
+    .map(|(d, a)| (deleted[d], added[a], 100))
+    .collect();
+
+    if let Some(min_similarity) = min_similarity {
+        let paired = |i: usize, pairs: &[(usize, usize, u8)]| {
+            pairs.iter().any(|&(d, a, _)| d == i || a == i)
+        };
+
+        let mut added_contents = Vec::new();
+        for &a in added.iter().filter(|&&a| !paired(a, &pairs)) {
+            let new_path = new_is_working_tree.then_some(&changes[a].path);
+            added_contents.push((a, contents(&changes[a].new, new_path)?));
+        }
+
+        let mut candidates = Vec::new();
+        for &d in deleted.iter().filter(|&&d| !paired(d, &pairs)) {
+            let old = contents(&changes[d].old, None)?;
+            for (a, new) in added_contents.iter() {
+                let score = similarity(&old, new);
+                if score >= min_similarity {
+                    candidates.push((d, *a, score));
+                }
+            }
+        }
+
+        // the most similar pairs win
+        candidates.sort_by_key(|&(_, _, score)| std::cmp::Reverse(score));
+        for (d, a, score) in candidates {
+            if !paired(d, &pairs) && !paired(a, &pairs) {
+                pairs.push((d, a, score));
+            }
+        }
+    }
+
+    let mut changes: Vec<Option<FileChange>> = changes.into_iter().map(Some).collect();
+    for (d, a, score) in pairs {
+        let old = changes[d].take().expect("each file is paired once");
+        let added = changes[a].as_mut().expect("each file is paired once");
+        added.old_path = Some(old.path);
+        added.old = old.old;
+        added.status = ChangeStatus::Renamed(score);
+    }
+
+    Ok(changes.into_iter().flatten().collect())
+}
+
+/// How similar two files are, as a percentage: how much of the larger one is
+/// made up of lines that are unchanged from the other.
+fn similarity(old: &[u8], new: &[u8]) -> u8 {
+    let max_len = old.len().max(new.len());
+    if max_len == 0 {
+        return 100;
+    }
+
+    let (old_lines, new_lines) = (lines(old), lines(new));
+    let common: usize = diff::diff(&old_lines, &new_lines)
+        .into_iter()
+        .filter_map(|edit| match edit {
+            Edit::Equal { old, .. } => Some(old_lines[old].len()),
+            _ => None,
+        })
+        .sum();
+
+    (common * 100 / max_len) as u8
+}
+
+/// How many lines were added and removed in a file, or its sizes before and
+/// after if it's binary.
+enum LineCounts {
+    Text { insertions: usize, deletions: usize },
+    Binary { old_len: usize, new_len: usize },
+}
+
+/// Print a `--stat` histogram of insertions and deletions per file, followed
+/// by a summary of the totals.
+fn print_stat(changes: &[FileChange], new_is_working_tree: bool) -> Result<()> {
+    let mut counts = Vec::with_capacity(changes.len());
+    for change in changes.iter() {
+        let old = contents(&change.old, None)?;
+        let new_path = new_is_working_tree.then_some(&change.path);
+        let new = contents(&change.new, new_path)?;
+        counts.push(line_counts(&old, &new));
+    }
+
+    let names: Vec<String> = changes
+        .iter()
+        .map(|change| match &change.old_path {
+            Some(old_path) => format!(
+                "{} => {}",
+                old_path.to_string_lossy(),
+                change.path.to_string_lossy()
+            ),
+            None => change.path.to_string_lossy().into_owned(),
+        })
+        .collect();
+    let name_width = names.iter().map(String::len).max().unwrap_or(0);
+
+    let max_change = counts
+        .iter()
+        .map(|counts| match counts {
+            LineCounts::Text {
+                insertions,
+                deletions,
+            } => insertions + deletions,
+            LineCounts::Binary { .. } => 0,
+        })
+        .max()
+        .unwrap_or(0);
+    let count_width = max_change.to_string().len().max(3);
+
+    // like git, fit everything in 80 columns, scaling the histogram down if
+    // there are too many changes to show one character per line
+    let graph_width = 80usize.saturating_sub(name_width + count_width + 6).max(10);
+    let scale = |n: usize| match n {
+        0 => 0,
+        n if max_change <= graph_width => n,
+        n => 1 + n * (graph_width - 1) / max_change,
+    };
+
+    let colors = Colors::for_stdout("diff")?;
+    let (mut total_insertions, mut total_deletions) = (0, 0);
+    for (name, counts) in names.iter().zip(counts) {
+        match counts {
+            LineCounts::Text {
+                insertions,
+                deletions,
+            } => {
+                total_insertions += insertions;
+                total_deletions += deletions;
+                println!(
+                    " {name:<name_width$} | {:>count_width$} {}{}",
+                    insertions + deletions,
+                    colors
+                        .style(Style::new().fg(Color::Green))
+                        .paint("+".repeat(scale(insertions))),
+                    colors
+                        .style(Style::new().fg(Color::Red))
+                        .paint("-".repeat(scale(deletions))),
+                );
+            }
+            LineCounts::Binary { old_len, new_len } => {
+                println!(" {name:<name_width$} | Bin {old_len} -> {new_len} bytes");
+            }
+        }
+    }
+
+    let plural =
+        |n: usize, one: &str, many: &str| format!("{n} {}", if n == 1 { one } else { many });
+    let mut summary = format!(" {} changed", plural(changes.len(), "file", "files"));
+    if total_insertions > 0 || total_deletions == 0 {
+        summary.push_str(&format!(
+            ", {}(+)",
+            plural(total_insertions, "insertion", "insertions")
+        ));
+    }
+    if total_deletions > 0 || total_insertions == 0 {
+        summary.push_str(&format!(
+            ", {}(-)",
+            plural(total_deletions, "deletion", "deletions")
+        ));
+    }
+    println!("{summary}");
+
+    Ok(())
+}
+
+/// The contents of one side of a changed file: read from `path` in the working
+/// tree if given, or else from the object store. Submodules are shown as the
+/// commit they point at, like git does.
+pub(crate) fn contents(
+    file: &Option<(ObjectMode, ObjectHash)>,
+    path: Option<&OsString>,
+) -> Result<Vec<u8>> {
+    let Some((mode, hash)) = file else {
+        return Ok(Vec::new());
+    };
+
+    match (mode, path) {
+        (ObjectMode::Gitlink, _) => Ok(format!("Subproject commit {hash}\n").into_bytes()),
+        (_, Some(path)) => std::fs::read(repository::work_tree_path(path))
+            .with_context(|| format!("read {}", path.to_string_lossy())),
+        (_, None) => ObjectBuf::read_at_hash(hash.as_hex())?.read_to_vec(),
+    }
+}
+
+fn line_counts(old: &[u8], new: &[u8]) -> LineCounts {
+    if is_binary(old) || is_binary(new) {
+        return LineCounts::Binary {
+            old_len: old.len(),
+            new_len: new.len(),
+        };
+    }
+
+    let (mut insertions, mut deletions) = (0, 0);
+    for edit in diff::diff(&lines(old), &lines(new)) {
+        match edit {
+            Edit::Insert { .. } => insertions += 1,
+            Edit::Delete { .. } => deletions += 1,
+            Edit::Equal { .. } => (),
+        }
+    }
+
+    LineCounts::Text {
+        insertions,
+        deletions,
+    }
+}
+
+fn lines(contents: &[u8]) -> Vec<&[u8]> {
+    contents.split_inclusive(|&b| b == b'\n').collect()
+}
@@ -0,0 +1,109 @@
+use crate::config::Config;
+use crate::graph;
+use crate::hash::HashAlgorithm;
+use crate::object::{objects_dir, ObjectBuf};
+use crate::output::outln;
+use crate::pack::{self, OpenOptions, PackStream};
+use crate::refs;
+use crate::remote_url::RemoteUrl;
+use crate::subcommand::clone::{fetch_packfile, fetch_refs, find_object_format};
+use eyre::{Context, Result};
+use std::io::Write;
+use tempfile::NamedTempFile;
+
+pub fn run(remote: &str) -> Result<()> {
+    fetch(remote)
+}
+
+/// Download every branch from the given remote that we don't already have,
+/// recording each as a remote-tracking ref under `refs/remotes/<remote>/`.
+pub(crate) fn fetch(remote: &str) -> Result<()> {
+    let config = Config::read_default().context("read config")?;
+    let repo_url: RemoteUrl = config
+        .get(&format!("remote.{remote}.url"))
+        .ok_or_else(|| eyre::eyre!("'{remote}' does not appear to be a configured remote"))?
+        .parse()?;
+
+    let (remote_refs, extras) = fetch_refs(&repo_url, "git-upload-pack")?;
+    let object_format = find_object_format(&extras)?;
+    if object_format != HashAlgorithm::current()? {
+        eyre::bail!(
+            "mismatched object format: the remote uses {}, but this repository uses {}",
+            object_format.name(),
+            HashAlgorithm::current()?.name()
+        );
+    }
+
+    let branches: Vec<(&str, &str)> = remote_refs
+        .iter()
+        .filter_map(|_ref| Some((_ref.name.strip_prefix("refs/heads/")?, _ref.hash.as_str())))
+        .collect();
+
+    let mut wants: Vec<&str> = branches
+        .iter()
+        .map(|(_, hash)| *hash)
+        .filter(|hash| !ObjectBuf::exists(hash))
+        .collect();
+    wants.sort_unstable();
+    wants.dedup();
+
+    if !wants.is_empty() {
+        let mut local_refs = refs::list("refs/heads")?;
+        local_refs.extend(refs::list(&format!("refs/remotes/{remote}"))?);
+        let haves: Vec<&str> = local_refs.iter().map(|(_, hash)| hash.as_str()).collect();
+
+        let packfile = fetch_packfile(&repo_url, &wants, &haves)?;
+        if packfile.is_empty() {
+            eyre::bail!("oops! looks like we didn't receive anything in the packfile");
+        }
+
+        let mut f = NamedTempFile::new().context("create temp file")?;
+        f.write_all(&packfile)?;
+
+        // objects are written out as they're read, so the whole pack is never
+        // held in memory at once
+        let cache_limit = pack::delta_base_cache_limit(&config)?;
+        let options = OpenOptions {
+            cache_limit,
+            ..Default::default()
+        };
+        PackStream::open(f.path(), None, options)
+            .context("read packfile")?
+            .unpack_into(&objects_dir())
+            .check()
+            .context("unpack packfile contents")?;
+    }
+
+    outln!("From {repo_url}");
+    for (branch, hash) in branches {
+        let tracking_ref = format!("refs/remotes/{remote}/{branch}");
+
+        let message = match refs::resolve(&tracking_ref)? {
+            Some(old_hash) if old_hash == hash => continue,
+            Some(old_hash) if graph::is_ancestor(&old_hash, hash)? => {
+                outln!(
+                    "   {}..{}  {branch} -> {remote}/{branch}",
+                    &old_hash[..7],
+                    &hash[..7]
+                );
+                "fast-forward"
+            }
+            Some(old_hash) => {
+                outln!(
+                    " + {}...{} {branch} -> {remote}/{branch}  (forced update)",
+                    &old_hash[..7],
+                    &hash[..7]
+                );
+                "forced-update"
+            }
+            None => {
+                outln!(" * [new branch]      {branch} -> {remote}/{branch}");
+                "storing head"
+            }
+        };
+
+        refs::update(&tracking_ref, hash, &format!("fetch {remote}: {message}"))?;
+    }
+
+    Ok(())
+}
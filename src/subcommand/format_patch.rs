@@ -0,0 +1,166 @@
+use crate::commit::Commit;
+use crate::convert::is_binary;
+use crate::error::GitError;
+use crate::graph::{self, WalkOrder};
+use crate::output::outln;
+use crate::patch;
+use crate::refs;
+use crate::subcommand::diff::{compare, contents, tree_side, ChangeStatus, FileChange};
+use eyre::{Context, Result};
+use std::collections::BTreeMap;
+
+/// The longest the subject part of a patch's file name may be.
+const MAX_SLUG_LEN: usize = 52;
+
+/// Write each commit in `range` (`<since>..<until>`, or just `<since>` for
+/// everything since it up to HEAD) to its own `NNNN-<subject>.patch` file in
+/// mbox format, oldest first, ready to be applied with `am`. Merge commits
+/// are skipped, like git does.
+pub fn run(range: &str) -> Result<()> {
+    let (since, until) = range.split_once("..").unwrap_or((range, "HEAD"));
+    let since = lookup(if since.is_empty() { "HEAD" } else { since })?;
+    let until = lookup(if until.is_empty() { "HEAD" } else { until })?;
+
+    let mut commits = Vec::new();
+    for hash in graph::walk_range(&until, &since, WalkOrder::Topo)?
+        .into_iter()
+        .rev()
+    {
+        let commit = Commit::read_at_hash(&hash)?;
+        if commit.parent_hashes.len() <= 1 {
+            commits.push((hash, commit));
+        }
+    }
+
+    for (i, (hash, commit)) in commits.iter().enumerate() {
+        let subject = commit.format("%(subject)")?;
+        let file_name = format!("{:04}-{}.patch", i + 1, slug(&subject));
+
+        let numbering = match commits.len() {
+            1 => String::from("[PATCH]"),
+            total => format!("[PATCH {}/{total}]", i + 1),
+        };
+        let mut out = format!(
+            "From {hash} Mon Sep 17 00:00:00 2001\n\
+             From: {}\n\
+             Date: {}\n\
+             Subject: {numbering} {subject}\n\n",
+            commit.author.ident(),
+            commit.author.format_rfc2822()
+        )
+        .into_bytes();
+        let body = commit.format("%(body)")?;
+        if !body.is_empty() {
+            out.extend_from_slice(body.as_bytes());
+            out.push(b'\n');
+        }
+        out.extend_from_slice(b"---\n");
+
+        let old = match commit.parent_hashes.first() {
+            Some(parent) => tree_side(parent)?,
+            None => BTreeMap::new(),
+        };
+        for change in compare(&old, &tree_side(hash)?, false) {
+            write_diff(&mut out, &change)?;
+        }
+        out.extend_from_slice(format!("-- \n{}\n\n", env!("CARGO_PKG_VERSION")).as_bytes());
+
+        std::fs::write(&file_name, out).with_context(|| format!("write {file_name}"))?;
+        outln!("{file_name}");
+    }
+
+    Ok(())
+}
+
+fn lookup(name: &str) -> Result<String> {
+    Ok(
+        refs::lookup_commit(name)?.ok_or_else(|| GitError::UnknownRevision {
+            name: name.to_owned(),
+        })?,
+    )
+}
+
+/// Turn a subject into something safe to use in a file name, like git: runs
+/// of anything other than letters, digits, `.`, and `_` become a single `-`.
+fn slug(subject: &str) -> String {
+    let mut slug = String::with_capacity(subject.len());
+    for c in subject.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= MAX_SLUG_LEN {
+            break;
+        }
+    }
+    slug.trim_end_matches(['-', '.']).to_owned()
+}
+
+/// Write a file's changes as a git-style unified diff.
+fn write_diff(out: &mut Vec<u8>, change: &FileChange) -> Result<()> {
+    let path = change.path.to_string_lossy();
+    out.extend_from_slice(format!("diff --git a/{path} b/{path}\n").as_bytes());
+
+    match (&change.old, &change.new) {
+        (None, Some((mode, hash))) => out.extend_from_slice(
+            format!(
+                "new file mode {mode}\nindex 0000000..{}\n",
+                &hash.as_hex()[..7]
+            )
+            .as_bytes(),
+        ),
+        (Some((mode, hash)), None) => out.extend_from_slice(
+            format!(
+                "deleted file mode {mode}\nindex {}..0000000\n",
+                &hash.as_hex()[..7]
+            )
+            .as_bytes(),
+        ),
+        (Some((old_mode, old_hash)), Some((new_mode, new_hash))) => {
+            if old_mode != new_mode {
+                out.extend_from_slice(
+                    format!("old mode {old_mode}\nnew mode {new_mode}\n").as_bytes(),
+                );
+            }
+            if old_hash == new_hash {
+                return Ok(());
+            }
+            let index = format!(
+                "index {}..{}",
+                &old_hash.as_hex()[..7],
+                &new_hash.as_hex()[..7]
+            );
+            match old_mode == new_mode {
+                true => out.extend_from_slice(format!("{index} {new_mode}\n").as_bytes()),
+                false => out.extend_from_slice(format!("{index}\n").as_bytes()),
+            }
+        }
+        (None, None) => return Ok(()),
+    }
+
+    let old = contents(&change.old, None)?;
+    let new = contents(&change.new, None)?;
+    let old_name = match change.status {
+        ChangeStatus::Added => String::from("/dev/null"),
+        _ => format!("a/{path}"),
+    };
+    let new_name = match change.status {
+        ChangeStatus::Deleted => String::from("/dev/null"),
+        _ => format!("b/{path}"),
+    };
+
+    if is_binary(&old) || is_binary(&new) {
+        out.extend_from_slice(
+            format!("Binary files {old_name} and {new_name} differ\n").as_bytes(),
+        );
+        return Ok(());
+    }
+
+    out.extend_from_slice(format!("--- {old_name}\n+++ {new_name}\n").as_bytes());
+    for hunk in patch::hunks(&old, &new) {
+        out.extend(hunk.to_bytes());
+    }
+
+    Ok(())
+}
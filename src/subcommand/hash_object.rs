@@ -1,10 +1,33 @@
-use crate::object::{Object, ObjectHashable};
-use eyre::Result;
+use crate::convert::EolConversion;
+use crate::object::{ObjectBuf, ObjectHashable, ObjectType};
+use crate::output::outln;
+use crate::parser::Parser;
+use crate::repository;
+use eyre::{Context, Result};
+use std::io::Cursor;
 
-pub fn run(write: bool, path: &str) -> Result<()> {
-    let hash = Object::blob(path).hash(write)?;
+/// Hash the file at `path` as a blob, converting its line endings the same way
+/// staging it would. With `filter_path`, the conversion is decided by that
+/// path's attributes instead of the file's own (for files that will be stored
+/// under a different name); with `no_filters`, the file is hashed as-is.
+pub fn run(write: bool, path: &str, filter_path: Option<&str>, no_filters: bool) -> Result<()> {
+    let conversion = match (no_filters, filter_path) {
+        (true, _) => EolConversion::None,
+        (false, filter_path) => {
+            EolConversion::for_path(&repository::from_prefix(filter_path.unwrap_or(path)))?
+        }
+    };
 
-    println!("{hash}");
+    let contents = std::fs::read(path).with_context(|| format!("read {path}"))?;
+    let contents = conversion.to_git(contents);
+    let hash = ObjectBuf {
+        object_type: ObjectType::Blob,
+        content_len: contents.len(),
+        contents: Parser::new(Cursor::new(contents)),
+    }
+    .hash(write)?;
+
+    outln!("{hash}");
 
     Ok(())
 }
@@ -1,15 +1,74 @@
-use crate::pack::Pack;
-use eyre::Result;
+use crate::config::Config;
+use crate::object::ObjectType;
+use crate::output::outln;
+use crate::pack::{self, OpenOptions, Pack, ProgressFn};
+use crate::tree::Tree;
+use crate::utils::print_progress;
+use eyre::{Context, Result};
 use std::path::Path;
 
 /// Given a `.pack` packfile, create a corresponding `.idx` index file that maps its contents.
-pub fn run(pack_file: impl AsRef<Path>) -> Result<()> {
-    let pack_file: &Path = pack_file.as_ref();
+///
+/// With `strict`, every tree in the pack is also checked for well-formed,
+/// correctly-sorted entries. A `.rev` reverse index is also written with
+/// `rev_index` (or if `pack.writeReverseIndex` is set).
+///
+/// With `verbose`, progress is shown while objects are indexed and deltas are
+/// resolved, followed by how many there were and the longest delta chain.
+pub fn run(
+    pack_file: impl AsRef<Path>,
+    strict: bool,
+    rev_index: bool,
+    verbose: bool,
+) -> Result<()> {
+    let pack_file = pack_file.as_ref();
     let index_file = pack_file.with_extension("idx");
 
-    let pack = Pack::open(pack_file)?;
+    let config = Config::read_default()?;
+    let cache_limit = pack::delta_base_cache_limit(&config)?;
+    let mut print_progress = print_progress;
+    let progress: Option<&mut ProgressFn> = match verbose {
+        true => Some(&mut print_progress),
+        false => None,
+    };
+    let options = OpenOptions {
+        cache_limit,
+        lazy: true,
+        ..Default::default()
+    };
+    let pack = Pack::open(pack_file, progress, options)?;
     pack.write_index(index_file)?;
-    println!("{}", pack.checksum);
+
+    if verbose {
+        let deltas = pack.contents.iter().filter(|obj| obj.depth > 0).count();
+        let max_depth = pack.contents.iter().map(|obj| obj.depth).max();
+        outln!(
+            "Total {} (delta {deltas}), max chain depth {}",
+            pack.obj_count,
+            max_depth.unwrap_or_default()
+        );
+    }
+
+    let rev_index = rev_index || config.get_bool("pack.writereverseindex")?.unwrap_or(false);
+    if rev_index {
+        pack.write_reverse_index(pack_file.with_extension("rev"))?;
+    }
+    outln!("{}", pack.checksum);
+
+    if strict {
+        for object in pack.contents {
+            if object.object_type != ObjectType::Tree {
+                continue;
+            }
+
+            let hash = object.hash.clone();
+            object
+                .into_inner()
+                .and_then(Tree::from_buf)
+                .and_then(|tree| tree.check_sorted())
+                .with_context(|| format!("invalid tree {hash}"))?;
+        }
+    }
 
     Ok(())
 }
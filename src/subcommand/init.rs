@@ -1,26 +1,89 @@
+use crate::hash::HashAlgorithm;
+use crate::output::outln;
+use crate::refs;
 use eyre::{Context, Result};
 use std::path::Path;
 
 pub fn run() -> Result<()> {
-    with_default_branch("main")
+    with_default_branch("main", HashAlgorithm::Sha1)
 }
 
-pub fn with_default_branch(branch: &str) -> Result<()> {
-    let pwd = Path::new(".").canonicalize()?;
+/// Create a repository whose `HEAD` points at `branch`, naming objects with
+/// the given hash algorithm.
+pub fn with_default_branch(branch: &str, object_format: HashAlgorithm) -> Result<()> {
+    init_at(Path::new("."), branch, object_format, false)
+}
+
+/// Like [`with_default_branch`], but in the given directory rather than the
+/// current one. With `bare`, the directory itself is the git directory, and
+/// there's no working tree.
+pub(crate) fn init_at(
+    root: &Path,
+    branch: &str,
+    object_format: HashAlgorithm,
+    bare: bool,
+) -> Result<()> {
+    refs::check_branch_name(branch)?;
+
+    let pwd = root.canonicalize()?;
+    let (git_dir, shown_dir) = match bare {
+        true => (root.to_owned(), pwd),
+        false => (root.join(".git"), pwd.join(".git")),
+    };
 
-    if Path::new(".git").exists() {
-        eprintln!("Git repository already exists in {}/.git", pwd.display());
+    if git_dir.join("HEAD").exists() {
+        eprintln!("Git repository already exists in {}", shown_dir.display());
         return Ok(());
     }
 
-    for dir in [".git", ".git/objects", ".git/refs", ".git/refs/heads"] {
-        std::fs::create_dir(dir).with_context(|| format!("create {dir} directory"))?;
+    if !bare {
+        std::fs::create_dir(&git_dir).context("create .git directory")?;
     }
+    // only the log directories are created here; the reflog for HEAD (and the
+    // default branch) is started by the first update to the branch, whose old
+    // value is the null hash
+    for dir in [
+        "objects",
+        "refs",
+        "refs/heads",
+        "logs",
+        "logs/refs",
+        "logs/refs/heads",
+    ] {
+        std::fs::create_dir(git_dir.join(dir))
+            .with_context(|| format!("create {dir} directory"))?;
+    }
+
+    // anything but SHA-1 needs the `objectformat` extension, which is only
+    // understood by version 1 repositories
+    let config = match object_format {
+        HashAlgorithm::Sha1 => format!(
+            "[core]\n\
+             \trepositoryformatversion = 0\n\
+             \tfilemode = true\n\
+             \tbare = {bare}\n\
+             \tlogallrefupdates = true\n",
+        ),
+        object_format => format!(
+            "[core]\n\
+             \trepositoryformatversion = 1\n\
+             \tfilemode = true\n\
+             \tbare = {bare}\n\
+             \tlogallrefupdates = true\n\
+             [extensions]\n\
+             \tobjectformat = {}\n",
+            object_format.name()
+        ),
+    };
+    std::fs::write(git_dir.join("config"), config).context("create config")?;
 
-    std::fs::write(".git/HEAD", format!("ref: refs/heads/{}\n", branch))
-        .context("create .git/HEAD")?;
+    std::fs::write(
+        git_dir.join("HEAD"),
+        format!("ref: refs/heads/{}\n", branch),
+    )
+    .context("create HEAD")?;
 
-    println!("Initialized Git repository in {}/.git", pwd.display());
+    outln!("Initialized Git repository in {}", shown_dir.display());
 
     Ok(())
 }
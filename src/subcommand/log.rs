@@ -0,0 +1,113 @@
+use crate::commit::{self, Commit};
+use crate::graph::{self, WalkOrder};
+use crate::refs::{self, Head};
+use crate::subcommand::rev_list;
+use eyre::Result;
+use std::time::{Duration, SystemTime};
+
+/// Which commits `log` shows, out of those it walks.
+#[derive(Debug, Default)]
+pub struct LogFilter {
+    /// Only show commits whose author (`Name <email>`) contains one of these.
+    pub authors: Vec<String>,
+    /// Only show commits whose message contains one of these.
+    pub greps: Vec<String>,
+    /// Only show commits made at or after this date.
+    pub since: Option<String>,
+    /// Only show commits made at or before this date.
+    pub until: Option<String>,
+}
+
+/// Show the commits reachable from the given revisions (or `HEAD`) that match
+/// `filter`.
+pub fn run(
+    revisions: &[String],
+    topo_order: bool,
+    date_order: bool,
+    filter: &LogFilter,
+) -> Result<()> {
+    let order = WalkOrder::from_flags(topo_order, date_order);
+    let since = filter.since.as_deref().map(parse_date).transpose()?;
+    let until = filter.until.as_deref().map(parse_date).transpose()?;
+
+    let tips = if revisions.is_empty() {
+        // a new branch doesn't exist until its first commit
+        if let Head::Branch(branch) = refs::read_head()? {
+            if refs::resolve("HEAD")?.is_none() {
+                eyre::bail!("your current branch '{branch}' does not have any commits yet");
+            }
+        }
+        rev_list::lookup_all(&[String::from("HEAD")])?
+    } else {
+        rev_list::lookup_all(revisions)?
+    };
+
+    let mut shown = 0;
+    for hash in graph::walk(&tips, order)?.iter() {
+        let commit = Commit::read_at_hash(hash)?;
+
+        // like git, dates are compared against when the commit was made,
+        // rather than when it was authored
+        let time = commit.committer.timestamp;
+        let author = commit.author.ident();
+        if since.is_some_and(|since| time < since)
+            || until.is_some_and(|until| time > until)
+            || !matches_any(&author, &filter.authors)
+            || !matches_any(&String::from_utf8_lossy(&commit.message), &filter.greps)
+        {
+            continue;
+        }
+
+        if shown > 0 {
+            println!();
+        }
+        shown += 1;
+        println!("commit {hash}");
+        if commit.parent_hashes.len() > 1 {
+            let parents: Vec<&str> = commit.parent_hashes.iter().map(|p| &p[..7]).collect();
+            println!("Merge: {}", parents.join(" "));
+        }
+        println!("Author: {author}");
+        println!("Date:   {}", commit.author.format_date());
+        println!();
+        for line in String::from_utf8_lossy(&commit.message).lines() {
+            println!("    {line}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether `text` contains any of `patterns` (or there aren't any patterns).
+fn matches_any(text: &str, patterns: &[String]) -> bool {
+    patterns.is_empty()
+        || patterns
+            .iter()
+            .any(|pattern| text.contains(pattern.as_str()))
+}
+
+/// Parse a date given to `--since` or `--until`: anything accepted for
+/// `GIT_COMMITTER_DATE`, a bare `YYYY-MM-DD` (meaning midnight UTC), or a
+/// relative date like `2 weeks ago`.
+fn parse_date(s: &str) -> Result<SystemTime> {
+    if let Some((time, _)) =
+        commit::parse_date(s).or_else(|| commit::parse_date(&format!("{s} 00:00:00")))
+    {
+        return Ok(time);
+    }
+
+    let relative = s.strip_suffix(" ago").and_then(|ago| {
+        let (n, unit) = ago.split_once(' ')?;
+        let seconds = match unit.trim_end_matches('s') {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 60 * 60,
+            "day" => 24 * 60 * 60,
+            "week" => 7 * 24 * 60 * 60,
+            _ => return None,
+        };
+        let ago = Duration::from_secs(n.parse::<u64>().ok()?.checked_mul(seconds)?);
+        SystemTime::now().checked_sub(ago)
+    });
+    relative.ok_or_else(|| eyre::eyre!("invalid date '{s}'"))
+}
@@ -1,21 +1,73 @@
+use crate::ignore::Ignore;
 use crate::index::{Index, IndexEntryPermissions};
+use crate::pathspec::Pathspec;
+use crate::repository;
+use crate::subcommand::status::working_tree_changes;
 use eyre::{Context, Result};
+use std::os::unix::ffi::OsStrExt;
 
-pub fn run(cached: bool, staged: bool) -> Result<()> {
+pub fn run(
+    cached: bool,
+    staged: bool,
+    others: bool,
+    modified: bool,
+    deleted: bool,
+    exclude_standard: bool,
+    paths: &[String],
+) -> Result<()> {
     let index = Index::read_default().context("read index")?;
+    // like git, only list files under the current directory by default
+    let pathspec = match paths {
+        [] => Pathspec::new(&[String::from(".")]),
+        paths => Pathspec::new(paths),
+    };
 
-    for entry in index.entries.iter() {
-        if staged {
-            let mode = match &entry.permissions {
-                IndexEntryPermissions::None => "000000",
-                IndexEntryPermissions::RegularFile => "100644",
-                IndexEntryPermissions::ExecutableFile => "100755",
-            };
+    // like git, list the cached files if nothing else was asked for
+    let cached = cached || !(staged || others || modified || deleted);
 
-            print!("{} {} {}\t", mode, entry.hash, entry.flags & 0x3000);
+    if cached || staged {
+        for entry in index.entries.iter() {
+            if !pathspec.matches(entry.name.as_bytes()) {
+                continue;
+            }
+
+            if staged {
+                let mode = match &entry.permissions {
+                    IndexEntryPermissions::None => "000000",
+                    IndexEntryPermissions::RegularFile => "100644",
+                    IndexEntryPermissions::ExecutableFile => "100755",
+                };
+
+                print!("{} {} {}\t", mode, entry.hash, entry.flags & 0x3000);
+            }
+
+            println!("{}", repository::to_prefix(&entry.name).display());
+        }
+    }
+
+    if others || modified || deleted {
+        let ignore = if exclude_standard {
+            Some(Ignore::read_standard().context("read ignore files")?)
+        } else {
+            None
+        };
+        let changes = working_tree_changes(&index, &pathspec, ignore.as_ref())?;
+
+        // like git, deleted files are also listed as modified
+        let mut listed = Vec::new();
+        if deleted {
+            listed.extend(changes.deleted.iter());
+        }
+        if modified {
+            listed.extend(changes.modified.iter().chain(changes.deleted.iter()));
+        }
+        if others {
+            listed.extend(changes.untracked.iter());
         }
 
-        println!("{}", entry.name);
+        for name in listed {
+            println!("{}", repository::to_prefix(name).display());
+        }
     }
 
     Ok(())
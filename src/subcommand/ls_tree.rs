@@ -1,11 +1,12 @@
-use crate::object::{ObjectBuf, ObjectMode, ObjectType};
+use crate::object::{ObjectBuf, ObjectHash, ObjectType};
 use crate::tree::Tree;
 use eyre::Result;
 use std::fmt::Debug;
 use std::io::BufRead;
 
 pub fn run(name_only: bool, object_hash: &str) -> Result<()> {
-    let object = ObjectBuf::read_at_hash(object_hash)?;
+    let object_hash: ObjectHash = object_hash.parse()?;
+    let object = ObjectBuf::read_at_hash(object_hash.as_hex())?;
     print_tree(name_only, object)
 }
 
@@ -16,15 +17,11 @@ pub(crate) fn print_tree<R: BufRead + Debug>(name_only: bool, object: ObjectBuf<
 
     for entry in Tree::from_buf(object)?.entries().iter() {
         if !name_only {
-            let object_type = if entry.mode == ObjectMode::Directory {
-                "tree"
-            } else {
-                "blob"
-            };
+            let object_type = entry.mode.object_type();
             print!("{:0>6} {} {}\t", entry.mode, object_type, entry.hash);
         }
 
-        println!("{}", entry.name);
+        println!("{}", entry.name.to_string_lossy());
     }
 
     Ok(())
@@ -0,0 +1,303 @@
+use crate::commit::{Commit, CommitAttribution};
+use crate::diff::{self, Merge};
+use crate::graph;
+use crate::hooks;
+use crate::index::Index;
+use crate::object::{Object, ObjectBuf, ObjectHash, ObjectHashable, ObjectMode};
+use crate::output::outln;
+use crate::refs;
+use crate::repository;
+use crate::subcommand::checkout;
+use crate::tree::{Tree, TreeEntry};
+use eyre::{Context, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsString;
+use std::path::Path;
+
+/// The outcome of merging two trees into the working tree.
+pub(crate) struct TreeMerge {
+    /// Paths that couldn't be merged automatically.
+    pub conflicts: Vec<OsString>,
+    /// The merged tree, if there weren't any conflicts.
+    pub tree: Option<ObjectHash>,
+}
+
+// FIXME: make sure that working directory is clean first
+pub fn run(branch: &str) -> Result<()> {
+    let ours =
+        refs::resolve("HEAD")?.ok_or_else(|| eyre::eyre!("HEAD does not point to a commit yet"))?;
+    let theirs = refs::lookup_commit(branch)?
+        .ok_or_else(|| eyre::eyre!("{branch} - not something we can merge"))?;
+
+    if graph::is_ancestor(&theirs, &ours)? {
+        outln!("Already up to date.");
+        return Ok(());
+    }
+
+    if graph::is_ancestor(&ours, &theirs)? {
+        outln!("Updating {}..{}", &ours[..7], &theirs[..7]);
+        fast_forward(
+            Some(&ours),
+            &theirs,
+            &format!("merge {branch}: Fast-forward"),
+        )?;
+        outln!("Fast-forward");
+        hooks::notify("post-merge", &["0"])?;
+        return Ok(());
+    }
+
+    // run before the working tree is touched, so a rejected merge leaves
+    // nothing behind
+    hooks::run_required("pre-merge-commit", &[]).context("not merging")?;
+
+    let base = graph::merge_base(&ours, &theirs)?;
+    let base_tree = match &base {
+        Some(base) => Some(Commit::read_at_hash(base)?.tree_hash),
+        None => None,
+    };
+
+    let merge = merge_trees(
+        base_tree.as_deref(),
+        &Commit::read_at_hash(&ours)?.tree_hash,
+        &Commit::read_at_hash(&theirs)?.tree_hash,
+        "HEAD",
+        branch,
+    )?;
+
+    let Some(tree_hash) = merge.tree else {
+        // leave a note so the merge commit can be created once the
+        // conflicts have been resolved
+        std::fs::write(
+            repository::current_git_dir().join("MERGE_HEAD"),
+            format!("{theirs}\n"),
+        )
+        .context("write MERGE_HEAD")?;
+
+        report_conflicts(&merge.conflicts);
+        eyre::bail!("automatic merge failed; fix conflicts and then commit the result");
+    };
+
+    let commit = Commit {
+        tree_hash: tree_hash.to_string(),
+        parent_hashes: vec![ours, theirs],
+        author: CommitAttribution::author()?,
+        committer: CommitAttribution::committer()?,
+        extra_headers: Vec::new(),
+        message: format!("Merge branch '{branch}'\n").into_bytes(),
+    };
+    let hash = Object::commit(commit).hash(true)?;
+    refs::update_head(
+        hash.as_hex(),
+        &format!("merge {branch}: Merge made by the 'resolve' strategy."),
+    )?;
+    hooks::notify("post-merge", &["0"])?;
+
+    outln!("Merge made by the 'resolve' strategy.");
+
+    Ok(())
+}
+
+/// Advance the current branch from `from` to `to` (which must be one of its
+/// descendants), updating the working tree and index to match.
+pub(crate) fn fast_forward(from: Option<&str>, to: &str, message: &str) -> Result<()> {
+    let from_tree = match from {
+        Some(from) => Some(Commit::read_at_hash(from)?.tree_hash),
+        None => None,
+    };
+    let to_tree = Commit::read_at_hash(to)?.tree_hash;
+
+    checkout::update_working_tree(from_tree.as_deref(), &to_tree).context("update working tree")?;
+    refs::update_head(to, message)
+}
+
+/// Merge the changes made between `base` and `theirs` into `ours`, which must
+/// be the tree that's currently checked out. The working tree is updated with
+/// the result (including conflict markers, where needed), and every cleanly
+/// merged path is staged.
+pub(crate) fn merge_trees(
+    base: Option<&str>,
+    ours: &str,
+    theirs: &str,
+    ours_label: &str,
+    theirs_label: &str,
+) -> Result<TreeMerge> {
+    let base = flatten(base)?;
+    let ours = flatten(Some(ours))?;
+    let theirs = flatten(Some(theirs))?;
+
+    let paths: BTreeSet<&OsString> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect();
+
+    let mut merged = Vec::new();
+    let mut conflicts = Vec::new();
+    // paths whose merged result has been written to the working tree
+    let mut updated = Vec::new();
+
+    for path in paths {
+        let (b, o, t) = (base.get(path), ours.get(path), theirs.get(path));
+        let (path, display) = (Path::new(path), path.to_string_lossy());
+
+        // unchanged by them (or changed identically by both), so keep ours
+        if same(o, t) || same(b, t) {
+            merged.extend(o.cloned());
+            continue;
+        }
+
+        // unchanged by us, so take theirs
+        if same(b, o) {
+            match t {
+                Some(t) => checkout::write_entry(path, t.mode, t.hash.as_hex())?,
+                None => checkout::remove_file(path)?,
+            }
+            merged.extend(t.cloned());
+            updated.push(path);
+            continue;
+        }
+
+        // changed by both
+        match (o, t) {
+            (Some(o), Some(t)) => match merge_blobs(b, o, t, ours_label, theirs_label)? {
+                Some(Merge {
+                    contents,
+                    conflicts: 0,
+                }) => {
+                    std::fs::write(repository::work_tree_path(path), contents)
+                        .with_context(|| format!("write {display}"))?;
+                    let Some(mode) = merge_modes(b, o, t) else {
+                        conflicts.push(path.as_os_str().to_owned());
+                        continue;
+                    };
+                    checkout::set_permissions(path, mode)?;
+                    merged.push(TreeEntry {
+                        name: path.as_os_str().to_owned(),
+                        mode,
+                        hash: Object::blob(path).hash(true)?,
+                    });
+                    updated.push(path);
+                }
+                Some(Merge { contents, .. }) => {
+                    std::fs::write(repository::work_tree_path(path), contents)
+                        .with_context(|| format!("write {display}"))?;
+                    conflicts.push(path.as_os_str().to_owned());
+                }
+                // binary files can't be merged line-by-line, so keep ours
+                None => conflicts.push(path.as_os_str().to_owned()),
+            },
+
+            // modified on one side and deleted on the other; leave whichever
+            // version was modified in the working tree
+            _ => {
+                if let Some(t) = t {
+                    checkout::write_blob(path, t.hash.as_hex())?;
+                }
+                conflicts.push(path.as_os_str().to_owned());
+            }
+        }
+    }
+
+    // stage the merged paths on top of the current index (so that untracked
+    // files stay untracked); conflicted paths are left staged as they were in
+    // our tree
+    let mut index = Index::read_default_or_empty().context("read index")?;
+    for path in updated {
+        if repository::work_tree_path(path).exists() {
+            index.add(path)?;
+        } else {
+            index.remove(path);
+        }
+    }
+    index.write_default().context("write index")?;
+
+    let tree = if conflicts.is_empty() {
+        Some(Tree::write_flat(merged)?)
+    } else {
+        None
+    };
+
+    Ok(TreeMerge { conflicts, tree })
+}
+
+pub(crate) fn report_conflicts(conflicts: &[OsString]) {
+    // conflicts are reported even with `--quiet`, since they need attention
+    for path in conflicts.iter() {
+        println!(
+            "CONFLICT (content): Merge conflict in {}",
+            path.to_string_lossy()
+        );
+    }
+}
+
+/// Perform a line-level merge of a blob that was modified on both sides.
+/// Returns `None` if any version of the blob is binary.
+fn merge_blobs(
+    base: Option<&TreeEntry>,
+    ours: &TreeEntry,
+    theirs: &TreeEntry,
+    ours_label: &str,
+    theirs_label: &str,
+) -> Result<Option<Merge>> {
+    fn read_text(entry: Option<&TreeEntry>) -> Result<Option<String>> {
+        let Some(entry) = entry else {
+            return Ok(Some(String::new()));
+        };
+
+        let contents = ObjectBuf::read_at_hash(entry.hash.as_hex())?.read_to_vec()?;
+        Ok(String::from_utf8(contents)
+            .ok()
+            .filter(|contents| !contents.contains('\0')))
+    }
+
+    let (Some(base), Some(ours), Some(theirs)) = (
+        read_text(base)?,
+        read_text(Some(ours))?,
+        read_text(Some(theirs))?,
+    ) else {
+        return Ok(None);
+    };
+
+    Ok(Some(diff::merge3(
+        &base,
+        &ours,
+        &theirs,
+        ours_label,
+        theirs_label,
+    )))
+}
+
+/// Merge a path's mode the same way as its contents: whichever side changed
+/// it wins, unless both changed it differently (which is a conflict).
+fn merge_modes(
+    base: Option<&TreeEntry>,
+    ours: &TreeEntry,
+    theirs: &TreeEntry,
+) -> Option<ObjectMode> {
+    match base.map(|base| base.mode) {
+        _ if ours.mode == theirs.mode => Some(ours.mode),
+        Some(mode) if mode == ours.mode => Some(theirs.mode),
+        Some(mode) if mode == theirs.mode => Some(ours.mode),
+        _ => None,
+    }
+}
+
+fn flatten(tree_hash: Option<&str>) -> Result<HashMap<OsString, TreeEntry>> {
+    let Some(tree_hash) = tree_hash else {
+        return Ok(HashMap::new());
+    };
+
+    Ok(Tree::read_at_hash(tree_hash)?
+        .flatten()?
+        .into_iter()
+        .map(|entry| (entry.name.clone(), entry))
+        .collect())
+}
+
+fn same(a: Option<&TreeEntry>, b: Option<&TreeEntry>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.mode == b.mode && a.hash == b.hash,
+        (None, None) => true,
+        _ => false,
+    }
+}
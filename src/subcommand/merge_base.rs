@@ -0,0 +1,29 @@
+use crate::error::GitError;
+use crate::graph;
+use crate::refs;
+use eyre::Result;
+
+/// Print the best common ancestor of two commits, returning whether there
+/// was one (unrelated histories don't have any).
+pub fn run(a: &str, b: &str) -> Result<bool> {
+    match graph::merge_base(&lookup(a)?, &lookup(b)?)? {
+        Some(base) => {
+            println!("{base}");
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Check whether `ancestor` is an ancestor of (or the same as) `descendant`.
+pub fn is_ancestor(ancestor: &str, descendant: &str) -> Result<bool> {
+    graph::is_ancestor(&lookup(ancestor)?, &lookup(descendant)?)
+}
+
+fn lookup(name: &str) -> Result<String> {
+    Ok(
+        refs::lookup_commit(name)?.ok_or_else(|| GitError::BadObject {
+            name: name.to_owned(),
+        })?,
+    )
+}
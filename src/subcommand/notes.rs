@@ -0,0 +1,48 @@
+use crate::object::{ObjectBuf, ObjectMode};
+use crate::refs;
+use crate::tree::Tree;
+use eyre::{Context, Result};
+
+const NOTES_REF: &str = "refs/notes/commits";
+
+/// Print the note attached to the given object.
+///
+/// Notes are stored in the tree of the commit at `refs/notes/commits`, with
+/// each note a blob named after the object it annotates. Once there are many
+/// notes, their names are fanned out into subtrees by leading hex digits
+/// (e.g. `ab/cdef...`), possibly over several levels.
+pub fn show(object: &str) -> Result<()> {
+    let hash = refs::lookup(object)?.ok_or_else(|| eyre::eyre!("failed to resolve '{object}'"))?;
+
+    let note = match refs::resolve(NOTES_REF)? {
+        Some(_) => find_note(Tree::read_tree_ish(NOTES_REF)?, &hash)?,
+        None => None,
+    };
+    let note = note.ok_or_else(|| eyre::eyre!("no note found for object {hash}"))?;
+
+    let mut blob = ObjectBuf::read_at_hash(&note).with_context(|| format!("read note {note}"))?;
+    std::io::copy(&mut blob.reader(), &mut std::io::stdout().lock())
+        .context("write note to stdout")?;
+
+    Ok(())
+}
+
+/// Find the note blob for `hash` in the given notes tree, descending into any
+/// fanout subtree whose name is a prefix of what's left of the hash.
+fn find_note(tree: Tree, hash: &str) -> Result<Option<String>> {
+    for entry in tree.entries() {
+        let name = entry.name.to_string_lossy();
+        if entry.mode == ObjectMode::Directory {
+            if let Some(rest) = hash.strip_prefix(name.as_ref()) {
+                let subtree = Tree::read_at_hash(entry.hash.as_hex())?;
+                if let Some(note) = find_note(subtree, rest)? {
+                    return Ok(Some(note));
+                }
+            }
+        } else if name == hash {
+            return Ok(Some(entry.hash.to_string()));
+        }
+    }
+
+    Ok(None)
+}
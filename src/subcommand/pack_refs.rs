@@ -0,0 +1,8 @@
+use crate::refs;
+use eyre::Result;
+
+/// Pack loose refs into `.git/packed-refs`; only tags (and refs that are
+/// already packed) unless `all` is set.
+pub fn run(all: bool) -> Result<()> {
+    refs::pack(all)
+}
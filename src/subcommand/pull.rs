@@ -0,0 +1,52 @@
+use crate::config::Config;
+use crate::graph;
+use crate::output::outln;
+use crate::refs::{self, Head};
+use eyre::{Context, Result};
+
+pub fn run() -> Result<()> {
+    let Head::Branch(branch) = refs::read_head()? else {
+        eyre::bail!("you are not currently on a branch");
+    };
+
+    let config = Config::read_default().context("read config")?;
+    let remote = config
+        .get(&format!("branch.{branch}.remote"))
+        .unwrap_or("origin")
+        .to_owned();
+    let merge = config
+        .get(&format!("branch.{branch}.merge"))
+        .map(String::from)
+        .unwrap_or_else(|| format!("refs/heads/{branch}"));
+    let upstream = merge
+        .strip_prefix("refs/heads/")
+        .ok_or_else(|| eyre::eyre!("upstream {merge} is not a branch"))?;
+
+    crate::subcommand::fetch::fetch(&remote)?;
+
+    let theirs = refs::resolve(&format!("refs/remotes/{remote}/{upstream}"))?
+        .ok_or_else(|| eyre::eyre!("couldn't find remote ref {merge}"))?;
+
+    let ours = refs::resolve(&format!("refs/heads/{branch}"))?;
+
+    // we can only fast-forward if our branch is strictly behind upstream,
+    // which is the case when HEAD is an ancestor of the fetched tip
+    if let Some(ours) = &ours {
+        if graph::is_ancestor(&theirs, ours)? {
+            outln!("Already up to date.");
+            return Ok(());
+        }
+
+        if !graph::is_ancestor(ours, &theirs)? {
+            eyre::bail!("not possible to fast-forward, merge required");
+        }
+
+        outln!("Updating {}..{}", &ours[..7], &theirs[..7]);
+    }
+
+    crate::subcommand::merge::fast_forward(ours.as_deref(), &theirs, "pull: Fast-forward")?;
+
+    outln!("Fast-forward");
+
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use crate::config::Config;
+use crate::graph;
+use crate::hash::HashAlgorithm;
+use crate::object::ObjectBuf;
+use crate::output::outln;
+use crate::pack::write_pack;
+use crate::packet_line::{pkt_line_data_iter, pkt_line_str, PacketLine};
+use crate::refs;
+use crate::remote_url::RemoteUrl;
+use crate::subcommand::clone::fetch_refs;
+use eyre::{Context, Result};
+
+pub fn run(remote: &str, branch: &str) -> Result<()> {
+    // the remote may either be the name of a configured remote, or a URL
+    let config = Config::read_default().context("read config")?;
+    let configured_url = config.get(&format!("remote.{remote}.url"));
+    let repo_url: RemoteUrl = match configured_url {
+        Some(url) => url.parse()?,
+        None if remote.contains(['/', ':']) => remote.parse()?,
+        None => eyre::bail!("'{remote}' does not appear to be a configured remote"),
+    };
+
+    let ref_name = format!("refs/heads/{branch}");
+    let new_hash = refs::resolve(&ref_name)?
+        .ok_or_else(|| eyre::eyre!("src refspec {branch} does not match any"))?;
+
+    // the remote uses the "null" hash for refs that don't exist yet
+    let null_hash = HashAlgorithm::current()?.null_hash();
+    let (remote_refs, _) = fetch_refs(&repo_url, "git-receive-pack")?;
+    let old_hash = remote_refs
+        .iter()
+        .find(|_ref| _ref.name == ref_name)
+        .map(|_ref| _ref.hash.as_str())
+        .unwrap_or(&null_hash);
+
+    if old_hash == new_hash {
+        outln!("Everything up-to-date");
+        return Ok(());
+    }
+
+    // refuse to overwrite any remote history that we don't have locally
+    if old_hash != null_hash {
+        if !ObjectBuf::exists(old_hash) {
+            eyre::bail!(
+                "[rejected] {branch} -> {branch} (fetch first): the remote contains work that you do not have locally"
+            );
+        }
+
+        if !graph::is_ancestor(old_hash, &new_hash)? {
+            eyre::bail!(
+                "[rejected] {branch} -> {branch} (non-fast-forward): the tip of your current branch is behind its remote counterpart"
+            );
+        }
+    }
+
+    // the remote already has every object reachable from its branches, so
+    // we only need to send the objects that aren't reachable from those
+    let remote_tips: Vec<&str> = remote_refs
+        .iter()
+        .filter(|_ref| _ref.name.starts_with("refs/heads/"))
+        .map(|_ref| _ref.hash.as_str())
+        .collect();
+    let objects = graph::rev_list_objects(&[&new_hash], &remote_tips)?;
+
+    let mut body = Vec::new();
+    // like git, the command isn't newline-terminated (the capabilities are last)
+    body.extend_from_slice(
+        &PacketLine::bytes(format!("{old_hash} {new_hash} {ref_name}\0report-status")).repr(),
+    );
+    body.extend_from_slice(&PacketLine::flush().repr());
+    write_pack(&objects, &mut body).context("build packfile")?;
+
+    let resp = reqwest::blocking::Client::new()
+        .post(format!("{}/git-receive-pack", repo_url.http_base()?))
+        .header(
+            reqwest::header::CONTENT_TYPE,
+            "application/x-git-receive-pack-request",
+        )
+        .body(body)
+        .send()?;
+
+    if !resp.status().is_success() {
+        eyre::bail!("remote responded with HTTP {}", resp.status());
+    }
+
+    // The report-status response starts with the result of unpacking our
+    // packfile, followed by the result of each ref update:
+    //
+    //   unpack ok
+    //   ok refs/heads/main
+    //   ng refs/heads/other non-fast-forward
+    //
+    let report = resp.bytes()?;
+    let report_lines = pkt_line_data_iter(&report)
+        .map(|line| pkt_line_str(line?))
+        .collect::<Result<Vec<_>>>()?;
+    let mut report_lines = report_lines.into_iter();
+
+    match report_lines.next() {
+        Some("unpack ok") => (),
+        Some(status) => eyre::bail!(
+            "remote failed to unpack objects ({})",
+            status.trim_start_matches("unpack ")
+        ),
+        None => eyre::bail!("remote didn't report the status of the push"),
+    }
+
+    for line in report_lines {
+        if let Some(rejected) = line.strip_prefix("ng ") {
+            let (name, reason) = rejected.split_once(' ').unwrap_or((rejected, "rejected"));
+            eyre::bail!("[remote rejected] {name} ({reason})");
+        }
+    }
+
+    outln!("To {repo_url}");
+    if old_hash == null_hash {
+        outln!(" * [new branch]      {branch} -> {branch}");
+    } else {
+        outln!(
+            "   {}..{}  {branch} -> {branch}",
+            &old_hash[..7],
+            &new_hash[..7]
+        );
+    }
+
+    // only a configured remote has remote-tracking refs to update; a URL
+    // (including an scp-style one like `host:repo.git`) doesn't
+    if configured_url.is_some() {
+        refs::update(
+            &format!("refs/remotes/{remote}/{branch}"),
+            &new_hash,
+            "update by push",
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use crate::index::{Index, IndexEntry};
+use crate::repository;
+use crate::tree::{Tree, TreeEntry};
+use eyre::{Context, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsString;
+
+/// Replace the index with the contents of a tree. With `merge` and a single
+/// tree, entries that are unchanged keep their cached stats. With `merge` and
+/// two trees, the index is moved from the first tree to the second, keeping
+/// any changes that have been staged on top of the first.
+pub fn run(trees: &[String], merge: bool) -> Result<()> {
+    let entries = match (trees, merge) {
+        ([tree], false) => read_entries(tree)?
+            .iter()
+            .map(IndexEntry::from_tree_entry)
+            .collect::<Result<Vec<_>>>()?,
+        ([tree], true) => one_way_merge(tree)?,
+        ([old, new], true) => two_way_merge(old, new)?,
+        (_, false) => eyre::bail!("read-tree takes a single tree unless merging (-m)"),
+        (_, true) => eyre::bail!("merging more than two trees isn't supported yet"),
+    };
+
+    let mut index = Index {
+        version: 2,
+        entries,
+    };
+    index.entries.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    index.write_default().context("write index")?;
+
+    Ok(())
+}
+
+fn read_entries(tree_ish: &str) -> Result<Vec<TreeEntry>> {
+    Tree::read_tree_ish(tree_ish)?
+        .flatten()
+        .with_context(|| format!("read tree {tree_ish}"))
+}
+
+/// Read the current index, if there is one, keyed by path.
+fn read_index() -> Result<HashMap<OsString, IndexEntry>> {
+    if !repository::current_git_dir().join("index").exists() {
+        return Ok(HashMap::new());
+    }
+
+    let Index { entries, .. } = Index::read_default().context("read index")?;
+    Ok(entries
+        .into_iter()
+        .map(|entry| (entry.name.clone(), entry))
+        .collect())
+}
+
+fn one_way_merge(tree_ish: &str) -> Result<Vec<IndexEntry>> {
+    let mut index = read_index()?;
+
+    read_entries(tree_ish)?
+        .into_iter()
+        .map(|entry| match index.remove(&entry.name) {
+            Some(existing) if same(Some(&existing), Some(&entry))? => Ok(existing),
+            _ => IndexEntry::from_tree_entry(&entry),
+        })
+        .collect()
+}
+
+/// Move the index from `old` to `new`, following git's two-way merge rules:
+/// paths that differ between the trees take their new contents, unless
+/// they've been staged with something other than either version.
+fn two_way_merge(old: &str, new: &str) -> Result<Vec<IndexEntry>> {
+    let mut index = read_index()?;
+    let by_name = |entries: Vec<TreeEntry>| -> HashMap<OsString, TreeEntry> {
+        entries
+            .into_iter()
+            .map(|entry| (entry.name.clone(), entry))
+            .collect()
+    };
+    let old = by_name(read_entries(old)?);
+    let new = by_name(read_entries(new)?);
+
+    let names: BTreeSet<OsString> = index
+        .keys()
+        .chain(old.keys())
+        .chain(new.keys())
+        .cloned()
+        .collect();
+
+    let mut entries = Vec::new();
+    for name in names {
+        let staged = index.remove(&name);
+        let (old, new) = (old.get(&name), new.get(&name));
+
+        let unchanged = match (old, new) {
+            (Some(old), Some(new)) => old.mode == new.mode && old.hash == new.hash,
+            (None, None) => true,
+            _ => false,
+        };
+
+        if unchanged || same(staged.as_ref(), new)? {
+            entries.extend(staged);
+        } else if same(staged.as_ref(), old)? {
+            if let Some(new) = new {
+                entries.push(IndexEntry::from_tree_entry(new)?);
+            }
+        } else {
+            eyre::bail!(
+                "Entry '{}' would be overwritten by merge. Cannot merge.",
+                name.to_string_lossy()
+            );
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Whether an index entry has the same contents as a tree entry (where both
+/// being missing counts as the same).
+fn same(staged: Option<&IndexEntry>, entry: Option<&TreeEntry>) -> Result<bool> {
+    Ok(match (staged, entry) {
+        (Some(staged), Some(entry)) => staged.hash == entry.hash && staged.mode()? == entry.mode,
+        (None, None) => true,
+        _ => false,
+    })
+}
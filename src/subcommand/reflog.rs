@@ -0,0 +1,14 @@
+use crate::refs;
+use eyre::Result;
+
+/// Show the log of updates to the given ref, newest first.
+pub fn run(name: &str) -> Result<()> {
+    let full_name = refs::expand(name)?
+        .ok_or_else(|| eyre::eyre!("ambiguous argument '{name}': unknown revision"))?;
+
+    for (n, entry) in refs::read_reflog(&full_name)?.iter().rev().enumerate() {
+        println!("{} {name}@{{{n}}}: {}", &entry.new_hash[..7], entry.message);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use crate::config::{self, Config};
+use crate::refs;
+use crate::repository;
+use eyre::{Context, Result};
+use std::path::PathBuf;
+
+/// The config file remotes are configured in, which every working tree shares.
+fn config_path() -> PathBuf {
+    repository::common_dir().join("config")
+}
+
+/// List the configured remotes. With `verbose`, also show the URL each one
+/// fetches from and pushes to.
+pub fn list(verbose: bool) -> Result<()> {
+    let config = Config::read_default().context("read config")?;
+    for name in config.subsections("remote") {
+        if !verbose {
+            println!("{name}");
+            continue;
+        }
+
+        let url = config
+            .get(&format!("remote.{name}.url"))
+            .unwrap_or_default();
+        let push_url = config.get(&format!("remote.{name}.pushurl")).unwrap_or(url);
+        println!("{name}\t{url} (fetch)");
+        println!("{name}\t{push_url} (push)");
+    }
+
+    Ok(())
+}
+
+/// Add a remote that fetches every branch from `url` into
+/// `refs/remotes/<name>/`.
+pub fn add(name: &str, url: &str) -> Result<()> {
+    refs::check_ref_format(&format!("refs/remotes/{name}"))
+        .with_context(|| format!("'{name}' is not a valid remote name"))?;
+
+    let config = Config::read_default().context("read config")?;
+    if config.subsections("remote").contains(&name) {
+        eyre::bail!("remote {name} already exists");
+    }
+
+    config::set_value(config_path(), &format!("remote.{name}.url"), url)?;
+    config::set_value(
+        config_path(),
+        &format!("remote.{name}.fetch"),
+        &format!("+refs/heads/*:refs/remotes/{name}/*"),
+    )?;
+
+    Ok(())
+}
+
+/// Remove a remote, along with its remote-tracking refs and any branch
+/// configuration that tracks it.
+pub fn remove(name: &str) -> Result<()> {
+    let config = Config::read_default().context("read config")?;
+    if !config.subsections("remote").contains(&name) {
+        eyre::bail!("no such remote '{name}'");
+    }
+
+    for branch in config.subsections("branch") {
+        if config.get(&format!("branch.{branch}.remote")) == Some(name) {
+            config::unset_value(config_path(), &format!("branch.{branch}.remote"))?;
+            config::unset_value(config_path(), &format!("branch.{branch}.merge"))?;
+        }
+    }
+    config::remove_section(config_path(), "remote", Some(name))?;
+
+    for (tracking_ref, _) in refs::list(&format!("refs/remotes/{name}"))? {
+        refs::delete(&tracking_ref)?;
+    }
+
+    Ok(())
+}
+
+/// Change the URL a remote fetches from (and pushes to, unless it has a
+/// separate push URL).
+pub fn set_url(name: &str, url: &str) -> Result<()> {
+    let config = Config::read_default().context("read config")?;
+    if !config.subsections("remote").contains(&name) {
+        eyre::bail!("no such remote '{name}'");
+    }
+
+    config::set_value(config_path(), &format!("remote.{name}.url"), url)
+}
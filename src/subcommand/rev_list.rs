@@ -0,0 +1,28 @@
+use crate::error::GitError;
+use crate::graph::{self, WalkOrder};
+use crate::refs;
+use eyre::Result;
+
+/// Print the hash of every commit reachable from the given commits.
+pub fn run(commits: &[String], topo_order: bool, date_order: bool) -> Result<()> {
+    let order = WalkOrder::from_flags(topo_order, date_order);
+
+    for hash in graph::walk(&lookup_all(commits)?, order)? {
+        println!("{hash}");
+    }
+
+    Ok(())
+}
+
+pub(crate) fn lookup_all(names: &[String]) -> Result<Vec<String>> {
+    names
+        .iter()
+        .map(|name| {
+            Ok(
+                refs::lookup_commit(name)?.ok_or_else(|| GitError::UnknownRevision {
+                    name: name.to_owned(),
+                })?,
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,32 @@
+use crate::error::GitError;
+use crate::refs;
+use crate::repository;
+use eyre::Result;
+
+/// Print the object hash that each revision resolves to, after any of the
+/// requested details about the repository itself.
+pub fn run(
+    revisions: &[String],
+    show_toplevel: bool,
+    git_dir: bool,
+    is_inside_work_tree: bool,
+) -> Result<()> {
+    if show_toplevel {
+        println!("{}", repository::toplevel()?.display());
+    }
+    if git_dir {
+        println!("{}", repository::git_dir()?.display());
+    }
+    if is_inside_work_tree {
+        println!("{}", repository::is_inside_work_tree());
+    }
+
+    for revision in revisions {
+        let hash = refs::lookup(revision)?.ok_or_else(|| GitError::UnknownRevision {
+            name: revision.to_owned(),
+        })?;
+        println!("{hash}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use crate::commit::{Commit, CommitAttribution};
+use crate::hooks;
+use crate::object::{Object, ObjectHashable};
+use crate::output::outln;
+use crate::refs;
+use crate::subcommand::cherry_pick::apply_changes;
+use eyre::Result;
+
+// FIXME: make sure that working directory is clean first
+pub fn run(commit: &str) -> Result<()> {
+    let head =
+        refs::resolve("HEAD")?.ok_or_else(|| eyre::eyre!("HEAD does not point to a commit yet"))?;
+    let hash =
+        refs::lookup_commit(commit)?.ok_or_else(|| eyre::eyre!("bad revision '{commit}'"))?;
+    let commit = Commit::read_at_hash(&hash)?;
+
+    let parent_tree = match commit.parent_hashes.as_slice() {
+        [] => eyre::bail!("cannot revert the root commit {hash}"),
+        [parent_hash] => Commit::read_at_hash(parent_hash)?.tree_hash,
+        _ => eyre::bail!("commit {hash} is a merge but no -m option was given"),
+    };
+
+    // this is a cherry-pick in reverse: the commit's tree is the base, and
+    // the changes to apply are the ones that lead back to its parent
+    let subject = commit.summary();
+    let tree_hash = apply_changes(
+        &head,
+        Some(&commit.tree_hash),
+        &parent_tree,
+        &format!("parent of {} ({subject})", &hash[..7]),
+        "REVERT_HEAD",
+        &hash,
+    )?;
+
+    let message = format!("Revert \"{subject}\"\n\nThis reverts commit {hash}.\n");
+    let new_commit = Commit {
+        tree_hash: tree_hash.to_string(),
+        parent_hashes: vec![head],
+        author: CommitAttribution::author()?,
+        committer: CommitAttribution::committer()?,
+        extra_headers: Vec::new(),
+        message: message.into_bytes(),
+    };
+    let new_hash = Object::commit(new_commit).hash(true)?;
+    refs::update_head(new_hash.as_hex(), &format!("revert: Revert \"{subject}\""))?;
+    hooks::notify("post-commit", &[])?;
+
+    outln!("[{}] Revert \"{subject}\"", &new_hash.as_hex()[..7]);
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use crate::commit::Commit;
+use crate::error::GitError;
+use crate::graph::{self, WalkOrder};
+use crate::refs::{self, Head};
+use eyre::Result;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Show which of the commits on the given branches (or every local branch, if
+/// none are given) are reachable from each of them, like `git show-branch`.
+///
+/// Each branch gets a column, marked with `*` for the current branch and `+`
+/// for the others (or `-` for merges) where a commit is reachable from it.
+/// Commits are listed newest first, stopping at the first one that's
+/// reachable from every branch.
+pub fn run(names: &[String]) -> Result<()> {
+    let names = match names.is_empty() {
+        true => refs::list("refs/heads")?
+            .into_iter()
+            .map(|(name, _)| name.trim_start_matches("refs/heads/").to_owned())
+            .collect(),
+        false => names.to_vec(),
+    };
+    let mut tips = Vec::with_capacity(names.len());
+    for name in names.iter() {
+        tips.push(
+            refs::lookup_commit(name)?
+                .ok_or_else(|| GitError::UnknownRevision { name: name.clone() })?,
+        );
+    }
+
+    let current = match refs::read_head()? {
+        Head::Branch(branch) => names.iter().position(|name| *name == branch),
+        Head::Detached(_) => None,
+    };
+    let reachable = tips
+        .iter()
+        .map(|tip| graph::ancestors(tip))
+        .collect::<Result<Vec<HashSet<String>>>>()?;
+    let commit_names = name_commits(&names, &tips)?;
+
+    for (i, (name, tip)) in names.iter().zip(tips.iter()).enumerate() {
+        let marker = if Some(i) == current { '*' } else { '!' };
+        let subject = Commit::read_at_hash(tip)?.format("%(subject)")?;
+        println!("{}{marker} [{name}] {subject}", " ".repeat(i));
+    }
+    println!("{}", "-".repeat(names.len()));
+
+    for hash in graph::walk(&tips, WalkOrder::Date)? {
+        let commit = Commit::read_at_hash(&hash)?;
+        let markers: String = (0..names.len())
+            .map(|i| match reachable[i].contains(&hash) {
+                false => ' ',
+                true if commit.parent_hashes.len() > 1 => '-',
+                true if Some(i) == current => '*',
+                true => '+',
+            })
+            .collect();
+        println!(
+            "{markers} [{}] {}",
+            commit_names[&hash],
+            commit.format("%(subject)")?
+        );
+
+        if reachable.iter().all(|ancestors| ancestors.contains(&hash)) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Name every commit relative to the nearest branch tip it can be reached
+/// from, like `main~2` for a first-parent ancestor or `main^2` for the second
+/// parent of a merge (preferring earlier branches when there's a tie).
+fn name_commits(names: &[String], tips: &[String]) -> Result<HashMap<String, String>> {
+    let mut commit_names: HashMap<String, String> = HashMap::new();
+    // each commit to name, along with the name of the nearest commit it's
+    // reached from through only first parents, and how many steps away it is
+    let mut queue: VecDeque<(String, String, usize)> = VecDeque::new();
+    for (name, tip) in names.iter().zip(tips.iter()) {
+        queue.push_back((tip.clone(), name.clone(), 0));
+    }
+
+    while let Some((hash, base, generation)) = queue.pop_front() {
+        if commit_names.contains_key(&hash) {
+            continue;
+        }
+
+        let name = match generation {
+            0 => base.clone(),
+            1 => format!("{base}^"),
+            _ => format!("{base}~{generation}"),
+        };
+        for (i, parent) in Commit::read_at_hash(&hash)?
+            .parent_hashes
+            .into_iter()
+            .enumerate()
+        {
+            match i {
+                0 => queue.push_back((parent, base.clone(), generation + 1)),
+                _ => queue.push_back((parent, format!("{name}^{}", i + 1), 0)),
+            }
+        }
+        commit_names.insert(hash, name);
+    }
+
+    Ok(commit_names)
+}
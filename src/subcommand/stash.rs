@@ -0,0 +1,123 @@
+use crate::commit::{Commit, CommitAttribution};
+use crate::index::Index;
+use crate::object::{Object, ObjectHashable};
+use crate::output::outln;
+use crate::refs::{self, Head};
+use crate::repository;
+use crate::subcommand::checkout;
+use crate::subcommand::merge::{merge_trees, report_conflicts};
+use crate::tree::{Tree, TreeEntry};
+use eyre::{Context, Result};
+
+const STASH_REF: &str = "refs/stash";
+
+/// Record the state of the index and working tree, then reset both to `HEAD`.
+///
+/// Like git, each stash entry is a commit whose tree is a snapshot of the
+/// working tree, with two parents: the `HEAD` commit at the time of stashing,
+/// and a commit whose tree is a snapshot of the index.
+pub fn save() -> Result<()> {
+    let head = refs::resolve("HEAD")?
+        .ok_or_else(|| eyre::eyre!("you do not have the initial commit yet"))?;
+    let head_commit = Commit::read_at_hash(&head)?;
+    let branch = match refs::read_head()? {
+        Head::Branch(branch) => branch,
+        Head::Detached(_) => String::from("(no branch)"),
+    };
+
+    let index = Index::read_default().context("read index")?;
+    let index_tree = index.write_tree().context("write index tree")?;
+
+    // only tracked files are stashed; anything deleted from the working tree
+    // is left out of the snapshot
+    let mut worktree_entries = Vec::with_capacity(index.entries.len());
+    for entry in index.entries.iter() {
+        if std::fs::symlink_metadata(repository::work_tree_path(&entry.name)).is_err() {
+            continue;
+        }
+
+        let mut object = Object::blob(&entry.name);
+        worktree_entries.push(TreeEntry {
+            name: entry.name.clone(),
+            mode: object.mode()?,
+            hash: object
+                .hash(true)
+                .with_context(|| format!("write blob for {}", entry.name.to_string_lossy()))?,
+        });
+    }
+    let worktree_tree = Tree::write_flat(worktree_entries).context("write working tree")?;
+
+    if index_tree.as_hex() == head_commit.tree_hash
+        && worktree_tree.as_hex() == head_commit.tree_hash
+    {
+        outln!("No local changes to save");
+        return Ok(());
+    }
+
+    let subject = head_commit.summary();
+    let description = format!("{branch}: {} {subject}", &head[..7]);
+
+    let index_commit = Object::commit(Commit {
+        tree_hash: index_tree.to_string(),
+        parent_hashes: vec![head.clone()],
+        author: CommitAttribution::author()?,
+        committer: CommitAttribution::committer()?,
+        extra_headers: Vec::new(),
+        message: format!("index on {description}\n").into_bytes(),
+    })
+    .hash(true)?;
+
+    let message = format!("WIP on {description}");
+    let stash_commit = Object::commit(Commit {
+        tree_hash: worktree_tree.to_string(),
+        parent_hashes: vec![head.clone(), index_commit.to_string()],
+        author: CommitAttribution::author()?,
+        committer: CommitAttribution::committer()?,
+        extra_headers: Vec::new(),
+        message: format!("{message}\n").into_bytes(),
+    })
+    .hash(true)?;
+
+    refs::update(STASH_REF, stash_commit.as_hex(), &message)?;
+
+    checkout::update_working_tree(Some(worktree_tree.as_hex()), &head_commit.tree_hash)?;
+
+    outln!("Saved working directory and index state {message}");
+
+    Ok(())
+}
+
+/// Re-apply the most recent stash entry on top of `HEAD`, then drop it.
+/// If re-applying it causes conflicts, the entry is kept.
+// FIXME: make sure that working directory is clean first
+pub fn pop() -> Result<()> {
+    let stash = refs::resolve(STASH_REF)?.ok_or_else(|| eyre::eyre!("no stash entries found"))?;
+    let stash_commit = Commit::read_at_hash(&stash)?;
+    let base = stash_commit
+        .parent_hashes
+        .first()
+        .ok_or_else(|| eyre::eyre!("{stash} is not a stash commit"))?;
+
+    let head =
+        refs::resolve("HEAD")?.ok_or_else(|| eyre::eyre!("HEAD does not point to a commit yet"))?;
+
+    let merge = merge_trees(
+        Some(&Commit::read_at_hash(base)?.tree_hash),
+        &Commit::read_at_hash(&head)?.tree_hash,
+        &stash_commit.tree_hash,
+        "Updated upstream",
+        "Stashed changes",
+    )?;
+
+    if merge.tree.is_none() {
+        report_conflicts(&merge.conflicts);
+        eyre::bail!(
+            "conflicts in stashed changes; the stash entry is kept in case you need it again"
+        );
+    }
+
+    refs::delete_reflog_entry(STASH_REF, 0)?;
+    outln!("Dropped {STASH_REF}@{{0}} ({stash})");
+
+    Ok(())
+}
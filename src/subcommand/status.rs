@@ -1,22 +1,60 @@
+use crate::color::Colors;
+use crate::ignore::Ignore;
 use crate::index::{Index, IndexEntry};
+use crate::object::ObjectMode;
+use crate::pathspec::Pathspec;
+use crate::refs::{self, Head};
+use crate::repository;
+use crate::subcommand::diff::exact_renames;
 use ansi_term::{Color, Style};
 use eyre::{Context, Result};
 use std::collections::HashMap;
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStrExt;
+
+/// How the working tree differs from the index.
+pub(crate) struct WorkingTreeChanges {
+    pub modified: Vec<OsString>,
+    pub deleted: Vec<OsString>,
+    /// Files that aren't in the index at all, sorted by name.
+    pub untracked: Vec<OsString>,
+    /// Deleted files that reappear unchanged as untracked files elsewhere,
+    /// as `(from, to)` pairs. These are also listed as deleted and untracked.
+    pub renamed: Vec<(OsString, OsString)>,
+}
 
-pub fn run() -> Result<()> {
-    // TODO: display current branch
-    // TODO: compare branch to remote
-    // TODO: compare HEAD to index
-
-    let index = Index::read_default().context("read index")?;
-    let mut working_tree: HashMap<String, IndexEntry> = {
+/// Compare the working tree to the index, only considering paths that match
+/// the pathspec. If given, untracked files matched by `ignore` are left out.
+/// Skip-worktree entries are always considered clean, as are submodules that
+/// haven't been checked out.
+pub(crate) fn working_tree_changes(
+    index: &Index,
+    pathspec: &Pathspec,
+    ignore: Option<&Ignore>,
+) -> Result<WorkingTreeChanges> {
+    let mut working_tree: HashMap<OsString, IndexEntry> = {
         let Index { entries, .. } = Index::working_tree().context("read working tree")?;
-        HashMap::from_iter(entries.into_iter().map(|entry| (entry.name.clone(), entry)))
+        HashMap::from_iter(
+            entries
+                .into_iter()
+                .filter(|entry| pathspec.matches(entry.name.as_bytes()))
+                .map(|entry| (entry.name.clone(), entry)),
+        )
     };
 
     let mut modified = Vec::new();
     let mut deleted = Vec::new();
     for entry in index.entries.iter() {
+        if !pathspec.matches(entry.name.as_bytes()) {
+            continue;
+        }
+
+        // entries outside of a sparse checkout are expected to be missing
+        if entry.skip_worktree() {
+            working_tree.remove(&entry.name);
+            continue;
+        }
+
         match working_tree.get(&entry.name) {
             Some(working_copy) => {
                 if entry.hash != working_copy.hash {
@@ -25,58 +63,139 @@ pub fn run() -> Result<()> {
 
                 working_tree.remove(&entry.name);
             }
+            None if entry.mode()? == ObjectMode::Gitlink
+                && repository::work_tree_path(&entry.name).is_dir() => {}
             None => {
-                deleted.push(entry.name.clone());
+                deleted.push(entry);
             }
         }
     }
 
-    let mut added = working_tree.keys().collect::<Vec<_>>();
-    added.sort_unstable();
+    let mut untracked = working_tree
+        .into_values()
+        .filter(|entry| ignore.is_none_or(|ignore| !ignore.is_ignored(entry.name.as_bytes())))
+        .collect::<Vec<_>>();
+    untracked.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+    let renamed = exact_renames(
+        &deleted.iter().map(|entry| &entry.hash).collect::<Vec<_>>(),
+        &untracked
+            .iter()
+            .map(|entry| &entry.hash)
+            .collect::<Vec<_>>(),
+    )
+    .into_iter()
+    .map(|(d, u)| (deleted[d].name.clone(), untracked[u].name.clone()))
+    .collect();
+
+    Ok(WorkingTreeChanges {
+        modified,
+        deleted: deleted
+            .into_iter()
+            .map(|entry| entry.name.clone())
+            .collect(),
+        untracked: untracked.into_iter().map(|entry| entry.name).collect(),
+        renamed,
+    })
+}
+
+pub fn run(paths: &[String]) -> Result<()> {
+    // TODO: display current branch
+    // TODO: compare branch to remote
+    // TODO: compare HEAD to index
+
+    let index = Index::read_default_or_empty().context("read index")?;
+    let ignore = Ignore::read_standard().context("read ignore files")?;
+    let WorkingTreeChanges {
+        modified,
+        mut deleted,
+        untracked: mut added,
+        renamed,
+    } = working_tree_changes(&index, &Pathspec::new(paths), Some(&ignore))?;
+
+    // renamed files are shown as such, rather than as a deletion and an addition
+    deleted.retain(|file| !renamed.iter().any(|(from, _)| from == file));
+    added.retain(|file| !renamed.iter().any(|(_, to)| to == file));
 
     // ---
 
-    let head = std::fs::read_to_string(".git/HEAD").context("read .git/HEAD")?;
-    if !head.starts_with("ref: ") {
-        println!("In detached head mode, at {}\n", head);
-    } else {
-        assert!(
-            head.starts_with("ref: refs/heads/"),
-            "lazy assumption about branch naming"
-        );
-        println!("On branch {}", &head[16..]);
+    let colors = Colors::for_stdout("status")?;
+
+    match refs::read_head()? {
+        Head::Branch(branch) => println!("On branch {branch}"),
+        Head::Detached(hash) => println!("In detached head mode, at {hash}\n"),
+    }
+
+    if refs::resolve("HEAD")?.is_none() {
+        println!("\nNo commits yet\n");
     }
 
-    if !modified.is_empty() || !deleted.is_empty() {
+    if !modified.is_empty() || !deleted.is_empty() || !renamed.is_empty() {
         println!("Changes not staged for commit:");
         println!(
             "  {}",
-            Style::new()
-                .dimmed()
+            colors
+                .style(Style::new().dimmed())
                 .paint("(use \"git add <file>...\" to update what will be commmitted)")
         );
         println!(
             "  {}",
-            Style::new()
-                .dimmed()
+            colors
+                .style(Style::new().dimmed())
                 .paint("(use \"git restore <file>...\" to discard changes in working directory)")
         );
 
         for file in modified.iter() {
+            // a submodule is modified when it has a different commit checked out
+            let is_submodule = index.entries.iter().any(|entry| {
+                &entry.name == file && matches!(entry.mode(), Ok(ObjectMode::Gitlink))
+            });
             println!(
-                "\t{} {} {}",
-                Style::new().dimmed().fg(Color::Yellow).paint("[~]"),
-                Style::new().italic().fg(Color::Yellow).paint("modified:"),
-                Style::new().bold().fg(Color::Yellow).paint(file),
+                "\t{} {} {}{}",
+                colors
+                    .style(Style::new().dimmed().fg(Color::Yellow))
+                    .paint("[~]"),
+                colors
+                    .style(Style::new().italic().fg(Color::Yellow))
+                    .paint("modified:"),
+                colors
+                    .style(Style::new().bold().fg(Color::Yellow))
+                    .paint(repository::to_prefix(file).to_string_lossy()),
+                if is_submodule { " (new commits)" } else { "" },
             );
         }
 
         for file in deleted.iter() {
             println!(
                 "\t{} {} {}",
-                Style::new().dimmed().fg(Color::Red).paint("[x]"),
-                Style::new().italic().fg(Color::Red).paint("deleted:"),
-                Style::new().bold().fg(Color::Red).paint(file),
+                colors
+                    .style(Style::new().dimmed().fg(Color::Red))
+                    .paint("[x]"),
+                colors
+                    .style(Style::new().italic().fg(Color::Red))
+                    .paint("deleted:"),
+                colors
+                    .style(Style::new().bold().fg(Color::Red))
+                    .paint(repository::to_prefix(file).to_string_lossy()),
+            );
+        }
+
+        for (from, to) in renamed.iter() {
+            println!(
+                "\t{} {} {}",
+                colors
+                    .style(Style::new().dimmed().fg(Color::Cyan))
+                    .paint("[>]"),
+                colors
+                    .style(Style::new().italic().fg(Color::Cyan))
+                    .paint("renamed:"),
+                colors
+                    .style(Style::new().bold().fg(Color::Cyan))
+                    .paint(format!(
+                        "{} -> {}",
+                        repository::to_prefix(from).to_string_lossy(),
+                        repository::to_prefix(to).to_string_lossy()
+                    )),
             );
         }
 
@@ -87,28 +206,34 @@ pub fn run() -> Result<()> {
         println!("Untracked files:");
         println!(
             "  {}",
-            Style::new()
-                .dimmed()
+            colors
+                .style(Style::new().dimmed())
                 .paint("(use \"git add <file>...\" to include in what will be committed)")
         );
 
         for file in added.iter() {
             println!(
                 "\t{} {} {}",
-                Style::new().dimmed().fg(Color::Green).paint("[+]"),
-                Style::new().italic().fg(Color::Green).paint("added:"),
-                Style::new().bold().fg(Color::Green).paint(*file),
+                colors
+                    .style(Style::new().dimmed().fg(Color::Green))
+                    .paint("[+]"),
+                colors
+                    .style(Style::new().italic().fg(Color::Green))
+                    .paint("added:"),
+                colors
+                    .style(Style::new().bold().fg(Color::Green))
+                    .paint(repository::to_prefix(file).to_string_lossy()),
             );
         }
 
         println!();
     }
 
-    if modified.is_empty() && deleted.is_empty() && added.is_empty() {
+    if modified.is_empty() && deleted.is_empty() && added.is_empty() && renamed.is_empty() {
         println!(
             "{}",
-            Style::new()
-                .dimmed()
+            colors
+                .style(Style::new().dimmed())
                 .paint("nothing to commit, working tree clean")
         );
     }
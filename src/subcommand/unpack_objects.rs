@@ -1,11 +1,31 @@
-use eyre::Result;
-use std::io::{BufRead, BufReader};
+use crate::config::Config;
+use crate::object::objects_dir;
+use crate::pack::{self, OpenOptions, PackStream};
+use crate::utils::print_progress;
+use eyre::{Context, Result};
+use tempfile::NamedTempFile;
 
+/// Read a packfile from stdin and write each of its objects to the object
+/// store as a loose object, one at a time. Objects that can't be unpacked
+/// are reported once the rest of the pack has been.
 pub fn run() -> Result<()> {
-    let mut stdin = BufReader::new(std::io::stdin().lock());
+    // the pack is copied to disk first, since resolving a delta may mean
+    // going back to a base that was read earlier
+    let mut pack_file = NamedTempFile::new().context("create temp file")?;
+    std::io::copy(&mut std::io::stdin().lock(), &mut pack_file)
+        .context("read packfile from stdin")?;
 
-    // read 1 or more packfiles from stdin and unpack them to loose objects
-    todo!();
+    let cache_limit = pack::delta_base_cache_limit(&Config::read_default()?)?;
+    let mut print_progress = print_progress;
+    let stream = PackStream::open(
+        pack_file.path(),
+        Some(&mut print_progress),
+        OpenOptions {
+            cache_limit,
+            ..Default::default()
+        },
+    )
+    .context("read packfile")?;
 
-    Ok(())
+    stream.unpack_into(&objects_dir()).check()
 }
@@ -1,13 +1,162 @@
-use crate::pack::Pack;
-use eyre::Result;
+use crate::config::Config;
+use crate::pack::{self, OpenOptions, Pack, PackedObject};
+use eyre::{Context, Result};
+use std::collections::BTreeMap;
 use std::path::Path;
 
 /// Given a `.idx` index file, verify that the corresponding packfile exists and is well formed.
-pub fn run(index_file: &str) -> Result<()> {
-    let index_file: &Path = index_file.as_ref();
+///
+/// With `verbose`, every object in the pack is listed (in the order they appear in the pack)
+/// along with a histogram of delta chain lengths; with `stat_only`, only the histogram is shown.
+/// With `json`, the same information is written as a single JSON document instead.
+pub fn run(index_file: &str, verbose: bool, stat_only: bool, json: bool) -> Result<()> {
+    let index_file = Path::new(index_file);
 
     let pack = Pack::open_index(index_file)?;
-    dbg!(pack);
+    tracing::debug!(
+        "verified {} (version {}, {} objects)",
+        index_file.display(),
+        pack.version,
+        pack.obj_count
+    );
+
+    let pack_file = index_file.with_extension("pack");
+
+    // the idx only says where each object is, so hash every object in the
+    // pack to make sure it's really there
+    let config = Config::read_default()?;
+    let cache_limit = pack::delta_base_cache_limit(&config)?;
+    let options = OpenOptions {
+        cache_limit,
+        lazy: true,
+        ..Default::default()
+    };
+    let hashed = Pack::open(&pack_file, None, options).context("read packfile")?;
+    for obj in pack.contents.iter() {
+        if hashed.find(&obj.hash).is_none() {
+            eyre::bail!("idx entry for {} doesn't match the pack", obj.hash);
+        }
+    }
+
+    // objects are stored in order by hash, but listed in the order they appear in the pack
+    let mut objects: Vec<&PackedObject> = pack.contents.iter().collect();
+    objects.sort_by_key(|obj| obj.offset);
+
+    // the number of objects with each delta chain length (0 being non-delta)
+    let mut chain_lengths: BTreeMap<usize, usize> = BTreeMap::new();
+    for obj in objects.iter() {
+        *chain_lengths.entry(obj.depth).or_default() += 1;
+    }
+
+    if json {
+        return print_json(&pack, &pack_file, &objects, chain_lengths);
+    }
+
+    if verbose && !stat_only {
+        for obj in objects.iter() {
+            print!(
+                "{} {:<6} {} {} {}",
+                obj.hash, obj.object_type, obj.inflated_size, obj.packed_size, obj.offset
+            );
+            if obj.depth > 0 {
+                print!(" {}", obj.depth);
+            }
+            println!();
+        }
+    }
+
+    if verbose || stat_only {
+        for (depth, count) in chain_lengths.iter() {
+            let noun = if *count == 1 { "object" } else { "objects" };
+            match depth {
+                0 => println!("non delta: {count} {noun}"),
+                _ => println!("chain length = {depth}: {count} {noun}"),
+            }
+        }
+    }
+
+    if verbose && !stat_only {
+        println!("{}: ok", pack_file.display());
+    }
 
     Ok(())
 }
+
+#[cfg(feature = "json")]
+fn print_json(
+    pack: &Pack,
+    pack_file: &Path,
+    objects: &[&PackedObject],
+    chain_lengths: BTreeMap<usize, usize>,
+) -> Result<()> {
+    use eyre::Context;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Report {
+        objects: Vec<ObjectRecord>,
+        summary: Summary,
+    }
+
+    #[derive(Serialize)]
+    struct ObjectRecord {
+        hash: String,
+        #[serde(rename = "type")]
+        object_type: String,
+        size: usize,
+        size_in_pack: usize,
+        offset: usize,
+        depth: usize,
+    }
+
+    #[derive(Serialize)]
+    struct Summary {
+        pack: String,
+        checksum: String,
+        version: u32,
+        objects: usize,
+        non_delta: usize,
+        chain_lengths: BTreeMap<usize, usize>,
+    }
+
+    let report = Report {
+        objects: objects
+            .iter()
+            .map(|obj| ObjectRecord {
+                hash: obj.hash.to_string(),
+                object_type: obj.object_type.to_string(),
+                size: obj.inflated_size,
+                size_in_pack: obj.packed_size,
+                offset: obj.offset,
+                depth: obj.depth,
+            })
+            .collect(),
+        summary: Summary {
+            pack: pack_file.display().to_string(),
+            checksum: pack.checksum.to_string(),
+            version: pack.version,
+            objects: objects.len(),
+            non_delta: chain_lengths.get(&0).copied().unwrap_or_default(),
+            chain_lengths: chain_lengths
+                .into_iter()
+                .filter(|(depth, _)| *depth > 0)
+                .collect(),
+        },
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer(&mut stdout, &report).context("write JSON report")?;
+    println!();
+
+    Ok(())
+}
+
+#[cfg(not(feature = "json"))]
+fn print_json(
+    _pack: &Pack,
+    _pack_file: &Path,
+    _objects: &[&PackedObject],
+    _chain_lengths: BTreeMap<usize, usize>,
+) -> Result<()> {
+    eyre::bail!("JSON output isn't available; rebuild with `--features json` to enable it")
+}
@@ -0,0 +1,169 @@
+use crate::output::outln;
+use crate::refs::{self, Head};
+use crate::repository;
+use crate::subcommand::checkout::check_out_into;
+use eyre::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Where the administrative files of each linked working tree are kept, in a
+/// directory of their own.
+fn worktrees_dir() -> PathBuf {
+    repository::common_dir().join("worktrees")
+}
+
+/// Create a linked working tree at `path` with `branch` (or, detaching its
+/// `HEAD`, any other commit) checked out. It shares this repository's objects
+/// and refs, but has its own `HEAD` and index.
+pub fn add(path: &str, branch: &str) -> Result<()> {
+    let path = Path::new(path);
+    if path
+        .read_dir()
+        .is_ok_and(|mut entries| entries.next().is_some())
+    {
+        eyre::bail!("'{}' already exists", path.display());
+    }
+
+    let branch_hash = refs::resolve(&format!("refs/heads/{branch}"))?;
+    let (head, commit_hash) = match branch_hash {
+        Some(hash) => {
+            if let Some(checked_out) = checked_out_at(branch)? {
+                eyre::bail!(
+                    "'{branch}' is already checked out at '{}'",
+                    checked_out.display()
+                );
+            }
+            (format!("ref: refs/heads/{branch}"), hash)
+        }
+        None => {
+            let hash = refs::lookup_commit(branch)?
+                .ok_or_else(|| eyre::eyre!("invalid reference: {branch}"))?;
+            (hash.clone(), hash)
+        }
+    };
+
+    std::fs::create_dir_all(path).with_context(|| format!("create {}", path.display()))?;
+    let work_tree = path.canonicalize()?;
+    let admin_dir = admin_dir_for(&work_tree)?;
+    std::fs::create_dir_all(&admin_dir)
+        .with_context(|| format!("create {}", admin_dir.display()))?;
+    let admin_dir = admin_dir.canonicalize()?;
+
+    // the working tree and its administrative directory point at each other
+    std::fs::write(
+        work_tree.join(".git"),
+        format!("gitdir: {}\n", admin_dir.display()),
+    )
+    .context("write .git file")?;
+    std::fs::write(
+        admin_dir.join("gitdir"),
+        format!("{}\n", work_tree.join(".git").display()),
+    )
+    .context("write gitdir")?;
+    std::fs::write(admin_dir.join("commondir"), "../..\n").context("write commondir")?;
+    std::fs::write(admin_dir.join("HEAD"), format!("{head}\n")).context("write HEAD")?;
+
+    outln!("Preparing worktree (checking out '{branch}')");
+    check_out_into(
+        repository::common_dir(),
+        &work_tree,
+        &admin_dir.join("index"),
+        &commit_hash,
+    )
+}
+
+/// List the main working tree followed by each linked one, along with the
+/// commit and branch checked out in it.
+pub fn list() -> Result<()> {
+    let common_dir = repository::common_dir();
+    let mut rows = vec![(main_work_tree()?, refs::read_head_in(common_dir)?)];
+    for (admin_dir, work_tree) in linked()? {
+        rows.push((work_tree, refs::read_head_in(&admin_dir)?));
+    }
+
+    let width = rows
+        .iter()
+        .map(|(path, _)| path.as_os_str().len())
+        .max()
+        .unwrap_or_default();
+    for (path, head) in rows {
+        let (hash, label) = match head {
+            Head::Branch(branch) => (
+                refs::resolve(&format!("refs/heads/{branch}"))?,
+                format!("[{branch}]"),
+            ),
+            Head::Detached(hash) => (Some(hash), String::from("(detached HEAD)")),
+        };
+        let hash = hash.as_deref().map_or("0000000", |hash| &hash[..7]);
+        println!("{:width$} {hash} {label}", path.display().to_string());
+    }
+
+    Ok(())
+}
+
+/// The administrative directory and working tree of every linked working
+/// tree, skipping any whose working tree has since been deleted.
+fn linked() -> Result<Vec<(PathBuf, PathBuf)>> {
+    let entries = match std::fs::read_dir(worktrees_dir()) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).context("read worktrees directory"),
+    };
+
+    let mut worktrees = Vec::new();
+    for entry in entries {
+        let admin_dir = entry?.path();
+        let gitdir = std::fs::read_to_string(admin_dir.join("gitdir"))
+            .with_context(|| format!("read {}", admin_dir.join("gitdir").display()))?;
+        let dot_git = PathBuf::from(gitdir.trim_end());
+        if let Some(work_tree) = dot_git.parent().filter(|_| dot_git.exists()) {
+            worktrees.push((admin_dir, work_tree.to_owned()));
+        }
+    }
+    worktrees.sort();
+
+    Ok(worktrees)
+}
+
+/// The main working tree, which the common directory belongs to (wherever
+/// the command was run from).
+fn main_work_tree() -> Result<PathBuf> {
+    let common_dir = repository::common_dir();
+    let common_dir = common_dir
+        .canonicalize()
+        .with_context(|| format!("resolve {}", common_dir.display()))?;
+    Ok(common_dir.parent().unwrap_or(&common_dir).to_owned())
+}
+
+/// Find the working tree that already has `branch` checked out, if any.
+fn checked_out_at(branch: &str) -> Result<Option<PathBuf>> {
+    if matches!(refs::read_head_in(repository::common_dir())?, Head::Branch(head) if head == branch)
+    {
+        return Ok(Some(main_work_tree()?));
+    }
+
+    for (admin_dir, work_tree) in linked()? {
+        if matches!(refs::read_head_in(&admin_dir)?, Head::Branch(head) if head == branch) {
+            return Ok(Some(work_tree));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Pick an unused administrative directory for a new working tree, named
+/// after the last component of its path (with a number added if needed).
+fn admin_dir_for(work_tree: &Path) -> Result<PathBuf> {
+    let name = work_tree
+        .file_name()
+        .ok_or_else(|| eyre::eyre!("'{}' has no name", work_tree.display()))?
+        .to_string_lossy();
+
+    let mut admin_dir = worktrees_dir().join(&*name);
+    let mut n = 1;
+    while admin_dir.exists() {
+        admin_dir = worktrees_dir().join(format!("{name}{n}"));
+        n += 1;
+    }
+
+    Ok(admin_dir)
+}
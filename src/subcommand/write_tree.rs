@@ -1,10 +1,13 @@
 use crate::object::{Object, ObjectHashable};
+use crate::output::outln;
 use eyre::Result;
 
-pub fn run() -> Result<()> {
-    let hash = Object::tree(".").hash(true)?;
+/// Print the hash of a tree of the working directory, writing it (and every
+/// object in it) to the object store unless `dry_run` is set.
+pub fn run(dry_run: bool) -> Result<()> {
+    let hash = Object::tree(".").hash(!dry_run)?;
 
-    println!("{hash}");
+    outln!("{hash}");
 
     Ok(())
 }
@@ -1,2 +1,82 @@
+use crate::commit::CommitAttribution;
+use crate::object::{ObjectBuf, ObjectType};
+use eyre::{Context, Result};
+use std::fmt::Debug;
+use std::io::BufRead;
+
+/// An annotated tag object. (Lightweight tags are just refs, and have no
+/// object of their own.)
 #[derive(Debug)]
-pub struct Tag {}
+pub struct Tag {
+    pub object_hash: String,
+    pub object_type: ObjectType,
+    pub name: String,
+    /// Very old tags may not have a tagger.
+    pub tagger: Option<CommitAttribution>,
+}
+
+impl Tag {
+    pub fn read_at_hash(hash: &str) -> Result<Self> {
+        let obj = ObjectBuf::read_at_hash(hash).with_context(|| format!("read tag {hash}"))?;
+        Self::from_buf(obj)
+    }
+
+    /// Follow the given object through any (possibly chained) annotated
+    /// tags, returning the hash and type of the first object that isn't a tag.
+    pub fn peel(hash: &str) -> Result<(String, ObjectType)> {
+        let mut hash = hash.to_owned();
+        let mut object_type = ObjectBuf::read_at_hash(&hash)?.object_type;
+        while object_type == ObjectType::Tag {
+            let tag = Self::read_at_hash(&hash)?;
+            hash = tag.object_hash;
+            object_type = tag.object_type;
+        }
+
+        Ok((hash, object_type))
+    }
+
+    pub fn from_buf<R>(mut object: ObjectBuf<R>) -> Result<Self>
+    where
+        R: BufRead + Debug,
+    {
+        if object.object_type != ObjectType::Tag {
+            eyre::bail!("attempted to parse {} as tag", object.object_type);
+        }
+
+        let buf = object.read_to_vec()?;
+        let s = std::str::from_utf8(&buf).context("tag should contain valid UTF-8")?;
+
+        let mut object_hash: Option<String> = None;
+        let mut object_type: Option<ObjectType> = None;
+        let mut name: Option<String> = None;
+        let mut tagger: Option<CommitAttribution> = None;
+
+        // FIXME: parse the message (and signature, if any)
+        let (header, _message) = s.split_once("\n\n").unwrap_or((s, ""));
+        for line in header.lines() {
+            let (t, value) = line
+                .split_once(' ')
+                .ok_or_else(|| eyre::eyre!("unexpected line in tag \"{line}\""))?;
+            match t {
+                "object" => object_hash = Some(value.to_owned()),
+                "type" => {
+                    object_type = Some(
+                        value
+                            .parse()
+                            .map_err(|_| eyre::eyre!("unrecognized object type {value}"))?,
+                    )
+                }
+                "tag" => name = Some(value.to_owned()),
+                "tagger" => tagger = Some(value.parse()?),
+                _ => eyre::bail!("unexpected line in tag \"{line}\""),
+            }
+        }
+
+        Ok(Self {
+            object_hash: object_hash.ok_or_else(|| eyre::eyre!("object must be provided"))?,
+            object_type: object_type.ok_or_else(|| eyre::eyre!("type must be provided"))?,
+            name: name.ok_or_else(|| eyre::eyre!("tag must be provided"))?,
+            tagger,
+        })
+    }
+}
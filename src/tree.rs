@@ -1,18 +1,58 @@
-use crate::object::{ObjectBuf, ObjectHash, ObjectMode, ObjectType};
+use crate::commit::Commit;
+use crate::error::GitError;
+use crate::hash::HashAlgorithm;
+use crate::object::{ObjectBuf, ObjectHash, ObjectHashable, ObjectMode, ObjectType};
+use crate::object_store::{FsObjectStore, ObjectStore};
+use crate::refs;
+use crate::tag::Tag;
 use eyre::{Context, Result};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::io::Write;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::{fmt::Debug, io::BufRead};
 
 #[derive(Debug)]
 pub struct Tree(Vec<TreeEntry>);
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TreeEntry {
-    pub name: String,
+    /// The entry's name, as raw bytes (which needn't be valid UTF-8).
+    pub name: OsString,
     pub mode: ObjectMode,
     pub hash: ObjectHash,
 }
 
 impl Tree {
+    pub fn read_at_hash(hash: &str) -> Result<Self> {
+        Self::read_in(FsObjectStore::current(), hash)
+    }
+
+    /// Like [`Tree::read_at_hash`], but from the given object store.
+    pub fn read_in(store: &dyn ObjectStore, hash: &str) -> Result<Self> {
+        let obj = store
+            .read(hash)
+            .with_context(|| format!("read tree {hash}"))?;
+        Self::from_buf(obj)
+    }
+
+    /// Read the tree named on the command line, either directly or as the
+    /// tree of a commit (peeling any tags first).
+    pub fn read_tree_ish(name: &str) -> Result<Self> {
+        let hash = refs::lookup(name)?.ok_or_else(|| GitError::BadObject {
+            name: name.to_owned(),
+        })?;
+        let (hash, _) = Tag::peel(&hash)?;
+        let obj = ObjectBuf::read_at_hash(&hash).with_context(|| format!("read object {hash}"))?;
+
+        match obj.object_type {
+            ObjectType::Tree => Self::from_buf(obj),
+            ObjectType::Commit => Self::read_at_hash(&Commit::from_buf(obj)?.tree_hash),
+            object_type => eyre::bail!("{name} is a {object_type}, not a tree"),
+        }
+    }
+
     pub fn from_buf<R>(mut object: ObjectBuf<R>) -> Result<Self>
     where
         R: BufRead + Debug,
@@ -21,6 +61,7 @@ impl Tree {
             eyre::bail!("attempted to parse {} as tree", object.object_type);
         }
 
+        let algorithm = HashAlgorithm::current()?;
         let mut entries = Vec::new();
         loop {
             let mode = object
@@ -30,22 +71,19 @@ impl Tree {
                 .parse()
                 .map_err(|s| eyre::eyre!("expected valid file mode but got {s}"))?;
 
-            let name = object
-                .contents
-                .parse_str(b'\0')
-                .context("read tree entry name")?;
+            let name = OsString::from_vec(
+                object
+                    .contents
+                    .parse_bytes(b'\0')
+                    .context("read tree entry name")?,
+            );
 
-            let mut hash_buf = [0; 20];
-            object
+            let hash = object
                 .contents
-                .read_exact(&mut hash_buf)
-                .context("read tree entry SHA")?;
+                .read_hash(algorithm)
+                .context("read tree entry hash")?;
 
-            entries.push(TreeEntry {
-                mode,
-                name,
-                hash: ObjectHash::from_bytes(&hash_buf),
-            });
+            entries.push(TreeEntry { mode, name, hash });
 
             // once we reach EOF, break from the loop
             if object.contents.at_eof()? {
@@ -56,7 +94,138 @@ impl Tree {
         Ok(Self(entries))
     }
 
+    /// Write the tree hierarchy described by a flat list of entries, each named
+    /// by its full path (like those returned by [`Tree::flatten`]), returning
+    /// the hash of the root tree. Every subtree is written along the way.
+    pub fn write_flat(entries: Vec<TreeEntry>) -> Result<ObjectHash> {
+        let mut direct = Vec::new();
+        let mut subtrees: BTreeMap<OsString, Vec<TreeEntry>> = BTreeMap::new();
+
+        for entry in entries {
+            let name = entry.name.as_bytes();
+            match name.iter().position(|&b| b == b'/') {
+                Some(slash) => subtrees
+                    .entry(OsStr::from_bytes(&name[..slash]).to_owned())
+                    .or_default()
+                    .push(TreeEntry {
+                        name: OsStr::from_bytes(&name[slash + 1..]).to_owned(),
+                        ..entry
+                    }),
+                None => direct.push(entry),
+            }
+        }
+
+        for (name, entries) in subtrees {
+            direct.push(TreeEntry {
+                name,
+                mode: ObjectMode::Directory,
+                hash: Self::write_flat(entries)?,
+            });
+        }
+
+        // git sorts tree entries by name, as if directories had a trailing slash
+        direct.sort_unstable_by(TreeEntry::cmp_git);
+
+        Self(direct).hash(true)
+    }
+
+    /// Check that this tree's entries are in git's canonical order (by name,
+    /// as if directories had a trailing slash), with no duplicate names.
+    /// Trees written by git are always sorted, so anything else is corrupt.
+    pub fn check_sorted(&self) -> Result<()> {
+        for pair in self.0.windows(2) {
+            if pair[0].name == pair[1].name {
+                eyre::bail!("duplicate tree entry {}", pair[0].name.to_string_lossy());
+            }
+            if pair[0].cmp_git(&pair[1]) != Ordering::Less {
+                eyre::bail!(
+                    "tree entries out of order: {} before {}",
+                    pair[0].name.to_string_lossy(),
+                    pair[1].name.to_string_lossy()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn entries(&self) -> &Vec<TreeEntry> {
         &self.0
     }
+
+    /// Recursively list every non-tree entry reachable from this tree, each
+    /// named by its full path relative to the root of the tree.
+    pub fn flatten(&self) -> Result<Vec<TreeEntry>> {
+        let mut entries = Vec::new();
+        self.flatten_into(OsStr::new(""), &mut entries)?;
+        Ok(entries)
+    }
+
+    fn flatten_into(&self, prefix: &OsStr, entries: &mut Vec<TreeEntry>) -> Result<()> {
+        for entry in self.0.iter() {
+            let mut name = prefix.to_owned();
+            name.push(&entry.name);
+
+            if entry.mode == ObjectMode::Directory {
+                name.push("/");
+                Tree::read_at_hash(entry.hash.as_hex())?.flatten_into(&name, entries)?;
+            } else {
+                entries.push(TreeEntry {
+                    name,
+                    mode: entry.mode,
+                    hash: entry.hash.clone(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Compare two tree entry names the way git orders them (see `base_name_compare`
+/// in git's `tree.c`): byte by byte, as if directories had a trailing slash.
+/// Gitlinks sort like files, so a file or submodule `foo` comes before a
+/// directory `foo`, which in turn comes before `foo.txt`.
+pub(crate) fn base_name_compare(a: &[u8], a_is_dir: bool, b: &[u8], b_is_dir: bool) -> Ordering {
+    let len = a.len().min(b.len());
+    match a[..len].cmp(&b[..len]) {
+        Ordering::Equal => {}
+        ordering => return ordering,
+    }
+
+    let terminator = |name: &[u8], is_dir| match name.get(len) {
+        Some(&b) => b,
+        None if is_dir => b'/',
+        None => b'\0',
+    };
+    terminator(a, a_is_dir).cmp(&terminator(b, b_is_dir))
+}
+
+impl TreeEntry {
+    fn cmp_git(&self, other: &Self) -> Ordering {
+        base_name_compare(
+            self.name.as_bytes(),
+            self.mode == ObjectMode::Directory,
+            other.name.as_bytes(),
+            other.mode == ObjectMode::Directory,
+        )
+    }
+}
+
+impl ObjectHashable for Tree {
+    fn write<W: Write>(&mut self, mut w: W, _store: Option<&dyn ObjectStore>) -> Result<()> {
+        let mut buf = Vec::new();
+
+        for entry in self.0.iter() {
+            write!(buf, "{} ", entry.mode)?;
+            buf.write_all(entry.name.as_bytes())?;
+            buf.write_all(b"\0")?;
+            buf.write_all(entry.hash.as_bytes())?;
+        }
+
+        write!(w, "tree {}\0", buf.len())?;
+        w.write_all(&buf).context("tree contents")?;
+
+        Ok(())
+    }
 }
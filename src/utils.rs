@@ -1,16 +1,58 @@
-use crate::object::ObjectHash;
-use eyre::Result;
-use sha1::{Digest, Sha1};
+use crate::config::Config;
+use crate::hash::HashAlgorithm;
+use crate::output;
+use eyre::{Context, Result};
+use flate2::Compression;
 use std::fs::File;
-use std::io::{Seek, SeekFrom, Write};
+use std::io::{IsTerminal, Seek, SeekFrom, Write};
 
-/// Given a file, calculate the SHA-1 checksum for its contents and append it to the end.
+/// Given a file, calculate the checksum for its contents (using the repository's
+/// hash algorithm) and append it to the end.
 pub fn append_checksum(mut f: File) -> Result<()> {
     f.seek(SeekFrom::Start(0)).unwrap();
-    let mut hasher = Sha1::new();
+    let mut hasher = HashAlgorithm::current()?.hasher();
     std::io::copy(&mut f, &mut hasher)?;
-    let index_checksum = ObjectHash::from_hasher(hasher);
-    f.write_all(&index_checksum.as_bytes())?;
+    let index_checksum = hasher.finalize();
+    f.write_all(index_checksum.as_bytes())?;
 
     Ok(())
 }
+
+/// The zlib compression level set by the first of the given config keys that
+/// has a value (like `core.compression`): 0 to 9, or -1 for zlib's default.
+pub fn compression_level(config: &Config, keys: &[&str]) -> Result<Compression> {
+    let Some((key, value)) = keys.iter().find_map(|key| Some((key, config.get(key)?))) else {
+        return Ok(Compression::default());
+    };
+
+    let level: i32 = value
+        .parse()
+        .with_context(|| format!("bad numeric config value '{value}' for '{key}'"))?;
+    match level {
+        -1 => Ok(Compression::default()),
+        0..=9 => Ok(Compression::new(level as u32)),
+        _ => eyre::bail!("bad zlib compression level {level} for '{key}'"),
+    }
+}
+
+/// Print a progress line for the given stage to stderr the way git does
+/// (`Resolving deltas:  42% (21/50)`), overwriting the previous line until
+/// the stage is done. Nothing is printed unless stderr is a terminal (or with
+/// `--quiet`).
+pub fn print_progress(stage: &str, done: usize, total: usize) {
+    let mut stderr = std::io::stderr();
+    if !stderr.is_terminal() || output::is_quiet() || total == 0 {
+        return;
+    }
+
+    // only redraw when the percentage changes
+    let percent = done * 100 / total;
+    if done > 1 && done < total && percent == (done - 1) * 100 / total {
+        return;
+    }
+
+    let _ = write!(stderr, "\r{stage}: {percent:>3}% ({done}/{total})");
+    if done == total {
+        let _ = writeln!(stderr, ", done.");
+    }
+}